@@ -0,0 +1,163 @@
+"""
+Compact binary output of peaks and matches, for clients that move large spectra between a
+Web Worker and the page as one transferable ArrayBuffer instead of structured-cloned JSON.
+
+Layout (little-endian): the magic b"PVB1", a uint32 schema length, the schema as UTF-8 JSON,
+zero padding to a multiple of 8 bytes, then the sections at the schema's offsets (counted
+from the end of that padding). Every section starts 8-byte aligned, so peaks can be read
+as a Float64Array in place.
+"""
+
+import json
+import math
+import struct
+from typing import Dict, Iterable, List, Optional, Tuple
+
+import numpy as np
+
+BINARY_MAGIC = b"PVB1"
+BINARY_FORMAT = "protview-binary"
+BINARY_VERSION = 1
+
+# Fixed records: (field, struct code, DataView type), each field aligned to its size
+MATCH_FIELDS = (
+    ("peak_index", "I", "uint32"),      # into the peaks section, NO_PEAK when not in it
+    ("ion_type", "H", "uint16"),        # into the section's ion_types table
+    ("ion_charge", "b", "int8"),
+    ("flags", "B", "uint8"),            # MATCH_FLAGS
+    ("peak_mz", "d", "float64"),
+    ("peak_intensity", "d", "float64"),
+    ("theoretical_mz", "d", "float64"),
+    ("error_da", "d", "float64"),
+    ("error_ppm", "d", "float64"),
+)
+# Run output: where each spectrum's rows start in the shared peaks section
+SPECTRUM_FIELDS = (
+    ("first_peak", "I", "uint32"),
+    ("peak_count", "I", "uint32"),
+    ("scan_nr", "i", "int32"),          # -1 when the nativeID has none
+    ("ms_level", "i", "int32"),         # 0 when not recorded
+    ("retention_time", "d", "float64"),  # seconds, NaN when not recorded
+    ("precursor_mz", "d", "float64"),    # first precursor, NaN for none
+)
+MATCH_FLAGS = {"alternatives": 1, "isotopes": 2}
+NO_PEAK = 0xFFFFFFFF
+
+def _record_struct(fields) -> struct.Struct:
+    return struct.Struct("<" + "".join(code for _, code, _ in fields))
+
+MATCH_RECORD = _record_struct(MATCH_FIELDS)
+SPECTRUM_RECORD = _record_struct(SPECTRUM_FIELDS)
+
+def _record_schema(fields, count: int) -> Dict:
+    layout, offset = [], 0
+    for name, code, kind in fields:
+        layout.append({"name": name, "type": kind, "offset": offset})
+        offset += struct.calcsize("<" + code)
+    return {"count": count, "record_size": offset, "fields": layout}
+
+def encode_peaks(mz, intensity, ion_mobility=None) -> Tuple[bytes, Dict]:
+    """Peaks as interleaved float64 [mz, intensity(, ion_mobility)] rows, with their section schema."""
+    columns = [np.asarray(mz, dtype=np.float64), np.asarray(intensity, dtype=np.float64)]
+    names = ["mz", "intensity"]
+    if ion_mobility is not None:
+        columns.append(np.asarray(ion_mobility, dtype=np.float64))
+        names.append("ion_mobility")
+    if any(len(c) != len(columns[0]) for c in columns):
+        raise ValueError("peak arrays differ in length")
+    data = np.column_stack(columns).astype("<f8").tobytes() if len(columns[0]) else b""
+    return data, {"count": len(columns[0]), "dtype": "float64", "columns": names}
+
+def encode_matches(matches: List[dict], peak_mzs: Optional[List[float]] = None) -> Tuple[bytes, Dict]:
+    """
+    match_ions results as MATCH_FIELDS records, with their section schema. Ion type names
+    go to a string table; peak_index refers to peak_mzs (the encoded peaks) when given.
+    Alternatives and isotope peaks are flagged but not stored.
+    """
+    positions = {mz: k for k, mz in enumerate(peak_mzs or [])}
+    ion_types: Dict[str, int] = {}
+    out = bytearray()
+    for match in matches:
+        type_index = ion_types.setdefault(match["ion_type"], len(ion_types))
+        if type_index > 0xFFFF:
+            raise ValueError(f"more than {0xFFFF + 1} distinct ion types")
+        flags = ((MATCH_FLAGS["alternatives"] if match.get("alternatives") else 0) |
+                 (MATCH_FLAGS["isotopes"] if match.get("isotopes") else 0))
+        out += MATCH_RECORD.pack(positions.get(match["peak_mz"], NO_PEAK), type_index, match.get("ion_charge", 1), flags,
+                                 match["peak_mz"], match["peak_intensity"], match["theoretical_mz"],
+                                 match.get("error_da", match["peak_mz"] - match["theoretical_mz"]),
+                                 match.get("error_ppm", 0.0))
+    return bytes(out), {**_record_schema(MATCH_FIELDS, len(matches)), "ion_types": list(ion_types),
+                        "flags": MATCH_FLAGS, "no_peak": NO_PEAK}
+
+def encode_run(spectra: Iterable[Dict]) -> Dict[str, Tuple[bytes, Dict]]:
+    """
+    "peaks" and "spectra" sections for many spectra (iter_spectra output): all peaks in one
+    interleaved section, and a SPECTRUM_FIELDS record per spectrum locating its rows.
+    """
+    mz, intensity, records, total = [], [], bytearray(), 0
+    for spectrum in spectra:
+        peaks = spectrum["peaks"]
+        precursor = next((p["mz"] for p in spectrum.get("precursors") or [] if p.get("mz")), None)
+        rt = spectrum.get("retention_time")
+        records += SPECTRUM_RECORD.pack(total, len(peaks), spectrum.get("scan_nr") if spectrum.get("scan_nr") is not None else -1,
+                                        spectrum.get("ms_level") or 0, rt if rt is not None else math.nan,
+                                        precursor if precursor is not None else math.nan)
+        mz.extend(p["mz"] for p in peaks)
+        intensity.extend(p["intensity"] for p in peaks)
+        total += len(peaks)
+    return {"peaks": encode_peaks(mz, intensity),
+            "spectra": (bytes(records), _record_schema(SPECTRUM_FIELDS, len(records) // SPECTRUM_RECORD.size))}
+
+def _pad(length: int) -> int:
+    return -length % 8
+
+def pack_binary(sections: Dict[str, Tuple[bytes, Dict]], metadata: Optional[Dict] = None) -> bytes:
+    """
+    One buffer of named sections (from encode_peaks / encode_matches) plus JSON-ready
+    metadata (scan number, precursors, ...) in the schema.
+    """
+    # Offsets are relative to the end of the padded header, so they do not depend on its length
+    schema_sections, body, offset = {}, bytearray(), 0
+    for name, (data, schema) in sections.items():
+        schema_sections[name] = {**schema, "offset": offset, "length": len(data)}
+        body += data + b"\0" * _pad(len(data))
+        offset = len(body)
+    schema = json.dumps({"format": BINARY_FORMAT, "version": BINARY_VERSION, "byte_order": "little",
+                         "metadata": metadata or {}, "sections": schema_sections}).encode("utf-8")
+    header = BINARY_MAGIC + struct.pack("<I", len(schema)) + schema
+    return header + b"\0" * _pad(len(header)) + bytes(body)
+
+def read_binary(data: bytes) -> Dict:
+    """
+    A pack_binary buffer decoded back: the schema with "peaks" (as {"mz", "intensity", ...}
+    dicts), "matches" (with ion_type names) and "spectra" for the sections present.
+    """
+    if data[:4] != BINARY_MAGIC or len(data) < 8:
+        raise ValueError("not a ProtView binary buffer")
+    (length,) = struct.unpack_from("<I", data, 4)
+    try:
+        schema = json.loads(data[8:8 + length].decode("utf-8"))
+    except (UnicodeDecodeError, json.JSONDecodeError) as e:
+        raise ValueError(f"invalid binary schema: {e}") from e
+    if schema.get("version", 0) > BINARY_VERSION:
+        raise ValueError(f"Unsupported binary version {schema.get('version')!r}, this ProtView reads up to {BINARY_VERSION}")
+    start = 8 + length + _pad(8 + length)
+    result = dict(schema)
+    peaks = schema["sections"].get("peaks")
+    if peaks:
+        stride = len(peaks["columns"])
+        values = struct.unpack_from(f"<{peaks['count'] * stride}d", data, start + peaks["offset"])
+        result["peaks"] = [dict(zip(peaks["columns"], values[k:k + stride])) for k in range(0, len(values), stride)]
+    for name, record in (("matches", MATCH_RECORD), ("spectra", SPECTRUM_RECORD)):
+        section = schema["sections"].get(name)
+        if not section:
+            continue
+        names = [field["name"] for field in section["fields"]]
+        rows = [dict(zip(names, record.unpack_from(data, start + section["offset"] + k * section["record_size"])))
+                for k in range(section["count"])]
+        if name == "matches":
+            for row in rows:
+                row["ion_type"] = section["ion_types"][row["ion_type"]]
+        result[name] = rows
+    return result
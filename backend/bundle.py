@@ -0,0 +1,82 @@
+"""
+Annotated-analysis bundles: one versioned JSON document holding a spectrum, the peptide,
+the fragmentation and matching settings and the matches, so an annotated view can be
+shared and restored exactly.
+"""
+
+import json
+from datetime import datetime, timezone
+from typing import Dict, List, Optional, Union
+
+from .calculations import calculate_ions, match_ions, validate_peaks
+from .settings import FragmentationSettings, MatchSettings
+
+BUNDLE_FORMAT = "protview-annotation"
+# Bumped when a field changes meaning; loaders accept any version up to their own
+BUNDLE_VERSION = 1
+# Spectrum fields copied into the bundle when the metadata has them
+BUNDLE_SPECTRUM_FIELDS = ("scan_nr", "id", "usi", "source", "ms_level", "retention_time", "precursors")
+
+def export_bundle(peaks: List[dict], sequence: str, settings: FragmentationSettings, match_settings: MatchSettings,
+                  matches: Optional[List[dict]] = None, spectrum: Optional[Dict] = None) -> Dict:
+    """
+    The bundle of an annotated view, as a JSON-ready dict. spectrum holds the metadata to
+    keep (BUNDLE_SPECTRUM_FIELDS: scan_nr, source file, USI, precursors, ...); matches
+    default to a fresh match_ions run with the given settings.
+    """
+    validate_peaks(peaks)
+    if matches is None:
+        matches = match_ions(peaks, calculate_ions(sequence, settings), mass_type=settings.mass_type,
+                             settings=match_settings, mzpaf=True)
+    metadata = {key: (spectrum or {}).get(key) for key in BUNDLE_SPECTRUM_FIELDS}
+    return {
+        "format": BUNDLE_FORMAT,
+        "version": BUNDLE_VERSION,
+        "created": datetime.now(timezone.utc).isoformat(timespec="seconds"),
+        "spectrum": {**metadata, "peaks": [{"mz": p["mz"], "intensity": p["intensity"]} for p in peaks]},
+        "peptide": {"sequence": sequence, "charge": settings.precursor_charge},
+        "settings": settings.model_dump(),
+        "match_settings": match_settings.model_dump(),
+        "matches": matches,
+    }
+
+def load_bundle(data: Union[str, bytes, Dict], rematch: bool = False) -> Dict:
+    """
+    A bundle read back and checked: format and version, the peaks, and both settings
+    objects (returned validated, as dicts). The stored matches are kept as they are so the
+    view is the one that was shared; rematch recomputes them from the stored peaks and
+    settings instead, e.g. for a bundle written by an older ProtView.
+    """
+    if isinstance(data, (str, bytes)):
+        try:
+            data = json.loads(data)
+        except json.JSONDecodeError as e:
+            raise ValueError(f"invalid bundle JSON: {e}") from e
+    if not isinstance(data, dict) or data.get("format") != BUNDLE_FORMAT:
+        raise ValueError(f"not a ProtView annotation bundle (expected format {BUNDLE_FORMAT!r})")
+    version = data.get("version")
+    if not isinstance(version, int) or not 1 <= version <= BUNDLE_VERSION:
+        raise ValueError(f"Unsupported bundle version {version!r}, this ProtView reads up to {BUNDLE_VERSION}")
+    for key in ("spectrum", "peptide", "settings", "match_settings"):
+        if not isinstance(data.get(key), dict):
+            raise ValueError(f"Bundle is missing its {key!r} object")
+    peaks = data["spectrum"].get("peaks")
+    if not isinstance(peaks, list):
+        raise ValueError("Bundle spectrum has no peak list")
+    validate_peaks(peaks)
+    sequence = data["peptide"].get("sequence")
+    if not sequence:
+        raise ValueError("Bundle peptide has no sequence")
+
+    settings = FragmentationSettings.model_validate(data["settings"]).validate_options()
+    match_settings = MatchSettings.model_validate(data["match_settings"]).validate_options()
+    matches = data.get("matches")
+    if rematch or matches is None:
+        matches = match_ions(peaks, calculate_ions(sequence, settings), mass_type=settings.mass_type,
+                             settings=match_settings, mzpaf=True)
+    return {
+        **data,
+        "settings": settings.model_dump(),
+        "match_settings": match_settings.model_dump(),
+        "matches": matches,
+    }
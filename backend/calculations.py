@@ -1,33 +1,91 @@
+import bisect
+import re
 import numpy as np
 from functools import lru_cache
-from typing import List
+from typing import Dict, List, Optional, Tuple, Union
 
-# Standard atomic weights and masses
-ATOM_MASSES = {
-    "H": 1.007825035,
-    "O": 15.9949146,
-}
+from .masses import (AA_AVERAGE_MASS, AA_MASS, AA_NITROGEN_COUNT, C13_DELTA, ISOTOPE_LABELS, LABELING_SCHEMES,
+                     N15_DELTA, charge_carrier_mass, check_mass_type, molecule_mass, mz_from_mass, water_mass)
+from .glyco import oxonium_ions, parse_glycan_composition, y_ions
+from .mzpaf import format_mzpaf
+from .processing import signal_to_noise
+from .settings import N_TERMINAL_SERIES, TOLERANCE_UNITS, FragmentationSettings, MatchSettings
 
-PROTON_MASS = ATOM_MASSES["H"]
-H2O_MASS = 2 * ATOM_MASSES["H"] + ATOM_MASSES["O"]
+# Average masses only make sense for unresolved isotope envelopes,
+# so matching never uses a window narrower than this (Da)
+AVERAGE_MIN_TOLERANCE = 0.5
 
-# User provided masses
-AA_MASS = {'G': 57.02146374, 'A': 71.03711381, 'S': 87.03202844, 'P': 97.05276388, 'V': 99.06841395, 'T': 101.0476785, 'C': 103.0091845, 'L': 113.084064, 'I': 113.084064, 'N': 114.0429275,
-               'D': 115.0269431,
-               'Q': 128.0585775, 'K': 128.0949631, 'E': 129.0425931, 'M': 131.0404846, 'H': 137.0589119, 'F': 147.0684139, 'U': 150.9536334, 'R': 156.1011111, 'Y': 163.0633286, 'W': 186.079313,
-               'O': 237.1477269, 'n': 0.00000}
+# Series tried first when two ions claim the same peak equally well
+SERIES_PRIORITY = ("y", "b", "a", "c", "z", "x")
 
-@lru_cache(maxsize=50000)
-def pep_by_ion_calc(peptide: str) -> np.ndarray:
+# User-defined residues (nonstandard letters or overrides of AA_MASS).
+# They are used as given in both mass modes.
+CUSTOM_AA_MASS: Dict[str, float] = {}
+
+ResidueTable = Tuple[Tuple[str, float], ...]
+
+def _validate_residue_masses(masses: Dict[str, float]) -> Dict[str, float]:
+    validated = {}
+    for residue, mass in masses.items():
+        # Lowercase letters and brackets are reserved for terminal/mod syntax
+        if len(residue) != 1 or not residue.isalpha() or not residue.isupper():
+            raise ValueError(f"Residue symbol must be a single uppercase letter: {residue!r}")
+        validated[residue] = float(mass)
+    return validated
+
+def set_residue_masses(masses: Dict[str, float], replace: bool = False):
     """
-    Calculates b and y ions for a peptide sequence, handling [mass] or (mass) modifications.
-    Returns concatenated array of b-ions then y-ions.
+    Registers user-defined residue masses (e.g. J, crosslinker stubs) that take
+    precedence over AA_MASS. With replace=True previous custom entries are dropped.
+    """
+    validated = _validate_residue_masses(masses)
+    if replace:
+        CUSTOM_AA_MASS.clear()
+    CUSTOM_AA_MASS.update(validated)
+    pep_by_ion_calc.cache_clear()
+
+def reset_residue_masses():
+    """Drops all user-defined residues, restoring the built-in table."""
+    CUSTOM_AA_MASS.clear()
+    pep_by_ion_calc.cache_clear()
+
+def get_residue_masses(overrides: Optional[Dict[str, float]] = None, mass_type: str = "monoisotopic") -> Dict[str, float]:
+    """
+    Returns the effective residue table: built-ins (monoisotopic or average),
+    then user-defined residues, then any per-call overrides.
+    """
+    check_mass_type(mass_type)
+    table = dict(AA_MASS if mass_type == "monoisotopic" else AA_AVERAGE_MASS)
+    table.update(CUSTOM_AA_MASS)
+    if overrides:
+        table.update(_validate_residue_masses(overrides))
+    return table
+
+def _residue_table_key(overrides: Optional[Dict[str, float]]) -> Optional[ResidueTable]:
+    # Hashable form of per-call overrides so results stay cacheable
+    if not overrides:
+        return None
+    return tuple(sorted(_validate_residue_masses(overrides).items()))
+
+def tokenize_peptide(peptide: str, residue_masses: Optional[Dict[str, float]] = None) -> Tuple[List[Tuple[str, float]], float, float]:
     """
-    b_ion_vals = []
+    Splits a modified peptide into residues and terminal modifications.
+
+    N-term: n[229.1629]PEPTIDE, [229.1629]PEPTIDE or [229.1629]-PEPTIDE
+    C-term: PEPTIDEc[-0.984], PEPTIDE-c[-0.984] or PEPTIDE-[-0.984]
+    Other [mass] or (mass) blocks modify the preceding residue.
+    Only letters of residue_masses (default get_residue_masses()) count as residues.
+
+    Returns ([(residue, mod_mass), ...], n_term_mod, c_term_mod).
+    """
+    aa_mass = residue_masses if residue_masses is not None else get_residue_masses()
+    residues = []
+    n_term = 0.0
+    c_term = 0.0
+    in_c_term = False
     
     i = 0
     n = len(peptide)
-    pending_mod = 0.0
     
     while i < n:
         char = peptide[i]
@@ -42,187 +100,587 @@ def pep_by_ion_calc(peptide: str) -> np.ndarray:
             if j < n:
                 try:
                     # Extract mass between delimiters
-                    mod_str = peptide[i+1:j]
-                    mod = float(mod_str)
+                    mod = float(peptide[i+1:j])
                     
-                    if b_ion_vals:
-                         # Modify previous residue
-                         b_ion_vals[-1] += mod
+                    if not residues:
+                         n_term += mod
+                    elif in_c_term:
+                         c_term += mod
                     else:
-                         # Modification at start (N-term)
-                         pending_mod += mod
+                         # Modify previous residue
+                         residue, prev_mod = residues[-1]
+                         residues[-1] = (residue, prev_mod + mod)
                 except ValueError:
                     pass
             i = j + 1
             
-        elif char in AA_MASS:
-            mass = AA_MASS[char]
-            if pending_mod != 0:
-                mass += pending_mod
-                pending_mod = 0
-            b_ion_vals.append(mass)
+        elif char in ['c', '-'] and residues:
+            # C-term marker, the mass follows in brackets
+            in_c_term = True
+            i += 1
+            
+        elif char in aa_mass:
+            residues.append((char, 0.0))
+            in_c_term = False
             i += 1
         else:
+            # Includes the 'n' N-term marker and ProForma dashes
             i += 1
             
-    b_ions_arr = np.array(b_ion_vals)
+    return residues, n_term, c_term
+
+def parse_peptide(peptide: str, residue_masses: Optional[Dict[str, float]] = None) -> Tuple[List[float], float, float]:
+    """
+    Like tokenize_peptide, but with each residue resolved to its (modified) mass.
+    Returns (residue_masses, n_term_mod, c_term_mod).
+    """
+    aa_mass = residue_masses if residue_masses is not None else get_residue_masses()
+    tokens, n_term, c_term = tokenize_peptide(peptide, aa_mass)
+    return [aa_mass[residue] + mod for residue, mod in tokens], n_term, c_term
+
+def peptide_mass(sequence: str, residue_masses: Optional[Dict[str, float]] = None, mass_type: str = "monoisotopic") -> float:
+    """
+    Neutral monoisotopic (or average) mass of a (modified) peptide.
+    residue_masses are per-call overrides on top of the effective residue table.
+    """
+    residues, n_term, c_term = parse_peptide(sequence, get_residue_masses(residue_masses, mass_type))
+    if not residues:
+        return 0.0
+    return sum(residues) + n_term + c_term + water_mass(mass_type)
+
+def precursor_mz(sequence: str, charge: int, residue_masses: Optional[Dict[str, float]] = None, mass_type: str = "monoisotopic", electron_aware: bool = True) -> float:
+    """m/z of the intact peptide at the given charge."""
+    return mz_from_mass(peptide_mass(sequence, residue_masses, mass_type), charge, electron_aware)
+
+@lru_cache(maxsize=50000)
+def pep_by_ion_calc(peptide: str, residue_table: Optional[ResidueTable] = None, mass_type: str = "monoisotopic", electron_aware: bool = True) -> np.ndarray:
+    """
+    Calculates b and y ions for a peptide sequence, handling [mass] or (mass) modifications.
+    N-terminal mods are carried by every b-ion, C-terminal mods by every y-ion.
+    residue_table holds per-call residue overrides as sorted (residue, mass) pairs.
+    electron_aware=False protonates with a hydrogen atom (legacy behaviour).
+    Returns concatenated array of b-ions then y-ions.
+    """
+    proton = charge_carrier_mass(electron_aware)
+    overrides = dict(residue_table) if residue_table else None
+    residues, n_term, c_term = parse_peptide(peptide, get_residue_masses(overrides, mass_type))
+    
+    b_ions_arr = np.array(residues)
     if len(b_ions_arr) == 0:
         return np.array([])
         
-    # b1 = aa1 + H+
-    b_ions_arr[0] += PROTON_MASS
+    # b1 = N-term + aa1 + H+
+    b_ions_arr[0] += n_term + proton
     
     # Cumulative sum
     b_ions_cumulative = np.cumsum(b_ions_arr)
     
-    total_mh = b_ions_cumulative[-1] + (18.010564684) # WATER
+    total_mh = b_ions_cumulative[-1] + c_term + water_mass(mass_type)
     
     # y_ions calculation matching user logic
-    y_ions = total_mh - b_ions_cumulative + PROTON_MASS
+    y_ions = total_mh - b_ions_cumulative + proton
     
     # Override last y-ion to be total mass (y_n)
     y_ions[-1] = total_mh
     
     return np.concatenate((b_ions_cumulative, y_ions), axis=0)
 
-def calculate_ions(sequence: str, charge: int) -> List[dict]:
+def apply_isotope_label(sequence: str, label: str, residue_masses: Optional[Dict[str, float]] = None, mass_type: str = "monoisotopic") -> Tuple[str, Dict[str, float]]:
     """
-    Calculates theoretical ions using the robust PTM parser.
+    Resolves an ISOTOPE_LABELS entry into a sequence with the labeled N-term
+    prepended and the residue overrides that carry the labeled residue masses.
     """
-    # 1. Parse and calculate base (singly charged) ions
-    # The result array has [b1...bn, y(n-1)...y1??]
-    # We need to identify which is which.
+    if label not in ISOTOPE_LABELS:
+        raise ValueError(f"Unknown isotope label {label!r}, expected one of {tuple(ISOTOPE_LABELS)}")
+    spec = ISOTOPE_LABELS[label]
+    base = get_residue_masses(residue_masses, mass_type)
     
-    # Calculate pure lengths without mods for labeling
-    # We need to know how many residues.
-    # We can run the parser again or just use the length of b_ions (half the array).
+    overrides = dict(residue_masses or {})
+    if spec.get("n15"):
+        for residue, count in AA_NITROGEN_COUNT.items():
+            overrides[residue] = base[residue] + count * N15_DELTA
+    for residue, delta in spec.get("residues", {}).items():
+        overrides[residue] = overrides.get(residue, base[residue]) + delta
     
-    masses = pep_by_ion_calc(sequence)
+    if spec.get("n_term"):
+        sequence = f"[{spec['n_term']}]" + sequence
+    return sequence, overrides
+
+# Residues able to carry an extra proton on a fragment
+BASIC_RESIDUES = frozenset("KRH")
+
+def fragment_max_charge(residues: str, precursor_charge: int) -> int:
+    """
+    Realistic upper charge for a fragment: one charge for the terminus plus one per
+    basic residue, never above precursor_charge - 1 (or 1 for singly charged precursors).
+    """
+    basic = sum(1 for r in residues if r in BASIC_RESIDUES)
+    return max(1, min(precursor_charge - 1, 1 + basic))
+
+def _series_charge_limit(settings: FragmentationSettings, series: str, fragment: str) -> int:
+    limit = settings.series_max_charge.get(series)
+    z_max = settings.fragment_max_charge
+    if limit is None:
+        return z_max
+    if limit == "auto":
+        return min(z_max, fragment_max_charge(fragment, settings.precursor_charge))
+    if isinstance(limit, str) or limit < 1:
+        raise ValueError(f"Max charge for {series} ions must be a positive integer or 'auto', got {limit!r}")
+    return min(limit, z_max)
+
+# Residues that make a fragment prone to each neutral loss
+LOSS_RESIDUES = {"H2O": frozenset("STED"), "NH3": frozenset("RKNQ")}
+
+# Phosphorylated S/T lose H3PO4 (97.977 Da) readily; Y keeps its phosphate
+PHOSPHO_MASS = 79.966331
+PHOSPHO_LOSS_RESIDUES = frozenset("ST")
+PHOSPHO_MASS_TOLERANCE = 0.01
+
+def _series_offsets(mass_type: str) -> Dict[str, float]:
+    # Singly protonated a/c and x/z ions relative to b and y
+    return {
+        "a": -molecule_mass({"C": 1, "O": 1}, mass_type),
+        "b": 0.0,
+        "c": molecule_mass({"N": 1, "H": 3}, mass_type),
+        "x": molecule_mass({"C": 1, "O": 1, "H": -2}, mass_type),
+        "y": 0.0,
+        "z": -molecule_mass({"N": 1, "H": 2}, mass_type),  # z-dot (z+1)
+    }
+
+def calculate_ions(sequence: str, settings: Union[FragmentationSettings, int]) -> List[dict]:
+    """
+    Calculates theoretical ions using the robust PTM parser.
+    settings is a FragmentationSettings (a bare int is read as the precursor charge).
+    Each ion carries its label ("b5", "y3-H2O"), series, index, loss, charge and m/z.
+    Peptides with phospho S/T also get -H3PO4 fragments and precursor ions ("M-H3PO4",
+    series "M") unless settings.phospho_losses is off, and with settings.glycan the
+    oxonium ions (series "oxonium") and Y ions (series "Y") of that glycan.
+    """
+    if not isinstance(settings, FragmentationSettings):
+        settings = FragmentationSettings(precursor_charge=settings)
+    return _calculate_ions(sequence, settings.validate_options())
+
+def _calculate_ions(sequence: str, settings: FragmentationSettings) -> List[dict]:
+    # calculate_ions body; settings are already validated
+    residue_masses = settings.residue_masses or None
+    mass_type = settings.mass_type
+    if settings.n_term_mod:
+        sequence = f"[{settings.n_term_mod}]" + sequence
+    if settings.c_term_mod:
+        sequence = sequence + f"-[{settings.c_term_mod}]"
+    if settings.label:
+        sequence, residue_masses = apply_isotope_label(sequence, settings.label, residue_masses, mass_type)
+    
+    proton = charge_carrier_mass(settings.electron_aware)
+    masses = pep_by_ion_calc(sequence, _residue_table_key(residue_masses), mass_type, settings.electron_aware)
     
     if len(masses) == 0:
         return []
         
+    # masses holds [b1 ... bn] followed by [y(n-1) ... y1, yn]
     num_residues = len(masses) // 2
     b_masses = masses[:num_residues]
     y_masses = masses[num_residues:]
+    y_by_length = {num_residues - 1 - i: m for i, m in enumerate(y_masses[:-1])}
+    y_by_length[num_residues] = y_masses[-1]
     
-    ions = []
+    # Residue letters, for charge limits and loss rules derived from fragment composition
+    tokens = tokenize_peptide(sequence, get_residue_masses(residue_masses, mass_type))[0]
+    letters = "".join(r for r, _ in tokens)
+    # 0-based positions of phosphorylated S/T, which give -H3PO4 fragments
+    phospho_sites = [k for k, (r, mod) in enumerate(tokens)
+                     if r in PHOSPHO_LOSS_RESIDUES and mod and abs(mod - PHOSPHO_MASS) <= PHOSPHO_MASS_TOLERANCE]
+    if not settings.phospho_losses:
+        phospho_sites = []
+    offsets = _series_offsets(mass_type)
+    loss_masses = {"H2O": water_mass(mass_type), "NH3": molecule_mass({"N": 1, "H": 3}, mass_type),
+                   "H3PO4": molecule_mass({"H": 3, "P": 1, "O": 4}, mass_type)}
     
-    # Generate b-ions
-    for i, m in enumerate(b_masses):
-        # b1, b2 ...
-        ion_idx = i + 1
-        for z in range(1, charge + 1):
-             # m is singly charged (MH+)
-             # m = Neutral + H
-             # mz = (Neutral + zH) / z = (m - H + zH) / z = (m + (z-1)H) / z
-             mz = (m + (z - 1) * PROTON_MASS) / z
-             ions.append({
-                 "type": f"b{ion_idx}",
-                 "charge": z,
-                 "mz": mz
-             })
-
-    # Generate y-ions
-    # y_masses from user code:
-    # y[0] corresponds to removing b1 -> y_(n-1)
-    # y[-1] corresponds to full mass -> y_n
-    
-    # Wait, usually:
-    # y1 is suffix length 1.
-    # User code: y = M - b + H.
-    # If b=b1 (prefix 1), y = M - b1 + H = (ResTotal + H2O + H) - (Res1 + H) + H = ResSuffix + H2O + H.
-    # This is MH+ of suffix length (n-1). = y_(n-1).
-    
-    # So y_masses[0] is y_(n-1)
-    # y_masses[-1] is y_(0)? No users code set y[-1] = total. So y_n.
-    
-    # Let's map indices:
-    # i goes 0 to n-1.
-    # len = n_residues.
-    # y_index = n_residues - (i + 1) ? 
-    # No, if i=0 (b1), we get y_(n-1).
-    # If i = n-1 (bn), we get y_0? (which is usually not valid, or H2O+H)
-    
-    # User code override y[-1] = total. Total is y_n.
-    # So y_masses is [y_n-1, y_n-2, ... y_1? ... y_n?]
-    # This order is confusing.
-    
-    # Let's simple check user logic: `y_ions = total - b + H`.
-    # b is [b1, b2, ... bn]
-    # y is [y(n-1), y(n-2), ... y0]
-    
-    # So:
-    # i=0: b1 -> y(n-1)
-    # i=n-2: b(n-1) -> y1
-    # i=n-1: bn -> y0 (replaced by y_n)
-    
-    for i, m in enumerate(y_masses):
-        if i == num_residues - 1:
-            ion_label = f"y{num_residues}" # The override (Full peptide)
+    ions = []
+    for series in settings.series:
+        if series in N_TERMINAL_SERIES:
+            fragments = [(i + 1, m) for i, m in enumerate(b_masses)]
         else:
-            ion_label = f"y{num_residues - 1 - i}"
-            
-        # Filter mostly y0?
-        if ion_label == "y0": continue
+            fragments = [(length, y_by_length[length]) for length in range(num_residues, 0, -1) if length in y_by_length]
         
-        for z in range(1, charge + 1):
-             mz = (m + (z - 1) * PROTON_MASS) / z
-             ions.append({
-                 "type": ion_label,
-                 "charge": z,
-                 "mz": mz
-             })
+        for ion_idx, base in fragments:
+            fragment = letters[:ion_idx] if series in N_TERMINAL_SERIES else letters[-ion_idx:]
+            z_max = _series_charge_limit(settings, series, fragment)
+            
+            variants = [("", base + offsets[series])]
+            for loss in settings.losses:
+                if settings.restrict_losses and not LOSS_RESIDUES[loss] & set(fragment):
+                    continue
+                variants.append((loss, base + offsets[series] - loss_masses[loss]))
+            first = 0 if series in N_TERMINAL_SERIES else num_residues - ion_idx
+            if any(first <= k < first + ion_idx for k in phospho_sites):
+                variants.append(("H3PO4", base + offsets[series] - loss_masses["H3PO4"]))
+            
+            for loss, m in variants:
+                label = f"{series}{ion_idx}" + (f"-{loss}" if loss else "")
+                for z in range(settings.min_charge, z_max + 1):
+                    # m is singly charged (MH+): mz = (m + (z-1)H+) / z
+                    mz = (m + (z - 1) * proton) / z
+                    if settings.decimals is not None:
+                        mz = round(mz, settings.decimals)
+                    ions.append({
+                        "type": label,
+                        "series": series,
+                        "index": ion_idx,
+                        "loss": loss,
+                        "charge": z,
+                        "mz": mz
+                    })
+
+    # Precursor minus H3PO4, usually the base peak of a pS/pT spectrum; y_n is [M+H]+
+    if phospho_sites and num_residues in y_by_length:
+        m = y_by_length[num_residues] - loss_masses["H3PO4"]
+        for z in range(settings.min_charge, settings.precursor_charge + 1):
+            mz = (m + (z - 1) * proton) / z
+            if settings.decimals is not None:
+                mz = round(mz, settings.decimals)
+            ions.append({"type": "M-H3PO4", "series": "M", "index": num_residues, "loss": "H3PO4", "charge": z, "mz": mz})
+
+    if settings.glycan and num_residues in y_by_length:
+        composition = parse_glycan_composition(settings.glycan)
+        glyco = (oxonium_ions(composition, proton, mass_type)
+                 + y_ions(y_by_length[num_residues], composition, settings.precursor_charge, proton, mass_type))
+        if settings.decimals is not None:
+            for ion in glyco:
+                ion["mz"] = round(ion["mz"], settings.decimals)
+        ions.extend(glyco)
              
     return ions
 
+def calculate_ions_batch(peptides: List[Union[str, dict]], settings: FragmentationSettings) -> List[dict]:
+    """
+    Theoretical ions for many peptides in one call. Each entry is a sequence string
+    or {"sequence": ..., "charge": ...} overriding the precursor charge.
+    Settings are validated once; a bad sequence only fails its own entry.
+    Returns [{"sequence", "charge", "ions", "error"}] in input order.
+    """
+    settings.validate_options()
+    by_charge = {settings.precursor_charge: settings}
+    
+    results = []
+    for entry in peptides:
+        if isinstance(entry, str):
+            sequence, charge = entry, settings.precursor_charge
+        else:
+            sequence = entry.get("sequence", "")
+            charge = entry.get("charge") or settings.precursor_charge
+        
+        result = {"sequence": sequence, "charge": charge, "ions": [], "error": None}
+        try:
+            if charge not in by_charge:
+                by_charge[charge] = settings.model_copy(update={"precursor_charge": charge}).validate_options()
+            result["ions"] = _calculate_ions(sequence, by_charge[charge])
+        except ValueError as e:
+            result["error"] = f"{sequence}: {e}"
+        results.append(result)
+    return results
+
+def calculate_labeled_ions(sequence: str, settings: FragmentationSettings, scheme: str) -> Dict[str, List[dict]]:
+    """
+    Calculates the light and heavy theoretical series of a LABELING_SCHEMES pair
+    in one call, e.g. for side-by-side SILAC or dimethyl spectra.
+    """
+    if scheme not in LABELING_SCHEMES:
+        raise ValueError(f"Unknown labeling scheme {scheme!r}, expected one of {tuple(LABELING_SCHEMES)}")
+    light_label, heavy_label = LABELING_SCHEMES[scheme]
+    
+    result = {}
+    for state, label in (("light", light_label), ("heavy", heavy_label)):
+        ions = calculate_ions(sequence, settings.model_copy(update={"label": label}))
+        for ion in ions:
+            ion["label"] = label
+        result[state] = ions
+    return result
+
 def parse_spectrum(spectrum_text: str):
+    """
+    Parses pasted "m/z intensity" lines. Raises ValueError naming the offending line.
+    """
     import re
     peaks = []
-    for line in spectrum_text.strip().splitlines():
+    for line_nr, line in enumerate(spectrum_text.strip().splitlines(), start=1):
         line = line.strip()
         if not line: continue
+        parts = re.split(r'\s+', line)
+        if len(parts) < 2:
+            raise ValueError(f"Peak list line {line_nr}: expected 'm/z intensity', got {line!r}")
         try:
-            parts = re.split(r'\s+', line)
-            if len(parts) >= 2:
-                peaks.append({"mz": float(parts[0]), "intensity": float(parts[1])})
-        except: pass
+            peaks.append({"mz": float(parts[0]), "intensity": float(parts[1])})
+        except ValueError:
+            raise ValueError(f"Peak list line {line_nr}: non-numeric value in {line!r}") from None
     return peaks
 
-def match_ions(peaks, theoretical_ions, tolerance=0.5):
+def validate_peaks(peaks) -> None:
+    """Raises ValueError for the first peak lacking a numeric mz/intensity."""
+    for i, peak in enumerate(peaks):
+        for key in ("mz", "intensity"):
+            if key not in peak:
+                raise ValueError(f"Peak {i}: missing '{key}'")
+            if not isinstance(peak[key], (int, float)) or peak[key] != peak[key]:
+                raise ValueError(f"Peak {i}: '{key}' must be a number, got {peak[key]!r}")
+
+def tolerance_bounds(tolerance, unit: str = "da") -> Tuple[float, float]:
+    """
+    (low, high) limits on observed - theoretical m/z from a symmetric tolerance (0.5 -> -0.5/+0.5)
+    or an explicit pair such as (-10, 20); unit is "da" or "ppm".
+    """
+    if unit not in TOLERANCE_UNITS:
+        raise ValueError(f"Unknown tolerance unit {unit!r}, expected one of {TOLERANCE_UNITS}")
+    if isinstance(tolerance, (int, float)):
+        if tolerance < 0:
+            raise ValueError(f"Tolerance must be non-negative, got {tolerance}")
+        return -float(tolerance), float(tolerance)
+    try:
+        if isinstance(tolerance, str):
+            raise TypeError
+        low, high = (float(t) for t in tolerance)
+    except (TypeError, ValueError):
+        raise ValueError(f"Tolerance must be a number or a (low, high) pair, got {tolerance!r}") from None
+    if low > high:
+        raise ValueError(f"Tolerance window is empty: low {low} > high {high}")
+    return low, high
+
+def _series_rank(ion) -> Tuple[int, int, int]:
+    """Tie-break order for one-to-one assignment: preferred series, lower charge, no loss."""
+    series = ion.get("series") or ion["type"][:1]
+    rank = SERIES_PRIORITY.index(series) if series in SERIES_PRIORITY else len(SERIES_PRIORITY)
+    return rank, ion["charge"], 1 if ion.get("loss") else 0
+
+def match_ions(peaks, theoretical_ions, tolerance=0.5, mass_type="monoisotopic", strategy="closest",
+               assignment="all", tolerance_unit="da", settings: Optional[MatchSettings] = None, mzpaf: bool = False):
     """
-    Matches theoretical ions to observed peaks within a tolerance (Da).
-    Greedy matching: for each theoretical ion, find the closest observed peak.
+    Matches theoretical ions to observed peaks within a tolerance, in Da or ppm
+    (tolerance_unit). A number is a symmetric window; a (low, high) pair bounds the signed
+    error observed - theoretical, e.g. (-10, 20) ppm for systematically shifted masses.
+    A MatchSettings, when given, replaces tolerance, tolerance_unit, strategy and assignment
+    and may set per-series or per-m/z-range tolerances (see MatchSettings.tolerance_rules).
+    Greedy matching: for each theoretical ion, pick one observed peak in the window:
+    the closest ("closest"), the most intense ("most_intense"), or the one with the highest
+    intensity scaled down linearly with its distance ("intensity_weighted").
+    With assignment="all" several ions may claim the same peak. With "one_to_one" each peak
+    goes to one ion: pairs are taken best score first (ties by SERIES_PRIORITY, then charge),
+    an ion whose peaks are all taken is dropped, and the dropped ion types are listed in
+    "alternatives" of the match that holds the peak they wanted.
+    In average mode the tolerance is widened to at least AVERAGE_MIN_TOLERANCE.
+    With settings.min_snr, peaks below that signal-to-noise never match (see
+    processing.estimate_noise); intensity ranks still count every peak.
+    Peaks are sorted by m/z once (already sorted input is used as is) and the ions' candidate
+    windows are found by one vectorized binary search, so the cost is O((peaks + ions) log peaks).
+    With mzpaf, each match also gets its "mzpaf" label; scoring callers that only count or
+    sum the matches leave it off.
     Returns list of matched annotations.
     """
-    matches = []
-    # Sort peaks by intensity? Or just iterate?
-    # Iterate theoretical ions
-    for ion in theoretical_ions:
+    check_mass_type(mass_type)
+    if settings is None:
+        settings = MatchSettings(tolerance=tolerance, tolerance_unit=tolerance_unit, strategy=strategy,
+                                 assignment=assignment)
+    settings.validate_options()
+    strategy, assignment = settings.strategy, settings.assignment
+    validate_peaks(peaks)
+    # Bounds per distinct (tolerance, unit), validated up front
+    bounds = {(settings.tolerance, settings.tolerance_unit): tolerance_bounds(settings.tolerance, settings.tolerance_unit)}
+    for rule in settings.tolerance_rules:
+        bounds[(rule.tolerance, rule.unit)] = tolerance_bounds(rule.tolerance, rule.unit)
+
+    if any(peaks[i]["mz"] > peaks[i + 1]["mz"] for i in range(len(peaks) - 1)):
+        peaks = sorted(peaks, key=lambda p: p["mz"])
+    peak_mzs = [peak["mz"] for peak in peaks]
+    # Peaks below min_snr are skipped; an "snr" already on the peaks (processing step) is reused
+    eligible = None
+    if settings.min_snr is not None:
+        snr = [peak.get("snr") for peak in peaks]
+        if any(value is None for value in snr):
+            snr = [peak["snr"] for peak in signal_to_noise(peaks, settings.noise_window)]
+        eligible = [value >= settings.min_snr for value in snr]
+    
+    # Every ion's window in Da and the peaks inside it, found for all ions at once: the windows
+    # are contiguous runs of the sorted peaks, from the first >= low to the last <= high
+    targets = np.asarray([ion["mz"] for ion in theoretical_ions], dtype=np.float64)
+    limits = [(bounds[key], key[1] == "ppm") for key in map(settings.tolerance_for, theoretical_ions)]
+    scale = np.where(np.asarray([ppm for _, ppm in limits], dtype=bool), targets * 1e-6, 1.0)
+    lows = np.asarray([low for (low, _), _ in limits], dtype=np.float64) * scale
+    highs = np.asarray([high for (_, high), _ in limits], dtype=np.float64) * scale
+    if mass_type == "average":
+        lows, highs = np.minimum(lows, -AVERAGE_MIN_TOLERANCE), np.maximum(highs, AVERAGE_MIN_TOLERANCE)
+    peak_array = np.asarray(peak_mzs, dtype=np.float64)
+    starts = np.searchsorted(peak_array, targets + lows, side="left").tolist()
+    ends = np.searchsorted(peak_array, targets + highs, side="right").tolist()
+    windows_da = list(zip(lows.tolist(), highs.tolist()))
+
+    # (score, diff, peak index) of every peak in each ion's window, best first
+    candidates = []
+    for n, ion in enumerate(theoretical_ions):
         target_mz = ion["mz"]
-        
-        best_peak = None
-        min_diff = float('inf')
-        
-        for peak in peaks:
-            diff = abs(peak["mz"] - target_mz)
-            if diff <= tolerance and diff < min_diff:
-                min_diff = diff
-                best_peak = peak
-        
-        if best_peak:
-            # Check if this annotation already exists for this peak?
-            # A peak might be multiple things? 
-            # For simplicity, let's just add the match. 
-            # The frontend can handle overlapping labels.
-            matches.append({
-                "peak_mz": best_peak["mz"],
-                "peak_intensity": best_peak["intensity"],
-                "ion_type": ion["type"],
-                "ion_charge": ion["charge"],
-                "theoretical_mz": ion["mz"],
-                "error": min_diff
-            })
+        half_width = max(abs(windows_da[n][0]), abs(windows_da[n][1]))
+        window = []
+        # Ties go to the lower m/z
+        for i in range(starts[n], ends[n]):
+            if eligible is not None and not eligible[i]:
+                continue
+            diff = abs(peak_mzs[i] - target_mz)
+            if strategy == "closest":
+                score = -diff
+            elif strategy == "most_intense":
+                score = peaks[i]["intensity"]
+            else:
+                score = peaks[i]["intensity"] * (1 - diff / half_width if half_width else 1)
+            window.append((score, diff, i))
+        window.sort(key=lambda c: -c[0])
+        candidates.append(window)
+
+    # Chosen (diff, peak index) per ion
+    chosen = [window[0][1:] if window else None for window in candidates]
+    alternatives: Dict[int, List[str]] = {}
+    if assignment == "one_to_one":
+        pairs = sorted(((score, n, diff, i) for n, window in enumerate(candidates) for score, diff, i in window),
+                       key=lambda p: (-p[0], _series_rank(theoretical_ions[p[1]]), p[1]))
+        chosen = [None] * len(theoretical_ions)
+        owner: Dict[int, int] = {}
+        for _, n, diff, i in pairs:
+            if chosen[n] is None and i not in owner:
+                chosen[n] = (diff, i)
+                owner[i] = n
+        for n, window in enumerate(candidates):
+            if window and chosen[n] is None:
+                alternatives.setdefault(owner[window[0][2]], []).append(theoretical_ions[n]["type"])
+
+    # Rank 1 is the base peak; equally intense peaks share a rank
+    descending = sorted(-peak["intensity"] for peak in peaks)
+    base_intensity = -descending[0] if descending else 0.0
+
+    matches = []
+    for n, ion in enumerate(theoretical_ions):
+        if chosen[n] is None:
+            continue
+        min_diff, i = chosen[n]
+        best_peak = peaks[i]
+        # In "all" mode a peak may explain several ions; the frontend handles overlapping labels
+        match = {
+            "peak_mz": best_peak["mz"],
+            "peak_intensity": best_peak["intensity"],
+            "ion_type": ion["type"],
+            "ion_charge": ion["charge"],
+            "theoretical_mz": ion["mz"],
+            "error": min_diff,
+            # Signed, observed minus theoretical, so systematic calibration shifts show up
+            "error_da": best_peak["mz"] - ion["mz"],
+            "error_ppm": (best_peak["mz"] - ion["mz"]) / ion["mz"] * 1e6 if ion["mz"] else 0.0,
+            "relative_intensity": best_peak["intensity"] / base_intensity * 100 if base_intensity > 0 else 0.0,
+            "intensity_rank": bisect.bisect_left(descending, -best_peak["intensity"]) + 1
+        }
+        if assignment == "one_to_one":
+            match["alternatives"] = alternatives.get(n, [])
+        if settings.isotope_peaks:
+            match["isotopes"] = _match_isotopes(peaks, peak_mzs, ion, windows_da[n], settings.isotope_peaks)
+        if mzpaf:
+            match["mzpaf"] = format_mzpaf(match)
+        matches.append(match)
             
     return matches
 
+def _match_isotopes(peaks, peak_mzs, ion, window_da, count: int) -> List[dict]:
+    """
+    The +1..+count isotope peaks of a matched fragment, spaced C13_DELTA / charge, each the
+    closest peak in the ion's own window. Stops at the first missing one, so a hit at the
+    expected spacing also supports the assigned charge.
+    """
+    low_da, high_da = window_da
+    isotopes = []
+    for k in range(1, count + 1):
+        target_mz = ion["mz"] + k * C13_DELTA / abs(ion["charge"])
+        best = None
+        i = bisect.bisect_left(peak_mzs, target_mz + low_da)
+        while i < len(peak_mzs) and peak_mzs[i] <= target_mz + high_da:
+            if best is None or abs(peak_mzs[i] - target_mz) < abs(peak_mzs[best] - target_mz):
+                best = i
+            i += 1
+        if best is None:
+            break
+        isotopes.append({
+            "isotope": k,
+            "theoretical_mz": target_mz,
+            "peak_mz": peaks[best]["mz"],
+            "peak_intensity": peaks[best]["intensity"],
+            "error_da": peaks[best]["mz"] - target_mz,
+        })
+    return isotopes
+
+def annotate_spectrum(peaks, theoretical_ions, tolerance=0.5, mass_type="monoisotopic", strategy="closest",
+                      assignment="all", tolerance_unit="da", settings: Optional[MatchSettings] = None) -> Dict:
+    """
+    Every peak with its assignments, for colouring the whole spectrum. Runs match_ions with
+    the same options and returns {"peaks": [...], "summary": {...}}: each peak carries
+    "status" ("matched", "isotope" for an isotope peak of a match, or "unmatched") and
+    "annotations" (its matches without the peak fields); the summary counts matched ions
+    and peaks and the explained intensity.
+    """
+    matches = match_ions(peaks, theoretical_ions, tolerance, mass_type, strategy, assignment, tolerance_unit, settings,
+                         mzpaf=True)
+    by_mz: Dict[float, List[dict]] = {}
+    for match in matches:
+        annotation = {k: v for k, v in match.items() if k not in ("peak_mz", "peak_intensity")}
+        by_mz.setdefault(match["peak_mz"], []).append(annotation)
+
+    isotope_mzs = {isotope["peak_mz"] for match in matches for isotope in match.get("isotopes", [])}
+
+    annotated = []
+    for peak in sorted(peaks, key=lambda p: p["mz"]):
+        annotations = by_mz.get(peak["mz"], [])
+        status = "matched" if annotations else "isotope" if peak["mz"] in isotope_mzs else "unmatched"
+        annotated.append({**peak, "status": status, "annotations": annotations})
+
+    total_intensity = sum(peak["intensity"] for peak in peaks)
+    matched = [peak for peak in annotated if peak["annotations"]]
+    matched_intensity = sum(peak["intensity"] for peak in matched)
+    return {
+        "peaks": annotated,
+        "summary": {
+            "peak_count": len(annotated),
+            "matched_peaks": len(matched),
+            "unmatched_peaks": len(annotated) - len(matched),
+            "theoretical_ions": len(theoretical_ions),
+            "matched_ions": len(matches),
+            "explained_intensity": matched_intensity / total_intensity * 100 if total_intensity > 0 else 0.0,
+            "mean_abs_error_ppm": sum(abs(m["error_ppm"]) for m in matches) / len(matches) if matches else None,
+        },
+    }
+
+
+ION_LABEL = re.compile(r'([abcxyz])(\d+)')
+
+def fragment_coverage(sequence: str, matches: List[dict], residue_masses: Optional[Dict[str, float]] = None) -> List[dict]:
+    """
+    Backbone cleavage sites of a (modified) peptide with the matched fragments supporting
+    them. Site k lies between residues k and k + 1 (1-based, k = 1 .. n - 1); it is covered
+    by the N-terminal ions a/b/c k and the C-terminal ions x/y/z n - k. Neutral-loss and
+    higher-charge matches count as evidence for their site too.
+    """
+    residues, _, _ = tokenize_peptide(sequence, get_residue_masses(residue_masses))
+    n = len(residues)
+    sites = [{"position": k, "residue": residues[k - 1][0], "next_residue": residues[k][0],
+              "n_terminal": [], "c_terminal": [], "ions": []} for k in range(1, n)]
+    for match in matches:
+        label = ION_LABEL.match(match["ion_type"])
+        if not label:
+            continue
+        series, index = label.group(1), int(label.group(2))
+        position = index if series in N_TERMINAL_SERIES else n - index
+        if not 1 <= position < n:
+            continue
+        site = sites[position - 1]
+        side = site["n_terminal"] if series in N_TERMINAL_SERIES else site["c_terminal"]
+        if series not in side:
+            side.append(series)
+        ion = match["ion_type"] if match["ion_charge"] == 1 else f"{match['ion_type']} ({match['ion_charge']}+)"
+        if ion not in site["ions"]:
+            site["ions"].append(ion)
+    for site in sites:
+        site["n_terminal"].sort()
+        site["c_terminal"].sort()
+        site["covered"] = bool(site["n_terminal"] or site["c_terminal"])
+    return sites
@@ -0,0 +1,59 @@
+"""
+Chimeric spectra: one spectrum annotated with several co-isolated candidate peptides at
+once, with the explained intensity broken down by peptide.
+"""
+
+from typing import Dict, List, Optional
+
+from .calculations import calculate_ions, match_ions
+from .mzpaf import format_mzpaf
+from .settings import FragmentationSettings, MatchSettings
+
+def annotate_chimeric(peaks: List[dict], peptides: List[Dict], settings: Optional[FragmentationSettings] = None,
+                      match_settings: Optional[MatchSettings] = None) -> Dict:
+    """
+    Matches each of peptides ({"sequence", optional "charge"}, the charge replacing
+    settings.precursor_charge) against the same peaks. Every match carries "peptide", the
+    index into peptides, which its mzPAF also gives as the analyte ("2@y4"). Per peptide
+    "explained_intensity" is the share (percent) of the total intensity in peaks it matches
+    and "unique_intensity" that in peaks no other peptide matches; "breakdown" splits the total into each peptide's unique part,
+    "shared" (peaks matched by several peptides) and "unexplained".
+    """
+    if not peptides:
+        raise ValueError("Chimeric annotation needs at least one peptide")
+    settings = settings or FragmentationSettings()
+    match_settings = match_settings or MatchSettings()
+    total = sum(p["intensity"] for p in peaks)
+
+    matches = []
+    claimed: Dict[float, set] = {}
+    results = []
+    for index, peptide in enumerate(peptides):
+        charge = peptide.get("charge") or settings.precursor_charge
+        ions = calculate_ions(peptide["sequence"], settings.model_copy(update={"precursor_charge": charge}))
+        peptide_matches = match_ions(peaks, ions, mass_type=settings.mass_type, settings=match_settings)
+        for match in peptide_matches:
+            match["peptide"] = index
+            match["mzpaf"] = format_mzpaf(match)
+            claimed.setdefault(match["peak_mz"], set()).add(index)
+        matches.extend(peptide_matches)
+        results.append({"sequence": peptide["sequence"], "charge": charge, "theoretical_ions": len(ions),
+                        "matched_ions": len(peptide_matches)})
+
+    intensity = {p["mz"]: p["intensity"] for p in peaks}
+    for index, result in enumerate(results):
+        mine = [mz for mz, owners in claimed.items() if index in owners]
+        unique = sum(intensity[mz] for mz in mine if len(claimed[mz]) == 1)
+        result["explained_intensity"] = 100.0 * sum(intensity[mz] for mz in mine) / total if total else 0.0
+        result["unique_intensity"] = 100.0 * unique / total if total else 0.0
+    shared = sum(intensity[mz] for mz, owners in claimed.items() if len(owners) > 1)
+    explained = sum(intensity[mz] for mz in claimed)
+    return {
+        "peptides": results,
+        "matches": sorted(matches, key=lambda m: m["peak_mz"]),
+        "breakdown": {
+            "peptides": [r["unique_intensity"] for r in results],
+            "shared": 100.0 * shared / total if total else 0.0,
+            "unexplained": 100.0 * (total - explained) / total if total else 0.0,
+        },
+    }
@@ -0,0 +1,234 @@
+"""
+Elemental compositions of residues/modifications and theoretical isotope distributions.
+"""
+
+import re
+from collections import Counter
+from typing import Dict, List, Optional, Tuple
+
+from .calculations import get_residue_masses, tokenize_peptide
+from .masses import AA_MASS, PROTON_MASS
+
+Composition = Dict[str, int]
+
+# (mass, abundance) of the stable isotopes, lightest first
+ISOTOPES = {
+    "H": [(1.00782503223, 0.999885), (2.01410177812, 0.000115)],
+    "C": [(12.0, 0.9893), (13.00335483507, 0.0107)],
+    "N": [(14.00307400443, 0.99636), (15.00010889888, 0.00364)],
+    "O": [(15.99491461957, 0.99757), (16.99913175650, 0.00038), (17.99915961286, 0.00205)],
+    "P": [(30.97376199842, 1.0)],
+    "S": [(31.9720711744, 0.9499), (32.9714589098, 0.0075), (33.967867004, 0.0425), (35.96708071, 0.0001)],
+    "Se": [(73.9224764, 0.0089), (75.9192136, 0.0937), (76.9199140, 0.0763), (77.9173091, 0.2377),
+           (79.9165213, 0.4961), (81.9166994, 0.0873)],
+}
+
+RESIDUE_FORMULAS = {
+    'G': "C2H3NO", 'A': "C3H5NO", 'S': "C3H5NO2", 'P': "C5H7NO", 'V': "C5H9NO", 'T': "C4H7NO2",
+    'C': "C3H5NOS", 'L': "C6H11NO", 'I': "C6H11NO", 'N': "C4H6N2O2", 'D': "C4H5NO3",
+    'Q': "C5H8N2O2", 'K': "C6H12N2O", 'E': "C5H7NO3", 'M': "C5H9NOS", 'H': "C6H7N3O",
+    'F': "C9H9NO", 'U': "C3H5NOSe", 'R': "C6H12N4O", 'Y': "C9H9NO2", 'W': "C11H10N2O",
+    'O': "C12H19N3O2",
+}
+
+# Common modification deltas (Unimod) recognised from bracketed masses
+MOD_FORMULAS = {
+    15.994915: "O",             # Oxidation
+    57.021464: "C2H3NO",        # Carbamidomethyl
+    79.966331: "HO3P",          # Phospho
+    42.010565: "C2H2O",         # Acetyl
+    14.015650: "CH2",           # Methyl
+    28.031300: "C2H4",          # Dimethyl
+    0.984016: "H-1N-1O",        # Deamidation
+    -0.984016: "HNO-1",         # Amidation
+    -17.026549: "H-3N-1",       # Pyro-glu from Q
+    -18.010565: "H-2O-1",       # Pyro-glu from E / water loss
+    43.005814: "CHNO",          # Carbamyl
+    114.042927: "C4H6N2O2",     # GlyGly
+    203.079373: "C8H13NO5",     # HexNAc
+    229.162932: "C12H20N2O2",   # TMT6plex (nominal composition, heavy atoms ignored)
+    304.207146: "C15H28N4O4",   # TMTpro (nominal composition, heavy atoms ignored)
+    144.102063: "C7H12N2O",     # iTRAQ4plex (nominal composition, heavy atoms ignored)
+}
+MOD_MATCH_TOLERANCE = 0.002
+
+# Averagine building block (Senko et al. 1995), per 111.1254 Da
+AVERAGINE = {"C": 4.9384, "H": 7.7583, "N": 1.3577, "O": 1.4773, "S": 0.0417}
+AVERAGINE_MASS = 111.1254
+
+_FORMULA_TOKEN = re.compile(r'([A-Z][a-z]?)(-?\d*)')
+
+def parse_formula(formula: str) -> Composition:
+    """
+    Parses a formula like "C6H12O6" or "H-2O-1" (negative counts allowed).
+    """
+    composition = Counter()
+    pos = 0
+    for match in _FORMULA_TOKEN.finditer(formula):
+        if match.start() != pos:
+            raise ValueError(f"Invalid formula {formula!r} at position {pos}")
+        element, count = match.group(1), match.group(2)
+        if element not in ISOTOPES:
+            raise ValueError(f"Unknown element {element!r} in formula {formula!r}")
+        composition[element] += int(count) if count not in ("", "-") else (-1 if count == "-" else 1)
+        pos = match.end()
+    if pos != len(formula):
+        raise ValueError(f"Invalid formula {formula!r} at position {pos}")
+    return dict(composition)
+
+def format_formula(composition: Composition) -> str:
+    """Hill notation: C, H, then remaining elements alphabetically."""
+    order = [e for e in ("C", "H") if composition.get(e)]
+    order += sorted(e for e in composition if e not in ("C", "H") and composition[e])
+    return "".join(f"{e}{composition[e] if composition[e] != 1 else ''}" for e in order)
+
+def add_compositions(*compositions: Composition) -> Composition:
+    total = Counter()
+    for comp in compositions:
+        total.update(comp)
+    return {e: n for e, n in total.items() if n}
+
+def monoisotopic_mass(composition: Composition) -> float:
+    return sum(ISOTOPES[e][0][0] * n for e, n in composition.items())
+
+def averagine_composition(mass: float) -> Composition:
+    """
+    Closest integer averagine composition for a mass; hydrogens absorb the rounding error.
+    """
+    units = mass / AVERAGINE_MASS
+    composition = {e: int(round(units * n)) for e, n in AVERAGINE.items()}
+    residual = mass - monoisotopic_mass(composition)
+    composition["H"] = max(0, composition["H"] + int(round(residual / ISOTOPES["H"][0][0])))
+    return {e: n for e, n in composition.items() if n}
+
+def modification_composition(mass: float) -> Composition:
+    """Known Unimod composition for a mass delta, falling back to averagine."""
+    for known_mass, formula in MOD_FORMULAS.items():
+        if abs(known_mass - mass) <= MOD_MATCH_TOLERANCE:
+            return parse_formula(formula)
+    if mass > 0:
+        return averagine_composition(mass)
+    return {}
+
+def peptide_composition(sequence: str, residue_masses: Optional[Dict[str, float]] = None) -> Composition:
+    """
+    Elemental composition of the neutral (modified) peptide.
+    Residues without a known formula (user-defined ones) are approximated with averagine.
+    """
+    table = get_residue_masses(residue_masses)
+    tokens, n_term, c_term = tokenize_peptide(sequence, table)
+    parts = [parse_formula("H2O")]
+    for residue, mod in tokens:
+        # A user override of a standard letter no longer matches its formula
+        if residue in RESIDUE_FORMULAS and table[residue] == AA_MASS.get(residue):
+            parts.append(parse_formula(RESIDUE_FORMULAS[residue]))
+        else:
+            parts.append(averagine_composition(table[residue]))
+        if mod:
+            parts.append(modification_composition(mod))
+    for mod in (n_term, c_term):
+        if mod:
+            parts.append(modification_composition(mod))
+    return add_compositions(*parts)
+
+def fragment_composition(sequence: str, ion_type: str, residue_masses: Optional[Dict[str, float]] = None) -> Composition:
+    """
+    Neutral composition of a b or y fragment, e.g. ion_type "b5" or "y3".
+    """
+    match = re.fullmatch(r'([by])(\d+)', ion_type)
+    if not match:
+        raise ValueError(f"Unsupported fragment type {ion_type!r}, expected b<n> or y<n>")
+    series, length = match.group(1), int(match.group(2))
+
+    table = get_residue_masses(residue_masses)
+    tokens, n_term, c_term = tokenize_peptide(sequence, table)
+    if not 1 <= length <= len(tokens):
+        raise ValueError(f"Fragment {ion_type} out of range for a {len(tokens)}-residue peptide")
+
+    # Rebuild the fragment as its own sequence; water is subtracted again for b-ions
+    def mod_str(m):
+        return f"[{m}]" if m else ""
+    if series == "b":
+        part = tokens[:length]
+        sub = mod_str(n_term) + "".join(r + mod_str(m) for r, m in part)
+        return add_compositions(peptide_composition(sub, residue_masses), {"H": -2, "O": -1})
+    part = tokens[-length:]
+    sub = "".join(r + mod_str(m) for r, m in part) + (f"-{mod_str(c_term)}" if c_term else "")
+    return peptide_composition(sub, residue_masses)
+
+def _convolve(a: List[Tuple[float, float]], b: List[Tuple[float, float]], max_peaks: int) -> List[Tuple[float, float]]:
+    # Each entry is (abundance, abundance-weighted mass) for one nominal isotope offset
+    out = [[0.0, 0.0] for _ in range(min(max_peaks, len(a) + len(b) - 1))]
+    for i, (pa, ma) in enumerate(a):
+        for j, (pb, mb) in enumerate(b):
+            k = i + j
+            if k >= len(out):
+                break
+            p = pa * pb
+            out[k][0] += p
+            # Weighted mass of the combination: (ma/pa + mb/pb) * p
+            if p > 0:
+                out[k][1] += (ma / pa + mb / pb) * p
+    return [(p, m) for p, m in out]
+
+def _element_distribution(element: str, max_peaks: int) -> List[Tuple[float, float]]:
+    isotopes = ISOTOPES[element]
+    mono = isotopes[0][0]
+    dist = [(0.0, 0.0)] * (int(round(isotopes[-1][0] - mono)) + 1)
+    for mass, abundance in isotopes:
+        dist[int(round(mass - mono))] = (abundance, mass * abundance)
+    return dist[:max_peaks]
+
+def isotope_distribution(composition: Composition, charge: int = 0, max_peaks: int = 6) -> List[dict]:
+    """
+    Aggregated isotope distribution (one peak per nominal offset) of a composition.
+    charge > 0 returns protonated m/z values, charge = 0 neutral masses.
+    Abundances are relative to the most abundant peak.
+    """
+    if any(n < 0 for n in composition.values()):
+        raise ValueError(f"Composition has negative element counts: {format_formula(composition)}")
+    result = [(1.0, 0.0)]
+    for element, count in composition.items():
+        # Exponentiation by squaring keeps large proteins tractable
+        base = _element_distribution(element, max_peaks)
+        while count:
+            if count & 1:
+                result = _convolve(result, base, max_peaks)
+            count >>= 1
+            if count:
+                base = _convolve(base, base, max_peaks)
+
+    top = max(p for p, _ in result)
+    peaks = []
+    for k, (p, m) in enumerate(result):
+        if p <= 0:
+            continue
+        mass = m / p
+        mz = (mass + charge * PROTON_MASS) / charge if charge > 0 else mass
+        peaks.append({"isotope": k, "mz": mz, "abundance": p / top})
+    return peaks
+
+def averagine_distribution(mass: float, charge: int = 0, max_peaks: int = 6) -> List[dict]:
+    """Isotope distribution of a neutral mass approximated with averagine."""
+    dist = isotope_distribution(averagine_composition(mass), 0, max_peaks)
+    # Shift so the monoisotopic peak lands exactly on the requested mass
+    shift = mass - dist[0]["mz"]
+    for peak in dist:
+        neutral = peak["mz"] + shift
+        peak["mz"] = (neutral + charge * PROTON_MASS) / charge if charge > 0 else neutral
+    return dist
+
+def peptide_isotope_distribution(sequence: str, charge: int, ion_type: Optional[str] = None, method: str = "full", max_peaks: int = 6, residue_masses: Optional[Dict[str, float]] = None) -> List[dict]:
+    """
+    Isotope envelope of a peptide (ion_type None) or one of its b/y fragments.
+    method "full" uses the elemental composition, "averagine" only its mass.
+    """
+    if ion_type:
+        composition = fragment_composition(sequence, ion_type, residue_masses)
+    else:
+        composition = peptide_composition(sequence, residue_masses)
+    if method == "full":
+        return isotope_distribution(composition, charge, max_peaks)
+    if method == "averagine":
+        return averagine_distribution(monoisotopic_mass(composition), charge, max_peaks)
+    raise ValueError(f"Unknown isotope method {method!r}, expected 'full' or 'averagine'")
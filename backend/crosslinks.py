@@ -0,0 +1,120 @@
+"""
+Crosslinked peptide pairs (XL-MS): theoretical ions of the alpha and beta peptide, whose
+fragments spanning the link site carry the crosslinker and the partner peptide or, for
+MS-cleavable crosslinkers, one of the linker stubs.
+"""
+
+from typing import Dict, List, Optional
+
+from .calculations import calculate_ions, get_residue_masses, match_ions, peptide_mass, tokenize_peptide
+from .decoys import format_peptide
+from .masses import mz_from_mass
+from .settings import ION_SERIES, FragmentationSettings, MatchSettings
+
+# Linker mass added by the crosslink, and for MS-cleavable ones the stub masses left on
+# each peptide when the linker breaks (DSSO: alkene, sulfenic acid, thiol; DSBU: Bu, BuUr)
+CROSSLINKERS = {
+    "DSS": {"mass": 138.068080, "stubs": {}},
+    "BS3": {"mass": 138.068080, "stubs": {}},
+    "DSG": {"mass": 96.021129, "stubs": {}},
+    "EDC": {"mass": -18.010565, "stubs": {}},
+    "DSSO": {"mass": 158.003765, "stubs": {"A": 54.010565, "S": 103.993200, "T": 85.982635}},
+    "DSBU": {"mass": 196.084792, "stubs": {"Bu": 85.052764, "BuUr": 111.032028}},
+}
+
+PEPTIDE_MARKS = {"alpha": "α", "beta": "β"}
+
+def _with_site_mass(peptide: str, site: int, mass: float, residue_table: Dict[str, float]) -> str:
+    # residue_table is the full get_residue_masses table, not just the per-call overrides
+    tokens, n_term, c_term = tokenize_peptide(peptide, residue_table)
+    if not 1 <= site <= len(tokens):
+        raise ValueError(f"Link site {site} out of range for {peptide!r} ({len(tokens)} residues)")
+    tokens = list(tokens)
+    tokens[site - 1] = (tokens[site - 1][0], tokens[site - 1][1] + mass)
+    return format_peptide(tokens, n_term, c_term)
+
+def _spans(ion: dict, site: int, length: int) -> bool:
+    # Whether the fragment contains the linked residue
+    return ion["index"] >= site if ion["series"] in ("a", "b", "c") else ion["index"] > length - site
+
+def _relabel(ion: dict, peptide: str, link: Optional[str]) -> dict:
+    mark = PEPTIDE_MARKS[peptide]
+    label = f"{ion['series']}{ion['index']}{mark}" + (f"-{ion['loss']}" if ion["loss"] else "")
+    if link and link != "partner":
+        label += f"+{link}"
+    return {**ion, "type": label, "peptide": peptide, "link": link}
+
+def resolve_crosslinker(crosslinker: Optional[str] = None, linker_mass: Optional[float] = None,
+                        stub_masses: Optional[Dict[str, float]] = None) -> Dict:
+    """{"mass", "stubs"} of a named crosslinker (CROSSLINKERS), overridden by linker_mass / stub_masses."""
+    if crosslinker is not None and crosslinker not in CROSSLINKERS:
+        raise ValueError(f"Unknown crosslinker {crosslinker!r}, expected one of {tuple(CROSSLINKERS)}")
+    known = CROSSLINKERS.get(crosslinker, {"mass": None, "stubs": {}})
+    mass = linker_mass if linker_mass is not None else known["mass"]
+    if mass is None:
+        raise ValueError("A crosslinker name or linker_mass is required")
+    return {"mass": mass, "stubs": stub_masses if stub_masses else known["stubs"]}
+
+def crosslink_ions(alpha: str, beta: str, alpha_site: int, beta_site: int, linker_mass: float,
+                   stub_masses: Optional[Dict[str, float]] = None,
+                   settings: Optional[FragmentationSettings] = None) -> List[dict]:
+    """
+    Theoretical ions of a crosslinked pair with 1-based link sites. Each ion gets
+    "peptide" ("alpha"/"beta") and "link": None for fragments without the linked residue,
+    "partner" for those carrying the linker and the other peptide, or the stub name for
+    cleavable-linker fragments. Labels mark the peptide, e.g. "b3α", "y5β", "y7β+A".
+    Intact peptide + stub ions (series "M", e.g. "Mα+A") are added for each stub.
+    """
+    settings = (settings or FragmentationSettings()).validate_options()
+    residue_masses = settings.residue_masses or None
+    masses = {"alpha": peptide_mass(alpha, residue_masses, settings.mass_type),
+              "beta": peptide_mass(beta, residue_masses, settings.mass_type)}
+    # The full table (built-ins plus overrides) decides which letters are residues
+    table = get_residue_masses(residue_masses, settings.mass_type)
+    ions = []
+    for name, peptide, site, partner in (("alpha", alpha, alpha_site, "beta"), ("beta", beta, beta_site, "alpha")):
+        length = len(tokenize_peptide(peptide, table)[0])
+        linked = calculate_ions(_with_site_mass(peptide, site, linker_mass + masses[partner], table), settings)
+        # Backbone series only; precursor/glycan extras would describe the whole complex
+        for ion in linked:
+            if ion["series"] not in ION_SERIES:
+                continue
+            ions.append(_relabel(ion, name, "partner" if _spans(ion, site, length) else None))
+        for stub, stub_mass in (stub_masses or {}).items():
+            stubbed = _with_site_mass(peptide, site, stub_mass, table)
+            for ion in calculate_ions(stubbed, settings):
+                if ion["series"] in ION_SERIES and _spans(ion, site, length):
+                    ions.append(_relabel(ion, name, stub))
+            for z in range(1, settings.precursor_charge + 1):
+                mz = mz_from_mass(masses[name] + stub_mass, z, settings.electron_aware)
+                ions.append({"type": f"M{PEPTIDE_MARKS[name]}+{stub}", "series": "M", "index": length, "loss": "",
+                             "charge": z, "mz": round(mz, settings.decimals) if settings.decimals is not None else mz,
+                             "peptide": name, "link": stub})
+    return ions
+
+def crosslink_precursor_mz(alpha: str, beta: str, linker_mass: float, charge: int,
+                           residue_masses: Optional[Dict[str, float]] = None, mass_type: str = "monoisotopic",
+                           electron_aware: bool = True) -> float:
+    mass = peptide_mass(alpha, residue_masses, mass_type) + peptide_mass(beta, residue_masses, mass_type) + linker_mass
+    return mz_from_mass(mass, charge, electron_aware)
+
+def annotate_crosslink(peaks: List[dict], alpha: str, beta: str, alpha_site: int, beta_site: int,
+                       crosslinker: Optional[str] = "DSS", linker_mass: Optional[float] = None,
+                       stub_masses: Optional[Dict[str, float]] = None,
+                       settings: Optional[FragmentationSettings] = None,
+                       match_settings: Optional[MatchSettings] = None) -> Dict:
+    """Matches of a crosslinked pair's ions (see crosslink_ions) with the linker used and precursor m/z."""
+    settings = (settings or FragmentationSettings()).validate_options()
+    linker = resolve_crosslinker(crosslinker, linker_mass, stub_masses)
+    ions = crosslink_ions(alpha, beta, alpha_site, beta_site, linker["mass"], linker["stubs"], settings)
+    matches = match_ions(peaks, ions, mass_type=settings.mass_type, settings=match_settings or MatchSettings(),
+                         mzpaf=True)
+    return {
+        "linker_mass": linker["mass"],
+        "stub_masses": linker["stubs"],
+        "precursor_mz": crosslink_precursor_mz(alpha, beta, linker["mass"], settings.precursor_charge,
+                                               settings.residue_masses or None, settings.mass_type,
+                                               settings.electron_aware),
+        "theoretical_ions": len(ions),
+        "matches": matches,
+    }
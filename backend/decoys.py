@@ -0,0 +1,56 @@
+"""
+Decoy peptides for target-decoy statistics. Modifications stay on their residues and
+terminal modifications on their terminus, so decoys keep the target's mass.
+"""
+
+import random
+from typing import Dict, List, Optional, Tuple
+
+from .calculations import get_residue_masses, tokenize_peptide
+
+DECOY_METHODS = ("reverse", "pseudo_reverse", "shuffle")
+SHUFFLE_ATTEMPTS = 10
+
+def _format_mod(mass: float) -> str:
+    return f"[{mass:+.6f}".rstrip("0").rstrip(".") + "]"
+
+def format_peptide(tokens: List[Tuple[str, float]], n_term: float, c_term: float) -> str:
+    """Modified sequence string of tokenize_peptide output, e.g. "[+42.010565]-PEPM[+15.994915]K"."""
+    sequence = "".join(residue + (_format_mod(mod) if mod else "") for residue, mod in tokens)
+    if n_term:
+        sequence = f"{_format_mod(n_term)}-{sequence}"
+    if c_term:
+        sequence = f"{sequence}-{_format_mod(c_term)}"
+    return sequence
+
+def decoy_peptide(peptide: str, method: str = "pseudo_reverse", seed: int = 0,
+                  residue_masses: Optional[Dict[str, float]] = None) -> str:
+    """
+    Decoy of one (modified) peptide: "reverse" reverses all residues, "pseudo_reverse" all
+    but the C-terminal one (keeping the tryptic K/R in place) and "shuffle" permutes all but
+    the C-terminal one. Shuffles are seeded by seed and the peptide, so a peptide always
+    gets the same decoy; up to SHUFFLE_ATTEMPTS are tried to avoid returning the target.
+    residue_masses are per-call overrides, so custom residues move like standard ones.
+    """
+    if method not in DECOY_METHODS:
+        raise ValueError(f"Unknown decoy method {method!r}, expected one of {DECOY_METHODS}")
+    tokens, n_term, c_term = tokenize_peptide(peptide, get_residue_masses(residue_masses))
+    if not tokens:
+        raise ValueError(f"peptide {peptide!r} has no residues")
+    if method == "reverse":
+        return format_peptide(tokens[::-1], n_term, c_term)
+    body, last = tokens[:-1], tokens[-1:]
+    if method == "pseudo_reverse":
+        return format_peptide(body[::-1] + last, n_term, c_term)
+    rng = random.Random(f"{seed}:{peptide}")
+    shuffled = list(body)
+    for _ in range(SHUFFLE_ATTEMPTS):
+        rng.shuffle(shuffled)
+        if shuffled != body:
+            break
+    return format_peptide(shuffled + last, n_term, c_term)
+
+def generate_decoys(peptides: List[str], method: str = "pseudo_reverse", seed: int = 0,
+                    residue_masses: Optional[Dict[str, float]] = None) -> List[Dict]:
+    """{"target", "decoy"} for every peptide, see decoy_peptide."""
+    return [{"target": peptide, "decoy": decoy_peptide(peptide, method, seed, residue_masses)} for peptide in peptides]
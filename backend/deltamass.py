@@ -0,0 +1,124 @@
+"""
+Precursor mass deltas across a PSM set: the histogram open-search results are explored
+with, its peaks, and the Unimod modifications those peaks most likely are.
+"""
+
+from typing import Dict, List, Optional
+
+from .calculations import peptide_mass
+from .masses import C13_DELTA, mass_from_mz
+
+# Unimod monoisotopic deltas (Da) of common modifications and artefacts
+UNIMOD_DELTAS = {
+    "Unmodified": 0.0,
+    "Oxidation": 15.994915,
+    "Dioxidation": 31.989829,
+    "Carbamidomethyl": 57.021464,
+    "Phospho": 79.966331,
+    "Acetyl": 42.010565,
+    "Methyl": 14.015650,
+    "Dimethyl": 28.031300,
+    "Trimethyl": 42.046950,
+    "Formyl": 27.994915,
+    "Carbamyl": 43.005814,
+    "Deamidated": 0.984016,
+    "Amidated": -0.984016,
+    "Gln->pyro-Glu": -17.026549,
+    "Glu->pyro-Glu": -18.010565,
+    "GG": 114.042927,
+    "HexNAc": 203.079373,
+    "Hex": 162.052824,
+    "Cation:Na": 21.981943,
+    "Cation:K": 37.955882,
+    "TMT6plex": 229.162932,
+    "TMTpro": 304.207146,
+    "iTRAQ4plex": 144.102063,
+    "Sulfo": 79.956815,
+    "Nitro": 44.985078,
+    "13C isotope error": C13_DELTA,
+    "2x 13C isotope error": 2 * C13_DELTA,
+}
+
+# Unimod record ids of the UNIMOD_DELTAS entries, for formats referring to "UNIMOD:35"
+UNIMOD_ACCESSIONS = {
+    1: "Acetyl", 2: "Amidated", 4: "Carbamidomethyl", 5: "Carbamyl", 7: "Deamidated", 21: "Phospho",
+    27: "Glu->pyro-Glu", 28: "Gln->pyro-Glu", 30: "Cation:Na", 34: "Methyl", 35: "Oxidation", 36: "Dimethyl",
+    37: "Trimethyl", 40: "Sulfo", 41: "Hex", 43: "HexNAc", 121: "GG", 122: "Formyl", 214: "iTRAQ4plex",
+    354: "Nitro", 425: "Dioxidation", 530: "Cation:K", 737: "TMT6plex", 2016: "TMTpro",
+}
+
+def psm_delta(psm: Dict, electron_aware: bool = True) -> float:
+    """
+    Observed minus theoretical neutral precursor mass of a PSM given as {"peptide",
+    "precursor_mass"} (neutral) or {"peptide", "precursor_mz", "charge"}; modifications in
+    the peptide count towards the theoretical mass. "sequence" stands in for "peptide", so
+    parsed identifications (see identifications.py) can be passed as they are.
+    """
+    peptide = psm.get("peptide") or psm.get("sequence")
+    if psm.get("precursor_mass") is not None:
+        observed = psm["precursor_mass"]
+    elif psm.get("precursor_mz") is not None and psm.get("charge"):
+        observed = mass_from_mz(psm["precursor_mz"], psm["charge"], electron_aware)
+    else:
+        raise ValueError(f"PSM {peptide!r}: needs precursor_mass, or precursor_mz and charge")
+    return observed - peptide_mass(peptide)
+
+def annotate_delta(delta: float, tolerance: float = 0.01) -> List[str]:
+    """Names of UNIMOD_DELTAS entries within tolerance (Da) of a delta, closest first."""
+    hits = [(abs(mass - delta), name) for name, mass in UNIMOD_DELTAS.items() if abs(mass - delta) <= tolerance]
+    return [name for _, name in sorted(hits)]
+
+def delta_mass_histogram(psms: List[Dict], bin_width: float = 0.01, min_delta: float = -200.0,
+                         max_delta: float = 500.0, min_count: int = 3, peak_window: int = 5,
+                         annotation_tolerance: Optional[float] = None) -> Dict:
+    """
+    Histogram of PSM precursor mass deltas (see psm_delta) between min_delta and max_delta.
+    "bins" lists only the non-empty bins as {"delta" (bin centre), "count"}. "peaks" are bins
+    holding at least min_count PSMs and the most of any bin within +-peak_window bins, most
+    populated first. A peak takes in the PSMs of the unbroken run of non-empty bins around
+    it (up to peak_window either side), so a mode split over a bin edge stays one peak; it
+    reports their count and mean delta and the matching UNIMOD_DELTAS names (within
+    annotation_tolerance Da, by default 2 bin widths).
+    """
+    if bin_width <= 0:
+        raise ValueError(f"bin_width must be positive, got {bin_width}")
+    if max_delta <= min_delta:
+        raise ValueError(f"max_delta ({max_delta}) must be above min_delta ({min_delta})")
+    tolerance = annotation_tolerance if annotation_tolerance is not None else 2 * bin_width
+    counts: Dict[int, List[float]] = {}
+    outside = 0
+    for psm in psms:
+        delta = psm_delta(psm)
+        if not min_delta <= delta < max_delta:
+            outside += 1
+            continue
+        counts.setdefault(int((delta - min_delta) // bin_width), []).append(delta)
+
+    def centre(k: int) -> float:
+        return min_delta + (k + 0.5) * bin_width
+
+    peaks = []
+    for k, deltas in counts.items():
+        if len(deltas) < min_count:
+            continue
+        neighbours = [len(counts.get(j, ())) for j in range(k - peak_window, k + peak_window + 1) if j != k]
+        # Among equal neighbours the lowest bin is the peak
+        if any(n > len(deltas) for n in neighbours) or any(
+                len(counts.get(j, ())) == len(deltas) for j in range(k - peak_window, k)):
+            continue
+        members = list(deltas)
+        for step in (-1, 1):
+            j = k + step
+            while abs(j - k) <= peak_window and j in counts:
+                members += counts[j]
+                j += step
+        mean = sum(members) / len(members)
+        peaks.append({"delta": mean, "count": len(members), "modifications": annotate_delta(mean, tolerance)})
+    peaks.sort(key=lambda p: (-p["count"], p["delta"]))
+    return {
+        "bin_width": bin_width,
+        "bins": [{"delta": centre(k), "count": len(counts[k])} for k in sorted(counts)],
+        "peaks": peaks,
+        "total": len(psms),
+        "outside_range": outside,
+    }
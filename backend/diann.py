@@ -0,0 +1,91 @@
+"""
+DIA-NN report.tsv results: precursors with their run, scan, charge, retention time and
+q-values, the (UniMod:n) modified sequences resolved to [mass] deltas.
+"""
+
+import csv
+import io
+import re
+from typing import Dict, List
+
+from .decoys import format_peptide
+from .deltamass import UNIMOD_ACCESSIONS, UNIMOD_DELTAS
+from .mzid import optional_float
+from .mzml import open_source
+
+DIANN_COLUMNS = ("Modified.Sequence", "Precursor.Charge", "Q.Value")
+# Scores copied into "scores" when present
+DIANN_SCORES = ("Q.Value", "Global.Q.Value", "PEP", "Global.PEP", "CScore", "Decoy.CScore",
+                "Lib.Q.Value", "PG.Q.Value", "Precursor.Quantity")
+MODIFICATION = re.compile(r'\((UniMod:\d+|[+-]?\d+(?:\.\d+)?)\)|\[([+-]?\d+(?:\.\d+)?)\]')
+
+def parse_diann_sequence(modified: str) -> str:
+    """"(UniMod:1)PEPM(UniMod:35)TIDEK" (or with mass blocks) as "[+42.010565]-PEPM[+15.994915]TIDEK"."""
+    tokens: List[List] = []
+    n_term = 0.0
+    position = 0
+    for match in MODIFICATION.finditer(modified):
+        tokens.extend([r, 0.0] for r in modified[position:match.start()] if r.isalpha())
+        position = match.end()
+        mod = match.group(1) or match.group(2)
+        if mod.startswith("UniMod:"):
+            accession = int(mod.split(":")[1])
+            if accession not in UNIMOD_ACCESSIONS:
+                raise ValueError(f"Unknown modification {mod} in DIA-NN sequence {modified!r}")
+            mass = UNIMOD_DELTAS[UNIMOD_ACCESSIONS[accession]]
+        else:
+            mass = float(mod)
+        if tokens:
+            tokens[-1][1] += mass
+        else:
+            n_term += mass
+    tokens.extend([r, 0.0] for r in modified[position:] if r.isalpha())
+    return format_peptide([tuple(t) for t in tokens], n_term, 0.0)
+
+def parse_diann(source) -> List[Dict]:
+    """
+    Precursors of a DIA-NN report.tsv (bytes, path or binary file object), with the fields
+    of mzid.parse_mzid plus "q_value" and "pep": "scan_nr" is MS2.Scan when reported,
+    "spec_id" the Precursor.Id, "spectra_data" the run's File.Name, "retention_time" the
+    apex RT in seconds and "is_decoy" the Decoy column of --report-decoys output. "scores"
+    holds the DIANN_SCORES columns present.
+    """
+    stream = open_source(source)
+    try:
+        reader = csv.DictReader(io.TextIOWrapper(stream, encoding='utf-8-sig'), delimiter='\t')
+        missing = [c for c in DIANN_COLUMNS if c not in (reader.fieldnames or [])]
+        if missing:
+            raise ValueError(f"DIA-NN report is missing required column(s): {', '.join(missing)}")
+        psms = []
+        for row_nr, row in enumerate(reader, 1):
+            try:
+                psms.append(_precursor(row))
+            except (TypeError, ValueError) as e:
+                raise ValueError(f"DIA-NN report, data row {row_nr}: {e}") from e
+    finally:
+        if stream is not source:
+            stream.close()
+    return psms
+
+def _precursor(row: Dict[str, str]) -> Dict:
+    scan = optional_float(row.get("MS2.Scan"))
+    retention_time = optional_float(row.get("RT"))
+    scores = {column: optional_float(row[column]) for column in DIANN_SCORES if optional_float(row.get(column)) is not None}
+    return {
+        "scan_nr": int(scan) if scan is not None else None,
+        "spec_id": row.get("Precursor.Id") or '',
+        "spectra_data": row.get("File.Name") or row.get("Run") or '',
+        "sequence": parse_diann_sequence(row["Modified.Sequence"]),
+        "charge": int(row["Precursor.Charge"]),
+        "rank": 1,
+        "precursor_mz": optional_float(row.get("Precursor.Mz")),
+        "calculated_mz": None,
+        "retention_time": retention_time * 60 if retention_time is not None else None,
+        "pass_threshold": True,
+        "is_decoy": (row.get("Decoy") or "0").strip() == "1",
+        "proteins": [p for p in (row.get("Protein.Ids") or "").split(";") if p],
+        "score": optional_float(row.get("CScore")),
+        "q_value": optional_float(row.get("Q.Value")),
+        "pep": optional_float(row.get("PEP")),
+        "scores": scores,
+    }
@@ -0,0 +1,75 @@
+"""
+Delimited (TSV/CSV) tables of match results and annotated peak lists, for pasting into
+spreadsheets and supplementary tables.
+"""
+
+import csv
+import io
+from typing import Dict, Iterable, List, Optional, Sequence, Union
+
+# Default columns, in order; any other key of the rows may be asked for too
+MATCH_COLUMNS = ("peak_mz", "peak_intensity", "ion_type", "ion_charge", "theoretical_mz", "error_da", "error_ppm",
+                 "relative_intensity", "intensity_rank", "mzpaf")
+PEAK_COLUMNS = ("mz", "intensity", "status", "annotations")
+EXPORT_DECIMALS = 4
+DELIMITERS = {"tab": "\t", "comma": ",", "semicolon": ";"}
+# Separator for list cells (alternatives, annotations) inside one column
+LIST_SEPARATOR = ";"
+
+def _cell(value, decimals: Optional[int]) -> str:
+    if value is None:
+        return ""
+    if isinstance(value, bool):
+        return "true" if value else "false"
+    if isinstance(value, float):
+        return f"{value:.{decimals}f}" if decimals is not None else repr(value)
+    if isinstance(value, (list, tuple)):
+        # Annotation dicts (calculations.annotate_spectrum) are written as their labels
+        return LIST_SEPARATOR.join(_cell(v.get("mzpaf") or v.get("ion_type") if isinstance(v, dict) else v, decimals)
+                                   for v in value)
+    return str(value)
+
+def export_delimited(rows: Iterable[Dict], columns: Sequence[str], decimals: Union[int, Dict[str, int], None] = EXPORT_DECIMALS,
+                     delimiter: str = "\t", header: bool = True) -> str:
+    """
+    rows as delimited text, one line per row in the given column order. decimals is the
+    precision for every float column, or per column ({"peak_mz": 5, "error_ppm": 1}; unlisted
+    columns keep EXPORT_DECIMALS); None writes floats in full. Cells holding the delimiter,
+    quotes or newlines are quoted. delimiter may be a character or a DELIMITERS name.
+    """
+    delimiter = DELIMITERS.get(delimiter, delimiter)
+    if len(delimiter) != 1:
+        raise ValueError(f"Delimiter must be one character or one of {tuple(DELIMITERS)}, got {delimiter!r}")
+    if not columns:
+        raise ValueError("Export needs at least one column")
+    rows = list(rows)
+    known = set(columns) if not rows else set().union(*(row.keys() for row in rows))
+    unknown = [c for c in columns if c not in known]
+    if unknown:
+        raise ValueError(f"Unknown export column(s) {unknown}, available: {sorted(known)}")
+    precision = {c: (decimals.get(c, EXPORT_DECIMALS) if isinstance(decimals, dict) else decimals) for c in columns}
+
+    out = io.StringIO()
+    writer = csv.writer(out, delimiter=delimiter, lineterminator="\n", quoting=csv.QUOTE_MINIMAL)
+    if header:
+        writer.writerow(columns)
+    for row in rows:
+        writer.writerow([_cell(row.get(c), precision[c]) for c in columns])
+    return out.getvalue()
+
+def export_matches_tsv(matches: List[Dict], columns: Optional[Sequence[str]] = None,
+                       decimals: Union[int, Dict[str, int], None] = EXPORT_DECIMALS, delimiter: str = "\t") -> str:
+    """match_ions results as a table, MATCH_COLUMNS by default, sorted by peak m/z."""
+    rows = sorted(matches, key=lambda m: (m.get("peak_mz", 0.0), m.get("ion_type", "")))
+    return export_delimited(rows, columns or MATCH_COLUMNS, decimals, delimiter)
+
+def export_peaks_tsv(peaks: List[Dict], columns: Optional[Sequence[str]] = None,
+                     decimals: Union[int, Dict[str, int], None] = EXPORT_DECIMALS, delimiter: str = "\t") -> str:
+    """
+    A peak list as a table, by m/z. Annotated peaks (calculations.annotate_spectrum) give
+    PEAK_COLUMNS with each peak's annotations joined by LIST_SEPARATOR; plain peaks give
+    "mz" and "intensity".
+    """
+    if columns is None:
+        columns = PEAK_COLUMNS if peaks and "annotations" in peaks[0] else ("mz", "intensity")
+    return export_delimited(sorted(peaks, key=lambda p: p["mz"]), columns, decimals, delimiter)
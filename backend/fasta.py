@@ -0,0 +1,92 @@
+"""
+FASTA protein databases, held in memory with lookup by accession. UniProt headers are
+split into accession, entry name, gene, organism and description.
+"""
+
+import gzip
+import re
+from pathlib import Path
+from typing import Dict, Iterator, List, Optional, Union
+
+from .mzml import is_gzip
+
+# >sp|P69905|HBA_HUMAN Hemoglobin subunit alpha OS=Homo sapiens OX=9606 GN=HBA1 PE=1 SV=2
+UNIPROT_HEADER = re.compile(r'(sp|tr)\|([^|\s]+)\|(\S+)\s*(.*)')
+UNIPROT_TAG = re.compile(r'\s([A-Z]{2})=')
+
+def parse_header(header: str) -> Dict:
+    """Fields of a FASTA header (without ">"); non-UniProt headers give accession and description only."""
+    entry = {"accession": "", "entry_name": None, "database": None, "gene": None, "organism": None,
+             "description": "", "header": header}
+    match = UNIPROT_HEADER.match(header)
+    if not match:
+        accession, _, description = header.partition(" ")
+        entry.update(accession=accession, description=description.strip())
+        return entry
+    entry.update(database=match.group(1), accession=match.group(2), entry_name=match.group(3))
+    rest = match.group(4)
+    # Description runs up to the first KEY=value tag; OS values contain spaces
+    tags = list(UNIPROT_TAG.finditer(" " + rest))
+    entry["description"] = (" " + rest)[:tags[0].start()].strip() if tags else rest.strip()
+    for n, tag in enumerate(tags):
+        end = tags[n + 1].start() if n + 1 < len(tags) else len(rest) + 1
+        value = (" " + rest)[tag.end():end].strip()
+        if tag.group(1) == "GN":
+            entry["gene"] = value
+        elif tag.group(1) == "OS":
+            entry["organism"] = value
+    return entry
+
+def parse_fasta(text: str) -> List[Dict]:
+    """
+    Proteins of a FASTA file as parse_header entries plus "sequence" (uppercase, sequence
+    lines joined, whitespace and a trailing "*" removed). Raises ValueError for sequence
+    lines before the first header.
+    """
+    proteins = []
+    entry, lines = None, []
+    for number, line in enumerate(text.splitlines(), 1):
+        line = line.strip()
+        if not line or line.startswith(";"):
+            continue
+        if line.startswith(">"):
+            if entry is not None:
+                proteins.append({**entry, "sequence": "".join(lines).upper().rstrip("*")})
+            entry, lines = parse_header(line[1:].strip()), []
+        elif entry is None:
+            raise ValueError(f"FASTA line {number}: sequence before the first '>' header")
+        else:
+            lines.append("".join(line.split()))
+    if entry is not None:
+        proteins.append({**entry, "sequence": "".join(lines).upper().rstrip("*")})
+    return proteins
+
+class FastaDatabase:
+    """Proteins of one FASTA file, in file order, with lookup by accession or entry name."""
+
+    def __init__(self, proteins: List[Dict], file_path: Optional[Path] = None):
+        self.proteins = proteins
+        self.file_path = file_path
+        self._by_accession: Dict[str, Dict] = {}
+        for protein in proteins:
+            self._by_accession.setdefault(protein["accession"], protein)
+            if protein["entry_name"]:
+                self._by_accession.setdefault(protein["entry_name"], protein)
+
+    @classmethod
+    def from_file(cls, path: Union[str, Path]) -> "FastaDatabase":
+        """Reads a FASTA file, optionally gzipped."""
+        path = Path(path)
+        data = path.read_bytes()
+        if is_gzip(data):
+            data = gzip.decompress(data)
+        return cls(parse_fasta(data.decode("utf-8", errors="replace")), path)
+
+    def __len__(self) -> int:
+        return len(self.proteins)
+
+    def __iter__(self) -> Iterator[Dict]:
+        return iter(self.proteins)
+
+    def get(self, accession: str) -> Optional[Dict]:
+        return self._by_accession.get(accession)
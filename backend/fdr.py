@@ -0,0 +1,120 @@
+"""
+Target-decoy statistics for scored PSM lists: q-values from the decoy count at each score
+threshold and, optionally, posterior error probabilities from a two-component score mixture.
+"""
+
+import math
+from typing import Dict, List, Optional, Tuple
+
+PEP_ITERATIONS = 100
+PEP_CONVERGENCE = 1e-6
+
+def _ranked(psms: List[Dict], higher_is_better: bool) -> List[int]:
+    # Decoys first among equal scores, so ties never make a threshold look better than it is
+    for i, psm in enumerate(psms):
+        if psm.get("score") is None or isinstance(psm["score"], bool):
+            raise ValueError(f"PSM {i}: needs a numeric score")
+    sign = -1 if higher_is_better else 1
+    return sorted(range(len(psms)), key=lambda i: (sign * psms[i]["score"], not psms[i].get("is_decoy")))
+
+def q_values(psms: List[Dict], higher_is_better: bool = True, decoy_correction: bool = True) -> List[float]:
+    """
+    q-value of every PSM ({"score", "is_decoy"}), in input order: the lowest FDR at which it
+    is accepted, FDR being (decoys + 1) / targets above a threshold, or decoys / targets
+    without decoy_correction, capped at 1.
+    """
+    order = _ranked(psms, higher_is_better)
+    fdrs = []
+    targets = decoys = 0
+    for i in order:
+        if psms[i].get("is_decoy"):
+            decoys += 1
+        else:
+            targets += 1
+        fdrs.append(min(1.0, (decoys + int(decoy_correction)) / targets) if targets else 1.0)
+    # A q-value is the minimum FDR of this or any less stringent threshold
+    result = [1.0] * len(psms)
+    running = 1.0
+    for rank in range(len(order) - 1, -1, -1):
+        running = min(running, fdrs[rank])
+        result[order[rank]] = running
+    return result
+
+def _normal_pdf(x: float, mean: float, sd: float) -> float:
+    return math.exp(-0.5 * ((x - mean) / sd) ** 2) / (sd * math.sqrt(2 * math.pi))
+
+def _mean_sd(values: List[float], weights: Optional[List[float]] = None) -> Tuple[float, float]:
+    weights = weights or [1.0] * len(values)
+    total = sum(weights)
+    mean = sum(w * v for w, v in zip(weights, values)) / total
+    variance = sum(w * (v - mean) ** 2 for w, v in zip(weights, values)) / total
+    return mean, max(math.sqrt(variance), 1e-6)
+
+def posterior_error_probabilities(psms: List[Dict], higher_is_better: bool = True) -> List[float]:
+    """
+    PEP of every PSM, in input order. Decoy scores fix a normal null distribution; target
+    scores are fitted by EM as a mixture of that null (weight pi0) and a second normal for
+    correct matches, and PEP = pi0 f0 / (pi0 f0 + (1 - pi0) f1). The result is made
+    monotone in the score, so a better score never gets a higher PEP. Needs at least two
+    targets and two decoys.
+    """
+    target_scores = [p["score"] for p in psms if not p.get("is_decoy")]
+    decoy_scores = [p["score"] for p in psms if p.get("is_decoy")]
+    if len(target_scores) < 2 or len(decoy_scores) < 2:
+        raise ValueError(f"PEP needs at least 2 targets and 2 decoys, got {len(target_scores)} and {len(decoy_scores)}")
+    sign = 1 if higher_is_better else -1
+    targets = [sign * s for s in target_scores]
+    null_mean, null_sd = _mean_sd([sign * s for s in decoy_scores])
+
+    # Start the correct component on the upper half of the targets
+    upper = sorted(targets)[len(targets) // 2:]
+    mean, sd = _mean_sd(upper)
+    pi0 = 0.5
+
+    def null_share(x: float) -> float:
+        f0 = pi0 * _normal_pdf(x, null_mean, null_sd)
+        f1 = (1 - pi0) * _normal_pdf(x, mean, sd)
+        if f0 + f1 == 0:
+            # Far outside both components: the nearer mean wins
+            return 1.0 if abs(x - null_mean) / null_sd < abs(x - mean) / sd else 0.0
+        return f0 / (f0 + f1)
+
+    for _ in range(PEP_ITERATIONS):
+        shares = [null_share(x) for x in targets]
+        correct = [1 - s for s in shares]
+        new_pi0 = min(max(sum(shares) / len(targets), 1e-6), 1 - 1e-6)
+        if sum(correct) > 1e-9:
+            mean, sd = _mean_sd(targets, correct)
+        converged = abs(new_pi0 - pi0) < PEP_CONVERGENCE
+        pi0 = new_pi0
+        if converged:
+            break
+
+    order = _ranked(psms, higher_is_better)
+    result = [1.0] * len(psms)
+    running = 0.0
+    for i in order:
+        running = max(running, null_share(sign * psms[i]["score"]))
+        result[i] = running
+    return result
+
+def target_decoy_statistics(psms: List[Dict], higher_is_better: bool = True, decoy_correction: bool = True,
+                            pep: bool = False, fdr_threshold: float = 0.01) -> Dict:
+    """
+    Copies of the PSMs with "q_value" (and "pep" when requested) added, best score first,
+    and the number of targets accepted at fdr_threshold.
+    """
+    if not 0 < fdr_threshold <= 1:
+        raise ValueError(f"fdr_threshold must be in (0, 1], got {fdr_threshold}")
+    qs = q_values(psms, higher_is_better, decoy_correction)
+    peps = posterior_error_probabilities(psms, higher_is_better) if pep else [None] * len(psms)
+    scored = [{**psm, "is_decoy": bool(psm.get("is_decoy")), "q_value": q, "pep": e}
+              for psm, q, e in zip(psms, qs, peps)]
+    scored = [scored[i] for i in _ranked(psms, higher_is_better)]
+    return {
+        "psms": scored,
+        "targets": sum(1 for p in scored if not p["is_decoy"]),
+        "decoys": sum(1 for p in scored if p["is_decoy"]),
+        "fdr_threshold": fdr_threshold,
+        "accepted": sum(1 for p in scored if not p["is_decoy"] and p["q_value"] <= fdr_threshold),
+    }
@@ -0,0 +1,100 @@
+"""
+MS1 feature detection: isotope envelopes found in each MS1 scan are chained across
+adjacent scans into LC-MS features, which are then linked to the MS2 scans that sampled
+them.
+"""
+
+from typing import Dict, Iterable, List, Optional
+
+from .masses import C13_DELTA
+from .processing import centroid, deisotope, tolerance_to_da
+
+def _link_ms2(features: List[Dict], ms2_scans: List[Dict], tolerance: float, tolerance_unit: str, rt_margin: float):
+    # An MS2 belongs to a feature when its precursor hits one of the isotopes inside the RT range
+    for feature in features:
+        feature["ms2_scans"] = []
+    for scan in ms2_scans:
+        for feature in features:
+            if not feature["rt_start"] - rt_margin <= scan["retention_time"] <= feature["rt_end"] + rt_margin:
+                continue
+            if scan["charge"] and scan["charge"] != feature["charge"]:
+                continue
+            window = tolerance_to_da(feature["mono_mz"], tolerance, tolerance_unit)
+            offsets = [k * C13_DELTA / feature["charge"] for k in range(feature["isotope_count"])]
+            if any(abs(scan["precursor_mz"] - (feature["mono_mz"] + offset)) <= window for offset in offsets):
+                feature["ms2_scans"].append(scan["scan_nr"])
+
+def find_features(spectra: Iterable[Dict], tolerance: float = 10.0, tolerance_unit: str = "ppm",
+                  max_charge: int = 6, min_isotopes: int = 2, min_scans: int = 3, max_gap: int = 1,
+                  min_intensity: float = 0.0, rt_margin: float = 0.0) -> List[Dict]:
+    """
+    Features of a run given as spectra dicts ("retention_time", "ms_level", "scan_nr",
+    "peaks", optional "spectrum_type" and "precursors"; parse_run output or a reader's
+    iter_spectra). Each MS1 scan is centroided if marked as profile and deisotoped; an
+    envelope extends the open feature of the same charge whose monoisotopic m/z is within
+    tolerance and that was last seen at most max_gap MS1 scans earlier, else it starts a new
+    one. Features seen in at least min_scans scans come back most intense first with
+    "mono_mz" (intensity-weighted), "charge", "rt_start", "rt_end", "apex_rt",
+    "apex_scan", "apex_intensity", "scan_count", "isotope_count" (largest envelope) and
+    "ms2_scans", the MS2 scans whose precursor falls on one of its isotopes within the RT
+    range widened by rt_margin seconds.
+    """
+    open_features: List[Dict] = []
+    features: List[Dict] = []
+    ms2_scans = []
+    ms1_index = -1
+    for spectrum in spectra:
+        rt = spectrum.get("retention_time")
+        if rt is None:
+            continue
+        if spectrum.get("ms_level") not in (1, None):
+            for precursor in spectrum.get("precursors") or []:
+                if precursor.get("mz"):
+                    ms2_scans.append({"scan_nr": spectrum.get("scan_nr"), "retention_time": rt,
+                                      "precursor_mz": precursor["mz"], "charge": precursor.get("charge")})
+                    break
+            continue
+        ms1_index += 1
+        peaks = spectrum.get("peaks") or []
+        if spectrum.get("spectrum_type") == "profile":
+            peaks = centroid(peaks)
+        if min_intensity:
+            peaks = [p for p in peaks if p["intensity"] >= min_intensity]
+        envelopes = deisotope(peaks, tolerance, tolerance_unit, max_charge, min_isotopes, keep_unassigned=False)
+
+        # Close features that can no longer be extended
+        still_open = []
+        for feature in open_features:
+            (still_open if ms1_index - feature["_last"] <= max_gap + 1 else features).append(feature)
+        open_features = still_open
+
+        for envelope in sorted(envelopes, key=lambda e: -e["intensity"]):
+            mono_mz = envelope["original_mz"]
+            window = tolerance_to_da(mono_mz, tolerance, tolerance_unit)
+            match: Optional[Dict] = None
+            for feature in open_features:
+                if (feature["charge"] == envelope["charge"] and feature["_last"] < ms1_index
+                        and abs(feature["mono_mz"] - mono_mz) <= window
+                        and (match is None or abs(feature["mono_mz"] - mono_mz) < abs(match["mono_mz"] - mono_mz))):
+                    match = feature
+            if match is None:
+                match = {"charge": envelope["charge"], "mono_mz": mono_mz, "rt_start": rt, "apex_intensity": -1.0,
+                         "scan_count": 0, "isotope_count": 0, "_weight": 0.0, "_weighted_mz": 0.0}
+                open_features.append(match)
+            match["_weight"] += envelope["intensity"]
+            match["_weighted_mz"] += envelope["intensity"] * mono_mz
+            match["mono_mz"] = match["_weighted_mz"] / match["_weight"]
+            match["rt_end"] = rt
+            match["scan_count"] += 1
+            match["isotope_count"] = max(match["isotope_count"], envelope["isotope_count"])
+            match["_last"] = ms1_index
+            if envelope["intensity"] > match["apex_intensity"]:
+                match.update(apex_intensity=envelope["intensity"], apex_rt=rt, apex_scan=spectrum.get("scan_nr"))
+
+    features = [f for f in features + open_features if f["scan_count"] >= min_scans]
+    for feature in features:
+        for key in ("_last", "_weight", "_weighted_mz"):
+            del feature[key]
+    _link_ms2(features, ms2_scans, tolerance, tolerance_unit, rt_margin)
+    features.sort(key=lambda f: -f["apex_intensity"])
+    return features
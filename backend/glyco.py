@@ -0,0 +1,116 @@
+"""
+Glycopeptide fragments: oxonium ions of the glycan and the peptide + glycan Y-ion ladder,
+from a composition such as "HexNAc(4)Hex(5)NeuAc(2)".
+"""
+
+import re
+from itertools import product
+from typing import Dict, List, Optional
+
+from .masses import PROTON_MASS, molecule_mass
+
+# Monosaccharide residues (in-chain, i.e. minus water)
+MONOSACCHARIDES = {
+    "Hex": {"C": 6, "H": 10, "O": 5},
+    "HexNAc": {"C": 8, "H": 13, "N": 1, "O": 5},
+    "NeuAc": {"C": 11, "H": 17, "N": 1, "O": 8},
+    "NeuGc": {"C": 11, "H": 17, "N": 1, "O": 9},
+    "dHex": {"C": 6, "H": 10, "O": 4},
+    "Pent": {"C": 5, "H": 8, "O": 4},
+    "HexA": {"C": 6, "H": 8, "O": 6},
+}
+MONOSACCHARIDE_ALIASES = {"Fuc": "dHex", "Xyl": "Pent", "Sia": "NeuAc", "GlcNAc": "HexNAc", "GalNAc": "HexNAc",
+                          "Man": "Hex", "Gal": "Hex", "Glc": "Hex", "HexNac": "HexNAc"}
+
+# Oxonium ions: (label, composition, neutral loss); 204.087 HexNAc, 366.140 HexHexNAc,
+# 274.092 NeuAc-H2O, 138.055 the HexNAc ring fragment, ...
+_WATER = {"H": 2, "O": 1}
+OXONIUM_IONS = [
+    ("HexNAc", {"HexNAc": 1}, {}),
+    ("HexNAc-H2O", {"HexNAc": 1}, _WATER),
+    ("HexNAc-2H2O", {"HexNAc": 1}, {"H": 4, "O": 2}),
+    ("HexNAc-C2H6O3", {"HexNAc": 1}, {"C": 2, "H": 6, "O": 3}),
+    ("HexNAc-C2H4O2", {"HexNAc": 1}, {"C": 2, "H": 4, "O": 2}),
+    ("HexNAc-CH6O3", {"HexNAc": 1}, {"C": 1, "H": 6, "O": 3}),
+    ("Hex", {"Hex": 1}, {}),
+    ("HexHexNAc", {"Hex": 1, "HexNAc": 1}, {}),
+    ("Hex2HexNAc", {"Hex": 2, "HexNAc": 1}, {}),
+    ("HexNAc2", {"HexNAc": 2}, {}),
+    ("dHexHexNAc", {"dHex": 1, "HexNAc": 1}, {}),
+    ("NeuAc", {"NeuAc": 1}, {}),
+    ("NeuAc-H2O", {"NeuAc": 1}, _WATER),
+    ("NeuAcHexHexNAc", {"NeuAc": 1, "Hex": 1, "HexNAc": 1}, {}),
+    ("NeuGc", {"NeuGc": 1}, {}),
+    ("NeuGc-H2O", {"NeuGc": 1}, _WATER),
+]
+
+# Y ions are enumerated over every sub-composition up to this many
+MAX_Y_IONS = 2000
+
+_COMPOSITION_TOKEN = re.compile(r'([A-Za-z]+)(?:\((\d+)\))?')
+
+def parse_glycan_composition(glycan: str) -> Dict[str, int]:
+    """
+    {"HexNAc": 4, "Hex": 5, "NeuAc": 2} from "HexNAc(4)Hex(5)NeuAc(2)"; a missing count
+    means 1 and common names (Fuc, Xyl, Man, GlcNAc, ...) map to their generic residue.
+    """
+    composition: Dict[str, int] = {}
+    pos = 0
+    for match in _COMPOSITION_TOKEN.finditer(glycan.replace(" ", "")):
+        if match.start() != pos:
+            break
+        name = MONOSACCHARIDE_ALIASES.get(match.group(1), match.group(1))
+        if name not in MONOSACCHARIDES:
+            raise ValueError(f"Unknown monosaccharide {match.group(1)!r} in glycan {glycan!r}, expected one of {tuple(MONOSACCHARIDES)}")
+        composition[name] = composition.get(name, 0) + int(match.group(2) or 1)
+        pos = match.end()
+    if pos != len(glycan.replace(" ", "")) or not composition:
+        raise ValueError(f"Invalid glycan composition {glycan!r} at position {pos}, expected e.g. 'HexNAc(4)Hex(5)'")
+    return composition
+
+def format_glycan_composition(composition: Dict[str, int]) -> str:
+    return "".join(f"{name}({composition[name]})" for name in MONOSACCHARIDES if composition.get(name))
+
+def glycan_mass(composition: Dict[str, int], mass_type: str = "monoisotopic") -> float:
+    """Mass added to a peptide by a glycan of this composition."""
+    return sum(molecule_mass(MONOSACCHARIDES[name], mass_type) * count for name, count in composition.items())
+
+def oxonium_ions(composition: Optional[Dict[str, int]] = None, proton: float = PROTON_MASS,
+                 mass_type: str = "monoisotopic") -> List[dict]:
+    """
+    Singly charged oxonium ions (series "oxonium", type "oxo-HexNAc", ...), limited to
+    those the glycan composition can produce when one is given.
+    """
+    ions = []
+    for label, residues, loss in OXONIUM_IONS:
+        if composition is not None and any(composition.get(name, 0) < n for name, n in residues.items()):
+            continue
+        mz = glycan_mass(residues, mass_type) - molecule_mass(loss, mass_type) + proton
+        ions.append({"type": f"oxo-{label}", "series": "oxonium", "index": 0, "loss": "", "charge": 1, "mz": mz})
+    return ions
+
+def y_ions(peptide_mh: float, composition: Dict[str, int], max_charge: int, proton: float = PROTON_MASS,
+           mass_type: str = "monoisotopic") -> List[dict]:
+    """
+    Peptide + glycan ions for every sub-composition of the glycan, "Y0" being the bare
+    peptide and "Y" the intact glycopeptide, at charges 1..max_charge. peptide_mh is the
+    [M+H]+ of the peptide without its glycan.
+    """
+    names = [name for name in MONOSACCHARIDES if composition.get(name)]
+    counts = list(product(*(range(composition[name] + 1) for name in names)))
+    if len(counts) > MAX_Y_IONS:
+        raise ValueError(f"Glycan {format_glycan_composition(composition)} has {len(counts)} sub-compositions, over the limit of {MAX_Y_IONS}")
+    ions = []
+    for combination in counts:
+        part = dict(zip(names, combination))
+        if not any(combination):
+            label = "Y0"
+        elif part == {name: composition[name] for name in names}:
+            label = "Y"
+        else:
+            label = "Y+" + format_glycan_composition(part)
+        m = peptide_mh + glycan_mass(part, mass_type)
+        for z in range(1, max_charge + 1):
+            ions.append({"type": label, "series": "Y", "index": sum(combination), "loss": "",
+                         "charge": z, "mz": (m + (z - 1) * proton) / z})
+    return ions
@@ -0,0 +1,65 @@
+"""
+Picks the parser for an identification file from its content, so the PSM list can come
+from Percolator input (.pin) or results, mzIdentML, pepXML, mzTab, MaxQuant's msms.txt or a
+DIA-NN report alike.
+"""
+
+import re
+from pathlib import Path
+from typing import Dict, List, Union
+
+from .diann import DIANN_COLUMNS, parse_diann
+from .maxquant import MSMS_COLUMNS, parse_msms
+from .mzid import parse_mzid
+from .mztab import parse_mztab
+from .pepxml import parse_pepxml
+from .percolator import PERCOLATOR_COLUMNS, parse_percolator
+from .pin_parser import parse_pin
+from .readers import SNIFF_SIZE, sniff_head
+
+def sniff_identifications(data: bytes) -> str:
+    """
+    "mzid", "pepxml", "mztab", "msms", "percolator", "diann" or "pin", judged from the root
+    element or the header line.
+    """
+    head = sniff_head(data)
+    if b'<MzIdentML' in head:
+        return "mzid"
+    if b'<msms_pipeline_analysis' in head:
+        return "pepxml"
+    if re.match(rb'(COM[^\n]*\n)*MTD\t', head):
+        return "mztab"
+    header = head.split(b'\n', 1)[0]
+    columns = header.decode('utf-8', 'replace').lstrip('\ufeff').rstrip('\r').split('\t')
+    for file_format, required in (("msms", MSMS_COLUMNS), ("percolator", PERCOLATOR_COLUMNS),
+                                  ("diann", DIANN_COLUMNS)):
+        if all(column in columns for column in required):
+            return file_format
+    if len(columns) > 1:
+        return "pin"
+    raise ValueError("unrecognised identification file: expected .pin, mzIdentML, pepXML, mzTab, msms.txt, "
+                     "Percolator or DIA-NN output")
+
+def parse_identifications(file_path: Union[str, Path]) -> List[Dict]:
+    """
+    PSMs of an identification file, each with at least "scan_nr", "spec_id", "sequence"
+    and "charge" (see the format's parser for the rest).
+    """
+    with open(file_path, 'rb') as f:
+        head = f.read(SNIFF_SIZE)
+    file_format = sniff_identifications(head)
+    if file_format == "mzid":
+        return parse_mzid(file_path)
+    if file_format == "pepxml":
+        return parse_pepxml(file_path)
+    if file_format == "mztab":
+        # Peptide-level results when the file has no PSM section
+        sections = parse_mztab(file_path)
+        return sections["psms"] or sections["peptides"]
+    if file_format == "msms":
+        return parse_msms(file_path)
+    if file_format == "percolator":
+        return parse_percolator(file_path)
+    if file_format == "diann":
+        return parse_diann(file_path)
+    return parse_pin(str(file_path))
@@ -0,0 +1,115 @@
+"""
+Precursor isolation purity: how much of the MS1 signal inside an MS2 isolation window
+belongs to the selected precursor's isotope envelope, the rest being co-isolated ions;
+and the precursor charge implied by that envelope.
+"""
+
+import bisect
+import math
+from typing import Dict, List, Optional
+
+from .composition import averagine_distribution
+from .masses import C13_DELTA, PROTON_MASS
+from .processing import tolerance_to_da
+
+# Charges tried when the precursor charge is unknown
+MAX_PURITY_CHARGE = 6
+
+def _envelope(mzs: List[float], peaks: List[dict], precursor_mz: float, charge: int, low: float, high: float,
+              tolerance: float, tolerance_unit: str) -> List[dict]:
+    # Isotope peaks of the precursor inside [low, high], both below and above the selected one
+    isotopes = []
+    for direction in (-1, 1):
+        k = 0 if direction == 1 else -1
+        while low <= precursor_mz + k * C13_DELTA / charge <= high:
+            target = precursor_mz + k * C13_DELTA / charge
+            window = tolerance_to_da(target, tolerance, tolerance_unit)
+            i = bisect.bisect_left(mzs, target - window)
+            best = None
+            while i < len(mzs) and mzs[i] <= target + window:
+                if best is None or peaks[i]["intensity"] > peaks[best]["intensity"]:
+                    best = i
+                i += 1
+            if best is None:
+                # The envelope is only followed while it is unbroken
+                break
+            isotopes.append({"isotope": k, "mz": peaks[best]["mz"], "intensity": peaks[best]["intensity"]})
+            k += direction
+    return sorted(isotopes, key=lambda p: p["isotope"])
+
+def isolation_purity(ms1_peaks: List[dict], precursor_mz: float, charge: Optional[int],
+                     lower_offset: float, upper_offset: float, tolerance: float = 10.0,
+                     tolerance_unit: str = "ppm") -> Dict:
+    """
+    Fraction of the MS1 intensity in [precursor_mz - lower_offset, precursor_mz +
+    upper_offset] explained by the precursor's isotope peaks at C13_DELTA / charge spacing
+    (the most intense peak within tolerance of each). An unknown charge is taken as the
+    one giving the highest purity, up to MAX_PURITY_CHARGE. purity is None for an empty
+    window.
+    """
+    if lower_offset < 0 or upper_offset < 0:
+        raise ValueError(f"Isolation offsets must be non-negative, got {lower_offset} and {upper_offset}")
+    low, high = precursor_mz - lower_offset, precursor_mz + upper_offset
+    peaks = sorted((p for p in ms1_peaks if low <= p["mz"] <= high), key=lambda p: p["mz"])
+    mzs = [p["mz"] for p in peaks]
+    total = sum(p["intensity"] for p in peaks)
+    best = None
+    for z in ([charge] if charge else range(1, MAX_PURITY_CHARGE + 1)):
+        isotopes = _envelope(mzs, peaks, precursor_mz, z, low, high, tolerance, tolerance_unit)
+        explained = sum(p["intensity"] for p in isotopes)
+        if best is None or explained > best["precursor_intensity"]:
+            best = {"charge": z, "isotopes": isotopes, "precursor_intensity": explained}
+    return {
+        "precursor_mz": precursor_mz,
+        "charge": best["charge"],
+        "window": [low, high],
+        "purity": best["precursor_intensity"] / total if total else None,
+        "precursor_intensity": best["precursor_intensity"],
+        "window_intensity": total,
+        "isotopes": best["isotopes"],
+        "interfering_peaks": len(peaks) - len(best["isotopes"]),
+    }
+
+# Isotopes searched on either side of the precursor when determining its charge
+CHARGE_ISOTOPES_BELOW = 3
+CHARGE_ISOTOPES_ABOVE = 5
+
+def _shape_similarity(isotopes: List[dict], charge: int) -> float:
+    # Cosine between the observed envelope and averagine, the lowest isotope found taken as monoisotopic
+    mono_mz = isotopes[0]["mz"]
+    expected = averagine_distribution((mono_mz - PROTON_MASS) * charge, 0, len(isotopes))
+    abundance = {peak["isotope"]: peak["abundance"] for peak in expected}
+    observed = [p["intensity"] for p in isotopes]
+    model = [abundance.get(p["isotope"] - isotopes[0]["isotope"], 0.0) for p in isotopes]
+    norm = math.sqrt(sum(o * o for o in observed)) * math.sqrt(sum(m * m for m in model))
+    return sum(o * m for o, m in zip(observed, model)) / norm if norm else 0.0
+
+def determine_charge(peaks: List[dict], precursor_mz: float, max_charge: int = MAX_PURITY_CHARGE,
+                     tolerance: float = 10.0, tolerance_unit: str = "ppm", mz_window: float = 0.05) -> Dict:
+    """
+    Precursor charge from the isotope envelope around an approximate precursor_mz (snapped
+    to the most intense peak within mz_window Da). Each charge up to max_charge is scored
+    as the number of isotope peaks found at C13_DELTA / z spacing times the cosine
+    similarity of their intensities to an averagine envelope, so a z=2 envelope is not
+    mistaken for z=1 through its every other peak. "charge" is None when no charge finds
+    a second isotope; "candidates" lists every charge, best first.
+    """
+    if max_charge < 1:
+        raise ValueError(f"max_charge must be >= 1, got {max_charge}")
+    low, high = precursor_mz - mz_window, precursor_mz + mz_window
+    near = [p for p in peaks if low <= p["mz"] <= high]
+    if not near:
+        return {"charge": None, "precursor_mz": precursor_mz, "candidates": []}
+    selected = max(near, key=lambda p: p["intensity"])["mz"]
+    low, high = selected - CHARGE_ISOTOPES_BELOW * C13_DELTA - 0.5, selected + CHARGE_ISOTOPES_ABOVE * C13_DELTA + 0.5
+    ordered = sorted((p for p in peaks if low <= p["mz"] <= high), key=lambda p: p["mz"])
+    mzs = [p["mz"] for p in ordered]
+    candidates = []
+    for z in range(1, max_charge + 1):
+        isotopes = _envelope(mzs, ordered, selected, z, low, high, tolerance, tolerance_unit)
+        shape = _shape_similarity(isotopes, z) if len(isotopes) >= 2 else 0.0
+        candidates.append({"charge": z, "isotopes": len(isotopes), "shape_similarity": shape,
+                           "score": len(isotopes) * shape if len(isotopes) >= 2 else 0.0})
+    candidates.sort(key=lambda c: (-c["score"], c["charge"]))
+    best = candidates[0]
+    return {"charge": best["charge"] if best["score"] > 0 else None, "precursor_mz": selected, "candidates": candidates}
@@ -0,0 +1,245 @@
+"""
+Spectral libraries: NIST MSP and SpectraST sptxt reading (mzSpecLib in mzspeclib.py), and matching experimental
+spectra against the library entries by precursor m/z and spectrum similarity.
+"""
+
+import bisect
+import gzip
+import re
+from pathlib import Path
+from typing import Dict, Iterable, Iterator, List, Optional, Union
+
+from .decoys import format_peptide
+from .deltamass import UNIMOD_DELTAS
+from .masses import AA_MASS, HYDROGEN_MASS
+from .mzid import optional_float
+from .mzml import is_gzip
+from .mzspeclib import is_mzspeclib, parse_mzspeclib
+from .processing import normalize, tolerance_to_da
+from .progress import CancelToken, ProgressCallback, report
+from .similarity import entropy_similarity, spectrum_similarity
+
+LIBRARY_SCORES = ("cosine", "entropy")
+# NIST Mods= names that differ from UNIMOD_DELTAS
+NIST_MODIFICATIONS = {"CAM": "Carbamidomethyl", "Deamidation": "Deamidated", "Pyro-glu": "Gln->pyro-Glu",
+                      "Pyro-carbamidomethyl": "Carbamidomethyl"}
+# Modifications NIST writes on residue 0 that belong to the N-terminus
+N_TERMINAL_MODIFICATIONS = ("Acetyl", "TMT6plex", "TMTpro", "iTRAQ4plex", "Formyl", "Carbamyl")
+# sptxt "n[43]PEPTIDE" gives the N-terminus plus a hydrogen
+BRACKETED = re.compile(r'([A-Zn])\[(\d+(?:\.\d+)?)\]')
+
+def _nominal_delta(nominal: int) -> Optional[float]:
+    # The first UNIMOD_DELTAS entry with this nominal mass, e.g. 16 -> Oxidation, 80 -> Phospho
+    for mass in UNIMOD_DELTAS.values():
+        if mass and round(mass) == nominal:
+            return mass
+    return None
+
+def parse_sptxt_sequence(name: str) -> str:
+    """
+    SpectraST sequences with residue masses in brackets ("AC[160]M[147]K", "n[43]PEPTIDE")
+    as [mass] deltas. Integer masses are resolved to the UNIMOD_DELTAS entry of that
+    nominal delta; masses with decimals are used exactly.
+    """
+    tokens: List[List] = []
+    n_term = 0.0
+    position = 0
+    for match in BRACKETED.finditer(name):
+        tokens.extend([r, 0.0] for r in name[position:match.start()] if r.isalpha() and r.isupper())
+        position = match.end()
+        residue, text = match.group(1), match.group(2)
+        base = HYDROGEN_MASS if residue == 'n' else AA_MASS.get(residue)
+        if base is None:
+            raise ValueError(f"Unknown residue {residue!r} in library sequence {name!r}")
+        delta = float(text) - base
+        if '.' not in text:
+            delta = _nominal_delta(round(float(text) - round(base))) or delta
+        if residue == 'n':
+            n_term += delta
+        else:
+            tokens.append([residue, delta])
+    tokens.extend([r, 0.0] for r in name[position:] if r.isalpha() and r.isupper())
+    return format_peptide([tuple(t) for t in tokens], n_term, 0.0)
+
+def _nist_sequence(sequence: str, mods: str) -> str:
+    # Mods=2/0,A,Acetyl/4,M,Oxidation (0-based positions)
+    tokens = [[residue, 0.0] for residue in sequence]
+    n_term = 0.0
+    parts = mods.split('/')
+    for part in parts[1:]:
+        fields = part.split(',')
+        if len(fields) < 3:
+            raise ValueError(f"Malformed Mods entry {part!r}")
+        position, name = int(fields[0]), NIST_MODIFICATIONS.get(fields[2], fields[2])
+        if name not in UNIMOD_DELTAS:
+            raise ValueError(f"Unknown library modification {fields[2]!r}")
+        if position < 0 or (position == 0 and name in N_TERMINAL_MODIFICATIONS):
+            n_term += UNIMOD_DELTAS[name]
+        elif position < len(tokens):
+            tokens[position][1] += UNIMOD_DELTAS[name]
+        else:
+            raise ValueError(f"Mods position {position} beyond {sequence}")
+    return format_peptide([tuple(t) for t in tokens], n_term, 0.0)
+
+def _comment_fields(comment: str) -> Dict[str, str]:
+    # Comment: Spec=Consensus Mods=0 Parent=321.189 "Protein=sp|P1|X (quoted)"
+    fields = {}
+    for token in re.findall(r'"[^"]*"|\S+', comment):
+        token = token.strip('"')
+        if '=' in token:
+            key, value = token.split('=', 1)
+            fields[key] = value
+    return fields
+
+def _finish_entry(header: Dict[str, str], peaks: List[dict], line_nr: int, index: int) -> Dict:
+    name = header.get("Name", "")
+    comment = _comment_fields(header.get("Comment", ""))
+    peptide, _, charge = name.partition('/')
+    charge_value = int(re.match(r'\d+', charge).group(0)) if re.match(r'\d+', charge) else None
+    try:
+        if '[' in peptide:
+            sequence = parse_sptxt_sequence(peptide)
+        elif comment.get("Mods", "0") != "0":
+            sequence = _nist_sequence(peptide, comment["Mods"])
+        else:
+            sequence = peptide
+    except ValueError as e:
+        raise ValueError(f"Library entry {name!r} ending on line {line_nr}: {e}") from None
+    precursor = optional_float(header.get("PrecursorMZ")) or optional_float(comment.get("Parent"))
+    return {
+        "index": index,
+        "name": name,
+        "sequence": sequence,
+        "charge": charge_value,
+        "precursor_mz": precursor,
+        "proteins": [p for p in comment.get("Protein", "").split('/') if p and not p.isdigit()],
+        "is_decoy": "DECOY" in header.get("Comment", "") or header.get("Status") == "Decoy",
+        "comment": comment,
+        "peaks": sorted(peaks, key=lambda p: p["mz"]),
+    }
+
+def parse_msp(text: Union[str, Iterable[str]]) -> List[Dict]:
+    """
+    Entries of an MSP or sptxt library (text or an iterable of lines), each with "name"
+    ("PEPTIDE/2"), "sequence" (modifications from NIST Mods= or sptxt bracket masses as
+    [mass] deltas), "charge", "precursor_mz" (PrecursorMZ, else the comment's Parent),
+    "proteins", "is_decoy", "comment" (the Comment key=value fields) and "peaks" by m/z,
+    each {"mz", "intensity", "annotations"} with the annotation strings as written
+    ("y3/0.01", "b5-18^2"; "?" gives none).
+    """
+    lines = text.splitlines() if isinstance(text, str) else text
+    entries = []
+    header: Dict[str, str] = {}
+    peaks: List[dict] = []
+    line_nr = 0
+    for line_nr, line in enumerate(lines, 1):
+        line = line.strip()
+        if not line or line.startswith('#'):
+            continue
+        if line[0].isdigit() or (line[0] == '.' and len(line) > 1):
+            if "Name" not in header:
+                raise ValueError(f"Library line {line_nr}: peak before any Name: line")
+            fields = re.split(r'\s+', line, maxsplit=2)
+            try:
+                mz, intensity = float(fields[0]), float(fields[1])
+            except (IndexError, ValueError):
+                raise ValueError(f"Library line {line_nr}: expected 'm/z intensity [annotation]', got {line!r}") from None
+            # A quoted annotation may hold spaces, a bare one ends at the first (sptxt adds more columns)
+            rest = fields[2] if len(fields) > 2 else ''
+            annotation = rest[1:].split('"', 1)[0] if rest.startswith('"') else (rest.split() or [''])[0]
+            annotations = [a for a in annotation.split(',') if a and a != '?']
+            peaks.append({"mz": mz, "intensity": intensity, "annotations": annotations})
+            continue
+        key, sep, value = line.partition(':')
+        if not sep:
+            raise ValueError(f"Library line {line_nr}: expected 'Key: value', got {line!r}")
+        if key.strip() == "Name":
+            if header:
+                entries.append(_finish_entry(header, peaks, line_nr - 1, len(entries)))
+            header, peaks = {}, []
+        header[key.strip()] = value.strip()
+    if header:
+        entries.append(_finish_entry(header, peaks, line_nr, len(entries)))
+    return entries
+
+class SpectralLibrary:
+    """Entries of one library file, in file order, with precursor m/z lookup."""
+
+    def __init__(self, entries: List[Dict], file_path: Optional[Path] = None):
+        self.entries = entries
+        self.file_path = file_path
+        self._by_precursor = sorted((e for e in entries if e["precursor_mz"] is not None),
+                                    key=lambda e: e["precursor_mz"])
+        self._precursor_mzs = [e["precursor_mz"] for e in self._by_precursor]
+
+    @classmethod
+    def from_file(cls, path: Union[str, Path]) -> "SpectralLibrary":
+        """Reads an MSP, sptxt or mzSpecLib text library, optionally gzipped."""
+        path = Path(path)
+        data = path.read_bytes()
+        if is_gzip(data):
+            data = gzip.decompress(data)
+        text = data.decode("utf-8", errors="replace")
+        return cls(parse_mzspeclib(text) if is_mzspeclib(text) else parse_msp(text), path)
+
+    def __len__(self) -> int:
+        return len(self.entries)
+
+    def __iter__(self) -> Iterator[Dict]:
+        return iter(self.entries)
+
+    def within(self, low: float, high: float) -> List[Dict]:
+        """Entries with a precursor m/z in [low, high]."""
+        return self._by_precursor[bisect.bisect_left(self._precursor_mzs, low):
+                                  bisect.bisect_right(self._precursor_mzs, high)]
+
+def match_against_library(peaks: List[dict], library: Union[SpectralLibrary, List[Dict]],
+                          precursor_mz: Optional[float] = None, charge: Optional[int] = None,
+                          precursor_tolerance: float = 20.0, precursor_tolerance_unit: str = "ppm",
+                          tolerance: float = 0.02, tolerance_unit: str = "da", score: str = "cosine",
+                          max_results: int = 5, progress: Optional[ProgressCallback] = None,
+                          cancel: Optional[CancelToken] = None) -> List[Dict]:
+    """
+    Best library entries for an experimental spectrum, best first. With a precursor_mz only
+    entries within precursor_tolerance (and of the given charge, if any) are scored;
+    without one the whole library is. Both spectra are square-root scaled before the
+    cosine (spectrum_similarity); score picks it or the spectral "entropy" similarity for
+    ranking, and both values are reported with "matched_peaks" and the entry's "index",
+    "name", "sequence", "charge", "precursor_mz" and "precursor_error_ppm". progress gets the
+    share of candidates scored, and cancel is checked after each (see progress.report).
+    """
+    if score not in LIBRARY_SCORES:
+        raise ValueError(f"Unknown library score {score!r}, expected one of {LIBRARY_SCORES}")
+    if not isinstance(library, SpectralLibrary):
+        library = SpectralLibrary(library)
+    if precursor_mz is not None:
+        window = tolerance_to_da(precursor_mz, precursor_tolerance, precursor_tolerance_unit)
+        candidates = library.within(precursor_mz - window, precursor_mz + window)
+    else:
+        candidates = library.entries
+    if charge:
+        candidates = [e for e in candidates if e["charge"] in (None, charge)]
+
+    query = normalize(peaks, "sqrt")
+    results = []
+    for position, entry in enumerate(candidates, 1):
+        similarity = spectrum_similarity(query, normalize(entry["peaks"], "sqrt"), tolerance, tolerance_unit)
+        results.append({
+            "index": entry["index"],
+            "name": entry["name"],
+            "sequence": entry["sequence"],
+            "charge": entry["charge"],
+            "precursor_mz": entry["precursor_mz"],
+            "precursor_error_ppm": ((precursor_mz - entry["precursor_mz"]) / entry["precursor_mz"] * 1e6
+                                    if precursor_mz is not None and entry["precursor_mz"] else None),
+            "cosine": similarity["cosine"],
+            "entropy": entropy_similarity(peaks, entry["peaks"], tolerance, tolerance_unit),
+            "matched_peaks": similarity["matched_peaks"],
+        })
+        report(position, len(candidates), None, progress, cancel)
+    results.sort(key=lambda r: -r[score])
+    results = results[:max_results]
+    for rank, result in enumerate(results, 1):
+        result["rank"] = rank
+        result["score"] = result[score]
+    return results
@@ -0,0 +1,103 @@
+"""
+Modification site localization in the style of phosphoRS: every placement of an ambiguous
+modification is scored on its site-determining ions, the fragments whose m/z depends on
+where the modification sits.
+"""
+
+import math
+from itertools import combinations
+from typing import Dict, List, Optional
+
+from .calculations import calculate_ions, get_residue_masses, match_ions, tokenize_peptide
+from .decoys import format_peptide
+from .processing import tolerance_to_da
+from .settings import FragmentationSettings, MatchSettings
+
+# Modified residues within this of modification_mass count as carrying it
+SITE_MASS_TOLERANCE = 0.01
+MAX_ISOFORMS = 1000
+
+def _ion_key(ion: Dict) -> tuple:
+    return ion["type"], ion["charge"], round(ion["mz"], 4)
+
+def _binomial_tail(n: int, k: int, p: float) -> float:
+    """P(X >= k) for X ~ Binomial(n, p)."""
+    return sum(math.comb(n, j) * p ** j * (1 - p) ** (n - j) for j in range(k, n + 1))
+
+def match_probability(peaks: List[dict], settings: MatchSettings) -> float:
+    """
+    Chance that a random m/z falls within tolerance of some peak: the peaks' total window
+    width over the m/z range they span.
+    """
+    if len(peaks) < 2:
+        return 1.0
+    mzs = [p["mz"] for p in peaks]
+    low, high = min(mzs), max(mzs)
+    if high <= low:
+        return 1.0
+    tolerance = settings.tolerance
+    half_width = max(abs(t) for t in tolerance) if isinstance(tolerance, (tuple, list)) else tolerance
+    width = 2 * tolerance_to_da((low + high) / 2, half_width, settings.tolerance_unit)
+    return min(1.0, len(peaks) * width / (high - low))
+
+def localize_modification(peaks: List[dict], sequence: str, modification_mass: float = 79.966331,
+                          residues: str = "STY", settings: Optional[FragmentationSettings] = None,
+                          match_settings: Optional[MatchSettings] = None) -> Dict:
+    """
+    Site probabilities for the modification_mass instances in sequence (e.g.
+    "PEPT[+79.966]IDESK"). They are moved over every combination of the allowed residues;
+    each such isoform is scored on its site-determining ions, those not shared by all
+    isoforms, as -10 log10 of the binomial chance of matching at least as many of them at
+    random (see match_probability). Isoform probabilities are proportional to 1 / that
+    chance, and a site's probability is the sum over the isoforms modifying it.
+    """
+    settings = (settings or FragmentationSettings()).validate_options()
+    match_settings = (match_settings or MatchSettings()).validate_options()
+    tokens, n_term, c_term = tokenize_peptide(sequence, get_residue_masses(settings.residue_masses, settings.mass_type))
+    carrying = [k for k, (_, mod) in enumerate(tokens) if mod and abs(mod - modification_mass) <= SITE_MASS_TOLERANCE]
+    if not carrying:
+        raise ValueError(f"Sequence {sequence!r} carries no modification of mass {modification_mass}")
+    # Other modifications stay where they are
+    base = [(r, 0.0 if k in carrying else mod) for k, (r, mod) in enumerate(tokens)]
+    candidates = sorted(set(carrying) | {k for k, (r, mod) in enumerate(base) if r in residues and not mod})
+    placements = list(combinations(candidates, len(carrying)))
+    if len(placements) > MAX_ISOFORMS:
+        raise ValueError(f"{len(placements)} isoforms of {sequence!r} exceed the limit of {MAX_ISOFORMS}")
+
+    isoforms = []
+    for sites in placements:
+        placed = [(r, modification_mass if k in sites else mod) for k, (r, mod) in enumerate(base)]
+        isoform = format_peptide(placed, n_term, c_term)
+        ions = calculate_ions(isoform, settings)
+        isoforms.append({"sequence": isoform, "sites": [k + 1 for k in sites], "ions": ions})
+
+    shared = set.intersection(*({_ion_key(ion) for ion in iso["ions"]} for iso in isoforms))
+    p = match_probability(peaks, match_settings)
+    for iso in isoforms:
+        determining = [ion for ion in iso.pop("ions") if _ion_key(ion) not in shared]
+        matches = match_ions(peaks, determining, mass_type=settings.mass_type, settings=match_settings,
+                             mzpaf=True) if determining else []
+        matched = len({(m["ion_type"], m["ion_charge"]) for m in matches})
+        chance = max(_binomial_tail(len(determining), matched, p), 1e-300)
+        iso.update(site_determining_ions=len(determining), matched_site_determining_ions=matched,
+                   score=-10 * math.log10(chance), matches=matches)
+
+    # 1 / chance = 10^(score / 10), taken relative to the best to stay in range
+    best = max(iso["score"] for iso in isoforms)
+    weights = [10 ** ((iso["score"] - best) / 10) for iso in isoforms]
+    for iso, weight in zip(isoforms, weights):
+        iso["probability"] = weight / sum(weights)
+    isoforms.sort(key=lambda iso: -iso["probability"])
+    site_probabilities = [
+        {"position": k + 1, "residue": base[k][0],
+         "probability": sum(iso["probability"] for iso in isoforms if k + 1 in iso["sites"])}
+        for k in candidates
+    ]
+    return {
+        "sequence": sequence,
+        "modification_mass": modification_mass,
+        "modifications": len(carrying),
+        "match_probability": p,
+        "isoforms": isoforms,
+        "site_probabilities": site_probabilities,
+    }
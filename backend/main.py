@@ -1,92 +1,1017 @@
-from fastapi import FastAPI, HTTPException, UploadFile, File, Query
+from fastapi import FastAPI, HTTPException, UploadFile, File, Query, Request
+from fastapi.responses import JSONResponse, PlainTextResponse, Response
 from fastapi.staticfiles import StaticFiles
-from pydantic import BaseModel
-from typing import List, Optional
+from pydantic import BaseModel, Field
+from typing import Dict, List, Optional, Union
+import numpy as np
 import os
 import shutil
+import threading
+from collections import OrderedDict
 from pathlib import Path
 
-from .calculations import calculate_ions, match_ions
-from .mzml import LazyMzmlReader
-from .pin_parser import parse_pin
+from .binary import encode_matches, encode_peaks, encode_run, pack_binary
+from .bundle import export_bundle, load_bundle
+from .calculations import CUSTOM_AA_MASS, calculate_ions, calculate_ions_batch, fragment_coverage, precursor_mz, set_residue_masses
+from .chimeric import annotate_chimeric
+from .composition import peptide_isotope_distribution
+from .masses import AA_MASS
+from .crosslinks import annotate_crosslink
+from .decoys import generate_decoys
+from .deltamass import delta_mass_histogram
+from .export import export_matches_tsv, export_peaks_tsv
+from .fasta import FastaDatabase
+from .fdr import target_decoy_statistics
+from .features import find_features
+from .identifications import parse_identifications
+from .isolation import determine_charge, isolation_purity
+from .library import SpectralLibrary, match_against_library
+from .localization import localize_modification
+from .mgf import write_mgf
+from .mzspeclib import write_mzspeclib
+from .models import AlignmentResponse, AnalysisBundle, AnnotatedSpectrumResponse, BatchIonsResponse, ChargeDeterminationResponse, ChimericResponse, ChromatogramListResponse, ConsensusResponse, CrosslinkResponse, DecoyResponse, DeltaMassResponse, DigestResponse, FdrResponse, FeatureListResponse, IonsResponse, IsolationPurityResponse, IsotopeDistributionResponse, JobStatus, LibraryEntry, LibraryMatchResponse, LocalizationResponse, MassErrorStatistics, PeptideMappingResponse, PeptideProperties, ProteinCoverageResponse, ProteinEntry, ReporterIonsResponse, RtPredictionResponse, SearchResponse, SimilarityResponse, SpectrumListResponse, SpectrumQualityResponse, SpectrumResponse, TopDownResponse, UsiComponents, XicResponse
+from .mzml import SCAN_IN_ID, SCAN_METADATA_FIELDS, LazyMzmlReader, MzmlFeedParser, write_mzml
+from .processing import PROFILE_STEPS, bin_peaks
+from .progress import CancelToken, Job, ProgressCallback, report
+from .properties import calculate_peptide_properties
+from .proteins import digest, map_peptide, protein_coverage
+from .proxi import fetch_usi
+from .qc import TOP_PEAKS, mass_error_statistics, spectrum_quality
+from .readers import open_reader, parse_spectrum_upload
+from .reporters import extract_reporter_ions
+from .retention import rt_deviations
+from .scoring import hyperscore, xcorr
+from .search import search_spectrum
+from .settings import FragmentationSettings, MatchSettings, SearchSettings, SvgOptions
+from .similarity import align_spectra, consensus_spectrum, entropy_similarity, modified_cosine, spectrum_similarity
+from .spectrum import Spectrum
+from .svg import render_annotated_svg
+from .topdown import TOP_DOWN_SERIES, annotate_top_down
+from .usi import build_usi, parse_usi
+from .xic import extract_ion_chromatogram
 
 app = FastAPI()
 
 # Global state
 ACTIVE_READER: Optional[LazyMzmlReader] = None
+ACTIVE_FASTA: Optional[FastaDatabase] = None
+ACTIVE_LIBRARY: Optional[SpectralLibrary] = None
+
+# Recently viewed spectra, so re-annotation (e.g. a tolerance change) skips the file
+SPECTRUM_CACHE: "OrderedDict[int, Spectrum]" = OrderedDict()
+SPECTRUM_CACHE_SIZE = 32
+# load_scan also runs in background jobs
+SPECTRUM_CACHE_LOCK = threading.Lock()
+
+# Background jobs by id; finished ones beyond the newest MAX_FINISHED_JOBS are dropped
+JOBS: "OrderedDict[str, Job]" = OrderedDict()
+MAX_FINISHED_JOBS = 20
+
+# How many scans back to look for the MS1 of an MS2 without a usable spectrumRef
+MS1_SEARCH_LIMIT = 200
+
+# Library code raises ValueError for malformed input (sequences, settings, peaks, XML);
+# the message says what failed and where, so pass it on as a client error.
+@app.exception_handler(ValueError)
+async def value_error_handler(request: Request, exc: ValueError):
+    return JSONResponse(status_code=400, content={"detail": str(exc)})
+
+@app.exception_handler(Exception)
+async def unexpected_error_handler(request: Request, exc: Exception):
+    return JSONResponse(status_code=500, content={"detail": f"{type(exc).__name__}: {exc}"})
 
 # Input model for local loading
 class LocalLoadRequest(BaseModel):
     mzml_path: str
+    # Identifications: a .pin file, mzIdentML, pepXML, mzTab, MaxQuant msms.txt, Percolator
+    # results or a DIA-NN report (see identifications.sniff_identifications)
     pin_path: str
 
-class IonMatch(BaseModel):
-    peak_mz: float
-    peak_intensity: float
-    ion_type: str
-    ion_charge: int
-    theoretical_mz: float
-    error: float
+class FastaLoadRequest(BaseModel):
+    fasta_path: str
+
+class LibraryLoadRequest(BaseModel):
+    # MSP, SpectraST sptxt or mzSpecLib text, optionally gzipped
+    library_path: str
+
+class ResidueMassRequest(BaseModel):
+    masses: Dict[str, float]
+    replace: bool = False
+
+class IonsRequest(BaseModel):
+    sequence: str
+    settings: FragmentationSettings = Field(default_factory=FragmentationSettings)
+
+class BatchPeptide(BaseModel):
+    sequence: str
+    charge: Optional[int] = None
+
+class BatchIonsRequest(BaseModel):
+    peptides: List[Union[str, BatchPeptide]]
+    settings: FragmentationSettings = Field(default_factory=FragmentationSettings)
+
+class AnnotateRequest(IonsRequest, MatchSettings):
+    # Matching options (tolerance, tolerance_unit, strategy, assignment, tolerance_rules) come from MatchSettings
+    include_peaks: bool = True
+    peak_format: str = "objects"
+    mobility_min: Optional[float] = None
+    mobility_max: Optional[float] = None
+    # Peak list processing before matching, e.g. [{"step": "deisotope", "max_charge": 3}]
+    processing: List[Dict] = []
+    # Centroid before matching: None does so for spectra the file marks as profile data
+    centroid: Optional[bool] = None
+    # Hyperscore and XCorr of the annotation; left null unless asked for
+    scores: bool = False
+
+class TableExportRequest(AnnotateRequest):
+    table: str = "matches"            # matches, or peaks for every peak with its annotations
+    columns: Optional[List[str]] = None  # default export.MATCH_COLUMNS / PEAK_COLUMNS
+    decimals: Union[int, Dict[str, int], None] = 4
+    delimiter: str = "tab"            # tab, comma, semicolon or a single character
+
+class SvgExportRequest(AnnotateRequest):
+    options: SvgOptions = Field(default_factory=SvgOptions)
+
+class LocalizationRequest(IonsRequest, MatchSettings):
+    # sequence carries the modification(s) on any candidate site, e.g. "PEPT[+79.966]IDESK"
+    modification_mass: float = 79.966331
+    residues: str = "STY"
+    processing: List[Dict] = []
+    centroid: Optional[bool] = None
+
+class ChimericCandidate(BaseModel):
+    sequence: str
+    # Defaults to settings.precursor_charge
+    charge: Optional[int] = None
+
+class ChimericRequest(MatchSettings):
+    peptides: List[ChimericCandidate]
+    settings: FragmentationSettings = Field(default_factory=FragmentationSettings)
+    processing: List[Dict] = []
+    centroid: Optional[bool] = None
+
+class TopDownRequest(BaseModel):
+    sequence: str
+    # c and z-dot unless the request sets series
+    settings: FragmentationSettings = Field(default_factory=lambda: FragmentationSettings(series=TOP_DOWN_SERIES))
+    tolerance: float = 10.0
+    tolerance_unit: str = "ppm"
+    max_charge: int = 30
+    max_isotope_error: int = 2
+    processing: List[Dict] = []
+    centroid: Optional[bool] = None
+
+class CrosslinkRequest(MatchSettings):
+    alpha: str
+    beta: str
+    # 1-based linked residues
+    alpha_site: int
+    beta_site: int
+    # A known crosslinker, or linker_mass (and stub_masses for cleavable ones) directly
+    crosslinker: Optional[str] = "DSS"
+    linker_mass: Optional[float] = None
+    stub_masses: Dict[str, float] = {}
+    settings: FragmentationSettings = Field(default_factory=FragmentationSettings)
+    processing: List[Dict] = []
+    centroid: Optional[bool] = None
+
+class SimilarityRequest(BaseModel):
+    peaks_a: List[Dict[str, float]]
+    peaks_b: List[Dict[str, float]]
+    tolerance: float = 0.02
+    tolerance_unit: str = "da"
+    # Both set: also score the modified cosine, pairing peaks offset by the mass difference
+    precursor_mass_a: Optional[float] = None
+    precursor_mass_b: Optional[float] = None
+
+class ConsensusRequest(BaseModel):
+    # Scans of the loaded file and/or peak lists sent directly
+    scan_nrs: List[int] = []
+    spectra: List[List[Dict[str, float]]] = []
+    tolerance: float = 0.02
+    tolerance_unit: str = "da"
+    min_fraction: float = 0.5
+    normalization: Optional[str] = "base_peak"
+
+class ProteinCoverageRequest(BaseModel):
+    # A protein sequence, or the accession of one in the loaded FASTA
+    protein: Optional[str] = None
+    accession: Optional[str] = None
+    peptides: List[str]
+
+class DigestRequest(BaseModel):
+    # A protein sequence, or the accession of one in the loaded FASTA
+    protein: Optional[str] = None
+    accession: Optional[str] = None
+    enzyme: str = "trypsin"
+    missed_cleavages: int = 2
+    semi_specific: bool = False
+    min_length: int = 7
+    max_length: int = 50
+    min_mass: Optional[float] = None
+    max_mass: Optional[float] = None
+    # Only peptides covering this 1-based residue position (a clicked protein region)
+    position: Optional[int] = None
+
+class DecoyRequest(BaseModel):
+    peptides: List[str]
+    method: str = "pseudo_reverse"
+    seed: int = 0
+
+class DeltaMassRequest(BaseModel):
+    # {"peptide", "precursor_mass"} or {"peptide", "precursor_mz", "charge"}; "sequence" also works
+    psms: List[Dict]
+    bin_width: float = 0.01
+    min_delta: float = -200.0
+    max_delta: float = 500.0
+    min_count: int = 3
+    peak_window: int = 5
+    annotation_tolerance: Optional[float] = None
+
+class FdrRequest(BaseModel):
+    # {"score", "is_decoy", ...}; other fields are returned unchanged
+    psms: List[Dict]
+    higher_is_better: bool = True
+    decoy_correction: bool = True
+    pep: bool = False
+    fdr_threshold: float = 0.01
+
+class RtPredictionRequest(BaseModel):
+    # {"sequence", "retention_time"}; without slope/intercept the PSMs calibrate the model
+    psms: List[Dict]
+    slope: Optional[float] = None
+    intercept: Optional[float] = None
+    max_deviation: Optional[float] = None
+
+class QcPsm(BaseModel):
+    scan_nr: int
+    sequence: str
+    charge: int
+
+class MassErrorRequest(MatchSettings):
+    # Matched with the MatchSettings fields; settings.precursor_charge is replaced by each PSM's charge
+    psms: List[QcPsm]
+    settings: FragmentationSettings = Field(default_factory=FragmentationSettings)
+    unit: str = "ppm"
+    bin_width: Optional[float] = None
+    max_abs_error: Optional[float] = None
+
+class SearchRequest(BaseModel):
+    candidates: List[str]
+    # precursor_mz defaults to the scan's recorded precursor
+    settings: SearchSettings = Field(default_factory=SearchSettings)
+    processing: List[Dict] = []
+    centroid: Optional[bool] = None
 
 @app.post("/api/load_local")
 async def load_local_files(request: LocalLoadRequest):
     global ACTIVE_READER
     
-    try:
-        # Validate paths
-        if not os.path.exists(request.mzml_path):
-            raise HTTPException(status_code=400, detail=f"mzML file not found: {request.mzml_path}")
-        if not os.path.exists(request.pin_path):
-            raise HTTPException(status_code=400, detail=f"PIN file not found: {request.pin_path}")
-            
-        # Parse PIN (from local path)
-        peptides = parse_pin(request.pin_path)
+    # Validate paths
+    if not os.path.exists(request.mzml_path):
+        raise HTTPException(status_code=400, detail=f"mzML file not found: {request.mzml_path}")
+    if not os.path.exists(request.pin_path):
+        raise HTTPException(status_code=400, detail=f"Identification file not found: {request.pin_path}")
         
-        # Initialize Reader (indexes the file from local path)
-        ACTIVE_READER = LazyMzmlReader(request.mzml_path)
+    # Parse the identifications (.pin or mzIdentML, from local path)
+    peptides = parse_identifications(request.pin_path)
+    
+    # Initialize Reader (indexes the file from local path; mzML or mzXML)
+    ACTIVE_READER = open_reader(request.mzml_path)
+    SPECTRUM_CACHE.clear()
+    
+    return {
+        "status": "success",
+        "peptides": peptides,
+        "message": f"Loaded {len(peptides)} peptides from {os.path.basename(request.pin_path)}"
+    }
+
+@app.post("/api/load_fasta")
+async def load_fasta(request: FastaLoadRequest):
+    global ACTIVE_FASTA
+    if not os.path.exists(request.fasta_path):
+        raise HTTPException(status_code=400, detail=f"FASTA file not found: {request.fasta_path}")
+    ACTIVE_FASTA = FastaDatabase.from_file(request.fasta_path)
+    return {
+        "status": "success",
+        "proteins": len(ACTIVE_FASTA),
+        "message": f"Loaded {len(ACTIVE_FASTA)} proteins from {os.path.basename(request.fasta_path)}"
+    }
+
+def active_fasta() -> FastaDatabase:
+    if ACTIVE_FASTA is None:
+        raise HTTPException(status_code=400, detail="No FASTA file loaded.")
+    return ACTIVE_FASTA
+
+@app.post("/api/load_library")
+async def load_library(request: LibraryLoadRequest):
+    global ACTIVE_LIBRARY
+    if not os.path.exists(request.library_path):
+        raise HTTPException(status_code=400, detail=f"Library file not found: {request.library_path}")
+    ACTIVE_LIBRARY = SpectralLibrary.from_file(request.library_path)
+    return {
+        "status": "success",
+        "entries": len(ACTIVE_LIBRARY),
+        "message": f"Loaded {len(ACTIVE_LIBRARY)} library spectra from {os.path.basename(request.library_path)}"
+    }
+
+def active_library() -> SpectralLibrary:
+    if ACTIVE_LIBRARY is None:
+        raise HTTPException(status_code=400, detail="No spectral library loaded.")
+    return ACTIVE_LIBRARY
+
+@app.get("/api/library/export", response_class=PlainTextResponse)
+async def export_library_mzspeclib():
+    library = active_library()
+    name = library.file_path.stem if library.file_path else "library"
+    return PlainTextResponse(write_mzspeclib(library, name), headers={
+        "Content-Disposition": f'attachment; filename="{name}.mzlb.txt"'
+    })
+
+@app.get("/api/library/{index}", response_model=LibraryEntry)
+async def get_library_entry(index: int):
+    library = active_library()
+    if not 0 <= index < len(library):
+        raise HTTPException(status_code=404, detail=f"Library entry {index} not found.")
+    return library.entries[index]
+
+@app.get("/api/protein/{accession}", response_model=ProteinEntry)
+async def get_protein(accession: str):
+    protein = active_fasta().get(accession)
+    if protein is None:
+        raise HTTPException(status_code=404, detail=f"Protein {accession} not found in FASTA.")
+    return protein
+
+@app.get("/api/residue_masses")
+async def get_residue_masses():
+    return {"builtin": AA_MASS, "custom": CUSTOM_AA_MASS}
+
+@app.post("/api/residue_masses")
+async def update_residue_masses(request: ResidueMassRequest):
+    set_residue_masses(request.masses, replace=request.replace)
+    return {"status": "success", "custom": CUSTOM_AA_MASS}
+
+@app.get("/api/peptide_properties", response_model=PeptideProperties)
+async def get_peptide_properties(sequence: str = Query(..., description="Peptide sequence, modifications allowed")):
+    return calculate_peptide_properties(sequence)
+
+@app.get("/api/isotope_distribution", response_model=IsotopeDistributionResponse)
+async def get_isotope_distribution(
+    sequence: str = Query(..., description="Peptide sequence"),
+    charge: int = Query(1, description="Charge state"),
+    ion_type: Optional[str] = Query(None, description="Fragment such as b5 or y3; omit for the precursor"),
+    method: str = Query("full", description="full (elemental) or averagine"),
+    max_peaks: int = Query(6, description="Number of isotope peaks")
+):
+    return {"peaks": peptide_isotope_distribution(sequence, charge, ion_type, method, max_peaks)}
+
+def load_scan(scan_nr: int) -> Spectrum:
+    global ACTIVE_READER
+    
+    if ACTIVE_READER is None:
+        raise HTTPException(status_code=400, detail="No mzML file loaded.")
+    
+    with SPECTRUM_CACHE_LOCK:
+        if scan_nr in SPECTRUM_CACHE:
+            SPECTRUM_CACHE.move_to_end(scan_nr)
+            return SPECTRUM_CACHE[scan_nr]
         
-        return {
-            "status": "success",
-            "peptides": peptides,
-            "message": f"Loaded {len(peptides)} peptides from {os.path.basename(request.pin_path)}"
-        }
+    # Lazy load spectrum, straight from the decoded arrays; peak dicts are only built if needed
+    details = ACTIVE_READER.get_spectrum_arrays(scan_nr)
+    
+    if details is None:
+        raise HTTPException(status_code=404, detail=f"Scan {scan_nr} not found in mzML.")
+    
+    spectrum = Spectrum(details.pop("mz"), details.pop("intensity"), scan_nr, details, details.pop("ion_mobility"))
+    with SPECTRUM_CACHE_LOCK:
+        SPECTRUM_CACHE[scan_nr] = spectrum
+        if len(SPECTRUM_CACHE) > SPECTRUM_CACHE_SIZE:
+            SPECTRUM_CACHE.popitem(last=False)
+    return spectrum
+
+def find_ms1_scan(scan_nr: int, spectrum: Spectrum) -> int:
+    # The precursor's spectrumRef when it names a scan of this file, else the nearest earlier MS1
+    for precursor in spectrum.metadata.get("precursors") or []:
+        ref = SCAN_IN_ID.search(precursor.get("spectrum_ref") or "")
+        if ref and int(ref.group(1)) in ACTIVE_READER.scan_index:
+            return int(ref.group(1))
+    earlier = sorted((n for n in ACTIVE_READER.scan_index if n < scan_nr), reverse=True)
+    for candidate in earlier[:MS1_SEARCH_LIMIT]:
+        if load_scan(candidate).metadata.get("ms_level") == 1:
+            return candidate
+    raise HTTPException(status_code=404, detail=f"No MS1 scan found before scan {scan_nr}.")
+
+@app.get("/api/spectra", response_model=SpectrumListResponse)
+async def list_spectra():
+    if ACTIVE_READER is None:
+        raise HTTPException(status_code=400, detail="No mzML file loaded.")
+    return {"spectra": ACTIVE_READER.list_spectra()}
+
+def run_spectrum_quality(ms_level: Optional[int], tolerance: float, tolerance_unit: str, top_n: int,
+                         progress: Optional[ProgressCallback] = None, cancel: Optional[CancelToken] = None) -> Dict:
+    if ACTIVE_READER is None:
+        raise HTTPException(status_code=400, detail="No mzML file loaded.")
+    spectra = []
+    for details in ACTIVE_READER.iter_spectra(progress, cancel):
+        if ms_level is not None and details.get("ms_level") != ms_level:
+            continue
+        peaks = details["peaks"]
+        precursor = next((p["mz"] for p in details.get("precursors") or [] if p.get("mz")), None)
+        quality = spectrum_quality([p["mz"] for p in peaks], [p["intensity"] for p in peaks], precursor,
+                                   tolerance, tolerance_unit, top_n)
+        spectra.append({**{key: details.get(key) for key in ("index", "id", "scan_nr", "ms_level", "retention_time")},
+                        **quality})
+    return {"top_n": top_n, "spectra": spectra}
+
+@app.get("/api/spectra/quality", response_model=SpectrumQualityResponse)
+async def get_spectrum_quality(
+    ms_level: Optional[int] = Query(None, description="Only spectra of this MS level"),
+    tolerance: float = Query(20.0, description="Window for the precursor peak"),
+    tolerance_unit: str = Query("ppm", description="da or ppm"),
+    top_n: int = Query(TOP_PEAKS, description="Peaks whose share of the TIC is reported")
+):
+    return run_spectrum_quality(ms_level, tolerance, tolerance_unit, top_n)
+
+@app.get("/api/spectra/binary", response_class=Response)
+async def get_run_binary(ms_level: Optional[int] = Query(None, description="Only spectra of this MS level")):
+    # Every spectrum's peaks in one transferable buffer (see binary.py), indexed by a "spectra" section
+    if ACTIVE_READER is None:
+        raise HTTPException(status_code=400, detail="No mzML file loaded.")
+    spectra = (s for s in ACTIVE_READER.iter_spectra() if ms_level is None or s.get("ms_level") == ms_level)
+    return Response(pack_binary(encode_run(spectra), {"source": ACTIVE_READER.file_path.name}),
+                    media_type="application/octet-stream")
+
+@app.get("/api/chromatograms", response_model=ChromatogramListResponse)
+async def list_chromatograms():
+    if ACTIVE_READER is None:
+        raise HTTPException(status_code=400, detail="No mzML file loaded.")
+    return {"chromatograms": ACTIVE_READER.get_chromatograms()}
+
+@app.get("/api/xic", response_model=XicResponse)
+async def get_xic(
+    mz: float = Query(..., description="Target m/z"),
+    tolerance: float = Query(10.0, description="Extraction window half-width"),
+    tolerance_unit: str = Query("ppm", description="da or ppm"),
+    rt_min: Optional[float] = Query(None, description="Start of the RT range (seconds)"),
+    rt_max: Optional[float] = Query(None, description="End of the RT range (seconds)"),
+    ms_level: Optional[int] = Query(1, description="MS level of the spectra used"),
+    aggregate: str = Query("sum", description="sum or max of the peaks in the window")
+):
+    if ACTIVE_READER is None:
+        raise HTTPException(status_code=400, detail="No mzML file loaded.")
+    return extract_ion_chromatogram(ACTIVE_READER.iter_spectra(), mz, tolerance, tolerance_unit,
+                                    rt_min, rt_max, ms_level, aggregate)
+
+@app.get("/api/features", response_model=FeatureListResponse)
+async def get_features(
+    tolerance: float = Query(10.0, description="m/z tolerance for isotopes and linking scans"),
+    tolerance_unit: str = Query("ppm", description="da or ppm"),
+    max_charge: int = Query(6, description="Highest charge tried for isotope envelopes"),
+    min_isotopes: int = Query(2, description="Isotope peaks needed per envelope"),
+    min_scans: int = Query(3, description="MS1 scans a feature must span"),
+    max_gap: int = Query(1, description="MS1 scans a feature may skip"),
+    min_intensity: float = Query(0.0, description="Ignore MS1 peaks below this"),
+    rt_margin: float = Query(0.0, description="Seconds around a feature within which MS2 scans are linked")
+):
+    if ACTIVE_READER is None:
+        raise HTTPException(status_code=400, detail="No mzML file loaded.")
+    return {"features": find_features(ACTIVE_READER.iter_spectra(), tolerance, tolerance_unit, max_charge,
+                                      min_isotopes, min_scans, max_gap, min_intensity, rt_margin)}
+
+@app.get("/api/spectrum_by_selector")
+async def get_spectrum_by_selector(selector: str = Query(..., description="Scan number, 'scan=N', 'index=N' or a nativeID")):
+    if ACTIVE_READER is None:
+        raise HTTPException(status_code=400, detail="No mzML file loaded.")
+    peaks = ACTIVE_READER.get_spectrum_by_selector(selector)
+    if peaks is None:
+        raise HTTPException(status_code=404, detail=f"No spectrum matches {selector!r}.")
+    return {"selector": selector, "peaks": peaks}
+
+PEAK_FORMATS = ("objects", "arrays")
+
+def prepare_scan(scan_nr: int, mobility_min: Optional[float] = None, mobility_max: Optional[float] = None,
+                 processing: Optional[List[Dict]] = None, centroid: Optional[bool] = None) -> Spectrum:
+    # The cached spectrum stays raw; filters and processing produce new ones per request
+    spectrum = load_scan(scan_nr)
+    if mobility_min is not None or mobility_max is not None:
+        spectrum = spectrum.filter_mobility(mobility_min, mobility_max)
+    steps = list(processing or [])
+    if (spectrum.is_profile if centroid is None else centroid) and not any(step.get("step") == "centroid" for step in steps):
+        # After any smoothing / baseline steps, which work on the profile points
+        position = next((i for i, step in enumerate(steps) if step.get("step") not in PROFILE_STEPS), len(steps))
+        steps.insert(position, {"step": "centroid"})
+    return spectrum.process(steps) if steps else spectrum
+
+def annotate_scan(scan_nr: int, sequence: str, settings: FragmentationSettings, match_settings: MatchSettings,
+                  include_peaks: bool = True, peak_format: str = "objects",
+                  mobility_min: Optional[float] = None, mobility_max: Optional[float] = None,
+                  processing: Optional[List[Dict]] = None, centroid: Optional[bool] = None, scores: bool = False):
+    if peak_format not in PEAK_FORMATS:
+        raise ValueError(f"Unknown peak_format {peak_format!r}, expected one of {PEAK_FORMATS}")
+    
+    # 1. Load (or reuse) spectrum
+    spectrum = prepare_scan(scan_nr, mobility_min, mobility_max, processing, centroid)
         
-    except Exception as e:
-        raise HTTPException(status_code=500, detail=str(e))
+    # 2. Calculate Theoretical Ions
+    theoretical_ions = calculate_ions(sequence, settings)
+    
+    # 3. Match
+    matches = spectrum.match(theoretical_ions, mass_type=settings.mass_type, settings=match_settings, mzpaf=True)
+    
+    result = {
+        "scan_nr": scan_nr,
+        "peaks": spectrum.peaks() if include_peaks and peak_format == "objects" else [],
+        "matches": matches,
+        "precursors": spectrum.metadata.get("precursors", []),
+        "precursor_mz": precursor_mz(sequence, settings.precursor_charge, settings.residue_masses or None,
+                                     settings.mass_type, settings.electron_aware),
+        "coverage": fragment_coverage(sequence, matches, settings.residue_masses or None),
+    }
+    if scores:
+        backbone = [ion for ion in theoretical_ions if ion["series"] in ("b", "y") and not ion["loss"]]
+        result.update(hyperscore=hyperscore(matches)["hyperscore"], xcorr=xcorr(spectrum.peaks(), backbone))
+    result.update({key: spectrum.metadata.get(key) for key in SCAN_METADATA_FIELDS})
+    if include_peaks and peak_format == "arrays":
+        result.update(spectrum.arrays())
+    if include_peaks:
+        result["data_arrays"] = spectrum.metadata.get("data_arrays", {})
+    result["warnings"] = spectrum.metadata.get("warnings", [])
+    return result
 
-@app.get("/api/spectrum/{scan_nr}")
+@app.get("/api/spectrum/{scan_nr}", response_model=SpectrumResponse)
 async def get_spectrum(
     scan_nr: int,
     sequence: str = Query(..., description="Peptide sequence for annotation"),
     charge: int = Query(..., description="Precursor charge for annotation"),
+    tolerance: float = Query(0.5, description="Matching tolerance"),
+    tolerance_unit: str = Query("da", description="da or ppm"),
+    tolerance_low: Optional[float] = Query(None, description="Lower bound on observed - theoretical, with tolerance_high"),
+    tolerance_high: Optional[float] = Query(None, description="Upper bound on observed - theoretical, with tolerance_low"),
+    strategy: str = Query("closest", description="Peak choice within tolerance: closest, most_intense or intensity_weighted"),
+    assignment: str = Query("all", description="all, or one_to_one so each peak explains at most one ion"),
+    isotope_peaks: int = Query(0, description="Isotope peaks to look for after each monoisotopic match"),
+    min_snr: Optional[float] = Query(None, description="Ignore peaks below this signal-to-noise"),
+    mass_type: str = Query("monoisotopic", description="monoisotopic or average masses"),
+    label: Optional[str] = Query(None, description="Isotope label, e.g. silac_heavy, 15n, dimethyl_light"),
+    max_fragment_charge: Optional[str] = Query(None, description="Fragment charge limit for all series: a number or 'auto'"),
+    peak_format: str = Query("objects", description="objects ([{mz, intensity}]) or arrays ({mz: [], intensity: []})"),
+    mobility_min: Optional[float] = Query(None, description="Keep peaks with ion mobility at or above this value"),
+    mobility_max: Optional[float] = Query(None, description="Keep peaks with ion mobility at or below this value"),
+    centroid: Optional[bool] = Query(None, description="Centroid before matching; by default only profile spectra are"),
+    scores: bool = Query(False, description="Also compute the hyperscore and XCorr")
+):
+    series_max_charge = {}
+    if max_fragment_charge:
+        if max_fragment_charge != "auto" and not max_fragment_charge.isdigit():
+            raise ValueError(f"max_fragment_charge must be a number or 'auto', got {max_fragment_charge!r}")
+        limit = max_fragment_charge if max_fragment_charge == "auto" else int(max_fragment_charge)
+        series_max_charge = {"b": limit, "y": limit}
+    settings = FragmentationSettings(precursor_charge=charge, mass_type=mass_type, label=label,
+                                     series_max_charge=series_max_charge)
+    if (tolerance_low is None) != (tolerance_high is None):
+        raise ValueError("tolerance_low and tolerance_high must be given together")
+    window = (tolerance_low, tolerance_high) if tolerance_low is not None else tolerance
+    match_settings = MatchSettings(tolerance=window, tolerance_unit=tolerance_unit, strategy=strategy, assignment=assignment,
+                                   isotope_peaks=isotope_peaks, min_snr=min_snr)
+    return annotate_scan(scan_nr, sequence, settings, match_settings, peak_format=peak_format,
+                         mobility_min=mobility_min, mobility_max=mobility_max, centroid=centroid, scores=scores)
+
+@app.post("/api/spectrum/{scan_nr}/annotate", response_model=SpectrumResponse)
+async def annotate_spectrum_with_settings(scan_nr: int, request: AnnotateRequest):
+    # include_peaks=False re-matches a cached spectrum without resending its peaks
+    return annotate_scan(scan_nr, request.sequence, request.settings, request, request.include_peaks, request.peak_format,
+                         request.mobility_min, request.mobility_max, request.processing, request.centroid,
+                         request.scores)
+
+@app.post("/api/spectrum/{scan_nr}/annotated", response_model=AnnotatedSpectrumResponse)
+async def get_annotated_spectrum(scan_nr: int, request: AnnotateRequest):
+    # Every peak with its assignments (or "unmatched") and summary statistics
+    spectrum = prepare_scan(scan_nr, request.mobility_min, request.mobility_max, request.processing, request.centroid)
+    ions = calculate_ions(request.sequence, request.settings)
+    result = spectrum.annotate(ions, mass_type=request.settings.mass_type, settings=request)
+    return {"scan_nr": scan_nr, **result}
+
+@app.post("/api/spectrum/{scan_nr}/export_table", response_class=PlainTextResponse)
+async def export_scan_table(scan_nr: int, request: TableExportRequest):
+    if request.table not in ("matches", "peaks"):
+        raise ValueError(f"table must be 'matches' or 'peaks', got {request.table!r}")
+    spectrum = prepare_scan(scan_nr, request.mobility_min, request.mobility_max, request.processing, request.centroid)
+    ions = calculate_ions(request.sequence, request.settings)
+    if request.table == "matches":
+        matches = spectrum.match(ions, mass_type=request.settings.mass_type, settings=request, mzpaf=True)
+        text = export_matches_tsv(matches, request.columns, request.decimals, request.delimiter)
+    else:
+        peaks = spectrum.annotate(ions, mass_type=request.settings.mass_type, settings=request)["peaks"]
+        text = export_peaks_tsv(peaks, request.columns, request.decimals, request.delimiter)
+    extension = "csv" if request.delimiter in ("comma", ",") else "tsv"
+    return PlainTextResponse(text, headers={
+        "Content-Disposition": f'attachment; filename="scan_{scan_nr}_{request.table}.{extension}"'
+    })
+
+@app.post("/api/spectrum/{scan_nr}/svg", response_class=Response)
+async def export_scan_svg(scan_nr: int, request: SvgExportRequest):
+    spectrum = prepare_scan(scan_nr, request.mobility_min, request.mobility_max, request.processing, request.centroid)
+    matches = spectrum.match(calculate_ions(request.sequence, request.settings), mass_type=request.settings.mass_type,
+                             settings=request)
+    svg = render_annotated_svg(spectrum.peaks(), matches, request.options)
+    return Response(svg, media_type="image/svg+xml", headers={
+        "Content-Disposition": f'attachment; filename="scan_{scan_nr}.svg"'
+    })
+
+@app.post("/api/spectrum/{scan_nr}/binary", response_class=Response)
+async def get_annotated_binary(scan_nr: int, request: AnnotateRequest):
+    # Peaks and matches as one buffer for Web Workers; the schema header describes both layouts
+    spectrum = prepare_scan(scan_nr, request.mobility_min, request.mobility_max, request.processing, request.centroid)
+    matches = spectrum.match(calculate_ions(request.sequence, request.settings), mass_type=request.settings.mass_type,
+                             settings=request)
+    metadata = {"scan_nr": scan_nr, "sequence": request.sequence, "charge": request.settings.precursor_charge,
+                "precursors": spectrum.metadata.get("precursors", []),
+                **{key: spectrum.metadata.get(key) for key in SCAN_METADATA_FIELDS}}
+    sections = {"peaks": encode_peaks(spectrum.mz, spectrum.intensity, spectrum.ion_mobility),
+                "matches": encode_matches(matches, spectrum.mz.tolist())}
+    return Response(pack_binary(sections, metadata), media_type="application/octet-stream")
+
+@app.post("/api/spectrum/{scan_nr}/bundle", response_model=AnalysisBundle)
+async def export_scan_bundle(scan_nr: int, request: AnnotateRequest):
+    spectrum = prepare_scan(scan_nr, request.mobility_min, request.mobility_max, request.processing, request.centroid)
+    match_settings = MatchSettings.model_validate(request.model_dump(include=set(MatchSettings.model_fields)))
+    source = ACTIVE_READER.file_path.name
+    metadata = {**spectrum.metadata, "scan_nr": scan_nr, "source": source,
+                "usi": build_usi(source.split('.')[0], scan_nr, request.sequence, request.settings.precursor_charge)}
+    bundle = export_bundle(spectrum.peaks(), request.sequence, request.settings, match_settings, spectrum=metadata)
+    return JSONResponse(bundle, headers={
+        "Content-Disposition": f'attachment; filename="scan_{scan_nr}.protview.json"'
+    })
+
+@app.post("/api/bundle/load", response_model=AnalysisBundle)
+async def load_analysis_bundle(request: Request, rematch: bool = Query(False, description="Recompute the matches from the stored peaks and settings")):
+    # The bundle is the request body as saved, not wrapped in another object
+    return load_bundle(await request.body(), rematch)
+
+@app.post("/api/spectrum/{scan_nr}/localize", response_model=LocalizationResponse)
+async def localize_scan(scan_nr: int, request: LocalizationRequest):
+    spectrum = prepare_scan(scan_nr, processing=request.processing, centroid=request.centroid)
+    result = localize_modification(spectrum.peaks(), request.sequence, request.modification_mass,
+                                   request.residues, request.settings, request)
+    return {"scan_nr": scan_nr, **result}
+
+@app.post("/api/spectrum/{scan_nr}/chimeric", response_model=ChimericResponse)
+async def annotate_chimeric_scan(scan_nr: int, request: ChimericRequest):
+    spectrum = prepare_scan(scan_nr, processing=request.processing, centroid=request.centroid)
+    result = annotate_chimeric(spectrum.peaks(), [p.model_dump() for p in request.peptides], request.settings, request)
+    return {"scan_nr": scan_nr, "peaks": spectrum.peaks(), **result}
+
+@app.post("/api/spectrum/{scan_nr}/topdown", response_model=TopDownResponse)
+async def annotate_top_down_scan(scan_nr: int, request: TopDownRequest):
+    spectrum = prepare_scan(scan_nr, processing=request.processing, centroid=request.centroid)
+    result = annotate_top_down(spectrum.peaks(), request.sequence, request.settings, request.tolerance,
+                               request.tolerance_unit, request.max_charge, request.max_isotope_error)
+    return {"scan_nr": scan_nr, "sequence": request.sequence, **result}
+
+@app.post("/api/spectrum/{scan_nr}/crosslink", response_model=CrosslinkResponse)
+async def annotate_crosslinked_scan(scan_nr: int, request: CrosslinkRequest):
+    spectrum = prepare_scan(scan_nr, processing=request.processing, centroid=request.centroid)
+    result = annotate_crosslink(spectrum.peaks(), request.alpha, request.beta, request.alpha_site, request.beta_site,
+                                request.crosslinker, request.linker_mass, request.stub_masses, request.settings, request)
+    return {"scan_nr": scan_nr, "peaks": spectrum.peaks(), **result}
+
+@app.post("/api/spectrum/{scan_nr}/search", response_model=SearchResponse)
+async def search_scan(scan_nr: int, request: SearchRequest):
+    spectrum = prepare_scan(scan_nr, processing=request.processing, centroid=request.centroid)
+    settings = request.settings
+    if settings.precursor_mz is None:
+        recorded = next((p["mz"] for p in spectrum.metadata.get("precursors") or [] if p.get("mz")), None)
+        settings = settings.model_copy(update={"precursor_mz": recorded})
+    return {"scan_nr": scan_nr, "precursor_mz": settings.precursor_mz,
+            "results": search_spectrum(spectrum.peaks(), request.candidates, settings)}
+
+@app.post("/api/parse_spectrum")
+async def parse_uploaded_spectrum(file: UploadFile = File(...)):
+    # Raw bytes go straight to the XML parser; the encoding comes from the BOM or declaration
+    return {"peaks": parse_spectrum_upload(await file.read())}
+
+@app.post("/api/parse_run", response_model=SpectrumListResponse)
+async def parse_uploaded_run(request: Request):
+    # The request body (raw or gzipped mzML) is parsed as it arrives, never held whole
+    parser = MzmlFeedParser()
+    spectra = []
+    async for chunk in request.stream():
+        spectra.extend(summary for kind, summary in parser.feed(chunk) if kind == "spectrum")
+    spectra.extend(summary for kind, summary in parser.close() if kind == "spectrum")
+    return {"spectra": spectra}
+
+@app.get("/api/spectrum/{scan_nr}/mgf", response_class=PlainTextResponse)
+async def export_spectrum_mgf(scan_nr: int, charge: Optional[int] = Query(None, description="Charge to write when the file has none")):
+    spectrum = load_scan(scan_nr)
+    precursors = spectrum.metadata.get("precursors") or []
+    charge = charge or next((p["charge"] for p in precursors if p.get("charge")), None)
+    stem = ACTIVE_READER.file_path.name.split('.')[0]
+    metadata = {"title": f"{stem}.{scan_nr}.{scan_nr}.{charge or 0}", "scans": scan_nr}
+    if charge:
+        metadata["charge"] = charge
+    return PlainTextResponse(write_mgf([spectrum], [metadata]), headers={
+        "Content-Disposition": f'attachment; filename="{stem}_scan{scan_nr}.mgf"'})
+
+@app.get("/api/spectrum/{scan_nr}/mzml")
+async def export_spectrum_mzml(scan_nr: int, top_n: Optional[int] = Query(None, description="Keep only the n most intense peaks"),
+                               window_da: Optional[float] = Query(None, description="Apply top_n per m/z window of this width")):
+    spectrum = load_scan(scan_nr)
+    if top_n is not None:
+        spectrum = spectrum.filter_top_n(top_n, window_da)
+    stem = ACTIVE_READER.file_path.name.split('.')[0]
+    return Response(write_mzml([spectrum], run_id=stem), media_type="application/xml", headers={
+        "Content-Disposition": f'attachment; filename="{stem}_scan{scan_nr}.mzML"'})
+
+@app.get("/api/spectrum/{scan_nr}/binned")
+async def export_spectrum_binned(
+    scan_nr: int,
+    bin_width: float = Query(1.0, description="Bin width in Da"),
+    min_mz: float = Query(0.0, description="Lower edge of the first bin"),
+    max_mz: float = Query(2000.0, description="Upper edge of the last bin"),
+    aggregate: str = Query("sum", description="sum or max of the intensities in a bin")
+):
+    # Raw little-endian float32, read in the browser as a Float32Array
+    vector = bin_peaks(load_scan(scan_nr).peaks(), bin_width, min_mz, max_mz, aggregate)
+    return Response(np.asarray(vector, dtype='<f4').tobytes(), media_type="application/octet-stream",
+                    headers={"X-Bin-Width": str(bin_width), "X-Min-Mz": str(min_mz)})
+
+@app.get("/api/spectrum/{scan_nr}/reporters", response_model=ReporterIonsResponse)
+async def get_reporter_ions(
+    scan_nr: int,
+    plex: str = Query(..., description="tmt6, tmt10, tmt11, tmt16, tmt18, itraq4 or itraq8"),
+    tolerance: float = Query(0.003, description="Reporter matching tolerance"),
+    tolerance_unit: str = Query("da", description="da or ppm")
+):
+    return {"scan_nr": scan_nr, **extract_reporter_ions(load_scan(scan_nr).peaks(), plex, tolerance, tolerance_unit)}
+
+def run_library_match(scan_nr: int, precursor_tolerance: float, precursor_tolerance_unit: str, tolerance: float,
+                      tolerance_unit: str, score: str, max_results: int, progress: Optional[ProgressCallback] = None,
+                      cancel: Optional[CancelToken] = None) -> Dict:
+    library = active_library()
+    spectrum = load_scan(scan_nr)
+    # The recorded precursor narrows the candidates; without one the whole library is scored
+    precursor = next((p for p in spectrum.metadata.get("precursors") or [] if p.get("mz")), {})
+    update = (lambda percent, _: progress(percent, scan_nr)) if progress else None
+    results = match_against_library(spectrum.peaks(), library, precursor.get("mz"), precursor.get("charge"),
+                                    precursor_tolerance, precursor_tolerance_unit, tolerance, tolerance_unit,
+                                    score, max_results, update, cancel)
+    return {"scan_nr": scan_nr, "precursor_mz": precursor.get("mz"), "results": results}
+
+@app.get("/api/spectrum/{scan_nr}/library_match", response_model=LibraryMatchResponse)
+async def match_scan_against_library(
+    scan_nr: int,
+    precursor_tolerance: float = Query(20.0, description="Precursor window; entries outside it are not scored"),
+    precursor_tolerance_unit: str = Query("ppm", description="da or ppm"),
+    tolerance: float = Query(0.02, description="Fragment tolerance"),
+    tolerance_unit: str = Query("da", description="da or ppm"),
+    score: str = Query("cosine", description="cosine or entropy"),
+    max_results: int = Query(5, ge=1)
+):
+    return run_library_match(scan_nr, precursor_tolerance, precursor_tolerance_unit, tolerance, tolerance_unit,
+                             score, max_results)
+
+@app.get("/api/spectrum/{scan_nr}/isolation_purity", response_model=IsolationPurityResponse)
+async def get_isolation_purity(
+    scan_nr: int,
+    ms1_scan: Optional[int] = Query(None, description="MS1 scan; defaults to the precursor's or the nearest earlier one"),
+    isolation_width: float = Query(2.0, description="Window width (m/z) when the file records no offsets"),
+    tolerance: float = Query(10.0, description="Isotope peak matching tolerance"),
+    tolerance_unit: str = Query("ppm", description="da or ppm")
+):
+    spectrum = load_scan(scan_nr)
+    precursor = next((p for p in spectrum.metadata.get("precursors") or [] if p.get("mz")), None)
+    if precursor is None:
+        raise HTTPException(status_code=400, detail=f"Scan {scan_nr} has no precursor m/z.")
+    ms1_scan = ms1_scan if ms1_scan is not None else find_ms1_scan(scan_nr, spectrum)
+    # Offsets are taken around the isolation target, which may differ from the selected ion
+    target = precursor.get("isolation_target") or precursor["mz"]
+    lower = precursor.get("isolation_lower_offset")
+    upper = precursor.get("isolation_upper_offset")
+    lower = (lower if lower is not None else isolation_width / 2) + precursor["mz"] - target
+    upper = (upper if upper is not None else isolation_width / 2) + target - precursor["mz"]
+    result = isolation_purity(load_scan(ms1_scan).peaks(), precursor["mz"], precursor.get("charge"),
+                              lower, upper, tolerance, tolerance_unit)
+    return {"scan_nr": scan_nr, "ms1_scan": ms1_scan, **result}
+
+@app.get("/api/spectrum/{scan_nr}/charge", response_model=ChargeDeterminationResponse)
+async def get_precursor_charge(
+    scan_nr: int,
+    mz: Optional[float] = Query(None, description="Approximate precursor m/z; defaults to the recorded one"),
+    max_charge: int = Query(6, description="Highest charge tried"),
+    tolerance: float = Query(10.0, description="Isotope peak matching tolerance"),
+    tolerance_unit: str = Query("ppm", description="da or ppm")
+):
+    # The preceding MS1 shows the whole envelope; MGF/MS2 input falls back to the scan itself
+    spectrum = load_scan(scan_nr)
+    precursor = next((p for p in spectrum.metadata.get("precursors") or [] if p.get("mz")), {})
+    mz = mz if mz is not None else precursor.get("mz")
+    if mz is None:
+        raise HTTPException(status_code=400, detail=f"Scan {scan_nr} has no precursor m/z; pass mz.")
+    try:
+        source_scan = find_ms1_scan(scan_nr, spectrum)
+    except HTTPException:
+        source_scan = scan_nr
+    result = determine_charge(load_scan(source_scan).peaks(), mz, max_charge, tolerance, tolerance_unit)
+    return {"scan_nr": scan_nr, "source_scan": source_scan, "recorded_charge": precursor.get("charge"), **result}
+
+@app.get("/api/usi/parse", response_model=UsiComponents)
+async def parse_usi_components(usi: str = Query(..., description="Universal Spectrum Identifier, mzspec:...")):
+    return parse_usi(usi)
+
+@app.get("/api/spectrum/{scan_nr}/usi", response_model=UsiComponents)
+async def get_scan_usi(
+    scan_nr: int,
+    sequence: Optional[str] = Query(None, description="Peptide to add as the interpretation"),
+    charge: Optional[int] = Query(None, description="Precursor charge; defaults to the recorded one"),
+    collection: Optional[str] = Query(None, description="Dataset accession, e.g. PXD000561; USI000000 if unpublished")
+):
+    spectrum = load_scan(scan_nr)
+    precursors = spectrum.metadata.get("precursors") or []
+    charge = charge or next((p["charge"] for p in precursors if p.get("charge")), None)
+    run = ACTIVE_READER.file_path.name.split('.')[0]
+    return parse_usi(build_usi(run, scan_nr, sequence, charge, collection))
+
+@app.get("/api/usi")
+async def get_spectrum_by_usi(
+    usi: str = Query(..., description="Universal Spectrum Identifier, mzspec:..."),
+    provider: str = Query("pride", description="PROXI provider: pride, massive or peptideatlas"),
+    sequence: Optional[str] = Query(None, description="Peptide sequence to annotate with"),
+    charge: Optional[int] = Query(None, description="Precursor charge; defaults to the one reported"),
     tolerance: float = Query(0.5, description="Matching tolerance")
 ):
-    global ACTIVE_READER
-    
+    # A USI with an interpretation annotates with its own peptide unless another is given
+    components = parse_usi(usi)
+    sequence = sequence or components["sequence"]
+    charge = charge or components["charge"]
+    details = fetch_usi(components["usi"], provider)
+    spectrum = Spectrum.from_peaks(details.pop("peaks"), details["scan_nr"], details)
+    result = {"usi": components["usi"], "peaks": spectrum.peaks(), "precursors": details["precursors"],
+              "matches": [], "sequence": sequence, "charge": charge}
+    result.update({key: details.get(key) for key in SCAN_METADATA_FIELDS})
+    if sequence:
+        charge = charge or next((p["charge"] for p in details["precursors"] if p.get("charge")), None) or 2
+        settings = FragmentationSettings(precursor_charge=charge)
+        result["matches"] = spectrum.match(calculate_ions(sequence, settings), tolerance, mass_type=settings.mass_type,
+                                           mzpaf=True)
+    return result
+
+@app.post("/api/ions", response_model=IonsResponse)
+async def get_theoretical_ions(request: IonsRequest):
+    return {"ions": calculate_ions(request.sequence, request.settings)}
+
+@app.post("/api/ions/batch", response_model=BatchIonsResponse)
+async def get_theoretical_ions_batch(request: BatchIonsRequest):
+    peptides = [p if isinstance(p, str) else p.model_dump() for p in request.peptides]
+    return {"results": calculate_ions_batch(peptides, request.settings)}
+
+@app.post("/api/similarity", response_model=SimilarityResponse)
+async def compare_spectra(request: SimilarityRequest):
+    result = spectrum_similarity(request.peaks_a, request.peaks_b, request.tolerance, request.tolerance_unit)
+    result["entropy_similarity"] = entropy_similarity(request.peaks_a, request.peaks_b, request.tolerance,
+                                                      request.tolerance_unit)
+    if (request.precursor_mass_a is None) != (request.precursor_mass_b is None):
+        raise ValueError("precursor_mass_a and precursor_mass_b must be given together")
+    if request.precursor_mass_a is not None:
+        modified = modified_cosine(request.peaks_a, request.peaks_b, request.precursor_mass_a, request.precursor_mass_b,
+                                   request.tolerance, request.tolerance_unit)
+        result.update(modified_cosine=modified["cosine"], mass_shift=modified["mass_shift"],
+                      shifted_matches=modified["shifted_matches"])
+    return result
+
+@app.post("/api/align", response_model=AlignmentResponse)
+async def align_mirror_spectra(request: SimilarityRequest):
+    # peaks_a is drawn on top of the mirror plot, peaks_b below
+    return align_spectra(request.peaks_a, request.peaks_b, request.tolerance, request.tolerance_unit)
+
+@app.post("/api/consensus", response_model=ConsensusResponse)
+async def build_consensus(request: ConsensusRequest):
+    spectra = [load_scan(scan_nr).peaks() for scan_nr in request.scan_nrs] + request.spectra
+    peaks = consensus_spectrum(spectra, request.tolerance, request.tolerance_unit, request.min_fraction,
+                               request.normalization)
+    return {"spectra": len(spectra), "peaks": peaks}
+
+def request_protein(protein: Optional[str], accession: Optional[str]) -> str:
+    # Sequence sent directly, else looked up in the loaded FASTA
+    if protein is not None:
+        return protein
+    if accession is None:
+        raise ValueError("give either protein or accession")
+    entry = active_fasta().get(accession)
+    if entry is None:
+        raise HTTPException(status_code=404, detail=f"Protein {accession} not found in FASTA.")
+    return entry["sequence"]
+
+@app.get("/api/map_peptide", response_model=PeptideMappingResponse)
+async def map_peptide_to_proteins(
+    peptide: str = Query(..., description="Peptide sequence, modifications allowed"),
+    equate_il: bool = Query(False, description="Treat I and L as the same residue")
+):
+    return {"peptide": peptide, "proteins": map_peptide(peptide, active_fasta(), equate_il)}
+
+@app.post("/api/protein_coverage", response_model=ProteinCoverageResponse)
+async def get_protein_coverage(request: ProteinCoverageRequest):
+    return protein_coverage(request_protein(request.protein, request.accession), request.peptides)
+
+@app.post("/api/digest", response_model=DigestResponse)
+async def digest_protein(request: DigestRequest):
+    peptides = digest(request_protein(request.protein, request.accession), request.enzyme, request.missed_cleavages,
+                      request.semi_specific, request.min_length, request.max_length, request.min_mass, request.max_mass)
+    if request.position is not None:
+        peptides = [p for p in peptides if p["start"] <= request.position <= p["end"]]
+    return {"enzyme": request.enzyme, "peptides": peptides}
+
+@app.post("/api/decoys", response_model=DecoyResponse)
+async def get_decoys(request: DecoyRequest):
+    return {"method": request.method, "decoys": generate_decoys(request.peptides, request.method, request.seed)}
+
+@app.post("/api/delta_mass", response_model=DeltaMassResponse)
+async def get_delta_mass_histogram(request: DeltaMassRequest):
+    return delta_mass_histogram(request.psms, request.bin_width, request.min_delta, request.max_delta,
+                                request.min_count, request.peak_window, request.annotation_tolerance)
+
+@app.post("/api/fdr", response_model=FdrResponse)
+async def get_fdr(request: FdrRequest):
+    return target_decoy_statistics(request.psms, request.higher_is_better, request.decoy_correction,
+                                   request.pep, request.fdr_threshold)
+
+@app.post("/api/rt_prediction", response_model=RtPredictionResponse)
+async def get_rt_prediction(request: RtPredictionRequest):
+    return rt_deviations(request.psms, request.slope, request.intercept, request.max_deviation)
+
+def run_mass_errors(request: MassErrorRequest, progress: Optional[ProgressCallback] = None,
+                    cancel: Optional[CancelToken] = None) -> Dict:
+    if not request.psms:
+        raise ValueError("Mass error statistics need at least one PSM")
+    matches = []
+    for position, psm in enumerate(request.psms, 1):
+        settings = request.settings.model_copy(update={"precursor_charge": psm.charge})
+        spectrum = load_scan(psm.scan_nr)
+        matches.append(spectrum.match(calculate_ions(psm.sequence, settings), mass_type=settings.mass_type,
+                                      settings=request))
+        report(position, len(request.psms), psm.scan_nr, progress, cancel)
+    return mass_error_statistics(matches, request.unit, request.bin_width, request.max_abs_error)
+
+@app.post("/api/qc/mass_errors", response_model=MassErrorStatistics)
+async def get_mass_error_statistics(request: MassErrorRequest):
+    return run_mass_errors(request)
+
+# Background jobs for the long passes: start one, poll GET /api/jobs/{id} for percent and the
+# current scan, and DELETE it to stop at the next spectrum; the result comes with status "done"
+def start_job(kind: str, work) -> Dict:
+    finished = [job_id for job_id, job in JOBS.items() if job.status != "running"]
+    for job_id in finished[:-MAX_FINISHED_JOBS]:
+        del JOBS[job_id]
+    job = Job(kind, work).start()
+    JOBS[job.id] = job
+    return job.summary()
+
+def get_job(job_id: str) -> Job:
+    if job_id not in JOBS:
+        raise HTTPException(status_code=404, detail=f"No job {job_id!r}.")
+    return JOBS[job_id]
+
+@app.post("/api/jobs/spectrum_quality", response_model=JobStatus)
+async def start_spectrum_quality_job(
+    ms_level: Optional[int] = Query(None, description="Only spectra of this MS level"),
+    tolerance: float = Query(20.0, description="Window for the precursor peak"),
+    tolerance_unit: str = Query("ppm", description="da or ppm"),
+    top_n: int = Query(TOP_PEAKS, description="Peaks whose share of the TIC is reported")
+):
     if ACTIVE_READER is None:
         raise HTTPException(status_code=400, detail="No mzML file loaded.")
-        
-    try:
-        # 1. Lazy load spectrum
-        peaks = ACTIVE_READER.get_spectrum(scan_nr)
-        
-        if peaks is None:
-            raise HTTPException(status_code=404, detail=f"Scan {scan_nr} not found in mzML.")
-            
-        # 2. Calculate Theoretical Ions
-        theoretical_ions = calculate_ions(sequence, charge)
-        
-        # 3. Match
-        matches = match_ions(peaks, theoretical_ions, tolerance)
-        
-        return {
-            "scan_nr": scan_nr,
-            "peaks": peaks,
-            "matches": matches
-        }
-        
-    except Exception as e:
-        raise HTTPException(status_code=500, detail=str(e))
+    return start_job("spectrum_quality", lambda progress, cancel: run_spectrum_quality(
+        ms_level, tolerance, tolerance_unit, top_n, progress, cancel))
+
+@app.post("/api/jobs/mass_errors", response_model=JobStatus)
+async def start_mass_error_job(request: MassErrorRequest):
+    if ACTIVE_READER is None:
+        raise HTTPException(status_code=400, detail="No mzML file loaded.")
+    return start_job("mass_errors", lambda progress, cancel: run_mass_errors(request, progress, cancel))
+
+@app.post("/api/jobs/library_match/{scan_nr}", response_model=JobStatus)
+async def start_library_match_job(
+    scan_nr: int,
+    precursor_tolerance: float = Query(20.0, description="Precursor window; entries outside it are not scored"),
+    precursor_tolerance_unit: str = Query("ppm", description="da or ppm"),
+    tolerance: float = Query(0.02, description="Fragment tolerance"),
+    tolerance_unit: str = Query("da", description="da or ppm"),
+    score: str = Query("cosine", description="cosine or entropy"),
+    max_results: int = Query(5, ge=1)
+):
+    active_library()
+    return start_job("library_match", lambda progress, cancel: run_library_match(
+        scan_nr, precursor_tolerance, precursor_tolerance_unit, tolerance, tolerance_unit, score, max_results,
+        progress, cancel))
+
+@app.get("/api/jobs/{job_id}", response_model=JobStatus)
+async def get_job_status(job_id: str):
+    return get_job(job_id).summary()
+
+@app.delete("/api/jobs/{job_id}", response_model=JobStatus)
+async def cancel_job(job_id: str):
+    job = get_job(job_id)
+    job.cancel()
+    return job.summary(include_result=False)
 
 # Mount static files (Frontend)
 if os.path.exists("frontend"):
@@ -0,0 +1,110 @@
+"""
+Physical constants, residue mass tables and isotope label definitions.
+Monoisotopic values follow CODATA 2018 / AME2016; average masses use IUPAC standard atomic weights.
+"""
+
+# Elementary particles (Da)
+PROTON_MASS = 1.007276466621
+ELECTRON_MASS = 0.000548579909065
+NEUTRON_MASS = 1.00866491595
+
+# Monoisotopic masses of the most abundant isotopes
+ATOM_MASSES = {
+    "H": 1.00782503223,
+    "C": 12.0,
+    "N": 14.00307400443,
+    "O": 15.99491461957,
+    "P": 30.97376199842,
+    "S": 31.9720711744,
+}
+
+# Average (natural abundance) masses
+AVERAGE_ATOM_MASSES = {
+    "H": 1.00794,
+    "C": 12.0107,
+    "N": 14.0067,
+    "O": 15.9994,
+    "P": 30.973762,
+    "S": 32.065,
+}
+
+HYDROGEN_MASS = ATOM_MASSES["H"]  # = PROTON_MASS + ELECTRON_MASS
+H2O_MASS = 2 * ATOM_MASSES["H"] + ATOM_MASSES["O"]
+NH3_MASS = ATOM_MASSES["N"] + 3 * ATOM_MASSES["H"]
+CO_MASS = ATOM_MASSES["C"] + ATOM_MASSES["O"]
+AVERAGE_H2O_MASS = 2 * AVERAGE_ATOM_MASSES["H"] + AVERAGE_ATOM_MASSES["O"]
+
+C13_DELTA = 1.00335483507 # 13C - 12C, spacing of isotope peaks
+N15_DELTA = 0.99703489444 # 15N - 14N
+
+MASS_TYPES = ("monoisotopic", "average")
+
+# Residue masses (C-terminal water and N-terminal hydrogen excluded)
+AA_MASS = {'G': 57.02146374, 'A': 71.03711381, 'S': 87.03202844, 'P': 97.05276388, 'V': 99.06841395, 'T': 101.0476785, 'C': 103.0091845, 'L': 113.084064, 'I': 113.084064, 'N': 114.0429275,
+               'D': 115.0269431,
+               'Q': 128.0585775, 'K': 128.0949631, 'E': 129.0425931, 'M': 131.0404846, 'H': 137.0589119, 'F': 147.0684139, 'U': 150.9536334, 'R': 156.1011111, 'Y': 163.0633286, 'W': 186.079313,
+               'O': 237.1477269}
+
+AA_AVERAGE_MASS = {'G': 57.0513, 'A': 71.0779, 'S': 87.0773, 'P': 97.1152, 'V': 99.1311, 'T': 101.1039, 'C': 103.1429, 'L': 113.1576, 'I': 113.1576, 'N': 114.1026,
+                   'D': 115.0874,
+                   'Q': 128.1292, 'K': 128.1723, 'E': 129.1140, 'M': 131.1961, 'H': 137.1393, 'F': 147.1739, 'U': 150.0379, 'R': 156.1857, 'Y': 163.1733, 'W': 186.2099,
+                   'O': 237.2982}
+
+# Nitrogen atoms per residue, used for full 15N metabolic labeling
+AA_NITROGEN_COUNT = {'G': 1, 'A': 1, 'S': 1, 'P': 1, 'V': 1, 'T': 1, 'C': 1, 'L': 1, 'I': 1, 'N': 2,
+                     'D': 1,
+                     'Q': 2, 'K': 2, 'E': 1, 'M': 1, 'H': 3, 'F': 1, 'U': 1, 'R': 4, 'Y': 1, 'W': 2,
+                     'O': 3}
+
+# Label definitions: residue mass shifts, N-terminal shift, and/or full 15N
+ISOTOPE_LABELS = {
+    "none": {},
+    "silac_medium": {"residues": {"K": 4.0251069, "R": 6.0201290}},  # K(D4), R(13C6)
+    "silac_heavy": {"residues": {"K": 8.0141988, "R": 10.0082686}},  # K(13C6 15N2), R(13C6 15N4)
+    "15n": {"n15": True},
+    "dimethyl_light": {"residues": {"K": 28.0313001}, "n_term": 28.0313001},   # CH2O
+    "dimethyl_medium": {"residues": {"K": 32.0564058}, "n_term": 32.0564058},  # CD2O
+    "dimethyl_heavy": {"residues": {"K": 36.0756702}, "n_term": 36.0756702},   # 13CD2O
+}
+
+# Light/heavy pairs produced by calculate_labeled_ions
+LABELING_SCHEMES = {
+    "silac": ("none", "silac_heavy"),
+    "silac_medium": ("none", "silac_medium"),
+    "15n": ("none", "15n"),
+    "dimethyl": ("dimethyl_light", "dimethyl_heavy"),
+    "dimethyl_medium": ("dimethyl_light", "dimethyl_medium"),
+}
+
+def check_mass_type(mass_type: str):
+    if mass_type not in MASS_TYPES:
+        raise ValueError(f"Unknown mass type {mass_type!r}, expected one of {MASS_TYPES}")
+
+def molecule_mass(atoms: dict, mass_type: str = "monoisotopic") -> float:
+    """Mass of a small molecule given as {"C": 1, "O": 1}; negative counts subtract."""
+    check_mass_type(mass_type)
+    table = ATOM_MASSES if mass_type == "monoisotopic" else AVERAGE_ATOM_MASSES
+    return sum(table[e] * n for e, n in atoms.items())
+
+def water_mass(mass_type: str = "monoisotopic") -> float:
+    check_mass_type(mass_type)
+    return H2O_MASS if mass_type == "monoisotopic" else AVERAGE_H2O_MASS
+
+def charge_carrier_mass(electron_aware: bool = True) -> float:
+    """
+    Mass added per charge when protonating a neutral molecule.
+    electron_aware=False reproduces tools that add a whole hydrogen atom per charge.
+    """
+    return PROTON_MASS if electron_aware else HYDROGEN_MASS
+
+def mz_from_mass(neutral_mass: float, charge: int, electron_aware: bool = True) -> float:
+    """m/z of a neutral mass carrying `charge` protons."""
+    if charge < 1:
+        raise ValueError(f"Charge must be positive, got {charge}")
+    return (neutral_mass + charge * charge_carrier_mass(electron_aware)) / charge
+
+def mass_from_mz(mz: float, charge: int, electron_aware: bool = True) -> float:
+    """Neutral mass of an ion observed at m/z with `charge` protons."""
+    if charge < 1:
+        raise ValueError(f"Charge must be positive, got {charge}")
+    return mz * charge - charge * charge_carrier_mass(electron_aware)
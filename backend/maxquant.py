@@ -0,0 +1,131 @@
+"""
+MaxQuant msms.txt identifications: modified sequences in MaxQuant notation resolved to
+[mass] deltas, with the fragment ions MaxQuant matched.
+"""
+
+import csv
+import io
+from typing import Dict, List, Optional
+
+from .decoys import format_peptide
+from .deltamass import UNIMOD_DELTAS
+from .masses import PROTON_MASS
+from .mzid import optional_float
+from .mzml import open_source
+
+# MaxQuant modification names, old two-letter and current long form, to UNIMOD_DELTAS names;
+# other names are looked up by their part before " (", e.g. "Phospho (STY)" -> "Phospho"
+MAXQUANT_MODIFICATIONS = {
+    "ox": "Oxidation", "ac": "Acetyl", "ph": "Phospho", "de": "Deamidated", "gl": "Gln->pyro-Glu",
+    "ca": "Carbamidomethyl", "gg": "GG", "me": "Methyl", "di": "Dimethyl",
+    "Deamidation (NQ)": "Deamidated", "GlyGly (K)": "GG", "Gln->pyro-Glu": "Gln->pyro-Glu",
+    "Glu->pyro-Glu": "Glu->pyro-Glu",
+}
+# Fixed modifications, which MaxQuant leaves out of "Modified sequence"
+MAXQUANT_FIXED_MODIFICATIONS = {"C": UNIMOD_DELTAS["Carbamidomethyl"]}
+
+MSMS_COLUMNS = ("Raw file", "Scan number", "Modified sequence", "Charge")
+
+def modification_mass(name: str) -> float:
+    """Mass delta of a MaxQuant modification name such as "ox" or "Oxidation (M)"."""
+    unimod = MAXQUANT_MODIFICATIONS.get(name, name.split(" (")[0])
+    if unimod not in UNIMOD_DELTAS:
+        raise ValueError(f"Unknown MaxQuant modification {name!r}")
+    return UNIMOD_DELTAS[unimod]
+
+def parse_maxquant_sequence(modified: str, fixed_modifications: Optional[Dict[str, float]] = None) -> str:
+    """
+    "_(ac)PEPM(ox)TIDEK_" or "_(Acetyl (Protein N-term))PEPM(Oxidation (M))TIDEK_" as
+    "[+42.010565]-PEPM[+15.994915]TIDEK", plus fixed_modifications (default
+    MAXQUANT_FIXED_MODIFICATIONS) on every residue they name.
+    """
+    fixed = MAXQUANT_FIXED_MODIFICATIONS if fixed_modifications is None else fixed_modifications
+    text = modified.strip().strip('_')
+    tokens: List[List] = []
+    n_term = c_term = 0.0
+    i = 0
+    while i < len(text):
+        char = text[i]
+        if char == '(':
+            # Names nest parentheses, e.g. "(Acetyl (Protein N-term))"
+            depth, j = 0, i
+            while j < len(text):
+                depth += (text[j] == '(') - (text[j] == ')')
+                if depth == 0:
+                    break
+                j += 1
+            if depth:
+                raise ValueError(f"Unbalanced parentheses in MaxQuant sequence {modified!r}")
+            mass = modification_mass(text[i + 1:j])
+            if not tokens:
+                n_term += mass
+            elif j == len(text) - 1 and 'C-term' in text[i:j]:
+                c_term += mass
+            else:
+                tokens[-1][1] += mass
+            i = j + 1
+        elif char.isalpha():
+            tokens.append([char, fixed.get(char, 0.0)])
+            i += 1
+        else:
+            i += 1
+    return format_peptide([tuple(t) for t in tokens], n_term, c_term)
+
+def _fragments(row: Dict[str, str]) -> List[Dict]:
+    # Matches, Masses and Intensities are parallel ;-separated lists
+    labels = [v for v in (row.get("Matches") or "").split(";") if v]
+    mzs = (row.get("Masses") or "").split(";")
+    intensities = (row.get("Intensities") or "").split(";")
+    return [{"ion_type": label, "mz": optional_float(mzs[k]) if k < len(mzs) else None,
+             "intensity": optional_float(intensities[k]) if k < len(intensities) else None}
+            for k, label in enumerate(labels)]
+
+def parse_msms(source, fixed_modifications: Optional[Dict[str, float]] = None) -> List[Dict]:
+    """
+    PSMs of a MaxQuant msms.txt (bytes, path or binary file object), with the fields of
+    mzid.parse_mzid: "spec_id" is "<raw file>.<scan>", "spectra_data" the raw file,
+    "retention_time" in seconds, "is_decoy" from the Reverse column and "scores" the Score,
+    Delta score, PEP and Localization prob columns present. "fragments" lists MaxQuant's
+    own matches ({"ion_type", "mz", "intensity"}, labels as written, e.g. "y3(2+)").
+    """
+    stream = open_source(source)
+    try:
+        reader = csv.DictReader(io.TextIOWrapper(stream, encoding='utf-8-sig'), delimiter='\t')
+        missing = [c for c in MSMS_COLUMNS if c not in (reader.fieldnames or [])]
+        if missing:
+            raise ValueError(f"msms.txt is missing required column(s): {', '.join(missing)}")
+        psms = [_psm(row, row_nr, fixed_modifications) for row_nr, row in enumerate(reader, 1)]
+    finally:
+        if stream is not source:
+            stream.close()
+    return psms
+
+def _psm(row: Dict[str, str], row_nr: int, fixed_modifications: Optional[Dict[str, float]]) -> Dict:
+    try:
+        scan_nr = int(row["Scan number"])
+        charge = int(row["Charge"])
+        sequence = parse_maxquant_sequence(row["Modified sequence"], fixed_modifications)
+    except (TypeError, ValueError) as e:
+        raise ValueError(f"msms.txt data row {row_nr}: {e}") from e
+    mass = optional_float(row.get("Mass"))
+    retention_time = optional_float(row.get("Retention time"))
+    scores: Dict[str, float] = {}
+    for column in ("Score", "Delta score", "PEP", "Localization prob"):
+        if optional_float(row.get(column)) is not None:
+            scores[column] = optional_float(row[column])
+    return {
+        "scan_nr": scan_nr,
+        "spec_id": f"{row['Raw file']}.{scan_nr}",
+        "spectra_data": row["Raw file"],
+        "sequence": sequence,
+        "charge": charge,
+        "rank": 1,
+        "precursor_mz": optional_float(row.get("m/z")),
+        "calculated_mz": mass / charge + PROTON_MASS if mass is not None else None,
+        "retention_time": retention_time * 60 if retention_time is not None else None,
+        "pass_threshold": True,
+        "is_decoy": (row.get("Reverse") or "").strip() == "+",
+        "proteins": [p for p in (row.get("Proteins") or "").split(";") if p],
+        "scores": scores,
+        "fragments": _fragments(row),
+    }
@@ -0,0 +1,307 @@
+"""
+Mascot Generic Format (MGF) reading and writing, in the same peak and metadata shapes as the mzML reader.
+"""
+
+import re
+from typing import Dict, Iterable, Iterator, List, Optional, Union
+
+from .mzml import SCAN_IN_ID, SCAN_METADATA_FIELDS, LazyMzmlReader, peaks_to_arrays
+
+# TPP-style titles: "run.1234.1234.2" (start scan, end scan, charge)
+TPP_TITLE = re.compile(r'\.(\d+)\.\d+\.\d+(?:\s|$)')
+CHARGE_TOKEN = re.compile(r'(\d+)\s*([+-]?)')
+
+# Headers write_mgf handles itself, in the order they are written
+MGF_HEADER_ORDER = ("TITLE", "PEPMASS", "PEPMASS_INTENSITY", "CHARGE", "RTINSECONDS", "SCANS")
+
+def parse_charges(value: str) -> List[int]:
+    """CHARGE values such as "2+", "3-", "2+ and 3+" or "2,3"."""
+    charges = []
+    for number, sign in CHARGE_TOKEN.findall(value):
+        charges.append(-int(number) if sign == '-' else int(number))
+    if not charges:
+        raise ValueError(f"invalid CHARGE {value!r}")
+    return charges
+
+def _first_number(value: str, key: str) -> float:
+    # RTINSECONDS and SCANS may hold a range ("100.5-101.2"); the start is used
+    match = re.match(r'\s*(-?\d+(?:\.\d*)?(?:[eE][+-]?\d+)?)', value)
+    if not match:
+        raise ValueError(f"invalid {key} {value!r}")
+    return float(match.group(1))
+
+def empty_summary(index: int) -> Dict:
+    """An MS2 spectrum_summary with every field unset, filled in by the text-format readers."""
+    summary = {"index": index, "id": "", "scan_nr": None, "peak_count": 0}
+    summary.update({key: None for key in SCAN_METADATA_FIELDS})
+    summary["ms_level"] = 2
+    summary["precursors"] = []
+    return summary
+
+def precursor_entry(mz: Optional[float], charge: Optional[int], intensity: Optional[float]) -> Dict:
+    """A spectrum_summary precursor from just m/z, charge and intensity; the rest are unset."""
+    return {
+        "mz": mz, "charge": charge, "intensity": intensity,
+        "isolation_target": None, "isolation_lower_offset": None, "isolation_upper_offset": None,
+        "activation": [], "collision_energy": None, "spectrum_ref": None,
+    }
+
+def _number_by_position(scans: List[Optional[int]]) -> List[int]:
+    """
+    Scan numbers for spectra in file order: unnumbered ones (None) take their 1-based
+    position, or, when another spectrum already carries that number, the next numbers
+    after the largest one in the file.
+    """
+    taken = {scan for scan in scans if scan is not None}
+    spare = max(taken | {len(scans)}) + 1
+    numbers = []
+    for position, scan in enumerate(scans, start=1):
+        if scan is None:
+            if position in taken:
+                scan, spare = spare, spare + 1
+            else:
+                scan = position
+            taken.add(scan)
+        numbers.append(scan)
+    return numbers
+
+def _parse_block(lines: List[str], first_line: int, index: int) -> Dict:
+    """One BEGIN IONS ... END IONS block (without those two lines) as a summary with "peaks"."""
+    summary = empty_summary(index)
+    params = {}
+    pepmass = pepmass_intensity = None
+    charges = []
+    peaks = []
+
+    for line_nr, line in enumerate(lines, start=first_line):
+        line = line.strip()
+        if not line or line[0] in '#;!/':
+            continue
+        if '=' in line and not line[0].isdigit():
+            key, value = line.split('=', 1)
+            key = key.strip().upper()
+            value = value.strip()
+            try:
+                if key == 'TITLE':
+                    summary["id"] = value
+                elif key == 'PEPMASS':
+                    parts = value.split()
+                    pepmass = float(parts[0])
+                    pepmass_intensity = float(parts[1]) if len(parts) > 1 else None
+                elif key == 'CHARGE':
+                    charges = parse_charges(value)
+                elif key == 'RTINSECONDS':
+                    summary["retention_time"] = _first_number(value, key)
+                elif key == 'SCANS':
+                    summary["scan_nr"] = int(_first_number(value, key))
+                else:
+                    params[key] = value
+            except ValueError as e:
+                raise ValueError(f"MGF line {line_nr}: {e}") from None
+            continue
+
+        parts = line.split()
+        try:
+            mz = float(parts[0])
+            intensity = float(parts[1]) if len(parts) > 1 else 0.0
+        except ValueError:
+            raise ValueError(f"MGF line {line_nr}: expected 'm/z intensity', got {line!r}") from None
+        peaks.append({"mz": mz, "intensity": intensity})
+
+    if summary["scan_nr"] is None and summary["id"]:
+        scan = SCAN_IN_ID.search(summary["id"]) or TPP_TITLE.search(summary["id"])
+        if scan:
+            summary["scan_nr"] = int(scan.group(1))
+    # One precursor entry per candidate charge ("2+ and 3+"); an unknown charge gives one entry
+    if pepmass is not None:
+        summary["precursors"] = [precursor_entry(pepmass, z, pepmass_intensity) for z in (charges or [None])]
+    summary["peak_count"] = len(peaks)
+    summary["params"] = params
+    summary["peaks"] = peaks
+    return summary
+
+def parse_mgf(text: Union[str, Iterable[str]]) -> List[Dict]:
+    """
+    Parses every BEGIN IONS ... END IONS block of MGF text (or an iterable of lines).
+    Each spectrum is a summary like mzml.spectrum_summary (TITLE as id, SCANS or the
+    title's scan number, RTINSECONDS, PEPMASS/CHARGE as precursors) plus "params" for
+    the remaining headers and "peaks". Spectra with neither are numbered by position (see
+    _number_by_position). Raises ValueError naming the offending line.
+    """
+    lines = text.splitlines() if isinstance(text, str) else text
+    spectra = []
+    block = None
+    start = 0
+    for line_nr, line in enumerate(lines, start=1):
+        stripped = line.strip().upper()
+        if stripped == 'BEGIN IONS':
+            if block is not None:
+                raise ValueError(f"MGF line {line_nr}: BEGIN IONS inside the block opened on line {start}")
+            block, start = [], line_nr
+        elif stripped == 'END IONS':
+            if block is None:
+                raise ValueError(f"MGF line {line_nr}: END IONS without BEGIN IONS")
+            spectra.append(_parse_block(block, start + 1, len(spectra)))
+            block = None
+        elif block is not None:
+            block.append(line)
+    if block is not None:
+        raise ValueError(f"MGF line {start}: BEGIN IONS block is never closed")
+    for spectrum, scan_nr in zip(spectra, _number_by_position([s["scan_nr"] for s in spectra])):
+        spectrum["scan_nr"] = scan_nr
+    return spectra
+
+def format_charges(charges: Union[int, List[int]]) -> str:
+    """The inverse of parse_charges: 2 -> "2+", [2, 3] -> "2+ and 3+"."""
+    if isinstance(charges, int):
+        charges = [charges]
+    return " and ".join(f"{abs(z)}{'-' if z < 0 else '+'}" for z in charges)
+
+def _metadata_from_summary(spectrum: Dict) -> Dict:
+    """MGF headers for a parse_mgf / spectrum_summary style dict."""
+    precursors = spectrum.get("precursors") or []
+    metadata = dict(spectrum.get("params") or {})
+    if spectrum.get("id"):
+        metadata["title"] = spectrum["id"]
+    if precursors and precursors[0].get("mz") is not None:
+        metadata["pepmass"] = precursors[0]["mz"]
+        if precursors[0].get("intensity") is not None:
+            metadata["pepmass_intensity"] = precursors[0]["intensity"]
+        charges = [p["charge"] for p in precursors if p.get("charge")]
+        if charges:
+            metadata["charge"] = charges
+    if spectrum.get("retention_time") is not None:
+        metadata["rtinseconds"] = spectrum["retention_time"]
+    if spectrum.get("scan_nr") is not None:
+        metadata["scans"] = spectrum["scan_nr"]
+    return metadata
+
+def write_mgf(spectra: List, metadata: Optional[List[Dict]] = None) -> str:
+    """
+    Serialises spectra to MGF text. Each spectrum is a peak list ([{"mz", "intensity"}]),
+    a Spectrum, or a dict with "peaks" as returned by parse_mgf / the readers (its
+    title, precursor, RT and scan number become headers). metadata[i], when given, adds or
+    overrides headers: title, pepmass, pepmass_intensity, charge (int or list),
+    rtinseconds, scans, and any other key written verbatim in upper case.
+    """
+    if metadata is not None and len(metadata) != len(spectra):
+        raise ValueError(f"metadata has {len(metadata)} entries for {len(spectra)} spectra")
+    blocks = []
+    for i, spectrum in enumerate(spectra):
+        if isinstance(spectrum, dict):
+            headers = _metadata_from_summary(spectrum)
+            peaks = spectrum.get("peaks") or []
+        elif hasattr(spectrum, "peaks") and callable(spectrum.peaks):
+            headers = _metadata_from_summary(spectrum.metadata) if spectrum.metadata else {}
+            peaks = spectrum.peaks()
+        else:
+            headers, peaks = {}, spectrum
+        headers.update({k.lower() if k.upper() in MGF_HEADER_ORDER else k: v
+                        for k, v in (metadata[i] if metadata else {}).items()})
+
+        lines = ["BEGIN IONS"]
+        if headers.get("title") is not None:
+            lines.append(f"TITLE={headers['title']}")
+        if headers.get("pepmass") is not None:
+            pepmass = f"{float(headers['pepmass']):.6f}"
+            if headers.get("pepmass_intensity") is not None:
+                pepmass += f" {float(headers['pepmass_intensity']):.4f}"
+            lines.append(f"PEPMASS={pepmass}")
+        if headers.get("charge"):
+            lines.append(f"CHARGE={format_charges(headers['charge'])}")
+        if headers.get("rtinseconds") is not None:
+            lines.append(f"RTINSECONDS={float(headers['rtinseconds']):.4f}")
+        if headers.get("scans") is not None:
+            lines.append(f"SCANS={headers['scans']}")
+        for key, value in headers.items():
+            if key.upper() not in MGF_HEADER_ORDER and value is not None:
+                lines.append(f"{key.upper()}={value}")
+        for peak in peaks:
+            lines.append(f"{float(peak['mz']):.6f} {float(peak['intensity']):.4f}")
+        lines.append("END IONS")
+        blocks.append("\n".join(lines))
+    return "\n\n".join(blocks) + "\n" if blocks else ""
+
+class MgfReader(LazyMzmlReader):
+    """
+    Reads MGF files on demand: the index holds the byte offset of each BEGIN IONS line.
+    Spectra without SCANS or a scan number in their title are numbered by position (1-based),
+    as in parse_mgf.
+    """
+
+    def _build_index(self):
+        self.scan_index = {}
+        self.id_index = {}
+        self.chromatogram_index = {}
+        with self._open() as f:
+            offset = 0
+            position = 0
+            blocks = []
+            block_offset = None
+            title = scans = None
+            for raw in f:
+                stripped = raw.strip().upper()
+                if stripped == b'BEGIN IONS':
+                    block_offset, title, scans = offset, None, None
+                elif stripped == b'END IONS' and block_offset is not None:
+                    position += 1
+                    native_id = title or f"index={position - 1}"
+                    self.id_index[native_id] = block_offset
+                    scan = scans
+                    if scan is None and title:
+                        match = SCAN_IN_ID.search(title) or TPP_TITLE.search(title)
+                        scan = int(match.group(1)) if match else None
+                    blocks.append((scan, block_offset))
+                    block_offset = None
+                elif block_offset is not None:
+                    line = raw.decode('utf-8', 'replace').strip()
+                    if line.upper().startswith('TITLE='):
+                        title = line[6:].strip()
+                    elif line.upper().startswith('SCANS='):
+                        try:
+                            scans = int(_first_number(line[6:], 'SCANS'))
+                        except ValueError:
+                            scans = None
+                offset += len(raw)
+        for scan, (_, block_offset) in zip(_number_by_position([scan for scan, _ in blocks]), blocks):
+            self.scan_index[scan] = block_offset
+
+    def get_spectrum_by_offset(self, offset: int, with_metadata: bool = False) -> Dict:
+        lines = []
+        with self._open() as f:
+            f.seek(offset)
+            header = f.readline()
+            if header.strip().upper() != b'BEGIN IONS':
+                raise ValueError(f"no BEGIN IONS at byte {offset}")
+            for raw in f:
+                if raw.strip().upper() == b'END IONS':
+                    break
+                lines.append(raw.decode('utf-8', 'replace'))
+            else:
+                raise ValueError(f"spectrum at byte {offset} is truncated (no END IONS)")
+        # Line numbers in errors count from the BEGIN IONS line
+        spectrum = _parse_block(lines, 2, -1)
+        if spectrum["scan_nr"] is None:
+            # Numbered by position when the index was built
+            spectrum["scan_nr"] = next((scan for scan, at in self.scan_index.items() if at == offset), None)
+        if with_metadata:
+            return spectrum
+        return spectrum["peaks"]
+
+    def get_spectrum_arrays_by_offset(self, offset: int) -> Dict:
+        # Peaks are parsed from text line by line either way
+        return peaks_to_arrays(self.get_spectrum_by_offset(offset, with_metadata=True))
+
+    def _stream_spectra(self) -> Iterator[Dict]:
+        yield from self.list_spectra(include_peaks=True)
+
+    def list_spectra(self, include_peaks: bool = False) -> List[Dict]:
+        with self._open() as f:
+            spectra = parse_mgf(raw.decode('utf-8', 'replace') for raw in f)
+        if not include_peaks:
+            for spectrum in spectra:
+                del spectrum["peaks"]
+        return spectra
+
+    def get_chromatograms(self) -> List[Dict]:
+        return []
@@ -0,0 +1,629 @@
+"""
+Typed shapes of the data returned by the API. frontend/protview.d.ts mirrors these;
+keep both in sync when a field is added.
+"""
+
+from typing import Any, Dict, List, Optional
+
+from pydantic import BaseModel
+
+class Peak(BaseModel):
+    mz: float
+    intensity: float
+    ion_mobility: Optional[float] = None  # 1/K0 (Vs/cm2) or drift time, when the file has a mobility array
+    charge: Optional[int] = None          # set by deisotoping; the m/z is then the [M+H]+ of the envelope
+    isotope_count: Optional[int] = None
+    original_mz: Optional[float] = None   # observed monoisotopic m/z before deconvolution
+    area: Optional[float] = None          # set by centroiding: integrated profile peak area
+    noise: Optional[float] = None         # set by the "snr" step
+    snr: Optional[float] = None
+
+class Ion(BaseModel):
+    type: str              # label, e.g. "b5", "y3-H2O" or "M-H3PO4"
+    series: str            # "M" for precursor ions, "oxonium"/"Y" in glyco mode
+    index: int
+    loss: str = ""
+    charge: int
+    mz: float
+    label: Optional[str] = None  # isotope label, set by calculate_labeled_ions
+    peptide: Optional[str] = None  # crosslinks: "alpha" or "beta"
+    link: Optional[str] = None     # crosslinks: "partner", a stub name, or None if unlinked
+
+class CleavageSite(BaseModel):
+    position: int          # between residue position and position + 1 (1-based)
+    residue: str
+    next_residue: str
+    n_terminal: List[str]  # a/b/c series with a matched fragment ending here
+    c_terminal: List[str]  # x/y/z series
+    ions: List[str]        # matched labels, e.g. "b4", "y6-H2O (2+)"
+    covered: bool
+
+class IsotopeMatch(BaseModel):
+    isotope: int           # 1 for M+1, ...
+    theoretical_mz: float
+    peak_mz: float
+    peak_intensity: float
+    error_da: float
+
+class MatchResult(BaseModel):
+    peak_mz: float
+    peak_intensity: float
+    ion_type: str
+    ion_charge: int
+    theoretical_mz: float
+    error: float           # |observed - theoretical|, Da
+    error_da: float = 0.0  # observed - theoretical
+    error_ppm: float = 0.0
+    relative_intensity: float = 0.0  # % of the base peak
+    intensity_rank: int = 0          # 1 = base peak
+    # One-to-one assignment: ion types that also fit this peak but lost it
+    alternatives: List[str] = []
+    # Isotope peaks found after the monoisotopic one (MatchSettings.isotope_peaks)
+    isotopes: List[IsotopeMatch] = []
+    mzpaf: str = ""  # HUPO-PSI mzPAF, e.g. "b5-H2O^2/-3.4ppm"
+
+class IsotopePeak(BaseModel):
+    isotope: int
+    mz: float
+    abundance: float
+
+class Precursor(BaseModel):
+    mz: Optional[float] = None        # selected ion m/z
+    charge: Optional[int] = None
+    intensity: Optional[float] = None
+    isolation_target: Optional[float] = None
+    isolation_lower_offset: Optional[float] = None
+    isolation_upper_offset: Optional[float] = None
+    activation: List[str] = []        # e.g. ["HCD"] or ["ETD", "CID"]
+    collision_energy: Optional[float] = None
+    spectrum_ref: Optional[str] = None
+
+class ParseWarning(BaseModel):
+    code: str              # see mzml.WARNING_CODES
+    array: Optional[str] = None  # array name, None for the spectrum as a whole
+    message: str
+
+class SpectrumResponse(BaseModel):
+    scan_nr: int
+    peaks: List[Peak]
+    # Columnar alternative to peaks (peak_format="arrays")
+    mz: Optional[List[float]] = None
+    intensity: Optional[List[float]] = None
+    ion_mobility: Optional[List[float]] = None
+    # Other arrays of the mzML spectrum (charge, noise, resolution, ...) by CV name, unfiltered
+    data_arrays: Dict[str, List[float]] = {}
+    # Non-fatal decoding problems (mzml.WARNING_CODES), e.g. a truncated array
+    warnings: List[ParseWarning] = []
+    matches: List[MatchResult]
+    precursors: List[Precursor] = []  # as recorded in the mzML
+    precursor_mz: float               # theoretical, from the sequence
+    coverage: List[CleavageSite] = []   # per backbone cleavage site of the sequence
+    # With scores=true only
+    hyperscore: Optional[float] = None  # log10 X!Tandem hyperscore of the matches
+    xcorr: Optional[float] = None       # Comet-style fast XCorr of the b/y ions
+    retention_time: Optional[float] = None  # seconds
+    ms_level: Optional[int] = None
+    polarity: Optional[str] = None    # "positive" or "negative"
+    total_ion_current: Optional[float] = None
+    base_peak_mz: Optional[float] = None
+    base_peak_intensity: Optional[float] = None
+    filter_string: Optional[str] = None
+    spectrum_type: Optional[str] = None  # "centroid" or "profile"
+
+class PeakAnnotation(BaseModel):
+    ion_type: str
+    ion_charge: int
+    theoretical_mz: float
+    error: float
+    error_da: float = 0.0
+    error_ppm: float = 0.0
+    relative_intensity: float = 0.0
+    intensity_rank: int = 0
+    alternatives: List[str] = []
+    isotopes: List[IsotopeMatch] = []
+
+class AnnotatedPeak(Peak):
+    status: str            # "matched", "isotope" or "unmatched"
+    annotations: List[PeakAnnotation] = []
+
+class AnnotationSummary(BaseModel):
+    peak_count: int
+    matched_peaks: int
+    unmatched_peaks: int
+    theoretical_ions: int
+    matched_ions: int
+    explained_intensity: float  # % of the total ion intensity
+    mean_abs_error_ppm: Optional[float] = None
+
+class AnnotatedSpectrumResponse(BaseModel):
+    scan_nr: int
+    peaks: List[AnnotatedPeak]
+    summary: AnnotationSummary
+
+class SearchResult(BaseModel):
+    rank: int
+    sequence: str
+    score: Optional[float] = None  # the SearchSettings.score value
+    hyperscore: Optional[float] = None
+    xcorr: float
+    matched_ions: int
+    theoretical_ions: int
+    precursor_mz: float            # theoretical, at the search precursor charge
+    precursor_error: Optional[float] = None  # observed - theoretical, in the precursor tolerance unit
+    matches: List[MatchResult]
+    # Open search, when the precursor differs from the candidate
+    mass_offset: Optional[float] = None      # Da, observed - theoretical neutral mass
+    offset_position: Optional[int] = None    # 1-based residue the offset was placed on
+    offset_residue: Optional[str] = None
+    offset_sites: List[int] = []             # every position scoring as well
+    localized_sequence: Optional[str] = None
+
+class SearchResponse(BaseModel):
+    scan_nr: int
+    precursor_mz: Optional[float] = None  # observed m/z the candidates were filtered on
+    results: List[SearchResult]
+
+class LocalizationIsoform(BaseModel):
+    sequence: str                    # the modification(s) placed on sites
+    sites: List[int]                 # 1-based
+    site_determining_ions: int       # ions not shared by all isoforms
+    matched_site_determining_ions: int
+    score: float                     # -10 log10 P(matching as many at random)
+    probability: float
+    matches: List[MatchResult]       # of the site-determining ions
+
+class SiteProbability(BaseModel):
+    position: int                    # 1-based
+    residue: str
+    probability: float
+
+class LocalizationResponse(BaseModel):
+    scan_nr: int
+    sequence: str
+    modification_mass: float
+    modifications: int               # instances being localized
+    match_probability: float         # chance of a random m/z matching a peak
+    isoforms: List[LocalizationIsoform]  # most probable first
+    site_probabilities: List[SiteProbability]
+
+class CrosslinkResponse(BaseModel):
+    scan_nr: int
+    peaks: List[Peak]
+    linker_mass: float
+    stub_masses: Dict[str, float] = {}  # cleavable crosslinkers
+    precursor_mz: float    # crosslinked pair at the precursor charge
+    theoretical_ions: int
+    matches: List[MatchResult]           # labels like "b3α", "y5β", "y7β+A"
+
+class ChimericMatch(MatchResult):
+    peptide: int           # index into the request's peptides
+
+class ChimericPeptide(BaseModel):
+    sequence: str
+    charge: int
+    theoretical_ions: int
+    matched_ions: int
+    explained_intensity: float  # % of the total, shared peaks included
+    unique_intensity: float     # % of the total in peaks no other peptide matches
+
+class ChimericBreakdown(BaseModel):
+    peptides: List[float]  # unique_intensity of each peptide
+    shared: float          # % in peaks matched by several peptides
+    unexplained: float
+
+class ChimericResponse(BaseModel):
+    scan_nr: int
+    peaks: List[Peak]
+    peptides: List[ChimericPeptide]
+    matches: List[ChimericMatch]  # by peak m/z
+    breakdown: ChimericBreakdown
+
+class LibraryPeak(BaseModel):
+    mz: float
+    intensity: float
+    annotations: List[str] = []  # as written in the library, e.g. "y3/0.01"
+
+class LibraryEntry(BaseModel):
+    index: int
+    name: str                  # e.g. "PEPTIDEK/2"
+    sequence: str              # modifications as [mass] deltas
+    charge: Optional[int] = None
+    precursor_mz: Optional[float] = None
+    proteins: List[str] = []
+    is_decoy: bool = False
+    comment: Dict[str, str] = {}
+    peaks: List[LibraryPeak]
+
+class LibraryMatch(BaseModel):
+    rank: int
+    index: int                 # of the library entry
+    name: str
+    sequence: str
+    charge: Optional[int] = None
+    precursor_mz: Optional[float] = None
+    precursor_error_ppm: Optional[float] = None
+    score: float               # the requested score
+    cosine: float              # on square-root intensities
+    entropy: float
+    matched_peaks: int
+
+class LibraryMatchResponse(BaseModel):
+    scan_nr: int
+    precursor_mz: Optional[float] = None
+    results: List[LibraryMatch]  # best first
+
+class UsiComponents(BaseModel):
+    usi: str
+    collection: str                   # e.g. PXD000561, USI000000 for unpublished data
+    run: str
+    index_type: str                   # scan, index, nativeId or trace
+    index: str
+    scan_nr: Optional[int] = None
+    peptidoform: Optional[str] = None  # ProForma, as written in the USI
+    sequence: Optional[str] = None     # with [mass] deltas
+    charge: Optional[int] = None
+
+class BundleSpectrum(BaseModel):
+    scan_nr: Optional[int] = None
+    id: Optional[str] = None          # native id
+    usi: Optional[str] = None
+    source: Optional[str] = None      # spectrum file name
+    ms_level: Optional[int] = None
+    retention_time: Optional[float] = None  # seconds
+    precursors: Optional[List[Precursor]] = None
+    peaks: List[Peak]                 # as matched, after any processing
+
+class BundlePeptide(BaseModel):
+    sequence: str
+    charge: int
+
+class AnalysisBundle(BaseModel):
+    format: str                       # "protview-annotation"
+    version: int
+    created: Optional[str] = None     # ISO 8601, UTC
+    spectrum: BundleSpectrum
+    peptide: BundlePeptide
+    settings: Dict                    # FragmentationSettings
+    match_settings: Dict              # MatchSettings
+    matches: List[MatchResult]
+
+class MassErrorBin(BaseModel):
+    error: float                      # bin centre
+    count: int
+
+class SpectrumMassErrors(BaseModel):
+    count: int
+    median: Optional[float] = None
+
+class MassErrorStatistics(BaseModel):
+    unit: str                         # ppm or da
+    count: int
+    mean: Optional[float] = None
+    median: Optional[float] = None
+    mad: Optional[float] = None
+    robust_sd: Optional[float] = None  # 1.4826 * MAD
+    min: Optional[float] = None
+    max: Optional[float] = None
+    bin_width: Optional[float] = None
+    bins: List[MassErrorBin] = []     # min to max, empty bins included
+    recalibration_offset: Optional[float] = None  # subtract from observed m/z
+    suggested_tolerance: Optional[List[float]] = None  # [low, high] on observed - theoretical
+    spectra: List[SpectrumMassErrors] = []  # per PSM, in request order
+
+class DechargedPeak(BaseModel):
+    mass: float            # neutral, of the lowest observed isotope
+    intensity: float       # summed over the envelope
+    charge: int            # 1 for peaks in no envelope
+    isotope_count: int
+    mz: float              # lowest observed isotope
+
+class TopDownMatch(BaseModel):
+    ion_type: str          # e.g. "c42", "z118"
+    series: str
+    index: int
+    theoretical_mass: float  # neutral
+    observed_mass: float
+    error_ppm: float
+    isotope_error: int     # observed envelope starting this many C13 above the fragment
+    charge: int
+    peak_mz: float
+    peak_intensity: float
+    mzpaf: str = ""        # e.g. "c42+i^7/2.1ppm"
+
+class TopDownResponse(BaseModel):
+    scan_nr: int
+    sequence: str
+    precursor_mass: float  # neutral
+    masses: List[DechargedPeak]
+    matches: List[TopDownMatch]
+    theoretical_ions: int
+    matched_cleavages: int
+    cleavage_coverage: float  # percent of inter-residue bonds
+
+class ReporterChannel(BaseModel):
+    channel: str           # e.g. "127N"
+    mz: float              # theoretical reporter m/z
+    intensity: float       # 0 when no peak was found
+    observed_mz: Optional[float] = None
+    error_ppm: Optional[float] = None
+    relative: float        # share of the summed reporter intensity
+
+class ReporterIonsResponse(BaseModel):
+    scan_nr: int
+    plex: str
+    channels: List[ReporterChannel]
+    total_intensity: float
+
+class IsotopePeak(BaseModel):
+    isotope: int           # offset from the selected precursor peak, may be negative
+    mz: float
+    intensity: float
+
+class IsolationPurityResponse(BaseModel):
+    scan_nr: int
+    ms1_scan: int
+    precursor_mz: float
+    charge: int            # recorded, or the best fitting when unknown
+    window: List[float]    # [low, high] m/z
+    purity: Optional[float] = None  # None for an empty window
+    precursor_intensity: float
+    window_intensity: float
+    isotopes: List[IsotopePeak]
+    interfering_peaks: int
+
+class ChargeCandidate(BaseModel):
+    charge: int
+    isotopes: int          # isotope peaks found at this spacing
+    shape_similarity: float  # cosine to the averagine envelope
+    score: float
+
+class ChargeDeterminationResponse(BaseModel):
+    scan_nr: int
+    source_scan: int       # the MS1 used, or the scan itself without one
+    recorded_charge: Optional[int] = None
+    charge: Optional[int] = None  # None when no envelope was found
+    precursor_mz: float    # snapped to the observed peak
+    candidates: List[ChargeCandidate]  # best first
+
+class IonsResponse(BaseModel):
+    ions: List[Ion]
+
+class BatchIonsResult(BaseModel):
+    sequence: str
+    charge: int
+    ions: List[Ion]
+    error: Optional[str] = None
+
+class BatchIonsResponse(BaseModel):
+    results: List[BatchIonsResult]
+
+class IsotopeDistributionResponse(BaseModel):
+    peaks: List[IsotopePeak]
+
+class ConsensusPeak(Peak):
+    frequency: float       # fraction of the merged spectra containing the peak
+
+class ConsensusResponse(BaseModel):
+    spectra: int           # number of spectra merged
+    peaks: List[ConsensusPeak]
+
+class PeakPair(BaseModel):
+    top: int               # index into the top spectrum's peaks
+    bottom: int
+    error_da: float        # top - bottom m/z
+
+class AlignmentResponse(BaseModel):
+    pairs: List[PeakPair]
+    unpaired_top: List[int]
+    unpaired_bottom: List[int]
+
+class ShiftedMatch(BaseModel):
+    index_a: int
+    index_b: int
+    mz_a: float
+    mz_b: float
+
+class SimilarityResponse(BaseModel):
+    dot_product: float
+    cosine: float
+    spectral_angle: float  # normalized spectral contrast angle, 1 = identical
+    matched_peaks: int
+    entropy_similarity: Optional[float] = None  # weighted spectral entropy similarity, 1 = identical
+    # Set when both precursor masses were given
+    modified_cosine: Optional[float] = None
+    mass_shift: Optional[float] = None
+    shifted_matches: List[ShiftedMatch] = []
+
+class ProteinEntry(BaseModel):
+    accession: str
+    entry_name: Optional[str] = None  # UniProt only, e.g. HBA_HUMAN
+    database: Optional[str] = None    # "sp" (Swiss-Prot) or "tr" (TrEMBL)
+    gene: Optional[str] = None
+    organism: Optional[str] = None
+    description: str = ""
+    header: str
+    sequence: str
+
+class DigestPeptide(BaseModel):
+    sequence: str
+    start: int             # 1-based, inclusive
+    end: int
+    missed_cleavages: int
+    mass: float            # neutral monoisotopic
+
+class DigestResponse(BaseModel):
+    enzyme: str
+    peptides: List[DigestPeptide]
+
+class ProteinHit(BaseModel):
+    accession: str
+    start: int             # 1-based, inclusive
+    end: int
+    previous_residue: str  # "-" at the protein N-terminus
+    next_residue: str      # "-" at the C-terminus
+
+class PeptideMappingResponse(BaseModel):
+    peptide: str
+    proteins: List[ProteinHit]
+
+class PeptidePlacement(BaseModel):
+    peptide: str           # as given, with modifications
+    sequence: str          # residues only
+    starts: List[int]      # 1-based start positions in the protein; empty when absent
+
+class ProteinCoverageResponse(BaseModel):
+    length: int
+    counts: List[int]      # covering peptide occurrences per residue
+    ranges: List[List[int]]  # [start, end] covered stretches, 1-based inclusive
+    coverage: float        # percent of residues covered
+    peptides: List[PeptidePlacement]
+
+class PeptideProperties(BaseModel):
+    sequence: str
+    residues: str          # without modifications
+    length: int
+    monoisotopic_mass: float  # neutral, modifications included
+    average_mass: float
+    mz: Dict[str, float]   # [M+zH]z+ by charge "1".."6"
+    isoelectric_point: float
+    gravy: Optional[float] = None  # mean Kyte-Doolittle hydropathy
+    hydrophobicity: float  # SSRCalc-style retention index
+    formula: str           # Hill notation; approximate for unknown modification masses
+
+class DecoyPair(BaseModel):
+    target: str
+    decoy: str             # same modifications and mass as the target
+
+class DecoyResponse(BaseModel):
+    method: str
+    decoys: List[DecoyPair]
+
+class DeltaMassBin(BaseModel):
+    delta: float           # Da, bin centre
+    count: int
+
+class DeltaMassPeak(BaseModel):
+    delta: float           # Da, mean over the PSMs in the bin
+    count: int
+    modifications: List[str] = []  # likely Unimod names, closest first
+
+class DeltaMassResponse(BaseModel):
+    bin_width: float
+    bins: List[DeltaMassBin]       # non-empty bins only
+    peaks: List[DeltaMassPeak]     # most populated first
+    total: int
+    outside_range: int             # PSMs outside min_delta..max_delta
+
+class ScoredPsm(BaseModel, extra="allow"):
+    # Any other fields of the submitted PSM are passed through
+    score: float
+    is_decoy: bool
+    q_value: float
+    pep: Optional[float] = None    # posterior error probability, when requested
+
+class FdrResponse(BaseModel):
+    psms: List[ScoredPsm]          # best score first
+    targets: int
+    decoys: int
+    fdr_threshold: float
+    accepted: int                  # targets with q_value <= fdr_threshold
+
+class RtCalibration(BaseModel):
+    slope: float
+    intercept: float
+    r2: Optional[float] = None  # only when fitted to the PSMs
+    psms: int
+
+class RtPrediction(BaseModel):
+    sequence: str
+    hydrophobicity: float
+    predicted_rt: float
+    retention_time: Optional[float] = None
+    deviation: Optional[float] = None  # observed - predicted
+    outlier: bool
+
+class RtPredictionResponse(BaseModel):
+    calibration: RtCalibration
+    max_deviation: Optional[float] = None  # outlier threshold used
+    psms: List[RtPrediction]
+
+class SpectrumSummary(BaseModel):
+    index: int
+    id: str                # nativeID
+    scan_nr: Optional[int] = None
+    peak_count: int
+    retention_time: Optional[float] = None  # seconds
+    ms_level: Optional[int] = None
+    polarity: Optional[str] = None
+    total_ion_current: Optional[float] = None
+    base_peak_mz: Optional[float] = None
+    base_peak_intensity: Optional[float] = None
+    filter_string: Optional[str] = None
+    spectrum_type: Optional[str] = None  # "centroid" or "profile"
+    precursors: List[Precursor] = []
+
+class SpectrumListResponse(BaseModel):
+    spectra: List[SpectrumSummary]
+
+class SpectrumQuality(BaseModel):
+    index: int
+    id: str                # nativeID
+    scan_nr: Optional[int] = None
+    ms_level: Optional[int] = None
+    retention_time: Optional[float] = None  # seconds
+    peak_count: int
+    tic: float             # summed from the peaks
+    base_peak_mz: Optional[float] = None
+    base_peak_intensity: Optional[float] = None
+    dynamic_range: Optional[float] = None   # log10 of base peak / weakest non-zero peak
+    top_fraction: Optional[float] = None    # share of the TIC in the top_n peaks
+    precursor_mz: Optional[float] = None
+    precursor_intensity: Optional[float] = None  # most intense peak at the precursor m/z
+    precursor_present: Optional[bool] = None     # None for spectra without a precursor
+
+class SpectrumQualityResponse(BaseModel):
+    top_n: int
+    spectra: List[SpectrumQuality]
+
+class JobStatus(BaseModel):
+    id: str
+    kind: str              # spectrum_quality, mass_errors or library_match
+    status: str            # running, done, cancelled or failed
+    percent: float         # 0-100
+    current_scan: Optional[int] = None
+    error: Optional[str] = None
+    result: Optional[Any] = None  # the endpoint's response once done
+
+class Chromatogram(BaseModel):
+    index: int
+    id: str
+    type: Optional[str] = None        # tic, bpc, sic or srm
+    precursor_mz: Optional[float] = None  # SRM Q1
+    product_mz: Optional[float] = None    # SRM Q3
+    time: List[float]                 # seconds
+    intensity: List[float]
+
+class ChromatogramListResponse(BaseModel):
+    chromatograms: List[Chromatogram]
+
+class Feature(BaseModel):
+    mono_mz: float                    # intensity-weighted over its scans
+    charge: int
+    rt_start: float                   # seconds
+    rt_end: float
+    apex_rt: float
+    apex_scan: Optional[int] = None
+    apex_intensity: float             # summed isotope intensity at the apex
+    scan_count: int
+    isotope_count: int                # largest envelope
+    ms2_scans: List[Optional[int]] = []  # MS2 scans whose precursor hits the feature
+
+class FeatureListResponse(BaseModel):
+    features: List[Feature]           # most intense first
+
+class XicResponse(BaseModel):
+    target_mz: float
+    window: List[float]               # [low, high] m/z
+    time: List[float]                 # seconds
+    intensity: List[float]            # 0 for spectra without a peak in the window
+    scan_nrs: List[Optional[int]]
@@ -0,0 +1,149 @@
+"""
+MS1/MS2 text formats (McDonald et al. 2004): H header lines, then per scan an S line,
+optional I/Z/D lines and "m/z intensity" peak lines.
+"""
+
+from typing import Dict, Iterable, Iterator, List, Union
+
+from .masses import PROTON_MASS
+from .mgf import empty_summary, precursor_entry
+from .mzml import LazyMzmlReader, peaks_to_arrays
+
+def _parse_scan(lines: List[str], first_line: int, index: int) -> Dict:
+    """
+    One scan starting with its S line, as a summary like mzml.spectrum_summary with "peaks".
+    Each Z line becomes a precursor; I lines go to "params" (RetTime also to retention_time).
+    """
+    summary = empty_summary(index)
+    params = {}
+    charges = []
+    precursor_mz = None
+    peaks = []
+
+    for line_nr, line in enumerate(lines, start=first_line):
+        fields = line.strip().split()
+        if not fields:
+            continue
+        kind = fields[0]
+        if kind == 'S' and len(fields) < 3:
+            raise ValueError(f"MS2 line {line_nr}: S line needs start and end scan, got {line.strip()!r}")
+        if kind == 'Z' and len(fields) < 3:
+            raise ValueError(f"MS2 line {line_nr}: Z line needs charge and [M+H]+ mass, got {line.strip()!r}")
+        try:
+            if kind == 'S':
+                summary["scan_nr"] = int(fields[1])
+                summary["id"] = f"scan={summary['scan_nr']}"
+                if len(fields) > 3:
+                    precursor_mz = float(fields[3])
+            elif kind == 'I':
+                if len(fields) >= 3:
+                    params[fields[1]] = " ".join(fields[2:])
+                    if fields[1] == 'RetTime':
+                        # RetTime is given in minutes
+                        summary["retention_time"] = float(fields[2]) * 60
+            elif kind == 'Z':
+                charges.append((int(fields[1]), float(fields[2])))
+            elif kind != 'D':
+                peaks.append({"mz": float(fields[0]), "intensity": float(fields[1]) if len(fields) > 1 else 0.0})
+        except ValueError:
+            raise ValueError(f"MS2 line {line_nr}: non-numeric value in {line.strip()!r}") from None
+
+    # MS1 files have neither a precursor on the S line nor Z lines
+    summary["ms_level"] = 2 if precursor_mz is not None or charges else 1
+    for charge, mh_mass in charges:
+        # Z lines carry the singly protonated mass; S gives the isolated m/z when present
+        mz = precursor_mz if precursor_mz is not None else (mh_mass + (charge - 1) * PROTON_MASS) / charge
+        summary["precursors"].append(precursor_entry(mz, charge, None))
+    if precursor_mz is not None and not charges:
+        summary["precursors"].append(precursor_entry(precursor_mz, None, None))
+    summary["peak_count"] = len(peaks)
+    summary["params"] = params
+    summary["peaks"] = peaks
+    return summary
+
+def parse_ms2(text: Union[str, Iterable[str]]) -> List[Dict]:
+    """
+    Parses MS1 or MS2 text (or an iterable of lines) into spectrum summaries with "peaks".
+    File-level H lines are returned with each spectrum under "headers".
+    Raises ValueError naming the offending line.
+    """
+    lines = text.splitlines() if isinstance(text, str) else text
+    headers = {}
+    spectra = []
+    block = None
+    start = 0
+    for line_nr, line in enumerate(lines, start=1):
+        if line.startswith('H'):
+            fields = line.rstrip('\r\n').split('\t')
+            if len(fields) >= 2:
+                headers[fields[1]] = "\t".join(fields[2:])
+        elif line.startswith('S') and line[1:2] in ('\t', ' '):
+            if block is not None:
+                spectra.append(_parse_scan(block, start, len(spectra)))
+            block, start = [line], line_nr
+        elif block is not None:
+            block.append(line)
+        elif line.strip():
+            raise ValueError(f"MS2 line {line_nr}: peak or I/Z line before the first S line")
+    if block is not None:
+        spectra.append(_parse_scan(block, start, len(spectra)))
+    for spectrum in spectra:
+        spectrum["headers"] = headers
+    return spectra
+
+class Ms2Reader(LazyMzmlReader):
+    """
+    Reads MS1/MS2 files on demand: the index holds the byte offset of each S line.
+    """
+
+    def _build_index(self):
+        self.scan_index = {}
+        self.id_index = {}
+        self.chromatogram_index = {}
+        with self._open() as f:
+            offset = 0
+            for raw in f:
+                if raw.startswith(b'S') and raw[1:2] in (b'\t', b' '):
+                    fields = raw.split()
+                    try:
+                        scan_nr = int(fields[1])
+                    except (IndexError, ValueError):
+                        raise ValueError(f"{self.file_path.name}: invalid S line at byte {offset}: {raw.strip()!r}") from None
+                    self.scan_index[scan_nr] = offset
+                    self.id_index[f"scan={scan_nr}"] = offset
+                offset += len(raw)
+
+    def get_spectrum_by_offset(self, offset: int, with_metadata: bool = False) -> Dict:
+        with self._open() as f:
+            f.seek(offset)
+            lines = [f.readline().decode('utf-8', 'replace')]
+            if not lines[0].startswith('S'):
+                raise ValueError(f"no S line at byte {offset}")
+            for raw in f:
+                if raw.startswith(b'S') and raw[1:2] in (b'\t', b' '):
+                    break
+                lines.append(raw.decode('utf-8', 'replace'))
+        # Line numbers in errors count from the S line
+        spectrum = _parse_scan(lines, 1, -1)
+        if with_metadata:
+            return spectrum
+        return spectrum["peaks"]
+
+    def get_spectrum_arrays_by_offset(self, offset: int) -> Dict:
+        # Peaks are parsed from text line by line either way
+        return peaks_to_arrays(self.get_spectrum_by_offset(offset, with_metadata=True))
+
+    def _stream_spectra(self) -> Iterator[Dict]:
+        yield from self.list_spectra(include_peaks=True)
+
+    def list_spectra(self, include_peaks: bool = False) -> List[Dict]:
+        with self._open() as f:
+            spectra = parse_ms2(raw.decode('utf-8', 'replace') for raw in f)
+        for spectrum in spectra:
+            spectrum.pop("headers")
+            if not include_peaks:
+                del spectrum["peaks"]
+        return spectra
+
+    def get_chromatograms(self) -> List[Dict]:
+        return []
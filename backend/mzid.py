@@ -0,0 +1,137 @@
+"""
+mzIdentML 1.1/1.2 identifications: PSMs with their spectrum reference, modified peptide,
+scores and rank, read in one streaming pass.
+"""
+
+from typing import Dict, List, Optional
+
+from lxml import etree
+
+from .decoys import format_peptide
+from .deltamass import UNIMOD_DELTAS
+from .mzml import SCAN_IN_ID, local_name, open_source
+
+def optional_float(value: Optional[str]) -> Optional[float]:
+    """An attribute or column value as a float; None when missing, empty or not numeric."""
+    try:
+        return float(value) if value not in (None, '') else None
+    except ValueError:
+        return None
+
+def _params(element) -> Dict[str, object]:
+    # cvParam and userParam children by name, numeric values as floats
+    params = {}
+    for param in element:
+        if local_name(param) in ('cvParam', 'userParam') and param.get('name'):
+            value = param.get('value')
+            number = optional_float(value)
+            params[param.get('name')] = number if number is not None else value
+    return params
+
+def _modification_mass(modification) -> float:
+    mass = optional_float(modification.get('monoisotopicMassDelta'))
+    if mass is not None:
+        return mass
+    for cv in modification.findall('{*}cvParam'):
+        if cv.get('name') in UNIMOD_DELTAS:
+            return UNIMOD_DELTAS[cv.get('name')]
+    names = [cv.get('name') for cv in modification.findall('{*}cvParam')]
+    raise ValueError(f"Modification {names} has no monoisotopicMassDelta and is not a known Unimod name")
+
+def _parse_peptide(element) -> str:
+    # Modification location 0 is the N-terminus, length + 1 the C-terminus
+    sequence_element = element.find('{*}PeptideSequence')
+    sequence = (sequence_element.text or '').strip() if sequence_element is not None else ''
+    tokens = [[residue, 0.0] for residue in sequence]
+    n_term = c_term = 0.0
+    for modification in element.findall('{*}Modification'):
+        location = int(modification.get('location', 0))
+        mass = _modification_mass(modification)
+        if location <= 0:
+            n_term += mass
+        elif location > len(tokens):
+            c_term += mass
+        else:
+            tokens[location - 1][1] += mass
+    for substitution in element.findall('{*}SubstitutionModification'):
+        location = int(substitution.get('location', 0))
+        if 1 <= location <= len(tokens):
+            tokens[location - 1][0] = substitution.get('replacementResidue', tokens[location - 1][0])
+    return format_peptide([tuple(t) for t in tokens], n_term, c_term)
+
+def _scan_nr(result, params: Dict[str, object]) -> Optional[int]:
+    scan = params.get('scan number(s)')
+    if isinstance(scan, float):
+        return int(scan)
+    match = SCAN_IN_ID.search(result.get('spectrumID', ''))
+    return int(match.group(1)) if match else None
+
+def parse_mzid(source, max_rank: Optional[int] = 1) -> List[Dict]:
+    """
+    PSMs of an mzIdentML file (bytes, path or binary file object) in file order: "scan_nr"
+    (from the "scan number(s)" cvParam or a scan= spectrumID, else None), "spec_id" (the
+    spectrumID), "spectra_data" (the file it refers to), "sequence" with modifications as
+    [mass] deltas, "charge", "rank", "precursor_mz", "calculated_mz", "retention_time",
+    "pass_threshold", "is_decoy", "proteins" (accessions) and "scores", the item's cvParams
+    and userParams by name. Items ranked above max_rank (None: all) are skipped.
+    """
+    peptides: Dict[str, str] = {}
+    evidence: Dict[str, Dict] = {}
+    databases: Dict[str, str] = {}
+    spectra_data: Dict[str, str] = {}
+    psms = []
+    stream = open_source(source)
+    try:
+        for _, element in etree.iterparse(stream, events=("end",)):
+            name = local_name(element)
+            if name == 'DBSequence':
+                databases[element.get('id')] = element.get('accession', element.get('id'))
+            elif name == 'Peptide':
+                peptides[element.get('id')] = _parse_peptide(element)
+            elif name == 'PeptideEvidence':
+                evidence[element.get('id')] = {"protein": databases.get(element.get('dBSequence_ref')),
+                                               "is_decoy": element.get('isDecoy', 'false') == 'true'}
+            elif name == 'SpectraData':
+                spectra_data[element.get('id')] = element.get('location', '')
+            elif name == 'SpectrumIdentificationResult':
+                psms.extend(_parse_result(element, peptides, evidence, spectra_data, max_rank))
+            else:
+                continue
+            element.clear()
+    except etree.XMLSyntaxError as e:
+        raise ValueError(f"malformed mzIdentML after {len(psms)} PSMs: {e}") from e
+    finally:
+        if stream is not source:
+            stream.close()
+    return psms
+
+def _parse_result(result, peptides: Dict[str, str], evidence: Dict[str, Dict], spectra_data: Dict[str, str],
+                  max_rank: Optional[int]) -> List[Dict]:
+    params = _params(result)
+    retention_time = params.get('scan start time')
+    psms = []
+    for item in result.findall('{*}SpectrumIdentificationItem'):
+        rank = int(item.get('rank', 1))
+        if max_rank is not None and rank > max_rank:
+            continue
+        peptide_ref = item.get('peptide_ref')
+        if peptide_ref not in peptides:
+            raise ValueError(f"SpectrumIdentificationItem {item.get('id')!r} refers to unknown Peptide {peptide_ref!r}")
+        evidences = [evidence[ref.get('peptideEvidence_ref')] for ref in item.findall('{*}PeptideEvidenceRef')
+                     if ref.get('peptideEvidence_ref') in evidence]
+        psms.append({
+            "scan_nr": _scan_nr(result, params),
+            "spec_id": result.get('spectrumID', ''),
+            "spectra_data": spectra_data.get(result.get('spectraData_ref'), ''),
+            "sequence": peptides[peptide_ref],
+            "charge": int(item.get('chargeState', 0)) or None,
+            "rank": rank,
+            "precursor_mz": optional_float(item.get('experimentalMassToCharge')),
+            "calculated_mz": optional_float(item.get('calculatedMassToCharge')),
+            "retention_time": retention_time if isinstance(retention_time, float) else None,
+            "pass_threshold": item.get('passThreshold', 'true') == 'true',
+            "is_decoy": bool(evidences) and all(e["is_decoy"] for e in evidences),
+            "proteins": [e["protein"] for e in evidences if e["protein"]],
+            "scores": _params(item),
+        })
+    return psms
@@ -3,15 +3,28 @@ mzML file processing module with lazy loading capabilities.
 """
 
 import base64
+import binascii
+import codecs
+import gzip
+import html
+import io
 import logging
+import os
+import threading
 import zlib
+from collections import deque
+from concurrent.futures import ProcessPoolExecutor, ThreadPoolExecutor
+from functools import partial
 from pathlib import Path
-from typing import Dict, Optional, Union, Tuple
+from typing import Dict, Iterator, List, Optional, Union, Tuple
 import re
 
 import numpy as np
 from lxml import etree
 
+from .numpress import DECODERS as NUMPRESS_DECODERS, NUMPRESS_ACCESSIONS
+from .progress import CancelToken, ProgressCallback, report
+
 logger = logging.getLogger(__name__)
 
 # Constants for decoding
@@ -23,115 +36,250 @@ NP_DTYPE_MAPPING = {
     '64d': np.float64,
 }
 
+# Binary data type terms -> NP_DTYPE_MAPPING key
+PRECISION_ACCESSIONS = {
+    'MS:1000519': '32i',
+    'MS:1000521': '32f',
+    'MS:1000522': '64q',
+    'MS:1000523': '64d',
+}
+
+# Dissociation method terms under <activation>
+ACTIVATION_ACCESSIONS = {
+    'MS:1000133': 'CID',
+    'MS:1000422': 'HCD',
+    'MS:1000598': 'ETD',
+    'MS:1000250': 'ECD',
+    'MS:1002631': 'EThcD',
+    'MS:1000262': 'IRMPD',
+    'MS:1000435': 'PD',
+    'MS:1003246': 'UVPD',
+}
+
+# Units of "scan start time" -> seconds
+TIME_UNIT_SECONDS = {
+    'UO:0000010': 1.0,    # second
+    'UO:0000031': 60.0,   # minute
+    'UO:0000028': 1e-3,   # millisecond
+    'UO:0000032': 3600.0, # hour
+}
+
+# Scan-level fields added to spectrum_summary
+SCAN_METADATA_FIELDS = ("retention_time", "ms_level", "polarity", "total_ion_current",
+                        "base_peak_mz", "base_peak_intensity", "filter_string", "spectrum_type")
+
+# Chromatogram type terms
+CHROMATOGRAM_TYPES = {
+    'MS:1000235': 'tic',
+    'MS:1000628': 'bpc',
+    'MS:1000627': 'sic',
+    'MS:1001473': 'srm',
+}
+
+# Per-peak ion mobility arrays (timsTOF 1/K0, drift time, FAIMS); any of them fills Peak.ion_mobility
+ION_MOBILITY_ACCESSIONS = {
+    'MS:1002816': 'mean inverse reduced ion mobility array',
+    'MS:1003006': 'mean inverse reduced ion mobility array',
+    'MS:1003007': 'raw ion mobility array',
+    'MS:1003008': 'raw inverse reduced ion mobility array',
+    'MS:1002477': 'mean drift time array',
+    'MS:1003153': 'raw ion mobility drift time array',
+}
+
+# Further binary array terms, returned under their CV name (see _parse_spectrum_element)
+BINARY_ARRAY_NAMES = {
+    'MS:1000516': 'charge array',
+    'MS:1000517': 'signal to noise array',
+    'MS:1000595': 'time array',
+    'MS:1000617': 'wavelength array',
+    'MS:1000820': 'flow rate array',
+    'MS:1000821': 'pressure array',
+    'MS:1000822': 'temperature array',
+    'MS:1002529': 'resolution array',
+    'MS:1002530': 'baseline array',
+    'MS:1002742': 'noise array',
+    'MS:1002743': 'sampled noise m/z array',
+    'MS:1002744': 'sampled noise intensity array',
+    'MS:1002745': 'sampled noise baseline array',
+}
+NON_STANDARD_ARRAY = 'MS:1000786'
+
+# Byte order attributes written by legacy converters (mzML itself is little-endian)
+BIG_ENDIAN_VALUES = ('big', 'network')
+
 COMPRESSION_MAPPING = {
     'none': None,
     'zlib': 'zlib',
 }
 
+# _read_element's first read, doubled per read up to the maximum, and the largest read buffer
+# kept for the next element
+READ_CHUNK_SIZE = 16 * 1024
+MAX_READ_CHUNK_SIZE = 4 * 1024 * 1024
+MAX_KEPT_READ_BUFFER = 16 * 1024 * 1024
+
+# Whole-run passes (iter_spectra) decode spectra on this many workers; 1 parses in the calling
+# thread. "thread" workers share the reader and overlap the zlib, lxml and numpy work, which
+# runs without the GIL; "process" workers each get a copy of the index and also parallelize
+# the base64 decoding, at the cost of sending every spectrum back between processes.
+# Gzipped files are always parsed sequentially (see iter_spectra).
+PARSE_EXECUTORS = ("thread", "process")
+
+def _parse_setting(name: str, default, convert):
+    # A bad value is logged and replaced by the default rather than failing the import
+    value = os.environ.get(name)
+    if value is None:
+        return default
+    try:
+        return convert(value)
+    except ValueError as e:
+        logger.warning(f"Ignoring {name}={value!r} ({e}); using {default!r}")
+        return default
+
+def _worker_count(value: str) -> int:
+    workers = int(value)
+    if workers < 1:
+        raise ValueError("expected a positive integer")
+    return workers
+
+def _executor_name(value: str) -> str:
+    if value not in PARSE_EXECUTORS:
+        raise ValueError(f"expected one of {PARSE_EXECUTORS}")
+    return value
+
+PARSE_WORKERS = _parse_setting("PROTVIEW_PARSE_WORKERS", 1, _worker_count)
+PARSE_EXECUTOR = _parse_setting("PROTVIEW_PARSE_EXECUTOR", "thread", _executor_name)
+# Spectra per worker task, and tasks in flight per worker (bounding memory on big runs)
+PARSE_BATCH_SIZE = 32
+PARSE_QUEUE_DEPTH = 2
+
+# Codes of the non-fatal issues collected while decoding a spectrum; see _parse_spectrum_element
+WARNING_CODES = ("unknown_compression", "unknown_data_type", "decode_error", "truncated_array",
+                 "array_length", "length_mismatch")
+
+GZIP_MAGIC = b'\x1f\x8b'
+GZIP_CHUNK_SIZE = 1024 * 1024
+
+def is_gzip(data: bytes) -> bool:
+    return data[:2] == GZIP_MAGIC
+
+# indexedmzML: <indexListOffset> sits in the last few hundred bytes of the file
+INDEX_TAIL_SIZE = 4096
+INDEX_LIST_OFFSET = re.compile(rb'<indexListOffset>\s*(\d+)\s*</indexListOffset>')
+INDEX_BLOCK = re.compile(rb'<index\s+name="([^"]+)"\s*>(.*?)</index>', re.S)
+INDEX_OFFSET = re.compile(rb'<offset\s+idRef="([^"]*)"[^>]*>\s*(\d+)\s*</offset>')
+SPECTRUM_START_TAG = re.compile(rb'<spectrum\s+[^>]*id="([^"]*)"')
+SCAN_IN_ID = re.compile(r'scan=([0-9]+)')
+
+# Read buffers of LazyMzmlReader._read_element, one per thread so parse workers can share a reader
+_READ_BUFFERS = threading.local()
+
+def _read_buffer() -> bytearray:
+    buffer = getattr(_READ_BUFFERS, "buffer", None)
+    if buffer is None:
+        buffer = _READ_BUFFERS.buffer = bytearray(READ_CHUNK_SIZE)
+    return buffer
+
 class LazyMzmlReader:
     """
-    Reads mzML files on demand using an index of scan offsets.
-    Does NOT load the entire file into memory.
+    Reads mzML files on demand using an index of scan offsets, taken from the
+    indexedmzML <indexList> when the file has one. Does NOT load the entire file into memory.
     """
 
+    # Patterns for the offset index and spectrum start tags; other XML formats override them
+    INDEX_LIST_OFFSET = INDEX_LIST_OFFSET
+    INDEX_BLOCK = INDEX_BLOCK
+    INDEX_OFFSET = INDEX_OFFSET
+    # index name -> (key in the returned indices, element tag the offsets point at)
+    INDEX_ELEMENTS = {"spectrum": ("spectrum", "spectrum"), "chromatogram": ("chromatogram", "chromatogram")}
+    SPECTRUM_START_TAG = SPECTRUM_START_TAG
+
     def __init__(self, file_path: Union[str, Path]):
         self.file_path = Path(file_path)
         if not self.file_path.exists():
             raise FileNotFoundError(f"File not found: {self.file_path}")
         
-        self.scan_index: Dict[int, int] = {} # scan_nr -> byte_offset
+        with open(self.file_path, 'rb') as f:
+            self.compressed = is_gzip(f.read(2))
+        
+        self.scan_index: Dict[int, int] = {} # scan_nr -> byte_offset (in the decompressed stream for .gz)
+        self.id_index: Dict[str, int] = {} # nativeID -> byte_offset
+        self.chromatogram_index: Dict[str, int] = {} # chromatogram id -> byte_offset (indexedmzML only)
         self._build_index()
 
+    def _open(self):
+        """
+        Opens the file for binary reading; gzipped files are decompressed on the fly.
+        Seeking a gzip stream re-inflates from the start, so lookups are slower but memory stays flat.
+        """
+        if self.compressed:
+            return gzip.open(self.file_path, 'rb')
+        return open(self.file_path, 'rb')
+
     def _build_index(self):
         """
         Builds a map of scan numbers to file offsets.
-        Tries to use the native mzML index if available (usually at end of file).
-        If not, performs a linear scan of spectrum tags (still fast-ish).
+        Uses the indexedmzML <indexList> at the end of the file when it is present and valid;
+        otherwise performs a linear scan of spectrum tags (still fast-ish).
         """
         logger.info(f"Indexing {self.file_path}...")
         
-        # Simple regex strategy for robustness if XML parsing whole structure is too heavy
-        # We look for <spectrum ... id="...">
-        # or use lxml iterparse skipping content.
-        
-        # Using lxml iterparse with 'start' event to get positions
-        # Note: iterparse might be slow for huge files if we process everything. 
-        # But it's standard. Let's try optimized read.
-        
-        scan_pattern = re.compile(rb'<spectrum [^>]*id="[^"]*scan=([0-9]+)"[^>]*>')
-        # Alternative pattern for generic IDs if scan= is missing? 
-        # User prompt specified "locate the spectrum location".
-        
-        # Let's try a hybrid approach: Read file line by line (or chunk binary) to find offsets?
-        # Actually, standard mzML often has an indexed list at the end.
-        # But writing a robust parser for the tail index is complex.
-        # Let's stick to a linear scan using iterparse but clearing elements to save memory.
-        # However, getting *byte offsets* from iterparse is tricky (sourceline is strictly line number).
-        
-        # Better approach for Random Access:
-        # We need the BYTE OFFSET.
-        # Let's verify if 'indexedmzML' schema is used.
-        try:
-            with open(self.file_path, 'rb') as f:
-                # Naive greedy scan for '<spectrum' tags and extracting ID.
-                # This works for uncompressed XML mzML.
-                # For huge files this takes a few seconds.
-                mm = f.read() # WARNING: Reading WHOLE file into RAM? 
-                # User said "Do not read the whole file". We CANNOT read into memory.
-                
-                # Stream read
-                f.seek(0)
-                pos = 0
-                while True:
-                    chunk = f.read(1024 * 1024 * 10) # 10MB chunks
-                    if not chunk:
-                        break
-                        
-                    # Find all matches in this chunk
-                    # Use finditer
-                    for match in scan_pattern.finditer(chunk):
-                        # Calculate absolute position
-                        # match.start() is relative to chunk
-                        # CAREFUL: Matches traversing chunk boundaries? 
-                        # This naive chunking splits tags.
-                        pass
-                
-                # RE-EVALUATION: lxml iterparse usually handles file streaming.
-                # But it doesn't give byte offsets easily.
-                
-                # Let's assume standard mzML.
-                # We will index via `lxml`'s `iterparse`? No, we need random access later.
-                # If we use `lxml` later to parse *just* the spectrum, we can pass a file handle 
-                # seeked to that position? `etree.parse` expects a file-like object.
-                
-                # SOLUTION: Indexing is done once. We iterate the file using `etree.iterparse`.
-                # We store the `scan_nr` -> `offset` MAPPING?
-                # Actually, `iterparse` yields elements. Getting the file position of an element is hard.
-                
-                # Alternative: Just use the `mzml.py` provided by the user (which was in the repo)?
-                # The user pointed to a repo: `mscompress`.
-                # The local file I checked `mzml.py` had `MzMLProcessor` which read EVERYTHING.
-                # User specifically asked to CHANGE it to lazy load.
-                
-                # Let's use `pylib.seek`? No.
-                # For lazy loading XML, the <indexList> at the end is the official way.
-                # If that exists, we parse it.
-                # If not, strictly needed? 
-                
-                # Fallback: We will build a rudimentary index by reading the file line-by-line (binary mode)
-                # and looking for `<spectrum ... index="x" ... id="... scan=Y ...">`.
-                # We record `f.tell()` start positions.
-                pass
-                
-        except Exception:
-            pass
-            
-        # Re-implementation: Proper Lazy loading
-        # 1. Parse ONLY the index (if exists).
-        # 2. If no index, scan file once to build it.
-        
         self.scan_index = {}
+        indices = self._read_index_list()
+        self.id_index = indices.get("spectrum") or self._scan_spectrum_offsets()
+        self.chromatogram_index = indices.get("chromatogram", {})
+        for native_id, offset in self.id_index.items():
+            scan = SCAN_IN_ID.search(native_id)
+            if scan:
+                self.scan_index[int(scan.group(1))] = offset
+                
+        logger.info(f"Indexed {len(self.scan_index)} scans.")
+
+    def _read_index_list(self) -> Dict[str, Dict[str, int]]:
+        """
+        Parses the indexedmzML offset index: {"spectrum": {id: offset}, "chromatogram": {...}}.
+        Returns {} for plain mzML, gzipped files (offsets would need a full inflate to verify)
+        or an index whose first offset does not point at a matching element.
+        """
+        if self.compressed:
+            return {}
         with open(self.file_path, 'rb') as f:
+            f.seek(0, 2)
+            size = f.tell()
+            f.seek(max(0, size - INDEX_TAIL_SIZE))
+            tail = f.read()
+            match = self.INDEX_LIST_OFFSET.search(tail)
+            if not match or int(match.group(1)) >= size:
+                return {}
+            f.seek(int(match.group(1)))
+            index_xml = f.read()
+
+            indices = {}
+            for index in self.INDEX_BLOCK.finditer(index_xml):
+                name = index.group(1).decode()
+                if name not in self.INDEX_ELEMENTS:
+                    continue
+                key, tag = self.INDEX_ELEMENTS[name]
+                offsets = {self._offset_id(ref): int(offset)
+                           for ref, offset in self.INDEX_OFFSET.findall(index.group(2))}
+
+                # A stale index (file edited after writing) would silently return wrong spectra
+                if offsets:
+                    f.seek(next(iter(offsets.values())))
+                    if not f.read(64).lstrip().startswith(b'<' + tag.encode()):
+                        logger.warning(f"Ignoring invalid {name} index in {self.file_path}")
+                        return {}
+                indices[key] = offsets
+        return indices
+
+    def _offset_id(self, raw: bytes) -> str:
+        """Index key for an idRef / start tag id."""
+        return html.unescape(raw.decode())
+
+    def _scan_spectrum_offsets(self) -> Dict[str, int]:
+        """Linear scan for <spectrum ... id="..."> start tags; returns id -> byte offset."""
+        offsets = {}
+        with self._open() as f:
             # We will use a regex on the stream.
             # To handle boundary issues, we keep a small overlap buffer.
             buffer_size = 1024 * 1024 * 5 # 5MB
@@ -148,126 +296,1053 @@ class LazyMzmlReader:
                 
                 data_to_search = buffer + new_data
                 
-                # Find tags
-                # Look for start of spectrum: <spectrum
-                # We need the ID as well.
-                # Regex: <spectrum [^>]*index="(\d+)"[^>]*id="[^"]*scan=(\d+)"
-                # This regex captures scan number.
-                
-                # Note: 'index' attribute is sequential 0..N. 'id' contains 'scan=X'.
-                # We want scan=X.
-                
-                # Simple iterator
-                for match in re.finditer(rb'<spectrum\s+[^>]*id="[^"]*scan=([0-9]+)"', data_to_search):
-                    scan_nr = int(match.group(1))
+                for match in self.SPECTRUM_START_TAG.finditer(data_to_search):
                     # The absolute offset of this match start
                     # match.start() is relative to data_to_search
-                    # We accept this as the start of the spectrum block.
                     abs_pos = offset - len(buffer) + match.start()
-                    
-                    # Store scan_nr -> offset
-                    self.scan_index[scan_nr] = abs_pos
+                    offsets[self._offset_id(match.group(1))] = abs_pos
                 
                 # Setup next buffer
                 offset += len(new_data)
                 buffer = new_data[-overlap:] # Keep tail for overlap
-                
-        logger.info(f"Indexed {len(self.scan_index)} scans.")
+        return offsets
 
-    def get_spectrum(self, scan_nr: int) -> Dict:
+    def get_spectrum(self, scan_nr: int, with_metadata: bool = False) -> Dict:
         """
         Fetches and parses a specific spectrum by scan number.
+        with_metadata returns the spectrum_summary (precursors etc.) with "peaks" instead of bare peaks.
         """
         if scan_nr not in self.scan_index:
             return None # Or raise Error
-            
-        offset = self.scan_index[scan_nr]
-        
-        # Read from file at offset until </spectrum>
-        chunk_size = 1024 * 10 
-        spectrum_xml_str = b""
-        
-        with open(self.file_path, 'rb') as f:
-            f.seek(offset)
-            while True:
-                chunk = f.read(chunk_size)
-                if not chunk:
-                    break
-                spectrum_xml_str += chunk
-                if b'</spectrum>' in chunk:
-                    # Cut at the end tag
-                    end_idx = spectrum_xml_str.find(b'</spectrum>') + len(b'</spectrum>')
-                    spectrum_xml_str = spectrum_xml_str[:end_idx]
-                    break
-        
+        try:
+            return self.get_spectrum_by_offset(self.scan_index[scan_nr], with_metadata)
+        except ValueError as e:
+            raise ValueError(f"Scan {scan_nr} in {self.file_path.name}: {e}") from e
+
+    def get_spectrum_arrays(self, scan_nr: int) -> Optional[Dict]:
+        """
+        get_spectrum(scan_nr, with_metadata=True) with the peaks as float64 "mz", "intensity"
+        and "ion_mobility" (or None) arrays, for building a Spectrum without per-peak dicts.
+        """
+        if scan_nr not in self.scan_index:
+            return None
+        try:
+            return self.get_spectrum_arrays_by_offset(self.scan_index[scan_nr])
+        except ValueError as e:
+            raise ValueError(f"Scan {scan_nr} in {self.file_path.name}: {e}") from e
+
+    def get_spectrum_by_id(self, native_id: str) -> Dict:
+        """
+        Fetches a spectrum by its nativeID, e.g. "controllerType=0 controllerNumber=1 scan=42".
+        """
+        if native_id not in self.id_index:
+            return None
+        try:
+            return self.get_spectrum_by_offset(self.id_index[native_id])
+        except ValueError as e:
+            raise ValueError(f"Spectrum {native_id!r} in {self.file_path.name}: {e}") from e
+
+    def get_spectrum_by_selector(self, selector: Union[int, str]) -> Dict:
+        """
+        Fetches a spectrum by scan number, nativeID or zero-based index (see parse_selector).
+        """
+        kind, value = parse_selector(selector)
+        if kind == "scan":
+            return self.get_spectrum(value)
+        if kind == "id":
+            return self.get_spectrum_by_id(value)
+        # id_index keeps file order, both when read from the index list and when scanned
+        offsets = list(self.id_index.values())
+        if not 0 <= value < len(offsets):
+            return None
+        try:
+            return self.get_spectrum_by_offset(offsets[value])
+        except ValueError as e:
+            raise ValueError(f"Spectrum index {value} in {self.file_path.name}: {e}") from e
+
+    def get_spectrum_by_offset(self, offset: int, with_metadata: bool = False) -> Dict:
+        """
+        Parses the <spectrum> element starting at a byte offset (as stored in the index).
+        """
         # Now parse this single XML fragment
-        return self._parse_spectrum_xml(spectrum_xml_str)
+        return self._parse_spectrum_xml(self._read_element(offset, 'spectrum'), with_metadata)
+
+    def get_spectrum_arrays_by_offset(self, offset: int) -> Dict:
+        """The get_spectrum_arrays output for the element at a byte offset; other formats override it."""
+        return parse_spectrum_bytes(self._read_element(offset, 'spectrum'), with_metadata=True, as_arrays=True)
 
-    def _parse_spectrum_xml(self, xml_bytes: bytes) -> Dict:
+    def _read_element(self, offset: int, tag: str, end_tag: Optional[bytes] = None) -> bytes:
         """
-        Parses a single <spectrum> element.
+        Raw bytes of the <tag> element starting at a byte offset, up to its closing tag (or end_tag).
+        The file is read into a per-thread buffer kept between calls (see _read_buffer), so the
+        only allocation per element is the bytes copy handed to the parser.
         """
-        parser = etree.XMLParser(recover=True)
-        root = etree.fromstring(xml_bytes, parser)
-        
-        ns = {'mzml': 'http://psi.hupo.org/ms/mzml'}
-        # Handle namespaces if present (usually is)
-        # Check if root has ns
-        if root.nsmap:
-            # Use the default namespace
-            ns = root.nsmap
-            if None in ns:
-                ns['x'] = ns.pop(None) # Remap default to 'x'
-        
-        # Find binary arrays
-        mz_array = np.array([])
-        int_array = np.array([])
-        
-        # Scan binaryDataArrayList
-        # Using verify-agnostic xpath or simple loop
-        # Because namespaces are annoying in lxml
+        end_tag = end_tag or f'</{tag}>'.encode()
+        buffer = _read_buffer()
+        chunk_size = READ_CHUNK_SIZE
+        filled, end = 0, None
         
-        binary_data_list = root.findall(".//binaryDataArray", namespaces=ns) if not root.nsmap else \
-                           root.findall(".//{*}binaryDataArray")
-        
-        for bda in binary_data_list:
-            # Determine type (mz or int)
-            cv_params = bda.findall(".//{*}cvParam")
-            is_mz = False
-            is_int = False
-            dtype_map = '32f' # Default
-            compression = 'none'
-            
-            for cv in cv_params:
-                acc = cv.get('accession')
-                if acc == 'MS:1000514': is_mz = True # m/z array
-                if acc == 'MS:1000515': is_int = True # intensity array
-                if acc == 'MS:1000523': dtype_map = '64d'
-                if acc == 'MS:1000521': dtype_map = '32f'
-                if acc == 'MS:1000574': compression = 'zlib'
-                if acc == 'MS:1000576': compression = 'none'
-                
-            # Get binary data
-            bin_tag = bda.find(".//{*}binary")
-            if bin_tag is not None and bin_tag.text:
-                decoded = self._decode_data(bin_tag.text, dtype_map, compression)
-                if is_mz: mz_array = decoded
-                if is_int: int_array = decoded
-                
-        # Create peaks list
-        peaks = []
-        if len(mz_array) == len(int_array) and len(mz_array) > 0:
-            # Filter zero intensity if needed?
-            # Basic dict list
-            peaks = [{"mz": float(m), "intensity": float(i)} for m, i in zip(mz_array, int_array)]
+        try:
+            with self._open() as f:
+                f.seek(offset)
+                while True:
+                    if filled == len(buffer):
+                        # Double the buffer; later elements reuse the larger size
+                        buffer.extend(bytes(len(buffer)))
+                    # Reads still start small and double, so a short spectrum after a big one
+                    # does not pull megabytes off the disk
+                    view = memoryview(buffer)[filled:filled + chunk_size]
+                    try:
+                        read = f.readinto(view)
+                    finally:
+                        view.release()
+                    if not read:
+                        break
+                    # Search slightly before the new data in case the end tag straddles two reads
+                    search_from = max(0, filled - len(end_tag) + 1)
+                    filled += read
+                    end_idx = buffer.find(end_tag, search_from, filled)
+                    if end_idx != -1:
+                        # Cut at the end tag
+                        end = end_idx + len(end_tag)
+                        break
+                    chunk_size = min(chunk_size * 2, MAX_READ_CHUNK_SIZE)
             
-        return peaks
+            start = 0
+            while start < filled and buffer[start] in b" \t\r\n":
+                start += 1
+            if not buffer.startswith(f'<{tag}'.encode(), start, filled):
+                raise ValueError(f"no <{tag}> element at byte {offset}")
+            if end is None:
+                raise ValueError(f"{tag} at byte {offset} is truncated (no closing {end_tag.decode()})")
+            with memoryview(buffer) as view:
+                return bytes(view[start:end])
+        finally:
+            if len(buffer) > MAX_KEPT_READ_BUFFER:
+                # Don't hold on to the memory of one exceptionally large spectrum
+                del _READ_BUFFERS.buffer
+
+    def get_chromatograms(self) -> List[Dict]:
+        """
+        Every chromatogram in the file (see parse_chromatogram). With an indexedmzML offset
+        index only the chromatogram elements are read; otherwise the file is streamed.
+        """
+        if not self.chromatogram_index:
+            with self._open() as f:
+                return parse_chromatograms(f)
+        chromatograms = []
+        for chrom_id, offset in self.chromatogram_index.items():
+            try:
+                root = parse_xml_bytes(self._read_element(offset, 'chromatogram'), "chromatogram")
+                chromatograms.append(parse_chromatogram(root))
+            except ValueError as e:
+                raise ValueError(f"Chromatogram {chrom_id!r} in {self.file_path.name}: {e}") from e
+        return chromatograms
 
-    def _decode_data(self, b64_string: str, dtype_str: str, compression: str) -> np.ndarray:
-        decoded = base64.b64decode(b64_string.encode('ascii'))
-        if compression == 'zlib':
-            decoded = zlib.decompress(decoded)
+    def iter_spectra(self, progress: Optional[ProgressCallback] = None, cancel: Optional[CancelToken] = None,
+                     workers: Optional[int] = None, executor: Optional[str] = None) -> Iterator[Dict]:
+        """
+        Every spectrum with metadata and peaks, in file order, parsed from the index so a
+        whole-run pass (e.g. an XIC) holds only the spectra being decoded in memory.
+        Gzipped files are streamed in one sequential pass (see _stream_spectra) instead.
+        workers and executor default to PARSE_WORKERS / PARSE_EXECUTOR. progress gets the
+        share of the index read and the scan just parsed; cancel is checked after each
+        spectrum (raising progress.Cancelled).
+        """
+        offsets = list(self.id_index.items())
+        workers = workers or PARSE_WORKERS
+        if self.compressed:
+            # Every seek into a gzip stream inflates it again from the start, which makes
+            # offset lookups quadratic over a whole run; read it front to back instead
+            spectra = self._stream_spectra()
+        elif workers <= 1:
+            spectra = (_parse_offsets([entry], self)[0] for entry in offsets)
+        else:
+            spectra = self._parse_parallel(offsets, workers, executor or PARSE_EXECUTOR)
+        for position, spectrum in enumerate(spectra, 1):
+            report(position, len(offsets), spectrum.get("scan_nr"), progress, cancel)
+            yield spectrum
+
+    def _stream_spectra(self) -> Iterator[Dict]:
+        """
+        Spectra with metadata and peaks, inflating and parsing the file once from the start;
+        the iter_spectra path for gzipped files. Other formats override it.
+        """
+        parser = MzmlFeedParser(include_peaks=True)
+        try:
+            with open(self.file_path, 'rb') as f:
+                for chunk in iter(partial(f.read, GZIP_CHUNK_SIZE), b''):
+                    for kind, item in parser.feed(chunk):
+                        if kind == "spectrum":
+                            yield item
+            for kind, item in parser.close():
+                if kind == "spectrum":
+                    yield item
+        except ValueError as e:
+            raise ValueError(f"{self.file_path.name}: {e}") from e
+
+    def _parse_parallel(self, offsets: List[Tuple[str, int]], workers: int, executor: str) -> Iterator[Dict]:
+        # Batches go out in file order and come back in it, at most PARSE_QUEUE_DEPTH per worker ahead
+        if executor not in PARSE_EXECUTORS:
+            raise ValueError(f"Unknown parse executor {executor!r}, expected one of {PARSE_EXECUTORS}")
+        if executor == "process":
+            pool = ProcessPoolExecutor(workers, initializer=_init_parse_worker, initargs=(self,))
+            parse = _parse_offsets
+        else:
+            pool = ThreadPoolExecutor(workers)
+            parse = partial(_parse_offsets, reader=self)
+        pending = deque()
+        try:
+            for start in range(0, len(offsets), PARSE_BATCH_SIZE):
+                pending.append(pool.submit(parse, offsets[start:start + PARSE_BATCH_SIZE]))
+                if len(pending) >= workers * PARSE_QUEUE_DEPTH:
+                    yield from pending.popleft().result()
+            while pending:
+                yield from pending.popleft().result()
+        finally:
+            # A cancelled or abandoned pass drops the batches not yet started
+            pool.shutdown(wait=True, cancel_futures=True)
+
+    def list_spectra(self, include_peaks: bool = False) -> List[Dict]:
+        """
+        Summaries of every spectrum in the file, in file order (see parse_run).
+        """
+        with self._open() as f:
+            return parse_run(f, include_peaks)
+
+    def _parse_spectrum_xml(self, xml_bytes: bytes, with_metadata: bool = False) -> Dict:
+        """
+        Parses a single <spectrum> element.
+        """
+        return parse_spectrum_bytes(xml_bytes, with_metadata)
+
+# The reader of a "process" parse worker, set once per process so tasks carry only offsets
+_WORKER_READER: Optional[LazyMzmlReader] = None
+
+def _init_parse_worker(reader: LazyMzmlReader):
+    global _WORKER_READER
+    _WORKER_READER = reader
+
+def _parse_offsets(offsets: List[Tuple[str, int]], reader: Optional[LazyMzmlReader] = None) -> List[Dict]:
+    """Spectra (with metadata) at (nativeID, byte offset) pairs of a reader's index."""
+    reader = reader or _WORKER_READER
+    spectra = []
+    for native_id, offset in offsets:
+        try:
+            spectra.append(reader.get_spectrum_by_offset(offset, with_metadata=True))
+        except ValueError as e:
+            raise ValueError(f"Spectrum {native_id!r} in {reader.file_path.name}: {e}") from e
+    return spectra
+
+# Byte order marks, longest first (the UTF-32 LE mark starts with the UTF-16 LE one)
+_BOMS = [
+    (codecs.BOM_UTF32_LE, "utf-32-le"),
+    (codecs.BOM_UTF32_BE, "utf-32-be"),
+    (codecs.BOM_UTF8, "utf-8"),
+    (codecs.BOM_UTF16_LE, "utf-16-le"),
+    (codecs.BOM_UTF16_BE, "utf-16-be"),
+]
+
+def _normalize_encoding(data: bytes) -> bytes:
+    """
+    Strips a byte order mark. UTF-16/32 input is transcoded to UTF-8 and its encoding
+    declaration dropped; anything else is left to the parser, which honours the declaration.
+    """
+    for bom, encoding in _BOMS:
+        if data.startswith(bom):
+            if encoding == "utf-8":
+                return data[len(bom):]
+            try:
+                text = data[len(bom):].decode(encoding)
+            except UnicodeDecodeError as e:
+                raise ValueError(f"invalid {encoding} spectrum data: {e}") from e
+            return re.sub(r'^\s*<\?xml[^>]*\?>', '', text).encode("utf-8")
+    return data
+
+def _gunzip_chunks(data: bytes):
+    inflater = zlib.decompressobj(16 + zlib.MAX_WBITS)
+    view = memoryview(data)
+    try:
+        for start in range(0, len(view), GZIP_CHUNK_SIZE):
+            chunk = inflater.decompress(view[start:start + GZIP_CHUNK_SIZE])
+            if chunk:
+                yield chunk
+        yield inflater.flush()
+    except zlib.error as e:
+        raise ValueError(f"corrupt gzip data: {e}") from e
+    if not inflater.eof:
+        raise ValueError("truncated gzip data")
+
+def parse_spectrum_bytes(data: bytes, with_metadata: bool = False, as_arrays: bool = False) -> Union[List[Dict], Dict]:
+    """
+    Parses peaks from raw bytes without decoding them to text first.
+    Accepts a single <spectrum> element or a whole (indexed) mzML document,
+    in which case the first spectrum is used. Gzipped input is detected by its magic bytes.
+    with_metadata returns the spectrum_summary with "peaks", "data_arrays" and "warnings"
+    entries instead of the bare list; problems that leave part of the spectrum readable then
+    become warnings (see _parse_spectrum_element) rather than errors. as_arrays (with
+    with_metadata) gives the decoded "mz", "intensity" and "ion_mobility" arrays in place of
+    "peaks", skipping the per-peak dicts.
+    """
+    root = _parse_spectrum_root(data)
+    data_arrays = {}
+    if with_metadata:
+        warnings = []
+        arrays = _decode_spectrum_arrays(root, data_arrays, warnings)
+        summary = {**spectrum_summary(root), "data_arrays": data_arrays, "warnings": warnings}
+        if as_arrays:
+            return {**summary, **dict(zip(("mz", "intensity", "ion_mobility"), arrays))}
+        return {**summary, "peaks": arrays_to_peaks(*arrays)}
+    return _parse_spectrum_element(root, data_arrays)
+
+def _parse_spectrum_root(data: bytes):
+    root = parse_xml_bytes(data)
+    if root.tag.rsplit('}', 1)[-1] != 'spectrum':
+        spectrum = root.find(".//{*}spectrum")
+        if spectrum is None:
+            raise ValueError("no <spectrum> element found")
+        root = spectrum
+    return root
+
+def parse_xml_bytes(data: bytes, what: str = "spectrum"):
+    parser = etree.XMLParser(recover=True, huge_tree=True)
+    try:
+        if is_gzip(data):
+            # Inflate chunk by chunk into the incremental parser instead of materialising the XML
+            for chunk in _gunzip_chunks(data):
+                parser.feed(chunk)
+            root = parser.close()
+        else:
+            root = etree.fromstring(_normalize_encoding(bytes(data)), parser)
+    except etree.XMLSyntaxError as e:
+        raise ValueError(f"malformed {what} XML: {e}") from e
+    if root is None:
+        raise ValueError(f"malformed {what} XML: no element could be recovered")
+    return root
+
+def open_source(source):
+    """File object for parse_run: accepts raw (optionally gzipped) bytes, a path or an open binary file."""
+    if isinstance(source, (bytes, bytearray, memoryview)):
+        stream = io.BytesIO(bytes(source))
+        return gzip.GzipFile(fileobj=stream) if is_gzip(bytes(source[:2])) else stream
+    if isinstance(source, (str, Path)):
+        with open(source, 'rb') as f:
+            compressed = is_gzip(f.read(2))
+        return gzip.open(source, 'rb') if compressed else open(source, 'rb')
+    return source
+
+def local_name(element) -> str:
+    """Tag of an element without its namespace."""
+    return element.tag.rsplit('}', 1)[-1]
+
+def _cv_value(element, accession: str) -> Optional[str]:
+    """Value of a direct child cvParam, or None when the term is absent."""
+    for cv in element.findall("{*}cvParam"):
+        if cv.get('accession') == accession:
+            return cv.get('value', '')
+    return None
+
+def _cv_float(element, accession: str) -> Optional[float]:
+    value = _cv_value(element, accession)
+    if value is None or value == '':
+        return None
+    try:
+        return float(value)
+    except ValueError:
+        raise ValueError(f"cvParam {accession} has non-numeric value {value!r}") from None
+
+def _retention_time(scan) -> Optional[float]:
+    """Scan start time in seconds; minutes and other UO units are converted."""
+    for cv in scan.findall("{*}cvParam"):
+        if cv.get('accession') != 'MS:1000016':
+            continue
+        unit = cv.get('unitAccession')
+        if unit is None:
+            # Older converters give only the unit name
+            unit = {'second': 'UO:0000010', 'minute': 'UO:0000031'}.get(cv.get('unitName', 'second'))
+        if unit not in TIME_UNIT_SECONDS:
+            raise ValueError(f"unsupported scan start time unit {cv.get('unitAccession') or cv.get('unitName')!r}")
+        try:
+            return float(cv.get('value')) * TIME_UNIT_SECONDS[unit]
+        except (TypeError, ValueError):
+            raise ValueError(f"scan start time has non-numeric value {cv.get('value')!r}") from None
+    return None
+
+def parse_scan_metadata(spectrum) -> Dict:
+    """
+    Retention time (seconds), MS level, polarity, TIC, base peak, the Thermo filter string and
+    whether the arrays are centroided or profile data ("centroid"/"profile", None when unstated).
+    Spectrum-level terms come from the <spectrum> cvParams, time and filter from the first <scan>.
+    """
+    ms_level = _cv_value(spectrum, 'MS:1000511')
+    polarity = None
+    if _cv_value(spectrum, 'MS:1000130') is not None:
+        polarity = "positive"
+    elif _cv_value(spectrum, 'MS:1000129') is not None:
+        polarity = "negative"
+    spectrum_type = None
+    if _cv_value(spectrum, 'MS:1000127') is not None:
+        spectrum_type = "centroid"
+    elif _cv_value(spectrum, 'MS:1000128') is not None:
+        spectrum_type = "profile"
+    scan = spectrum.find("{*}scanList/{*}scan")
+    return {
+        "retention_time": _retention_time(scan) if scan is not None else None,
+        "ms_level": int(ms_level) if ms_level else None,
+        "polarity": polarity,
+        "total_ion_current": _cv_float(spectrum, 'MS:1000285'),
+        "base_peak_mz": _cv_float(spectrum, 'MS:1000504'),
+        "base_peak_intensity": _cv_float(spectrum, 'MS:1000505'),
+        "filter_string": _cv_value(scan, 'MS:1000512') if scan is not None else None,
+        "spectrum_type": spectrum_type,
+    }
+
+def parse_precursors(spectrum) -> List[Dict]:
+    """
+    Selected ion m/z, charge and intensity, isolation window and activation of each
+    <precursor>. Missing terms are None; activation lists dissociation methods in file order.
+    """
+    precursors = []
+    for precursor in spectrum.findall("{*}precursorList/{*}precursor"):
+        entry = {
+            "mz": None, "charge": None, "intensity": None,
+            "isolation_target": None, "isolation_lower_offset": None, "isolation_upper_offset": None,
+            "activation": [], "collision_energy": None,
+            "spectrum_ref": precursor.get('spectrumRef'),
+        }
+        window = precursor.find("{*}isolationWindow")
+        if window is not None:
+            entry["isolation_target"] = _cv_float(window, 'MS:1000827')
+            entry["isolation_lower_offset"] = _cv_float(window, 'MS:1000828')
+            entry["isolation_upper_offset"] = _cv_float(window, 'MS:1000829')
+        ion = precursor.find("{*}selectedIonList/{*}selectedIon")
+        if ion is not None:
+            entry["mz"] = _cv_float(ion, 'MS:1000744')
+            charge = _cv_float(ion, 'MS:1000041')
+            entry["charge"] = int(charge) if charge is not None else None
+            entry["intensity"] = _cv_float(ion, 'MS:1000042')
+        activation = precursor.find("{*}activation")
+        if activation is not None:
+            entry["activation"] = [ACTIVATION_ACCESSIONS[cv.get('accession')]
+                                   for cv in activation.findall("{*}cvParam")
+                                   if cv.get('accession') in ACTIVATION_ACCESSIONS]
+            entry["collision_energy"] = _cv_float(activation, 'MS:1000045')
+        precursors.append(entry)
+    return precursors
+
+def spectrum_summary(spectrum) -> Dict:
+    """
+    index, nativeID, scan number, peak count, scan metadata (SCAN_METADATA_FIELDS) and
+    precursors of a <spectrum> element, read without decoding the binary arrays.
+    """
+    native_id = spectrum.get('id', '')
+    scan = SCAN_IN_ID.search(native_id)
+    return {
+        "index": int(spectrum.get('index', -1)),
+        "id": native_id,
+        "scan_nr": int(scan.group(1)) if scan else None,
+        "peak_count": int(spectrum.get('defaultArrayLength', 0)),
+        **parse_scan_metadata(spectrum),
+        "precursors": parse_precursors(spectrum),
+    }
+
+def parse_selector(selector: Union[int, str]) -> Tuple[str, Union[int, str]]:
+    """
+    Normalises a spectrum selector to ("scan", n), ("index", n) or ("id", nativeID).
+    Accepted forms: 10234, "10234", "scan=10234", "index=5" or a full nativeID
+    such as "controllerType=0 controllerNumber=1 scan=10234".
+    """
+    if isinstance(selector, bool) or not isinstance(selector, (int, str)):
+        raise ValueError(f"Spectrum selector must be a scan number or string, got {selector!r}")
+    if isinstance(selector, int):
+        return "scan", selector
+    text = selector.strip()
+    if text.isdigit():
+        return "scan", int(text)
+    match = re.fullmatch(r'(scan|index)\s*=\s*(\d+)', text)
+    if match:
+        return match.group(1), int(match.group(2))
+    if not text:
+        raise ValueError("Spectrum selector is empty")
+    return "id", text
+
+def _selector_matches(summary: Dict, kind: str, value) -> bool:
+    if kind == "scan":
+        return summary["scan_nr"] == value
+    if kind == "index":
+        return summary["index"] == value
+    return summary["id"] == value
+
+def get_spectrum(source, selector: Union[int, str]) -> Optional[Dict]:
+    """
+    Streams a whole mzML file (bytes, path or binary file object) and returns the first
+    spectrum matching the selector as a spectrum_summary with "peaks", or None.
+    Only the matching spectrum's arrays are decoded.
+    """
+    kind, value = parse_selector(selector)
+    stream = open_source(source)
+    position = 0
+    try:
+        for _, element in etree.iterparse(stream, events=("end",)):
+            if local_name(element) != 'spectrum':
+                continue
+            summary = spectrum_summary(element)
+            # Fragments without an index attribute fall back to their position in the file
+            if summary["index"] < 0:
+                summary["index"] = position
+            position += 1
+            if _selector_matches(summary, kind, value):
+                try:
+                    summary["data_arrays"], summary["warnings"] = {}, []
+                    summary["peaks"] = _parse_spectrum_element(element, summary["data_arrays"], summary["warnings"])
+                except ValueError as e:
+                    raise ValueError(f"Spectrum {summary['id'] or summary['index']!r}: {e}") from e
+                return summary
+            _release(element)
+    except etree.XMLSyntaxError as e:
+        raise ValueError(f"malformed mzML after {position} spectra: {e}") from e
+    finally:
+        if stream is not source:
+            stream.close()
+    return None
+
+def _release(element):
+    """
+    Frees a consumed element during iterparse: clear() empties it, and deleting the earlier
+    siblings drops the empty shells the parent would otherwise keep, one per spectrum read.
+    """
+    element.clear()
+    while element.getprevious() is not None:
+        del element.getparent()[0]
+
+def parse_run(source, include_peaks: bool = False) -> List[Dict]:
+    """
+    Enumerates every spectrum of a whole mzML file (bytes, path or binary file object).
+    Each entry is a spectrum_summary, plus "peaks" (with "data_arrays" and "warnings") when
+    include_peaks is set.
+    Elements are cleared as they are consumed, so memory does not grow with the file.
+    """
+    spectra = []
+    stream = open_source(source)
+    try:
+        for _, element in etree.iterparse(stream, events=("end",)):
+            if local_name(element) != 'spectrum':
+                continue
+            summary = spectrum_summary(element)
+            if include_peaks:
+                try:
+                    summary["data_arrays"], summary["warnings"] = {}, []
+                    summary["peaks"] = _parse_spectrum_element(element, summary["data_arrays"], summary["warnings"])
+                except ValueError as e:
+                    raise ValueError(f"Spectrum {summary['id'] or summary['index']!r}: {e}") from e
+            spectra.append(summary)
+            _release(element)
+    except etree.XMLSyntaxError as e:
+        raise ValueError(f"malformed mzML after {len(spectra)} spectra: {e}") from e
+    finally:
+        if stream is not source:
+            stream.close()
+    return spectra
+
+class MzmlFeedParser:
+    """
+    Push-style mzML parser for input that arrives in chunks (an upload stream, a socket):
+    feed() takes the next bytes and returns the events completed so far, close() the rest.
+    Events are ("spectrum", summary) and ("chromatogram", chromatogram) tuples; summaries
+    carry "peaks" when include_peaks is set. Gzipped input is inflated as it is fed.
+    Only the element being built is held in memory, whatever the size of the file.
+    """
+
+    def __init__(self, include_peaks: bool = False):
+        self.include_peaks = include_peaks
+        self.spectrum_count = 0
+        self.chromatogram_count = 0
+        self._parser = etree.XMLPullParser(events=("end",), recover=False, huge_tree=True)
+        self._inflater = None
+        self._started = False
+        self._closed = False
+
+    def feed(self, chunk: bytes) -> List[Tuple[str, Dict]]:
+        if self._closed:
+            raise ValueError("feed() called after close()")
+        chunk = bytes(chunk)
+        if not self._started:
+            if not chunk:
+                return []
+            self._started = True
+            if is_gzip(chunk):
+                self._inflater = zlib.decompressobj(16 + zlib.MAX_WBITS)
+            elif chunk.startswith(codecs.BOM_UTF8):
+                chunk = chunk[len(codecs.BOM_UTF8):]
+        if self._inflater is not None:
+            try:
+                chunk = self._inflater.decompress(chunk)
+            except zlib.error as e:
+                raise ValueError(f"corrupt gzip data: {e}") from e
+        return self._push(chunk)
+
+    def close(self) -> List[Tuple[str, Dict]]:
+        if self._closed:
+            return []
+        self._closed = True
+        events = []
+        if self._inflater is not None:
+            events = self._push(self._inflater.flush())
+            if not self._inflater.eof:
+                raise ValueError("truncated gzip data")
+        try:
+            self._parser.close()
+        except etree.XMLSyntaxError as e:
+            raise ValueError(f"malformed mzML after {self.spectrum_count} spectra: {e}") from e
+        return events + self._events()
+
+    def _push(self, data: bytes) -> List[Tuple[str, Dict]]:
+        if data:
+            try:
+                self._parser.feed(data)
+            except etree.XMLSyntaxError as e:
+                raise ValueError(f"malformed mzML after {self.spectrum_count} spectra: {e}") from e
+        return self._events()
+
+    def _events(self) -> List[Tuple[str, Dict]]:
+        events = []
+        for _, element in self._parser.read_events():
+            name = local_name(element)
+            if name == 'spectrum':
+                summary = spectrum_summary(element)
+                if self.include_peaks:
+                    try:
+                        summary["data_arrays"], summary["warnings"] = {}, []
+                        summary["peaks"] = _parse_spectrum_element(element, summary["data_arrays"], summary["warnings"])
+                    except ValueError as e:
+                        raise ValueError(f"Spectrum {summary['id'] or summary['index']!r}: {e}") from e
+                self.spectrum_count += 1
+                events.append(("spectrum", summary))
+                _release(element)
+            elif name == 'chromatogram':
+                try:
+                    events.append(("chromatogram", parse_chromatogram(element)))
+                except ValueError as e:
+                    raise ValueError(f"Chromatogram {element.get('id')!r}: {e}") from e
+                self.chromatogram_count += 1
+                _release(element)
+        return events
+
+def parse_chromatogram(chromatogram) -> Dict:
+    """
+    id, type (tic, bpc, sic, srm or None), SRM precursor/product m/z and the time (seconds)
+    and intensity arrays of a <chromatogram> element.
+    """
+    chrom_type = None
+    for accession, name in CHROMATOGRAM_TYPES.items():
+        if _cv_value(chromatogram, accession) is not None:
+            chrom_type = name
+    precursor = chromatogram.find("{*}precursor/{*}isolationWindow")
+    product = chromatogram.find("{*}product/{*}isolationWindow")
+
+    time, intensity = np.array([]), np.array([])
+    for bda in chromatogram.findall(".//{*}binaryDataArray"):
+        accessions = {cv.get('accession'): cv for cv in bda.findall("{*}cvParam")}
+        if 'MS:1000595' in accessions:
+            unit = accessions['MS:1000595'].get('unitAccession', 'UO:0000031')
+            if unit not in TIME_UNIT_SECONDS:
+                raise ValueError(f"unsupported time array unit {unit!r}")
+            time = _decode_array(bda, "time") * TIME_UNIT_SECONDS[unit]
+        elif 'MS:1000515' in accessions:
+            intensity = _decode_array(bda, "intensity")
+    if len(time) != len(intensity):
+        raise ValueError(f"time and intensity arrays differ in length ({len(time)} vs {len(intensity)})")
+    return {
+        "index": int(chromatogram.get('index', -1)),
+        "id": chromatogram.get('id', ''),
+        "type": chrom_type,
+        "precursor_mz": _cv_float(precursor, 'MS:1000827') if precursor is not None else None,
+        "product_mz": _cv_float(product, 'MS:1000827') if product is not None else None,
+        "time": time.tolist(),
+        "intensity": intensity.tolist(),
+    }
+
+def parse_chromatograms(source) -> List[Dict]:
+    """
+    Streams a whole mzML file (bytes, path or binary file object) and returns every
+    <chromatogram> in <chromatogramList>, spectra are skipped without decoding.
+    """
+    chromatograms = []
+    stream = open_source(source)
+    try:
+        for _, element in etree.iterparse(stream, events=("end",)):
+            name = local_name(element)
+            if name == 'spectrum':
+                _release(element)
+            elif name == 'chromatogram':
+                try:
+                    chromatograms.append(parse_chromatogram(element))
+                except ValueError as e:
+                    raise ValueError(f"Chromatogram {element.get('id')!r}: {e}") from e
+                _release(element)
+    except etree.XMLSyntaxError as e:
+        raise ValueError(f"malformed mzML after {len(chromatograms)} chromatograms: {e}") from e
+    finally:
+        if stream is not source:
+            stream.close()
+    return chromatograms
+
+def _warn(warnings: Optional[List[Dict]], code: str, array_name: Optional[str], message: str):
+    # Strict parsing (no warnings list) turns the issue into an error
+    if warnings is None:
+        raise ValueError(message)
+    warnings.append({"code": code, "array": array_name, "message": message})
+
+def _decode_array(bda, array_name: str, warnings: Optional[List[Dict]] = None,
+                  expected_length: Optional[int] = None) -> np.ndarray:
+    """
+    Decodes one <binaryDataArray> using its precision, compression and byte order terms.
+    With a warnings list, an array that cannot be decoded is skipped (empty) and a
+    truncated one kept as far as it goes, each noted in warnings; without, they raise.
+    """
+    dtype_map = None
+    compression = 'none'
+    unknown_compression = None
+    for cv in bda.findall(".//{*}cvParam"):
+        acc = cv.get('accession')
+        if acc in PRECISION_ACCESSIONS: dtype_map = PRECISION_ACCESSIONS[acc]
+        if acc == 'MS:1000574': compression = 'zlib'
+        if acc == 'MS:1000576': compression = 'none'
+        if acc in NUMPRESS_ACCESSIONS:
+            scheme, zlibbed = NUMPRESS_ACCESSIONS[acc]
+            compression = f"numpress-{scheme}" + ("+zlib" if zlibbed else "")
+        elif acc not in ('MS:1000574', 'MS:1000576') and 'compression' in cv.get('name', '').lower():
+            unknown_compression = f"{cv.get('name')} ({acc})"
+
+    bin_tag = bda.find(".//{*}binary")
+    if bin_tag is None or not bin_tag.text:
+        return np.array([])
+    if unknown_compression:
+        _warn(warnings, "unknown_compression", array_name,
+              f"{array_name} array uses unsupported compression {unknown_compression}")
+        return np.array([])
+    if dtype_map is None and not compression.startswith('numpress-'):
+        _warn(warnings, "unknown_data_type", array_name,
+              f"{array_name} array has no known binary data type (expected one of {', '.join(PRECISION_ACCESSIONS)})")
+        return np.array([])
+    byte_order = (bda.get('endian') or bda.get('byteOrder') or
+                  bin_tag.get('endian') or bin_tag.get('byteOrder') or 'little')
+    big_endian = byte_order.lower() in BIG_ENDIAN_VALUES
+    try:
+        values = decode_data(bin_tag.text, dtype_map, compression, big_endian, warnings, array_name, expected_length)
+    except (ValueError, zlib.error) as e:
+        _warn(warnings, "decode_error", array_name,
+              f"could not decode {array_name} array ({compression}, {dtype_map}): {e}")
+        return np.array([])
+    if warnings is not None and expected_length is not None and len(values) != expected_length:
+        warnings.append({"code": "array_length", "array": array_name,
+                         "message": f"{array_name} array has {len(values)} values, the spectrum declares {expected_length}"})
+    return values
+
+def _array_name(bda, accessions: set) -> Optional[str]:
+    """CV name of a binaryDataArray other than m/z, intensity or ion mobility, or None."""
+    for accession in accessions:
+        if accession in BINARY_ARRAY_NAMES:
+            return BINARY_ARRAY_NAMES[accession]
+    if NON_STANDARD_ARRAY in accessions:
+        # The array's own name is the cvParam value
+        return _cv_value(bda, NON_STANDARD_ARRAY) or "non-standard data array"
+    for cv in bda.findall("{*}cvParam"):
+        if cv.get('name', '').endswith(' array'):
+            return cv.get('name')
+    return None
+
+def _parse_spectrum_element(root, extra_arrays: Optional[Dict[str, List[float]]] = None,
+                            warnings: Optional[List[Dict]] = None) -> List[Dict]:
+    """
+    Decodes the m/z and intensity arrays of a parsed <spectrum> element into peaks.
+    When extra_arrays is given, other arrays (charge, noise, resolution, ...) are decoded into
+    it keyed by CV name, in file order; otherwise they are skipped undecoded.
+    When warnings is given, non-fatal issues (WARNING_CODES: unsupported compression, undecodable
+    or truncated arrays, lengths that disagree) are appended to it as {"code", "array",
+    "message"} and whatever can be read is returned, m/z and intensity cut to the shorter of
+    the two; without it they raise ValueError.
+    """
+    return arrays_to_peaks(*_decode_spectrum_arrays(root, extra_arrays, warnings))
+
+def arrays_to_peaks(mz_array: np.ndarray, int_array: np.ndarray, mobility_array: Optional[np.ndarray] = None) -> List[Dict]:
+    """Peak dicts from decoded arrays, with "ion_mobility" when that array is given."""
+    peaks = []
+    if len(mz_array) > 0:
+        # tolist() yields Python floats (doubles) without another conversion
+        peaks = [{"mz": m, "intensity": i} for m, i in zip(mz_array.tolist(), int_array.tolist())]
+        if mobility_array is not None:
+            for peak, mobility in zip(peaks, mobility_array.tolist()):
+                peak["ion_mobility"] = mobility
+    return peaks
+
+def peaks_to_arrays(spectrum: Dict) -> Dict:
+    """A spectrum dict with its "peaks" replaced by "mz", "intensity" and "ion_mobility" arrays."""
+    peaks = spectrum.pop("peaks")
+    spectrum["mz"] = np.asarray([p["mz"] for p in peaks], dtype=np.float64)
+    spectrum["intensity"] = np.asarray([p["intensity"] for p in peaks], dtype=np.float64)
+    spectrum["ion_mobility"] = (np.asarray([p["ion_mobility"] for p in peaks], dtype=np.float64)
+                                if peaks and "ion_mobility" in peaks[0] else None)
+    return spectrum
+
+def _decode_spectrum_arrays(root, extra_arrays: Optional[Dict[str, List[float]]] = None,
+                            warnings: Optional[List[Dict]] = None
+                            ) -> Tuple[np.ndarray, np.ndarray, Optional[np.ndarray]]:
+    """(m/z, intensity, ion mobility or None) float64 arrays of a <spectrum>; see _parse_spectrum_element."""
+    ns = {'mzml': 'http://psi.hupo.org/ms/mzml'}
+    # Handle namespaces if present (usually is)
+    # Check if root has ns
+    if root.nsmap:
+        # Use the default namespace
+        ns = root.nsmap
+        if None in ns:
+            ns['x'] = ns.pop(None) # Remap default to 'x'
+    
+    # Find binary arrays
+    mz_array = np.array([])
+    int_array = np.array([])
+    
+    # Scan binaryDataArrayList
+    # Using verify-agnostic xpath or simple loop
+    # Because namespaces are annoying in lxml
+    
+    binary_data_list = root.findall(".//binaryDataArray", namespaces=ns) if not root.nsmap else \
+                       root.findall(".//{*}binaryDataArray")
+    
+    mobility_array = None
+    default_length = root.get('defaultArrayLength')
+    for bda in binary_data_list:
+        # Determine type (mz, int or ion mobility)
+        accessions = {cv.get('accession') for cv in bda.findall(".//{*}cvParam")}
+        is_mz = 'MS:1000514' in accessions # m/z array
+        is_int = 'MS:1000515' in accessions # intensity array
+        is_mobility = not accessions.isdisjoint(ION_MOBILITY_ACCESSIONS)
+        array_name = "m/z" if is_mz else "intensity" if is_int else "ion mobility" if is_mobility else None
+        if array_name is None:
+            array_name = _array_name(bda, accessions)
+            if extra_arrays is None or array_name is None:
+                continue
+            extra_arrays[array_name] = _decode_array(bda, array_name, warnings).tolist()
+            continue
+        
+        expected = bda.get('arrayLength') or default_length
+        decoded = _decode_array(bda, array_name, warnings, int(expected) if expected else None)
+        if len(decoded) == 0:
+            continue
+        if is_mz: mz_array = decoded
+        if is_int: int_array = decoded
+        if is_mobility: mobility_array = decoded
             
-        # Map dtype
-        dt = NP_DTYPE_MAPPING.get(dtype_str, np.float32)
-        return np.frombuffer(decoded, dtype=dt)
+    # Create peaks list
+    if len(mz_array) != len(int_array):
+        _warn(warnings, "length_mismatch", None,
+              f"m/z and intensity arrays differ in length ({len(mz_array)} vs {len(int_array)})")
+        length = min(len(mz_array), len(int_array))
+        mz_array, int_array = mz_array[:length], int_array[:length]
+    if mobility_array is not None and len(mobility_array) != len(mz_array):
+        _warn(warnings, "length_mismatch", "ion mobility",
+              f"ion mobility array has {len(mobility_array)} values for {len(mz_array)} peaks; it is left out")
+        mobility_array = None
+    return mz_array, int_array, mobility_array
+
+def _inflate(data: bytes, bufsize: int, warnings: Optional[List[Dict]] = None, array_name: str = "binary") -> bytes:
+    """
+    zlib-inflates into an output buffer of bufsize bytes. With a warnings list a stream that
+    ends early is read as far as it goes (see decode_data); intact streams, the usual case,
+    still take the presized single pass.
+    """
+    try:
+        return zlib.decompress(data, bufsize=bufsize)
+    except zlib.error:
+        if warnings is None:
+            raise
+    inflater = zlib.decompressobj()
+    decoded = inflater.decompress(data)
+    if not inflater.eof:
+        warnings.append({"code": "truncated_array", "array": array_name,
+                         "message": f"{array_name} array's zlib stream ends early; kept the {len(decoded)} bytes decoded"})
+    return decoded
+
+def decode_data(b64_string: str, dtype_str: Optional[str], compression: str, big_endian: bool = False,
+                 warnings: Optional[List[Dict]] = None, array_name: str = "binary",
+                 expected_length: Optional[int] = None) -> np.ndarray:
+    """
+    Decodes one binary array. The result is always float64: 64-bit input is returned as is
+    and narrower types are widened, so sub-ppm m/z values are never rounded through float32.
+    With a warnings list, a cut-off zlib stream or a trailing partial value is dropped and
+    noted ("truncated_array") rather than raised. expected_length (the declared number of
+    values) sizes the zlib output buffer up front instead of growing it while inflating.
+    """
+    # a2b_base64 takes the text as is, without an ASCII-encoded copy, and skips whitespace
+    decoded = binascii.a2b_base64(b64_string)
+    if compression == 'zlib' or compression.endswith('+zlib'):
+        itemsize = np.dtype(NP_DTYPE_MAPPING[dtype_str]).itemsize if dtype_str in NP_DTYPE_MAPPING else 8
+        bufsize = max(expected_length * itemsize, 1) if expected_length else zlib.DEF_BUF_SIZE
+        decoded = _inflate(decoded, bufsize, warnings, array_name)
+    if compression.startswith('numpress-'):
+        # Numpress always decodes to 64-bit values, whatever precision term is present
+        scheme = compression[len('numpress-'):].split('+')[0]
+        return NUMPRESS_DECODERS[scheme](decoded)
+        
+    # Map dtype, in the file's byte order whatever the host's
+    dt = np.dtype(NP_DTYPE_MAPPING[dtype_str]).newbyteorder('>' if big_endian else '<')
+    count = -1
+    if len(decoded) % dt.itemsize and warnings is not None:
+        warnings.append({"code": "truncated_array", "array": array_name,
+                         "message": f"{array_name} array has {len(decoded) % dt.itemsize} bytes after its last whole value"})
+        count = len(decoded) // dt.itemsize
+    # One vectorized view of the buffer; only non-native or narrower types are converted (and
+    # byte-swapped) in a single pass, native float64 is returned without a copy
+    values = np.frombuffer(decoded, dtype=dt, count=count)
+    return values if values.dtype == np.float64 else values.astype(np.float64)
+
+# Writing: one <spectrum> per input, zlib-compressed 64-bit arrays, indexedmzML wrapper
+MZML_NAMESPACE = "http://psi.hupo.org/ms/mzml"
+ACTIVATION_TERMS = {name: accession for accession, name in ACTIVATION_ACCESSIONS.items()}
+CV_TERM_NAMES = {
+    'MS:1000511': 'ms level', 'MS:1000579': 'MS1 spectrum', 'MS:1000580': 'MSn spectrum',
+    'MS:1000127': 'centroid spectrum', 'MS:1000128': 'profile spectrum', 'MS:1000130': 'positive scan', 'MS:1000129': 'negative scan',
+    'MS:1000285': 'total ion current', 'MS:1000504': 'base peak m/z', 'MS:1000505': 'base peak intensity',
+    'MS:1000016': 'scan start time', 'MS:1000512': 'filter string', 'MS:1000795': 'no combination',
+    'MS:1000744': 'selected ion m/z', 'MS:1000041': 'charge state', 'MS:1000042': 'peak intensity',
+    'MS:1000827': 'isolation window target m/z', 'MS:1000828': 'isolation window lower offset',
+    'MS:1000829': 'isolation window upper offset', 'MS:1000045': 'collision energy',
+    'MS:1000514': 'm/z array', 'MS:1000515': 'intensity array', 'MS:1000523': '64-bit float',
+    'MS:1000574': 'zlib compression', 'MS:1000133': 'collision-induced dissociation',
+    'MS:1000422': 'beam-type collision-induced dissociation', 'MS:1000598': 'electron transfer dissociation',
+    'MS:1000250': 'electron capture dissociation', 'MS:1002631': 'Electron-Transfer/Higher-Energy Collision Dissociation (EThcD)',
+    'MS:1000262': 'infrared multiphoton dissociation', 'MS:1000435': 'photodissociation',
+    'MS:1003246': 'ultraviolet photodissociation',
+}
+CV_UNITS = {
+    'MS:1000504': ('MS:1000040', 'm/z'), 'MS:1000744': ('MS:1000040', 'm/z'),
+    'MS:1000827': ('MS:1000040', 'm/z'), 'MS:1000828': ('MS:1000040', 'm/z'),
+    'MS:1000829': ('MS:1000040', 'm/z'), 'MS:1000514': ('MS:1000040', 'm/z'),
+    'MS:1000505': ('MS:1000131', 'number of detector counts'), 'MS:1000515': ('MS:1000131', 'number of detector counts'),
+    'MS:1000042': ('MS:1000131', 'number of detector counts'),
+    'MS:1000016': ('UO:0000010', 'second'), 'MS:1000045': ('UO:0000266', 'electronvolt'),
+}
+
+def _cv_param(accession: str, value=None) -> str:
+    attrs = f'cvRef="{accession.split(":")[0]}" accession="{accession}" name="{html.escape(CV_TERM_NAMES[accession])}"'
+    attrs += f' value="{html.escape(str(value))}"' if value is not None else ' value=""'
+    if accession in CV_UNITS:
+        unit, unit_name = CV_UNITS[accession]
+        attrs += f' unitCvRef="{unit.split(":")[0]}" unitAccession="{unit}" unitName="{unit_name}"'
+    return f'<cvParam {attrs}/>'
+
+def _binary_array_xml(values: np.ndarray, accession: str) -> str:
+    data = base64.b64encode(zlib.compress(np.asarray(values, dtype='<f8').tobytes())).decode('ascii')
+    return (f'<binaryDataArray encodedLength="{len(data)}">'
+            f'{_cv_param("MS:1000523")}{_cv_param("MS:1000574")}{_cv_param(accession)}'
+            f'<binary>{data}</binary></binaryDataArray>')
+
+def _writer_input(spectrum) -> Tuple[np.ndarray, np.ndarray, Dict]:
+    """(m/z, intensity, summary) of a peak list, a Spectrum or a summary dict with "peaks"."""
+    if isinstance(spectrum, dict):
+        peaks, summary = spectrum.get("peaks") or [], spectrum
+    elif hasattr(spectrum, "mz") and hasattr(spectrum, "intensity"):
+        return np.asarray(spectrum.mz, dtype=np.float64), np.asarray(spectrum.intensity, dtype=np.float64), \
+            dict(spectrum.metadata or {}, scan_nr=spectrum.metadata.get("scan_nr", spectrum.scan_nr))
+    else:
+        peaks, summary = spectrum, {}
+    mz = np.array([float(p["mz"]) for p in peaks], dtype=np.float64)
+    intensity = np.array([float(p["intensity"]) for p in peaks], dtype=np.float64)
+    return mz, intensity, summary
+
+def _spectrum_xml(index: int, mz: np.ndarray, intensity: np.ndarray, summary: Dict) -> Tuple[str, str]:
+    """(nativeID, <spectrum> XML) for one spectrum."""
+    scan_nr = summary.get("scan_nr")
+    native_id = summary.get("id") or f"scan={scan_nr if scan_nr is not None else index + 1}"
+    ms_level = summary.get("ms_level") or (2 if summary.get("precursors") else 1)
+    params = [_cv_param('MS:1000511', ms_level), _cv_param('MS:1000579' if ms_level == 1 else 'MS:1000580'),
+              _cv_param('MS:1000128' if summary.get("spectrum_type") == "profile" else 'MS:1000127')]
+    if summary.get("polarity") in ("positive", "negative"):
+        params.append(_cv_param('MS:1000130' if summary["polarity"] == "positive" else 'MS:1000129'))
+    # TIC and base peak describe the arrays actually written, not the original scan
+    if len(mz):
+        top = int(np.argmax(intensity))
+        params += [_cv_param('MS:1000285', float(intensity.sum())), _cv_param('MS:1000504', float(mz[top])),
+                   _cv_param('MS:1000505', float(intensity[top]))]
+
+    scan = []
+    if summary.get("retention_time") is not None:
+        scan.append(_cv_param('MS:1000016', summary["retention_time"]))
+    if summary.get("filter_string"):
+        scan.append(_cv_param('MS:1000512', summary["filter_string"]))
+    parts = [f'<spectrum index="{index}" id="{html.escape(native_id)}" defaultArrayLength="{len(mz)}">',
+             *params,
+             f'<scanList count="1">{_cv_param("MS:1000795")}<scan>{"".join(scan)}</scan></scanList>']
+
+    precursors = summary.get("precursors") or []
+    if precursors:
+        parts.append(f'<precursorList count="{len(precursors)}">')
+        for precursor in precursors:
+            ref = f' spectrumRef="{html.escape(precursor["spectrum_ref"])}"' if precursor.get("spectrum_ref") else ''
+            parts.append(f'<precursor{ref}>')
+            if precursor.get("isolation_target") is not None:
+                parts.append('<isolationWindow>' + _cv_param('MS:1000827', precursor["isolation_target"]) +
+                             ''.join(_cv_param(acc, precursor[key]) for acc, key in
+                                     (('MS:1000828', "isolation_lower_offset"), ('MS:1000829', "isolation_upper_offset"))
+                                     if precursor.get(key) is not None) + '</isolationWindow>')
+            ion = [_cv_param(acc, precursor[key]) for acc, key in
+                   (('MS:1000744', "mz"), ('MS:1000041', "charge"), ('MS:1000042', "intensity"))
+                   if precursor.get(key) is not None]
+            parts.append(f'<selectedIonList count="1"><selectedIon>{"".join(ion)}</selectedIon></selectedIonList>')
+            activation = [_cv_param(ACTIVATION_TERMS[name]) for name in precursor.get("activation") or []
+                          if name in ACTIVATION_TERMS]
+            if precursor.get("collision_energy") is not None:
+                activation.append(_cv_param('MS:1000045', precursor["collision_energy"]))
+            parts.append(f'<activation>{"".join(activation)}</activation></precursor>')
+        parts.append('</precursorList>')
+
+    parts += ['<binaryDataArrayList count="2">', _binary_array_xml(mz, 'MS:1000514'),
+              _binary_array_xml(intensity, 'MS:1000515'), '</binaryDataArrayList></spectrum>']
+    return native_id, "".join(parts)
+
+def write_mzml(spectra: List, metadata: Optional[List[Dict]] = None, run_id: str = "protview") -> bytes:
+    """
+    Serialises spectra to an indexedmzML document. Each spectrum is a peak list, a Spectrum,
+    or a dict with "peaks" in the shape of spectrum_summary; metadata[i], when given, adds or
+    overrides summary fields (id, scan_nr, ms_level, retention_time, polarity, precursors, ...).
+    Arrays are written as zlib-compressed 64-bit floats, sorted by m/z.
+    """
+    if metadata is not None and len(metadata) != len(spectra):
+        raise ValueError(f"metadata has {len(metadata)} entries for {len(spectra)} spectra")
+    head = (f'<?xml version="1.0" encoding="utf-8"?>\n'
+            f'<indexedmzML xmlns="{MZML_NAMESPACE}" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">\n'
+            f'<mzML xmlns="{MZML_NAMESPACE}" version="1.1.0">\n'
+            '<cvList count="2"><cv id="MS" fullName="Proteomics Standards Initiative Mass Spectrometry Ontology" '
+            'URI="https://raw.githubusercontent.com/HUPO-PSI/psi-ms-CV/master/psi-ms.obo"/>'
+            '<cv id="UO" fullName="Unit Ontology" '
+            'URI="https://raw.githubusercontent.com/bio-ontology-research-group/unit-ontology/master/unit.obo"/></cvList>\n'
+            '<fileDescription><fileContent>' + _cv_param('MS:1000580') + '</fileContent></fileDescription>\n'
+            '<softwareList count="1"><software id="ProtView" version="1.0"/></softwareList>\n'
+            '<instrumentConfigurationList count="1"><instrumentConfiguration id="IC1"/></instrumentConfigurationList>\n'
+            '<dataProcessingList count="1"><dataProcessing id="ProtView_processing">'
+            '<processingMethod order="0" softwareRef="ProtView"/></dataProcessing></dataProcessingList>\n'
+            f'<run id="{html.escape(run_id)}" defaultInstrumentConfigurationRef="IC1">\n'
+            f'<spectrumList count="{len(spectra)}" defaultDataProcessingRef="ProtView_processing">\n').encode('utf-8')
+    chunks = [head]
+    offsets = []
+    position = len(head)
+    for i, spectrum in enumerate(spectra):
+        mz, intensity, summary = _writer_input(spectrum)
+        if metadata:
+            summary = {**summary, **metadata[i]}
+        if len(mz) != len(intensity):
+            raise ValueError(f"spectrum {i}: m/z and intensity arrays differ in length ({len(mz)} vs {len(intensity)})")
+        order = np.argsort(mz, kind="stable")
+        native_id, xml = _spectrum_xml(i, mz[order], intensity[order], summary)
+        data = (xml + "\n").encode('utf-8')
+        offsets.append((native_id, position))
+        chunks.append(data)
+        position += len(data)
+    chunks.append(b'</spectrumList>\n</run>\n</mzML>\n')
+    position += len(chunks[-1])
+    index = ('<indexList count="1">\n<index name="spectrum">\n' +
+             "".join(f'<offset idRef="{html.escape(native_id)}">{offset}</offset>\n' for native_id, offset in offsets) +
+             '</index>\n</indexList>\n').encode('utf-8')
+    chunks.append(index)
+    chunks.append(f'<indexListOffset>{position}</indexListOffset>\n</indexedmzML>\n'.encode('utf-8'))
+    return b"".join(chunks)
@@ -0,0 +1,129 @@
+"""
+HUPO-PSI mzPAF peak annotations ("b5-H2O^2/-3.4ppm"): written for ProtView matches and
+parsed back into their parts, so annotations travel between viewers and libraries.
+"""
+
+import re
+from typing import Dict, List, Optional
+
+# Crosslink peptide marks (crosslinks.PEPTIDE_MARKS) as mzPAF analyte numbers
+ANALYTE_MARKS = {"α": 1, "β": 2}
+MZPAF_ERROR_DECIMALS = 1
+
+# ProtView ion labels: "b5", "y3-H2O", "b5α-H2O+partner", "M-H3PO4", "oxo-HexNAc"
+PROTVIEW_LABEL = re.compile(r'^(?P<series>[abcxyz])(?P<index>\d+)(?P<mark>[αβ]?)(?P<losses>(?:-[A-Za-z0-9]+)*)'
+                            r'(?:\+(?P<link>.+))?$')
+PRECURSOR_LABEL = re.compile(r'^M(?P<mark>[αβ]?)(?P<losses>(?:-[A-Za-z0-9]+)*)(?:\+(?P<link>.+))?$')
+
+# One mzPAF annotation: [analyte@]ion[losses][isotope][adduct][^charge][/error][*confidence]
+MZPAF = re.compile(r'''^(?:(?P<analyte>\d+)@)?
+    (?P<ion>\?|[abcxyz]\d+|p|I[A-Z](?:\[[^\]]+\])?|m\d+:\d+|r\[[^\]]+\]|_\{[^}]+\}|f\{[^}]+\}|s\{[^}]+\})
+    (?P<losses>(?:[+-](?:\d*[A-Z][A-Za-z0-9]*|\[[^\]]+\]))*)
+    (?P<isotope>(?:[+-]\d*i)*)
+    (?:\[(?P<adduct>M[^\]]*)\])?
+    (?:\^(?P<charge>\d+))?
+    (?:/(?P<error>-?\d+(?:\.\d*)?(?:[eE][+-]?\d+)?)(?P<unit>ppm)?)?
+    (?:\*(?P<confidence>\d*\.?\d+))?$''', re.VERBOSE)
+LOSS = re.compile(r'[+-](?:\d*[A-Z][A-Za-z0-9]*|\[[^\]]+\])')
+ISOTOPE = re.compile(r'([+-])(\d*)i')
+
+def _analyte(match: dict) -> Optional[int]:
+    # Chimeric matches carry the 0-based index of their peptide (crosslinks mark the label instead)
+    peptide = match.get("peptide")
+    return peptide + 1 if isinstance(peptide, int) and not isinstance(peptide, bool) else None
+
+def mzpaf_ion(ion_type: str, charge: int = 1, isotope: int = 0, analyte: Optional[int] = None,
+              error: Optional[float] = None, error_unit: str = "ppm", decimals: int = MZPAF_ERROR_DECIMALS) -> str:
+    """
+    mzPAF for one ProtView ion label: b/y-type ions and their losses as written, "M..."
+    precursor ions as "p", oxonium ions ("oxo-HexNAc") as the named compound _{HexNAc},
+    and crosslink link suffixes ("+partner", "+stub") as bracketed additions; the α/β
+    mark of a crosslink label sets the analyte. Labels with no mzPAF form (glycan Y ions)
+    give "?".
+    """
+    body = None
+    mark = ""
+    label = PROTVIEW_LABEL.match(ion_type)
+    precursor = PRECURSOR_LABEL.match(ion_type)
+    if label or precursor:
+        parts = label or precursor
+        mark = parts.group("mark")
+        body = (f"{parts.group('series')}{parts.group('index')}" if label else "p") + parts.group("losses")
+        if parts.group("link"):
+            body += f"+[{parts.group('link')}]"
+    elif ion_type.startswith("oxo-"):
+        body = f"_{{{ion_type[4:]}}}"
+    if body is None:
+        return "?"
+    if mark:
+        analyte = ANALYTE_MARKS[mark]
+    text = (f"{analyte}@" if analyte else "") + body
+    if isotope:
+        text += f"{'+' if isotope > 0 else '-'}{abs(isotope) if abs(isotope) != 1 else ''}i"
+    if charge and abs(charge) != 1:
+        text += f"^{abs(charge)}"
+    if error is not None:
+        text += f"/{error:.{decimals}f}" + ("ppm" if error_unit == "ppm" else "")
+    return text
+
+def format_mzpaf(match: dict, error_unit: str = "ppm", decimals: int = MZPAF_ERROR_DECIMALS) -> str:
+    """
+    mzPAF of a match_ions (or top-down) match, with its signed error in ppm or Da
+    (error_unit "da" writes the bare number, mzPAF's Da default).
+    """
+    error = match.get("error_ppm") if error_unit == "ppm" else match.get("error_da")
+    return mzpaf_ion(match["ion_type"], match.get("ion_charge", match.get("charge", 1)),
+                     match.get("isotope_error", 0), _analyte(match), error, error_unit, decimals)
+
+def _parse_one(text: str) -> Dict:
+    parts = MZPAF.match(text)
+    if not parts:
+        raise ValueError(f"Invalid mzPAF annotation {text!r}")
+    ion = parts.group("ion")
+    series = re.match(r'[abcxyz](?=\d)', ion)
+    losses = LOSS.findall(parts.group("losses"))
+    isotope = sum((1 if sign == '+' else -1) * int(count or 1) for sign, count in ISOTOPE.findall(parts.group("isotope")))
+    if (series or ion == "p") and all(re.match(r'-[A-Z]', loss) for loss in losses):
+        # The ProtView label of the same ion: "b5-H2O", "M-H3PO4"
+        ion_type = ("M" if ion == "p" else ion) + "".join(losses)
+    elif ion.startswith("_{"):
+        ion_type = f"oxo-{ion[2:-1]}"
+    else:
+        ion_type = None
+    return {
+        "annotation": text,
+        "analyte": int(parts.group("analyte")) if parts.group("analyte") else None,
+        "ion": ion,
+        "series": series.group(0) if series else ("p" if ion == "p" else None),
+        "index": int(ion[1:]) if series else None,
+        "losses": losses,
+        "isotope": isotope,
+        "adduct": parts.group("adduct"),
+        "charge": int(parts.group("charge") or 1),
+        "error": float(parts.group("error")) if parts.group("error") else None,
+        "error_unit": ("ppm" if parts.group("unit") else "da") if parts.group("error") else None,
+        "confidence": float(parts.group("confidence")) if parts.group("confidence") else None,
+        "ion_type": ion_type,
+    }
+
+def parse_mzpaf(text: str) -> List[Dict]:
+    """
+    The comma-separated annotations of one peak, each split into "analyte", "ion" ("b5",
+    "p", "IY", "m3:6", "r[TMT126]", "?"), "series"/"index" for backbone ions, "losses"
+    (signed, "-H2O", "+[Phospho]"), "isotope", "adduct", "charge", "error" with
+    "error_unit" ("ppm" or "da"), "confidence" and, where ProtView has one, "ion_type".
+    """
+    annotations = []
+    depth = 0
+    start = 0
+    # Commas inside [...] or {...} (formulas, SMILES) do not separate annotations
+    for i, char in enumerate(text):
+        if char in '[{':
+            depth += 1
+        elif char in ']}':
+            depth -= 1
+        elif char == ',' and depth == 0:
+            annotations.append(text[start:i])
+            start = i + 1
+    annotations.append(text[start:])
+    return [_parse_one(a.strip()) for a in annotations if a.strip()]
@@ -0,0 +1,193 @@
+"""
+HUPO-PSI mzSpecLib text format: reading into the library entries of library.parse_msp
+(ProForma peptidoforms resolved to [mass] deltas, mzPAF peak annotations kept as written)
+and writing library entries back out.
+"""
+
+import re
+from typing import Dict, Iterable, List, Optional, Tuple, Union
+
+from .decoys import format_peptide
+from .deltamass import UNIMOD_ACCESSIONS, UNIMOD_DELTAS
+from .mzid import optional_float
+
+MZSPECLIB_VERSION = "1.0"
+# Attributes read into the entry; everything else stays in "comment"
+SPECTRUM_NAME = "MS:1003061"
+SELECTED_ION_MZ = "MS:1000744"
+PRECURSOR_MZ = "MS:1003208"
+CHARGE_STATE = "MS:1000041"
+PROFORMA = "MS:1003169"
+PROTEIN_ACCESSION = "MS:1000885"
+SPECTRUM_ORIGIN = "MS:1003072"
+DECOY_ORIGIN = "MS:1003192"
+
+SECTION = re.compile(r'^<(\w+)(?:=([^>]*))?>\s*$')
+# "[1]MS:1000041|charge state=2": optional attribute group, accession|name=value
+ATTRIBUTE = re.compile(r'^(?:\[(\d+)\])?([^|=]+)(?:\|([^=]*))?=(.*)$')
+PROFORMA_MOD = re.compile(r'\[([^\]]*)\]')
+
+def proforma_mass(modification: str) -> float:
+    """Mass delta of a ProForma modification: "+15.995", "Oxidation", "U:Oxidation" or "UNIMOD:35"."""
+    value = modification.split('|')[0].strip()
+    number = optional_float(value)
+    if number is not None:
+        return number
+    kind, _, name = value.partition(':')
+    if not name:
+        kind, name = 'U', value
+    if kind.upper() == 'UNIMOD' and name.isdigit() and int(name) in UNIMOD_ACCESSIONS:
+        return UNIMOD_DELTAS[UNIMOD_ACCESSIONS[int(name)]]
+    if kind.upper() in ('U', 'UNIMOD'):
+        for unimod, mass in UNIMOD_DELTAS.items():
+            if unimod.lower() == name.lower():
+                return mass
+    raise ValueError(f"Unknown ProForma modification [{modification}]")
+
+def parse_proforma(sequence: str) -> Tuple[str, Optional[int]]:
+    """
+    A ProForma peptidoform ("[Acetyl]-PEPM[Oxidation]TIDEK/2") as its [mass] delta form
+    and charge (None without a /z suffix). Only single modifications on a residue or
+    terminus are supported.
+    """
+    sequence, _, charge = sequence.strip().partition('/')
+    tokens: List[List] = []
+    n_term = c_term = 0.0
+    position = 0
+    text = sequence
+    if text.startswith('['):
+        end = text.index(']-') if ']-' in text else -1
+        if end < 0:
+            raise ValueError(f"Malformed ProForma N-terminal modification in {sequence!r}")
+        n_term = sum(proforma_mass(m) for m in PROFORMA_MOD.findall(text[:end + 1]))
+        text = text[end + 2:]
+    c_start = text.rfind('-[')
+    if c_start >= 0:
+        c_term = sum(proforma_mass(m) for m in PROFORMA_MOD.findall(text[c_start + 1:]))
+        text = text[:c_start]
+    for match in PROFORMA_MOD.finditer(text):
+        tokens.extend([r, 0.0] for r in text[position:match.start()] if r.isalpha())
+        if not tokens:
+            raise ValueError(f"ProForma modification [{match.group(1)}] before any residue in {sequence!r}")
+        tokens[-1][1] += proforma_mass(match.group(1))
+        position = match.end()
+    tokens.extend([r, 0.0] for r in text[position:] if r.isalpha())
+    return format_peptide([tuple(t) for t in tokens], n_term, c_term), int(charge) if charge.isdigit() else None
+
+def _attribute_value(attributes: List[Tuple[str, str, str]], accession: str) -> Optional[str]:
+    return next((value for acc, _, value in attributes if acc == accession), None)
+
+def _finish_spectrum(key: str, attributes: List[Tuple[str, str, str]], analytes: List[List[Tuple[str, str, str]]],
+                     peaks: List[dict], index: int) -> Dict:
+    analyte = analytes[0] if analytes else []
+    proforma = _attribute_value(analyte, PROFORMA) or ''
+    try:
+        sequence, proforma_charge = parse_proforma(proforma) if proforma else ('', None)
+    except ValueError as e:
+        raise ValueError(f"mzSpecLib spectrum {key}: {e}") from None
+    charge = optional_float(_attribute_value(attributes, CHARGE_STATE) or _attribute_value(analyte, CHARGE_STATE))
+    precursor = optional_float(_attribute_value(attributes, SELECTED_ION_MZ) or _attribute_value(attributes, PRECURSOR_MZ))
+    charge_value = int(charge) if charge else proforma_charge
+    name = _attribute_value(attributes, SPECTRUM_NAME) or (f"{proforma.split('/')[0]}/{charge_value}" if proforma else key)
+    comment = {f"{acc}|{label}" if label else acc: value for acc, label, value in attributes + analyte}
+    return {
+        "index": index,
+        "name": name,
+        "sequence": sequence,
+        "charge": charge_value,
+        "precursor_mz": precursor,
+        "proteins": [value for acc, _, value in analyte if acc == PROTEIN_ACCESSION],
+        "is_decoy": any(acc == SPECTRUM_ORIGIN and DECOY_ORIGIN in value for acc, _, value in attributes),
+        "comment": comment,
+        "peaks": sorted(peaks, key=lambda p: p["mz"]),
+    }
+
+def parse_mzspeclib(text: Union[str, Iterable[str]]) -> List[Dict]:
+    """
+    Spectra of an mzSpecLib text library (text or an iterable of lines) as library entries
+    (see library.parse_msp) in file order. The first Analyte supplies the sequence
+    (ProForma), proteins and, failing the spectrum's, the charge; "comment" keeps every
+    spectrum and analyte attribute as "accession|name" -> value. Each peak's annotations
+    are its mzPAF strings, e.g. ["b2/0.5ppm", "y1^2/-1.1ppm"].
+    """
+    lines = text.splitlines() if isinstance(text, str) else text
+    entries: List[Dict] = []
+    current: Optional[Dict] = None
+    section = None
+    line_nr = 0
+
+    def finish():
+        if current is not None:
+            entries.append(_finish_spectrum(current["key"], current["attributes"], current["analytes"],
+                                            current["peaks"], len(entries)))
+
+    for line_nr, line in enumerate(lines, 1):
+        line = line.strip()
+        if not line or line.startswith('#'):
+            continue
+        header = SECTION.match(line)
+        if header:
+            section = header.group(1)
+            if section == 'Spectrum':
+                finish()
+                current = {"key": header.group(2), "attributes": [], "analytes": [], "peaks": []}
+            elif section == 'Analyte' and current is not None:
+                current["analytes"].append([])
+            continue
+        if current is None or section in ('mzSpecLib', 'AttributeSet', 'Cluster'):
+            continue
+        if section == 'Peaks':
+            fields = line.split('\t')
+            try:
+                mz, intensity = float(fields[0]), float(fields[1])
+            except (IndexError, ValueError):
+                raise ValueError(f"mzSpecLib line {line_nr}: expected 'm/z<TAB>intensity[<TAB>annotation]', got {line!r}") from None
+            annotations = [a.strip() for a in fields[2].split(',') if a.strip() and a.strip() != '?'] if len(fields) > 2 else []
+            current["peaks"].append({"mz": mz, "intensity": intensity, "annotations": annotations})
+            continue
+        attribute = ATTRIBUTE.match(line)
+        if not attribute:
+            raise ValueError(f"mzSpecLib line {line_nr}: expected 'accession|name=value', got {line!r}")
+        entry = (attribute.group(2).strip(), (attribute.group(3) or '').strip(), attribute.group(4).strip())
+        if section == 'Analyte' and current["analytes"]:
+            current["analytes"][-1].append(entry)
+        elif section == 'Spectrum':
+            current["attributes"].append(entry)
+    finish()
+    return entries
+
+def is_mzspeclib(text: str) -> bool:
+    """Whether library text starts with the <mzSpecLib> header (comments aside)."""
+    for line in text.lstrip('﻿').splitlines():
+        if line.strip() and not line.startswith('#'):
+            return line.strip().startswith('<mzSpecLib')
+    return False
+
+def write_mzspeclib(entries: Iterable[Dict], library_name: str = "ProView library") -> str:
+    """
+    mzSpecLib text of library entries (see parse_msp): name, precursor m/z and charge per
+    spectrum, one analyte with the sequence ([mass] deltas are valid ProForma), charge and
+    proteins, then the peaks with their annotations comma-separated.
+    """
+    lines = ["<mzSpecLib>", f"MS:1003186|library format version={MZSPECLIB_VERSION}",
+             f"MS:1003188|library name={library_name}"]
+    for key, entry in enumerate(entries, 1):
+        lines.append(f"<Spectrum={key}>")
+        lines.append(f"{SPECTRUM_NAME}|library spectrum name={entry['name']}")
+        if entry.get("precursor_mz") is not None:
+            lines.append(f"{SELECTED_ION_MZ}|selected ion m/z={entry['precursor_mz']}")
+        if entry.get("charge"):
+            lines.append(f"{CHARGE_STATE}|charge state={entry['charge']}")
+        if entry.get("is_decoy"):
+            lines.append(f"{SPECTRUM_ORIGIN}|spectrum origin type={DECOY_ORIGIN}|decoy spectrum")
+        if entry.get("sequence"):
+            lines.append("<Analyte=1>")
+            lines.append(f"{PROFORMA}|proforma peptidoform sequence={entry['sequence']}")
+            if entry.get("charge"):
+                lines.append(f"{CHARGE_STATE}|charge state={entry['charge']}")
+            lines.extend(f"{PROTEIN_ACCESSION}|protein accession={p}" for p in entry.get("proteins") or [])
+        lines.append("<Peaks>")
+        for peak in entry["peaks"]:
+            annotations = ",".join(peak.get("annotations") or []) or "?"
+            lines.append(f"{peak['mz']}\t{peak['intensity']}\t{annotations}")
+    return "\n".join(lines) + "\n"
@@ -0,0 +1,148 @@
+"""
+mzTab 1.0 identifications: the PSM and PEP sections, with mzTab modification strings
+resolved to [mass] deltas and spectra_ref resolved through the ms_run metadata.
+"""
+
+import io
+import re
+from typing import Dict, List, Optional, Tuple
+
+from .decoys import format_peptide
+from .deltamass import UNIMOD_ACCESSIONS, UNIMOD_DELTAS
+from .mzid import optional_float
+from .mzml import SCAN_IN_ID, open_source
+
+# "3-UNIMOD:35", "0-UNIMOD:1", "3|4-UNIMOD:21", "8[MS,MS:1001876,probability,0.9]-CHEMMOD:+15.9949"
+MODIFICATION = re.compile(r'^(?:(\d+)(?:\[[^\]]*\])?(?:\|\d+(?:\[[^\]]*\])?)*-)?(\w+:[^\[]+?)(?:\[.*\])?$')
+SPECTRA_REF = re.compile(r'ms_run\[(\d+)\]:(.*)')
+DECOY_COLUMN = 'opt_global_cv_MS:1002217_decoy_peptide'
+
+def _split_modifications(value: str) -> List[str]:
+    # Commas inside [...] parameters do not separate modifications
+    parts, depth, current = [], 0, ''
+    for char in value:
+        if char == ',' and depth == 0:
+            parts.append(current)
+            current = ''
+            continue
+        depth += char == '['
+        depth -= char == ']'
+        current += char
+    parts.append(current)
+    return [p.strip() for p in parts if p.strip()]
+
+def modification_mass(modification: str) -> float:
+    """Mass delta of one mzTab modification: UNIMOD:<id> (of UNIMOD_ACCESSIONS) or CHEMMOD:<mass>."""
+    kind, _, value = modification.partition(':')
+    if kind == 'CHEMMOD':
+        mass = optional_float(value)
+        if mass is None:
+            raise ValueError(f"mzTab modification {modification!r}: only CHEMMOD masses are supported, not formulas")
+        return mass
+    if kind == 'UNIMOD' and value.isdigit() and int(value) in UNIMOD_ACCESSIONS:
+        return UNIMOD_DELTAS[UNIMOD_ACCESSIONS[int(value)]]
+    raise ValueError(f"Unknown mzTab modification {modification!r}")
+
+def parse_mztab_sequence(sequence: str, modifications: Optional[str]) -> str:
+    """
+    The peptide with its mzTab modifications ("3-UNIMOD:35,0-UNIMOD:1"; position 0 is
+    the N-terminus, length + 1 the C-terminus) as [mass] deltas. Ambiguous positions
+    ("3|4-UNIMOD:21") take the first; modifications without a position are skipped.
+    """
+    tokens = [[residue, 0.0] for residue in sequence]
+    n_term = c_term = 0.0
+    if modifications and modifications != 'null':
+        for modification in _split_modifications(modifications):
+            match = MODIFICATION.match(modification)
+            if not match:
+                raise ValueError(f"Malformed mzTab modification {modification!r} on {sequence}")
+            if match.group(1) is None:
+                continue
+            position, mass = int(match.group(1)), modification_mass(match.group(2).strip())
+            if position == 0:
+                n_term += mass
+            elif position > len(tokens):
+                c_term += mass
+            else:
+                tokens[position - 1][1] += mass
+    return format_peptide([tuple(t) for t in tokens], n_term, c_term)
+
+def _score_names(metadata: Dict[str, str], prefix: str) -> Dict[int, str]:
+    # psm_search_engine_score[1] = [MS, MS:1002252, Comet:xcorr, ]
+    names = {}
+    for key, value in metadata.items():
+        match = re.fullmatch(rf'{prefix}_search_engine_score\[(\d+)\]', key)
+        if match:
+            fields = [f.strip() for f in value.strip('[]').split(',')]
+            names[int(match.group(1))] = fields[2] if len(fields) > 2 and fields[2] else key
+    return names
+
+def _row(header: List[str], fields: List[str]) -> Dict[str, Optional[str]]:
+    return {name: (value if value != 'null' else None) for name, value in zip(header, fields)}
+
+def _psm(row: Dict, metadata: Dict[str, str], score_names: Dict[int, str], score_column: str) -> Dict:
+    spectra_ref = (row.get('spectra_ref') or '').split('|')[0]
+    ref = SPECTRA_REF.match(spectra_ref)
+    scan = SCAN_IN_ID.search(spectra_ref)
+    scores = {}
+    for column, value in row.items():
+        match = re.fullmatch(rf'{score_column}\[(\d+)\]', column)
+        if match and value is not None:
+            scores[score_names.get(int(match.group(1)), column)] = optional_float(value) if optional_float(value) is not None else value
+    charge = optional_float(row.get('charge'))
+    return {
+        "scan_nr": int(scan.group(1)) if scan else None,
+        "spec_id": ref.group(2) if ref else spectra_ref,
+        "spectra_data": metadata.get(f"ms_run[{ref.group(1)}]-location", '') if ref else '',
+        "sequence": parse_mztab_sequence(row.get('sequence') or '', row.get('modifications')),
+        "charge": int(charge) if charge else None,
+        "rank": 1,
+        "precursor_mz": optional_float(row.get('exp_mass_to_charge') or row.get('mass_to_charge')),
+        "calculated_mz": optional_float(row.get('calc_mass_to_charge')),
+        "retention_time": optional_float((row.get('retention_time') or '').split('|')[0]),
+        "pass_threshold": True,
+        "is_decoy": row.get(DECOY_COLUMN) == '1',
+        "proteins": [row['accession']] if row.get('accession') else [],
+        "scores": scores,
+    }
+
+def parse_mztab(source) -> Dict[str, List[Dict]]:
+    """
+    {"psms": [...], "peptides": [...]} of an mzTab file (bytes, path or binary file
+    object), from the PSM and PEP sections, with the fields of mzid.parse_mzid. "scan_nr"
+    comes from a scan= spectra_ref (the first of a PEP row's), "spectra_data" from the
+    referenced ms_run location, and "scores" are keyed by the search engine score names
+    of the metadata. Retention times are taken as written (seconds in mzTab 1.0).
+    """
+    metadata: Dict[str, str] = {}
+    headers: Dict[str, List[str]] = {}
+    rows: Dict[str, List[Tuple[int, List[str]]]] = {"PSM": [], "PEP": []}
+    stream = open_source(source)
+    try:
+        for line_nr, line in enumerate(io.TextIOWrapper(stream, encoding='utf-8-sig'), 1):
+            fields = line.rstrip('\r\n').split('\t')
+            prefix = fields[0]
+            if prefix == 'MTD' and len(fields) >= 3:
+                metadata[fields[1]] = fields[2]
+            elif prefix in ('PSH', 'PEH'):
+                headers[{'PSH': 'PSM', 'PEH': 'PEP'}[prefix]] = fields
+            elif prefix in rows:
+                if prefix not in headers:
+                    raise ValueError(f"mzTab line {line_nr}: {prefix} row before its header line")
+                rows[prefix].append((line_nr, fields))
+    finally:
+        if stream is not source:
+            stream.close()
+
+    result = {}
+    for section, key, prefix, score_column in (("PSM", "psms", "psm", "search_engine_score"),
+                                               ("PEP", "peptides", "peptide", "best_search_engine_score")):
+        score_names = _score_names(metadata, prefix)
+        entries = []
+        for line_nr, fields in rows[section]:
+            try:
+                entries.append(_psm(_row(headers[section], fields), metadata, score_names, score_column))
+            except ValueError as e:
+                raise ValueError(f"mzTab line {line_nr}: {e}") from e
+        result[key] = entries
+    return result
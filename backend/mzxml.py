@@ -0,0 +1,211 @@
+"""
+mzXML support: the same lazy, offset-indexed access as LazyMzmlReader for the older format.
+Peaks and metadata come out in the same shapes as the mzML reader, so callers need not care.
+"""
+
+import re
+import zlib
+from typing import Dict, Iterator, List, Optional, Tuple
+
+import numpy as np
+from lxml import etree
+
+from .mzml import LazyMzmlReader, arrays_to_peaks, decode_data, local_name, open_source, parse_xml_bytes
+
+MZXML_INDEX_OFFSET = re.compile(rb'<indexOffset>\s*(\d+)\s*</indexOffset>')
+MZXML_OFFSET = re.compile(rb'<offset\s+id="([^"]*)"[^>]*>\s*(\d+)\s*</offset>')
+SCAN_START_TAG = re.compile(rb'<scan\s[^>]*?\bnum="(\d+)"')
+
+# xs:duration as used for retentionTime, e.g. "PT35.52S" or "PT1M5.2S"
+DURATION = re.compile(r'-?P(?:(\d+(?:\.\d*)?)D)?(?:T(?:(\d+(?:\.\d*)?)H)?(?:(\d+(?:\.\d*)?)M)?(?:(\d+(?:\.\d*)?)S)?)?')
+
+# activationMethod attribute values -> the names used by mzml.ACTIVATION_ACCESSIONS
+ACTIVATION_METHODS = {"CID": "CID", "HCD": "HCD", "ETD": "ETD", "ECD": "ECD", "ETHCD": "EThcD"}
+
+class MzxmlReader(LazyMzmlReader):
+    """
+    Reads mzXML files on demand. Scans are keyed "scan=N" in id_index, so scan numbers,
+    selectors and the API behave as for mzML.
+    """
+
+    INDEX_LIST_OFFSET = MZXML_INDEX_OFFSET
+    INDEX_OFFSET = MZXML_OFFSET
+    INDEX_ELEMENTS = {"scan": ("spectrum", "scan")}
+    SPECTRUM_START_TAG = SCAN_START_TAG
+
+    def _offset_id(self, raw: bytes) -> str:
+        return f"scan={int(raw)}"
+
+    def get_spectrum_by_offset(self, offset: int, with_metadata: bool = False) -> Dict:
+        """
+        Parses the <scan> element starting at a byte offset. MS2 scans are nested inside
+        their MS1 scan, so the element is cut after its <peaks> and closed by hand.
+        """
+        scan = self._read_scan(offset)
+        peaks = parse_scan_peaks(scan)
+        if with_metadata:
+            return {**scan_summary(scan), "peaks": peaks}
+        return peaks
+
+    def get_spectrum_arrays_by_offset(self, offset: int) -> Dict:
+        scan = self._read_scan(offset)
+        mz_array, int_array = scan_peak_arrays(scan)
+        return {**scan_summary(scan), "mz": mz_array, "intensity": int_array, "ion_mobility": None}
+
+    def _read_scan(self, offset: int):
+        xml = self._read_element(offset, 'scan', end_tag=b'</peaks>') + b'</scan>'
+        return parse_xml_bytes(xml, "scan")
+
+    def get_chromatograms(self) -> List[Dict]:
+        # mzXML has no chromatogram section
+        return []
+
+    def _stream_spectra(self) -> Iterator[Dict]:
+        yield from self.list_spectra(include_peaks=True)
+
+    def list_spectra(self, include_peaks: bool = False) -> List[Dict]:
+        with self._open() as f:
+            return parse_mzxml_run(f, include_peaks)
+
+def parse_duration(value: Optional[str]) -> Optional[float]:
+    """Seconds in an xs:duration; plain numbers are taken as seconds."""
+    if value is None or value == '':
+        return None
+    try:
+        return float(value)
+    except ValueError:
+        pass
+    match = DURATION.fullmatch(value.strip())
+    if not match or not any(match.groups()):
+        raise ValueError(f"invalid retentionTime {value!r}")
+    days, hours, minutes, seconds = (float(g) if g else 0.0 for g in match.groups())
+    total = days * 86400 + hours * 3600 + minutes * 60 + seconds
+    return -total if value.strip().startswith('-') else total
+
+def _float_attr(element, name: str) -> Optional[float]:
+    value = element.get(name)
+    if value is None or value == '':
+        return None
+    try:
+        return float(value)
+    except ValueError:
+        raise ValueError(f"attribute {name} has non-numeric value {value!r}") from None
+
+def parse_scan_peaks(scan) -> List[Dict]:
+    """
+    Decodes the interleaved <peaks> of a <scan>: base64, optional zlib, 32/64-bit,
+    network (big-endian) byte order by default, pairOrder m/z-int.
+    """
+    return arrays_to_peaks(*scan_peak_arrays(scan))
+
+def scan_peak_arrays(scan) -> Tuple[np.ndarray, np.ndarray]:
+    """The (m/z, intensity) float64 arrays of parse_scan_peaks, as strided views of one decoded buffer."""
+    peaks_tag = scan.find("{*}peaks")
+    if peaks_tag is None or not (peaks_tag.text or '').strip():
+        return np.array([], dtype=np.float64), np.array([], dtype=np.float64)
+    precision = peaks_tag.get('precision', '32')
+    if precision not in ('32', '64'):
+        raise ValueError(f"unsupported peaks precision {precision!r}")
+    dtype_map = '64d' if precision == '64' else '32f'
+    compression = peaks_tag.get('compressionType', 'none')
+    if compression not in ('none', 'zlib'):
+        raise ValueError(f"unsupported peaks compressionType {compression!r}")
+    order = peaks_tag.get('pairOrder') or peaks_tag.get('contentType') or 'm/z-int'
+    if order not in ('m/z-int', 'mz-int'):
+        raise ValueError(f"unsupported peaks pairOrder {order!r}")
+    big_endian = peaks_tag.get('byteOrder', 'network') in ('network', 'big')
+
+    try:
+        values = decode_data(peaks_tag.text.strip(), dtype_map, compression, big_endian)
+    except (ValueError, zlib.error) as e:
+        raise ValueError(f"could not decode peaks ({compression}, {dtype_map}): {e}") from e
+    if len(values) % 2:
+        raise ValueError(f"peaks hold an odd number of values ({len(values)}), expected m/z-int pairs")
+    return values[0::2], values[1::2]
+
+def parse_scan_precursors(scan) -> List[Dict]:
+    """<precursorMz> elements in the shape of mzml.parse_precursors."""
+    precursors = []
+    for precursor in scan.findall("{*}precursorMz"):
+        text = (precursor.text or '').strip()
+        try:
+            mz = float(text) if text else None
+        except ValueError:
+            raise ValueError(f"precursorMz has non-numeric value {text!r}") from None
+        charge = precursor.get('precursorCharge')
+        width = _float_attr(precursor, 'windowWideness')
+        method = precursor.get('activationMethod')
+        ref = precursor.get('precursorScanNum')
+        precursors.append({
+            "mz": mz,
+            "charge": int(charge) if charge else None,
+            "intensity": _float_attr(precursor, 'precursorIntensity'),
+            "isolation_target": mz if width is not None else None,
+            "isolation_lower_offset": width / 2 if width is not None else None,
+            "isolation_upper_offset": width / 2 if width is not None else None,
+            "activation": [ACTIVATION_METHODS.get(method.upper(), method)] if method else [],
+            "collision_energy": _float_attr(scan, 'collisionEnergy'),
+            "spectrum_ref": f"scan={ref}" if ref else None,
+        })
+    return precursors
+
+def scan_summary(scan, index: int = -1) -> Dict:
+    """A <scan> element in the shape of mzml.spectrum_summary."""
+    num = scan.get('num')
+    ms_level = scan.get('msLevel')
+    polarity = {"+": "positive", "-": "negative"}.get(scan.get('polarity'))
+    return {
+        "index": index,
+        "id": f"scan={num}" if num else "",
+        "scan_nr": int(num) if num else None,
+        "peak_count": int(scan.get('peaksCount', 0)),
+        "retention_time": parse_duration(scan.get('retentionTime')),
+        "ms_level": int(ms_level) if ms_level else None,
+        "polarity": polarity,
+        "total_ion_current": _float_attr(scan, 'totIonCurrent'),
+        "base_peak_mz": _float_attr(scan, 'basePeakMz'),
+        "base_peak_intensity": _float_attr(scan, 'basePeakIntensity'),
+        "filter_string": scan.get('filterLine'),
+        "spectrum_type": {"1": "centroid", "0": "profile"}.get(scan.get('centroided')),
+        "precursors": parse_scan_precursors(scan),
+    }
+
+def parse_mzxml_run(source, include_peaks: bool = False) -> List[Dict]:
+    """
+    Every scan of an mzXML file (bytes, path or binary file object) as scan_summary entries,
+    in scan-number order (nested MS2 scans close before their parent MS1 scan).
+    """
+    scans = []
+    stream = open_source(source)
+    try:
+        for _, element in etree.iterparse(stream, events=("end",)):
+            if local_name(element) != 'scan':
+                continue
+            summary = scan_summary(element)
+            if include_peaks:
+                try:
+                    summary["peaks"] = parse_scan_peaks(element)
+                except ValueError as e:
+                    raise ValueError(f"Scan {summary['scan_nr']}: {e}") from e
+            scans.append(summary)
+            # Keep nested scans' elements out of the parent, but not the parent's own attributes
+            for child in list(element):
+                if local_name(child) in ('scan', 'peaks'):
+                    element.remove(child)
+    except etree.XMLSyntaxError as e:
+        raise ValueError(f"malformed mzXML after {len(scans)} scans: {e}") from e
+    finally:
+        if stream is not source:
+            stream.close()
+    scans.sort(key=lambda s: s["scan_nr"] if s["scan_nr"] is not None else -1)
+    for index, summary in enumerate(scans):
+        summary["index"] = index
+    return scans
+
+def parse_mzxml_scan_bytes(data: bytes) -> List[Dict]:
+    """Peaks of the first <scan> in raw mzXML bytes (a whole file or a single scan)."""
+    root = parse_xml_bytes(data, "mzXML")
+    scan = root if local_name(root) == 'scan' else root.find(".//{*}scan")
+    if scan is None:
+        raise ValueError("no <scan> element found")
+    return parse_scan_peaks(scan)
@@ -0,0 +1,112 @@
+"""
+MS-Numpress decoders (linear, pic and slof), following the MSNumpress reference implementation.
+Each decoder takes the raw (already base64/zlib-decoded) bytes and returns float64 values.
+"""
+
+import struct
+
+import numpy as np
+
+# PSI-MS accession -> (numpress scheme, followed by zlib)
+NUMPRESS_ACCESSIONS = {
+    'MS:1002312': ('linear', False),
+    'MS:1002313': ('pic', False),
+    'MS:1002314': ('slof', False),
+    'MS:1002746': ('linear', True),
+    'MS:1002747': ('pic', True),
+    'MS:1002748': ('slof', True),
+}
+
+def _decode_fixed_point(data: bytes) -> float:
+    # The scaling factor is stored as a big-endian double
+    return struct.unpack('>d', data[:8])[0]
+
+class _NibbleReader:
+    """Reads the variable-length, half-byte encoded integers used by linear and pic."""
+
+    def __init__(self, data: bytes, start: int = 0):
+        self.data = data
+        self.pos = start
+        self.half = 0
+
+    def _next_nibble(self) -> int:
+        if self.pos >= len(self.data):
+            raise ValueError("corrupt numpress data: integer runs past the end of the buffer")
+        byte = self.data[self.pos]
+        if self.half == 0:
+            value = byte >> 4
+        else:
+            value = byte & 0xf
+            self.pos += 1
+        self.half = 1 - self.half
+        return value
+
+    def at_padding(self) -> bool:
+        # An odd number of nibbles leaves a zero half-byte at the end
+        return self.pos == len(self.data) - 1 and self.half == 1 and (self.data[self.pos] & 0xf) == 0
+
+    def done(self) -> bool:
+        return self.pos >= len(self.data) or self.at_padding()
+
+    def read_int(self) -> int:
+        head = self._next_nibble()
+        if head <= 8:
+            n, value = head, 0
+        else:
+            # Leading 0xf nibbles are implicit
+            n = head - 8
+            value = 0
+            for i in range(n):
+                value |= 0xf0000000 >> (4 * i)
+        for i in range(n, 8):
+            value |= self._next_nibble() << ((i - n) * 4)
+        # Reinterpret as a signed 32-bit integer
+        return value - (1 << 32) if value & 0x80000000 else value
+
+def decode_linear(data: bytes) -> np.ndarray:
+    """Linear prediction encoding, used for m/z and retention time arrays."""
+    if len(data) == 8:
+        return np.array([], dtype=np.float64)
+    if len(data) < 12:
+        raise ValueError(f"corrupt numpress linear data: {len(data)} bytes is too short")
+    fixed_point = _decode_fixed_point(data)
+    first = struct.unpack('<I', data[8:12])[0]
+    result = [first / fixed_point]
+    if len(data) == 12:
+        return np.array(result, dtype=np.float64)
+    if len(data) < 16:
+        raise ValueError(f"corrupt numpress linear data: {len(data)} bytes is too short")
+    second = struct.unpack('<I', data[12:16])[0]
+    result.append(second / fixed_point)
+
+    previous, current = first, second
+    reader = _NibbleReader(data, 16)
+    while not reader.done():
+        # Each value is stored as its residual against a linear extrapolation
+        predicted = current + (current - previous)
+        value = predicted + reader.read_int()
+        result.append(value / fixed_point)
+        previous, current = current, value
+    return np.array(result, dtype=np.float64)
+
+def decode_pic(data: bytes) -> np.ndarray:
+    """Positive integer compression, used for ion counts."""
+    reader = _NibbleReader(data)
+    result = []
+    while not reader.done():
+        result.append(float(reader.read_int() & 0xffffffff))
+    return np.array(result, dtype=np.float64)
+
+def decode_slof(data: bytes) -> np.ndarray:
+    """Short logged float, used for intensities."""
+    if len(data) < 8 or (len(data) - 8) % 2:
+        raise ValueError(f"corrupt numpress slof data: {len(data)} bytes")
+    fixed_point = _decode_fixed_point(data)
+    values = np.frombuffer(data, dtype='<u2', offset=8).astype(np.float64)
+    return np.exp(values / fixed_point) - 1
+
+DECODERS = {
+    'linear': decode_linear,
+    'pic': decode_pic,
+    'slof': decode_slof,
+}
@@ -0,0 +1,109 @@
+"""
+pepXML identifications (TPP, Comet, X!Tandem, ...): the search hits of each spectrum_query
+with their modifications, search scores and PeptideProphet/iProphet probabilities.
+"""
+
+from typing import Dict, List, Optional
+
+from lxml import etree
+
+from .decoys import format_peptide
+from .masses import AA_MASS, H2O_MASS, HYDROGEN_MASS, PROTON_MASS
+from .mzid import optional_float
+from .mzml import local_name, open_source
+
+# Protein accession prefixes of decoy hits
+DECOY_PREFIXES = ("DECOY_", "decoy_", "rev_", "REV_", "XXX_")
+
+def _parse_sequence(hit) -> str:
+    # pepXML gives modified masses: the whole residue, and H / OH plus the mod for the termini
+    tokens = [[residue, 0.0] for residue in hit.get('peptide', '')]
+    n_term = c_term = 0.0
+    info = hit.find('{*}modification_info')
+    if info is not None:
+        if optional_float(info.get('mod_nterm_mass')) is not None:
+            n_term = float(info.get('mod_nterm_mass')) - HYDROGEN_MASS
+        if optional_float(info.get('mod_cterm_mass')) is not None:
+            c_term = float(info.get('mod_cterm_mass')) - (H2O_MASS - HYDROGEN_MASS)
+        for mod in info.findall('{*}mod_aminoacid_mass'):
+            position = int(mod.get('position', 0))
+            if not 1 <= position <= len(tokens):
+                raise ValueError(f"search_hit {hit.get('peptide')!r}: modification position {position} out of range")
+            residue = tokens[position - 1][0]
+            delta = optional_float(mod.get('variable')) or optional_float(mod.get('static'))
+            if delta is None:
+                if residue not in AA_MASS:
+                    raise ValueError(f"search_hit {hit.get('peptide')!r}: no mass for modified residue {residue!r}")
+                delta = float(mod.get('mass')) - AA_MASS[residue]
+            tokens[position - 1][1] += delta
+    return format_peptide([(residue, round(mod, 6)) for residue, mod in tokens], round(n_term, 6), round(c_term, 6))
+
+def _scores(hit) -> Dict[str, object]:
+    scores = {}
+    for score in hit.findall('{*}search_score'):
+        value = optional_float(score.get('value'))
+        scores[score.get('name')] = value if value is not None else score.get('value')
+    # PeptideProphet / iProphet as "peptideprophet_probability", "interprophet_probability"
+    for analysis in hit.findall('{*}analysis_result'):
+        for result in analysis:
+            if local_name(result).endswith('_result') and optional_float(result.get('probability')) is not None:
+                scores[f"{analysis.get('analysis')}_probability"] = float(result.get('probability'))
+    return scores
+
+def parse_pepxml(source, max_rank: Optional[int] = 1) -> List[Dict]:
+    """
+    PSMs of a pepXML file (bytes, path or binary file object) in file order, with the same
+    fields as mzid.parse_mzid: "scan_nr" is the query's start_scan, "spec_id" its spectrum
+    attribute, "spectra_data" the run's base_name, modifications become [mass] deltas and
+    "scores" holds the search scores plus "<analysis>_probability" of each analysis_result.
+    A hit is a decoy when all its proteins start with one of DECOY_PREFIXES. Hits ranked
+    above max_rank (None: all) are skipped.
+    """
+    psms = []
+    base_name = ''
+    stream = open_source(source)
+    try:
+        for event, element in etree.iterparse(stream, events=("start", "end")):
+            name = local_name(element)
+            if event == "start":
+                if name == 'msms_run_summary':
+                    base_name = element.get('base_name', '')
+                continue
+            if name != 'spectrum_query':
+                continue
+            psms.extend(_parse_query(element, base_name, max_rank))
+            element.clear()
+    except etree.XMLSyntaxError as e:
+        raise ValueError(f"malformed pepXML after {len(psms)} PSMs: {e}") from e
+    finally:
+        if stream is not source:
+            stream.close()
+    return psms
+
+def _parse_query(query, base_name: str, max_rank: Optional[int]) -> List[Dict]:
+    charge = int(query.get('assumed_charge', 0)) or None
+    neutral_mass = optional_float(query.get('precursor_neutral_mass'))
+    psms = []
+    for hit in query.findall('{*}search_result/{*}search_hit'):
+        rank = int(hit.get('hit_rank', 1))
+        if max_rank is not None and rank > max_rank:
+            continue
+        proteins = [hit.get('protein')] + [p.get('protein') for p in hit.findall('{*}alternative_protein')]
+        proteins = [p for p in proteins if p]
+        calculated = optional_float(hit.get('calc_neutral_pep_mass'))
+        psms.append({
+            "scan_nr": int(query.get('start_scan')) if query.get('start_scan') else None,
+            "spec_id": query.get('spectrum', ''),
+            "spectra_data": base_name,
+            "sequence": _parse_sequence(hit),
+            "charge": charge,
+            "rank": rank,
+            "precursor_mz": neutral_mass / charge + PROTON_MASS if neutral_mass is not None and charge else None,
+            "calculated_mz": calculated / charge + PROTON_MASS if calculated is not None and charge else None,
+            "retention_time": optional_float(query.get('retention_time_sec')),
+            "pass_threshold": True,
+            "is_decoy": bool(proteins) and all(p.startswith(DECOY_PREFIXES) for p in proteins),
+            "proteins": proteins,
+            "scores": _scores(hit),
+        })
+    return psms
@@ -0,0 +1,81 @@
+"""
+Percolator results (.pout / tab-separated --results-psms output): PSMs with score, q-value
+and posterior error probability, the scan and charge recovered from the PSMId.
+"""
+
+import io
+import re
+from typing import Dict, List
+
+from .calculations import tokenize_peptide
+from .decoys import format_peptide
+from .mzid import optional_float
+from .mzml import open_source
+from .pepxml import DECOY_PREFIXES
+
+PERCOLATOR_COLUMNS = ("PSMId", "score", "q-value", "posterior_error_prob", "peptide")
+# Comet/Crux/MSFragger style ids ending in _<scan>_<charge>_<rank>
+PSM_ID = re.compile(r'^(.*?)_(\d+)_(\d+)_(\d+)$')
+FLANKED = re.compile(r'^[A-Z-]\.(.+)\.[A-Z-]$')
+
+def _sequence(peptide: str) -> str:
+    # "K.PEPM[15.9949]TIDEK.R" -> "PEPM[+15.9949]TIDEK"
+    match = FLANKED.match(peptide)
+    residues, n_term, c_term = tokenize_peptide(match.group(1) if match else peptide)
+    return format_peptide(residues, n_term, c_term)
+
+def parse_percolator(source) -> List[Dict]:
+    """
+    PSMs of a Percolator tab-separated result file (bytes, path or binary file object),
+    with the fields of mzid.parse_mzid plus "score", "q_value" and "pep" for FDR
+    filtering. The proteins are the trailing proteinIds column(s); "scan_nr", "charge" and
+    "rank" come from a PSMId ending in _<scan>_<charge>_<rank> (else None, None and 1). A
+    PSM is a decoy when its PSMId or all its proteins start with one of DECOY_PREFIXES.
+    """
+    stream = open_source(source)
+    try:
+        lines = io.TextIOWrapper(stream, encoding='utf-8-sig').read().splitlines()
+    finally:
+        if stream is not source:
+            stream.close()
+    if not lines:
+        raise ValueError("Percolator file is empty")
+    header = lines[0].split('\t')
+    missing = [c for c in PERCOLATOR_COLUMNS if c not in header]
+    if missing:
+        raise ValueError(f"Percolator file is missing required column(s): {', '.join(missing)}")
+    # proteinIds takes the last column and every extra field after it
+    protein_column = header.index("proteinIds") if "proteinIds" in header else len(header)
+    psms = []
+    for row_nr, line in enumerate(lines[1:], 1):
+        if not line.strip():
+            continue
+        fields = line.split('\t')
+        row = dict(zip(header, fields))
+        score = optional_float(row.get("score"))
+        if score is None:
+            raise ValueError(f"Percolator file, data row {row_nr}: invalid score {row.get('score')!r}")
+        psm_id = row["PSMId"]
+        ids = PSM_ID.match(psm_id)
+        proteins = [p for p in fields[protein_column:] if p]
+        psms.append({
+            "scan_nr": int(ids.group(2)) if ids else None,
+            "spec_id": psm_id,
+            "spectra_data": ids.group(1) if ids else '',
+            "sequence": _sequence(row["peptide"]),
+            "charge": int(ids.group(3)) if ids else None,
+            "rank": int(ids.group(4)) if ids else 1,
+            "precursor_mz": None,
+            "calculated_mz": None,
+            "retention_time": None,
+            "pass_threshold": True,
+            "is_decoy": psm_id.startswith(DECOY_PREFIXES) or psm_id.lower().startswith("decoy")
+                        or (bool(proteins) and all(p.startswith(DECOY_PREFIXES) for p in proteins)),
+            "proteins": proteins,
+            "score": score,
+            "q_value": optional_float(row.get("q-value")),
+            "pep": optional_float(row.get("posterior_error_prob")),
+            "scores": {"score": score, "q-value": optional_float(row.get("q-value")),
+                       "posterior_error_prob": optional_float(row.get("posterior_error_prob"))},
+        })
+    return psms
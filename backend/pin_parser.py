@@ -10,18 +10,24 @@ def parse_pin(file_path: str) -> list:
         
     Returns:
         List of dictionaries containing peptide metadata.
+
+    Raises:
+        ValueError: if the file cannot be read as a .pin table or lacks ScanNr/Peptide.
     """
-    try:
-        if not os.path.exists(file_path):
-            raise FileNotFoundError(f"PIN file not found: {file_path}")
+    if not os.path.exists(file_path):
+        raise FileNotFoundError(f"PIN file not found: {file_path}")
 
-        # Read TSV from file path
+    # Read TSV from file path
+    try:
+        df = pd.read_csv(file_path, sep='\t', on_bad_lines='skip')
+    except Exception:
+        # Fallback engine
         try:
-            df = pd.read_csv(file_path, sep='\t', on_bad_lines='skip')
-        except Exception:
-             # Fallback engine
-             df = pd.read_csv(file_path, sep='\t', engine='python')
+            df = pd.read_csv(file_path, sep='\t', engine='python')
+        except Exception as e:
+            raise ValueError(f"Could not read PIN file {file_path} as tab-separated text: {e}") from e
 
+    try:
         peptides = []
         
         # Identify charge columns
@@ -35,8 +41,11 @@ def parse_pin(file_path: str) -> list:
                  pt_map = {c.lower(): c for c in df.columns}
                  if col.lower() in pt_map:
                      df.rename(columns={pt_map[col.lower()]: col}, inplace=True)
+        missing = [col for col in required_cols if col not in df.columns]
+        if missing:
+            raise ValueError(f"PIN file {file_path} is missing required column(s): {', '.join(missing)}")
         
-        for _, row in df.iterrows():
+        for row_nr, row in df.iterrows():
             # Determine charge state
             charge = 2 # Default fallback
             for col in charge_cols:
@@ -58,8 +67,13 @@ def parse_pin(file_path: str) -> list:
             peptide_seq = peptide_seq.replace('[', '(').replace(']', ')')
 
                 
+            try:
+                scan_nr = int(row.get('ScanNr', 0))
+            except (TypeError, ValueError):
+                raise ValueError(f"PIN file {file_path}, data row {row_nr + 1}: invalid ScanNr {row.get('ScanNr')!r}") from None
+                
             item = {
-                "scan_nr": int(row.get('ScanNr', 0)),
+                "scan_nr": scan_nr,
                 "spec_id": str(row.get('SpecId', '')),
                 "sequence": peptide_seq,
                 "charge": charge
@@ -68,6 +82,7 @@ def parse_pin(file_path: str) -> list:
             
         return peptides
 
+    except ValueError:
+        raise
     except Exception as e:
-        print(f"Error parsing PIN file: {e}")
-        return []
+        raise ValueError(f"Error parsing PIN file {file_path}: {e}") from e
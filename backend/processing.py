@@ -0,0 +1,389 @@
+"""
+Peak list preprocessing applied before matching or scoring. Each step takes and returns
+[{"mz", "intensity", ...}] peak lists, so steps can be chained (see apply_processing).
+"""
+
+import bisect
+import math
+import statistics
+from collections import deque
+from typing import Callable, Dict, List, Optional
+
+from .masses import C13_DELTA, H2O_MASS, NH3_MASS, PROTON_MASS
+
+def _sorted_peaks(peaks: List[dict]) -> List[dict]:
+    if any(peaks[i]["mz"] > peaks[i + 1]["mz"] for i in range(len(peaks) - 1)):
+        return sorted(peaks, key=lambda p: p["mz"])
+    return list(peaks)
+
+def tolerance_to_da(mz: float, tolerance: float, tolerance_unit: str) -> float:
+    """Half-width in Da of a tolerance ("da" or "ppm") at m/z mz; arrays of m/z work too."""
+    if tolerance_unit == "ppm":
+        return mz * tolerance * 1e-6
+    if tolerance_unit != "da":
+        raise ValueError(f"Unknown tolerance unit {tolerance_unit!r}, expected 'da' or 'ppm'")
+    return tolerance
+
+def _closest_index(mzs: List[float], target: float, window: float, used: set, taken: set) -> Optional[int]:
+    # Peaks in used (accepted envelopes) or taken (the envelope being grown) are skipped
+    best = None
+    i = bisect.bisect_left(mzs, target - window)
+    while i < len(mzs) and mzs[i] <= target + window:
+        if i not in used and i not in taken and (best is None or abs(mzs[i] - target) < abs(mzs[best] - target)):
+            best = i
+        i += 1
+    return best
+
+def deisotope(peaks: List[dict], tolerance: float = 0.02, tolerance_unit: str = "da", max_charge: int = 4,
+              min_isotopes: int = 2, keep_unassigned: bool = True) -> List[dict]:
+    """
+    Collapses isotope envelopes to singly charged monoisotopic peaks. Envelopes are grown
+    from the most intense unused peak: for each charge up to max_charge, peaks spaced
+    C13_DELTA / z are followed down to the monoisotopic one and up to the last isotope, and
+    the charge giving the longest envelope (at least min_isotopes peaks) wins. The result
+    holds one peak per envelope at the [M+H]+ m/z with the summed intensity, "charge" and
+    "isotope_count", so it can be matched at charge 1. Peaks in no envelope are kept with
+    charge None unless keep_unassigned is False.
+    """
+    if max_charge < 1:
+        raise ValueError(f"max_charge must be >= 1, got {max_charge}")
+    if min_isotopes < 2:
+        raise ValueError(f"min_isotopes must be >= 2, got {min_isotopes}")
+    peaks = _sorted_peaks(peaks)
+    mzs = [peak["mz"] for peak in peaks]
+    used = set()
+    result = []
+
+    for start in sorted(range(len(peaks)), key=lambda i: -peaks[i]["intensity"]):
+        if start in used:
+            continue
+        best_envelope, best_charge = [start], None
+        for charge in range(1, max_charge + 1):
+            spacing = C13_DELTA / charge
+            window = tolerance_to_da(mzs[start], tolerance, tolerance_unit)
+            envelope = [start]
+            # Only this envelope's peaks; used is merged in once an envelope is accepted
+            taken = {start}
+            # Walk down to the monoisotopic peak, then up through the isotopes
+            while True:
+                below = _closest_index(mzs, mzs[envelope[0]] - spacing, window, used, taken)
+                if below is None:
+                    break
+                envelope.insert(0, below)
+                taken.add(below)
+            while True:
+                above = _closest_index(mzs, mzs[envelope[-1]] + spacing, window, used, taken)
+                if above is None:
+                    break
+                envelope.append(above)
+                taken.add(above)
+            if len(envelope) >= min_isotopes and len(envelope) > len(best_envelope):
+                best_envelope, best_charge = envelope, charge
+
+        if best_charge is None:
+            used.add(start)
+            if keep_unassigned:
+                result.append({**peaks[start], "charge": None, "isotope_count": 1})
+            continue
+        used.update(best_envelope)
+        mono = peaks[best_envelope[0]]
+        result.append({
+            "mz": (mono["mz"] - PROTON_MASS) * best_charge + PROTON_MASS,
+            "intensity": sum(peaks[i]["intensity"] for i in best_envelope),
+            "charge": best_charge,
+            "isotope_count": len(best_envelope),
+            "original_mz": mono["mz"],
+        })
+    result.sort(key=lambda p: p["mz"])
+    return result
+
+def centroid(peaks: List[dict], min_intensity: float = 0.0, max_gap: Optional[float] = None) -> List[dict]:
+    """
+    Picks peaks from profile data: every local maximum becomes one centroid whose m/z is the
+    intensity-weighted mean of the points around it, out to the neighbouring minima. The
+    centroid intensity is the apex height and "area" the trapezoidal integral of the profile
+    peak. Points further apart than max_gap (Da) are treated as separate peaks, which handles
+    the zero-intensity runs that instruments leave out of profile arrays.
+    """
+    peaks = _sorted_peaks(peaks)
+    mzs = [peak["mz"] for peak in peaks]
+    heights = [peak["intensity"] for peak in peaks]
+    n = len(peaks)
+
+    def joined(i: int) -> bool:
+        # Whether points i and i + 1 belong to the same profile trace
+        return max_gap is None or mzs[i + 1] - mzs[i] <= max_gap
+
+    result = []
+    i = 0
+    while i < n:
+        # A plateau of equal points counts as one maximum
+        j = i
+        while j + 1 < n and joined(j) and heights[j + 1] == heights[i]:
+            j += 1
+        rising = i == 0 or not joined(i - 1) or heights[i - 1] < heights[i]
+        falling = j == n - 1 or not joined(j) or heights[j + 1] < heights[j]
+        if rising and falling and heights[i] > 0 and heights[i] >= min_intensity:
+            start, end = i, j
+            while start > 0 and joined(start - 1) and 0 < heights[start - 1] <= heights[start]:
+                start -= 1
+            while end < n - 1 and joined(end) and 0 < heights[end + 1] <= heights[end]:
+                end += 1
+            total = sum(heights[start:end + 1])
+            area = sum((mzs[k + 1] - mzs[k]) * (heights[k] + heights[k + 1]) / 2 for k in range(start, end))
+            result.append({
+                "mz": sum(mzs[k] * heights[k] for k in range(start, end + 1)) / total,
+                "intensity": heights[i],
+                "area": area,
+            })
+        i = j + 1
+    return result
+
+SMOOTHING_METHODS = ("savitzky_golay", "moving_average")
+
+def _savgol_coefficients(half_window: int, order: int) -> List[float]:
+    """Weights of the least-squares polynomial fit evaluated at the window centre."""
+    offsets = range(-half_window, half_window + 1)
+    # Normal equations (A^T A) x = e0 of the Vandermonde matrix A, solved by Gauss-Jordan
+    size = order + 1
+    system = [[float(sum(j ** (r + c) for j in offsets)) for c in range(size)] + [1.0 if r == 0 else 0.0]
+              for r in range(size)]
+    for col in range(size):
+        pivot = max(range(col, size), key=lambda r: abs(system[r][col]))
+        system[col], system[pivot] = system[pivot], system[col]
+        for r in range(size):
+            if r != col:
+                factor = system[r][col] / system[col][col]
+                system[r] = [a - factor * b for a, b in zip(system[r], system[col])]
+    solution = [system[r][size] / system[r][r] for r in range(size)]
+    return [sum(solution[p] * j ** p for p in range(size)) for j in offsets]
+
+def smooth(peaks: List[dict], method: str = "savitzky_golay", window: int = 5, order: int = 2) -> List[dict]:
+    """
+    Smooths a profile spectrum over a window of points (odd, >= 3). Savitzky-Golay fits a
+    polynomial of the given order and keeps peak heights better than a moving average; its
+    first and last window // 2 points are left as they are. Negative results are clipped to 0.
+    """
+    if method not in SMOOTHING_METHODS:
+        raise ValueError(f"Unknown smoothing method {method!r}, expected one of {SMOOTHING_METHODS}")
+    if window < 3 or window % 2 == 0:
+        raise ValueError(f"window must be an odd number of points >= 3, got {window}")
+    if method == "savitzky_golay" and not 0 <= order < window:
+        raise ValueError(f"order must be between 0 and window - 1, got {order}")
+    peaks = _sorted_peaks(peaks)
+    heights = [peak["intensity"] for peak in peaks]
+    half = window // 2
+    n = len(heights)
+    smoothed = list(heights)
+    if method == "moving_average":
+        for i in range(n):
+            points = heights[max(0, i - half):i + half + 1]
+            smoothed[i] = sum(points) / len(points)
+    else:
+        weights = _savgol_coefficients(half, order)
+        for i in range(half, n - half):
+            smoothed[i] = sum(w * h for w, h in zip(weights, heights[i - half:i + half + 1]))
+    return [{**peak, "intensity": max(value, 0.0)} for peak, value in zip(peaks, smoothed)]
+
+def subtract_baseline(peaks: List[dict], window: float = 10.0) -> List[dict]:
+    """
+    Subtracts a rolling-minimum baseline: the lowest intensity within +-window / 2 Da of each
+    point. Chemical noise humps wider than the window are removed, narrower peaks are kept.
+    """
+    if window <= 0:
+        raise ValueError(f"window must be positive, got {window}")
+    peaks = _sorted_peaks(peaks)
+    mzs = [peak["mz"] for peak in peaks]
+    heights = [peak["intensity"] for peak in peaks]
+    half = window / 2
+    baseline = []
+    # Monotonic deque of candidate minima for the sliding m/z window
+    candidates = deque()
+    ahead = 0
+    for i, mz in enumerate(mzs):
+        while ahead < len(mzs) and mzs[ahead] <= mz + half:
+            while candidates and heights[candidates[-1]] >= heights[ahead]:
+                candidates.pop()
+            candidates.append(ahead)
+            ahead += 1
+        while mzs[candidates[0]] < mz - half:
+            candidates.popleft()
+        baseline.append(heights[candidates[0]])
+    return [{**peak, "intensity": max(height - base, 0.0)} for peak, height, base in zip(peaks, heights, baseline)]
+
+def estimate_noise(peaks: List[dict], window: float = 100.0) -> List[Optional[float]]:
+    """
+    Noise level at each peak (in input order): the median non-zero intensity of each window
+    Da wide m/z segment, interpolated linearly between segment centres. None where a
+    segment holds no signal at all.
+    """
+    if window <= 0:
+        raise ValueError(f"window must be positive, got {window}")
+    if not peaks:
+        return []
+    start = min(peak["mz"] for peak in peaks)
+    segments: Dict[int, List[float]] = {}
+    for peak in peaks:
+        if peak["intensity"] > 0:
+            segments.setdefault(int((peak["mz"] - start) // window), []).append(peak["intensity"])
+    if not segments:
+        return [None] * len(peaks)
+    centres = sorted(segments)
+    positions = [start + (k + 0.5) * window for k in centres]
+    medians = [statistics.median(segments[k]) for k in centres]
+
+    noise = []
+    for peak in peaks:
+        i = bisect.bisect_left(positions, peak["mz"])
+        if i == 0 or i == len(positions):
+            noise.append(medians[min(i, len(positions) - 1)])
+            continue
+        fraction = (peak["mz"] - positions[i - 1]) / (positions[i] - positions[i - 1])
+        noise.append(medians[i - 1] + fraction * (medians[i] - medians[i - 1]))
+    return noise
+
+def signal_to_noise(peaks: List[dict], window: float = 100.0) -> List[dict]:
+    """Adds "noise" and "snr" (intensity / noise, 0 where there is no signal) to every peak."""
+    return [{**peak, "noise": noise, "snr": peak["intensity"] / noise if noise else 0.0}
+            for peak, noise in zip(peaks, estimate_noise(peaks, window))]
+
+def top_n_indices(mzs: List[float], intensities: List[float], n: int, window_da: Optional[float] = None) -> List[int]:
+    """
+    Indices (ascending) of the n most intense peaks overall, or of each window_da wide m/z
+    bin counted from 0 when window_da is given. Equal intensities keep the lower index.
+    """
+    if n < 0:
+        raise ValueError(f"n must be non-negative, got {n}")
+    if window_da is not None and window_da <= 0:
+        raise ValueError(f"window_da must be positive, got {window_da}")
+    kept: Dict[int, int] = {}
+    keep = []
+    for i in sorted(range(len(mzs)), key=lambda i: -intensities[i]):
+        window = int(mzs[i] // window_da) if window_da is not None else 0
+        if kept.get(window, 0) < n:
+            kept[window] = kept.get(window, 0) + 1
+            keep.append(i)
+    return sorted(keep)
+
+def filter_top_n(peaks: List[dict], n: int, window_da: Optional[float] = None) -> List[dict]:
+    """
+    Keeps the n most intense peaks, per window_da Da when given: filter_top_n(peaks, 10, 100)
+    is the usual "10 most intense peaks per 100 Da" search engine preprocessing.
+    """
+    peaks = _sorted_peaks(peaks)
+    keep = top_n_indices([peak["mz"] for peak in peaks], [peak["intensity"] for peak in peaks], n, window_da)
+    return [peaks[i] for i in keep]
+
+NORMALIZATION_METHODS = ("tic", "base_peak", "sqrt", "rank")
+
+def normalize(peaks: List[dict], method: str = "base_peak") -> List[dict]:
+    """
+    Rescales intensities: "tic" divides by the total so they sum to 1, "base_peak" scales
+    the most intense peak to 100, "sqrt" takes square roots (damping dominant peaks before
+    similarity scoring) and "rank" replaces each intensity by (n - rank + 1) / n, rank 1
+    being the most intense. Chain them, e.g. sqrt then base_peak, for mirror plots.
+    """
+    if method not in NORMALIZATION_METHODS:
+        raise ValueError(f"Unknown normalization {method!r}, expected one of {NORMALIZATION_METHODS}")
+    intensities = [peak["intensity"] for peak in peaks]
+    if method == "tic":
+        total = sum(intensities)
+        scaled = [i / total if total else 0.0 for i in intensities]
+    elif method == "base_peak":
+        top = max(intensities, default=0.0)
+        scaled = [i * 100.0 / top if top else 0.0 for i in intensities]
+    elif method == "sqrt":
+        if any(i < 0 for i in intensities):
+            raise ValueError("sqrt normalization needs non-negative intensities")
+        scaled = [math.sqrt(i) for i in intensities]
+    else:
+        scaled = [0.0] * len(peaks)
+        for rank, i in enumerate(sorted(range(len(peaks)), key=lambda i: -intensities[i])):
+            scaled[i] = (len(peaks) - rank) / len(peaks)
+    return [{**peak, "intensity": value} for peak, value in zip(peaks, scaled)]
+
+def remove_precursor(peaks: List[dict], precursor_mz: Optional[float] = None, charge: Optional[int] = None,
+                     tolerance: float = 0.02, tolerance_unit: str = "da", isotopes: int = 3,
+                     neutral_losses: bool = True, min_mz: Optional[float] = None,
+                     contaminant_mzs: List[float] = ()) -> List[dict]:
+    """
+    Removes peaks that carry no sequence information before scoring: the unfragmented
+    precursor and its isotopes at every charge from 1 to charge (charge-reduced species,
+    as seen after ETD), optionally with H2O/NH3 losses, everything below min_mz (e.g. the
+    immonium region) and any contaminant_mzs. Without a precursor only the last two apply.
+    """
+    if (precursor_mz is None) != (charge is None):
+        raise ValueError("precursor_mz and charge must be given together")
+    if charge is not None and charge < 1:
+        raise ValueError(f"charge must be >= 1, got {charge}")
+    targets = list(contaminant_mzs)
+    if precursor_mz is not None:
+        mass = (precursor_mz - PROTON_MASS) * charge
+        losses = [0.0] + ([H2O_MASS, NH3_MASS] if neutral_losses else [])
+        for z in range(1, charge + 1):
+            for loss in losses:
+                mono = (mass - loss + z * PROTON_MASS) / z
+                targets.extend(mono + k * C13_DELTA / z for k in range(isotopes + 1))
+    targets.sort()
+    kept = []
+    for peak in peaks:
+        if min_mz is not None and peak["mz"] < min_mz:
+            continue
+        window = tolerance_to_da(peak["mz"], tolerance, tolerance_unit)
+        i = bisect.bisect_left(targets, peak["mz"] - window)
+        if i < len(targets) and targets[i] <= peak["mz"] + window:
+            continue
+        kept.append(peak)
+    return kept
+
+BIN_AGGREGATES = ("sum", "max")
+
+def bin_peaks(peaks: List[dict], bin_width: float = 1.0, min_mz: float = 0.0, max_mz: float = 2000.0,
+              aggregate: str = "sum") -> List[float]:
+    """
+    Fixed-length intensity vector: bin k covers [min_mz + k * bin_width, min_mz + (k + 1) * bin_width)
+    and holds the summed (or largest) intensity of its peaks; peaks outside the range are dropped.
+    """
+    if bin_width <= 0:
+        raise ValueError(f"bin_width must be positive, got {bin_width}")
+    if max_mz <= min_mz:
+        raise ValueError(f"max_mz ({max_mz}) must be above min_mz ({min_mz})")
+    if aggregate not in BIN_AGGREGATES:
+        raise ValueError(f"Unknown aggregate {aggregate!r}, expected one of {BIN_AGGREGATES}")
+    vector = [0.0] * math.ceil((max_mz - min_mz) / bin_width)
+    for peak in peaks:
+        k = math.floor((peak["mz"] - min_mz) / bin_width)
+        if 0 <= k < len(vector):
+            vector[k] = vector[k] + peak["intensity"] if aggregate == "sum" else max(vector[k], peak["intensity"])
+    return vector
+
+# Steps meant for profile data, run before (automatic) centroiding
+PROFILE_STEPS = ("smooth", "baseline")
+
+# Steps usable in apply_processing, by name
+PROCESSING_STEPS: Dict[str, Callable[..., List[dict]]] = {
+    "deisotope": deisotope,
+    "centroid": centroid,
+    "smooth": smooth,
+    "baseline": subtract_baseline,
+    "snr": signal_to_noise,
+    "top_n": filter_top_n,
+    "normalize": normalize,
+    "remove_precursor": remove_precursor,
+}
+
+def apply_processing(peaks: List[dict], steps: List[dict]) -> List[dict]:
+    """
+    Runs a chain of steps such as [{"step": "deisotope", "max_charge": 3}]; the remaining
+    keys of each entry are passed to the step function as keyword arguments.
+    """
+    for n, step in enumerate(steps):
+        options = dict(step)
+        name = options.pop("step", None)
+        if name not in PROCESSING_STEPS:
+            raise ValueError(f"Processing step {n}: unknown step {name!r}, expected one of {tuple(PROCESSING_STEPS)}")
+        try:
+            peaks = PROCESSING_STEPS[name](peaks, **options)
+        except TypeError as e:
+            raise ValueError(f"Processing step {n} ({name}): {e}") from None
+    return peaks
@@ -0,0 +1,98 @@
+"""
+Progress reporting and cancellation for long passes (whole-file parsing, library matching,
+batch annotation), and the background jobs that run them for the API.
+"""
+
+import threading
+import uuid
+from typing import Callable, Dict, Optional
+
+# progress(percent complete 0-100, current scan number or None)
+ProgressCallback = Callable[[float, Optional[int]], None]
+
+JOB_STATES = ("running", "done", "cancelled", "failed")
+
+class Cancelled(Exception):
+    """Raised inside an operation whose CancelToken was set."""
+
+class CancelToken:
+    """
+    Set from another thread (e.g. a DELETE /api/jobs/{id} request) to stop an operation
+    at its next progress check; the operation then raises Cancelled.
+    """
+
+    def __init__(self):
+        self._event = threading.Event()
+
+    def cancel(self):
+        self._event.set()
+
+    @property
+    def cancelled(self) -> bool:
+        return self._event.is_set()
+
+    def check(self):
+        if self._event.is_set():
+            raise Cancelled("Operation cancelled")
+
+def report(done: int, total: int, current: Optional[int] = None, progress: Optional[ProgressCallback] = None,
+           cancel: Optional[CancelToken] = None):
+    """
+    One step of a long loop: raises Cancelled when cancel is set, else passes the percent
+    complete (done of total items) and the current scan to progress.
+    """
+    if cancel is not None:
+        cancel.check()
+    if progress is not None:
+        progress(100.0 * done / total if total else 100.0, current)
+
+class Job:
+    """
+    An operation running in a background thread: work(progress, cancel) returns the result.
+    status moves from "running" to "done" (result set), "cancelled" or "failed" (error set).
+    """
+
+    def __init__(self, kind: str, work: Callable[[ProgressCallback, CancelToken], object]):
+        self.id = uuid.uuid4().hex
+        self.kind = kind
+        self.status = "running"
+        self.percent = 0.0
+        self.current_scan: Optional[int] = None
+        self.result = None
+        self.error: Optional[str] = None
+        self.token = CancelToken()
+        self._thread = threading.Thread(target=self._run, args=(work,), daemon=True)
+
+    def start(self) -> "Job":
+        self._thread.start()
+        return self
+
+    def cancel(self):
+        self.token.cancel()
+
+    def _update(self, percent: float, current: Optional[int]):
+        self.percent = percent
+        self.current_scan = current
+
+    def _run(self, work):
+        try:
+            self.result = work(self._update, self.token)
+            self.percent = 100.0
+            self.status = "done"
+        except Cancelled:
+            self.status = "cancelled"
+        except Exception as e:
+            # HTTPException keeps its message in detail
+            self.error = str(getattr(e, "detail", None) or e)
+            self.status = "failed"
+
+    def summary(self, include_result: bool = True) -> Dict:
+        return {
+            "id": self.id,
+            "kind": self.kind,
+            "status": self.status,
+            "percent": self.percent,
+            "current_scan": self.current_scan,
+            "error": self.error,
+            "result": self.result if include_result else None,
+        }
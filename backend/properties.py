@@ -0,0 +1,71 @@
+"""
+Physicochemical properties of a (modified) peptide for the info panel.
+"""
+
+from typing import Dict, Optional
+
+from .calculations import get_residue_masses, peptide_mass, tokenize_peptide
+from .composition import format_formula, peptide_composition
+from .masses import mz_from_mass
+from .retention import hydrophobicity
+
+# EMBOSS pKa values; termini are taken as unmodified
+PKA_N_TERM = 8.6
+PKA_C_TERM = 3.6
+PKA_POSITIVE = {"K": 10.8, "R": 12.5, "H": 6.5}
+PKA_NEGATIVE = {"D": 3.9, "E": 4.1, "C": 8.5, "Y": 10.1}
+
+# Kyte-Doolittle hydropathy
+HYDROPATHY = {
+    "A": 1.8, "R": -4.5, "N": -3.5, "D": -3.5, "C": 2.5, "Q": -3.5, "E": -3.5, "G": -0.4, "H": -3.2, "I": 4.5,
+    "L": 3.8, "K": -3.9, "M": 1.9, "F": 2.8, "P": -1.6, "S": -0.8, "T": -0.7, "W": -0.9, "Y": -1.3, "V": 4.2,
+}
+
+def net_charge(residues: str, ph: float) -> float:
+    """Net charge at a pH from the Henderson-Hasselbalch terms of the termini and side chains."""
+    positive = 1 / (1 + 10 ** (ph - PKA_N_TERM))
+    negative = 1 / (1 + 10 ** (PKA_C_TERM - ph))
+    for residue in residues:
+        if residue in PKA_POSITIVE:
+            positive += 1 / (1 + 10 ** (ph - PKA_POSITIVE[residue]))
+        elif residue in PKA_NEGATIVE:
+            negative += 1 / (1 + 10 ** (PKA_NEGATIVE[residue] - ph))
+    return positive - negative
+
+def isoelectric_point(residues: str) -> float:
+    """pH of zero net charge, by bisection over 0-14 to 0.001."""
+    low, high = 0.0, 14.0
+    while high - low > 0.001:
+        middle = (low + high) / 2
+        if net_charge(residues, middle) > 0:
+            low = middle
+        else:
+            high = middle
+    return round((low + high) / 2, 2)
+
+def calculate_peptide_properties(sequence: str, residue_masses: Optional[Dict[str, float]] = None) -> Dict:
+    """
+    Monoisotopic and average neutral mass, [M+zH]z+ m/z for z = 1-6, isoelectric point,
+    GRAVY (mean Kyte-Doolittle hydropathy), SSRCalc-style hydrophobicity index (see
+    retention.hydrophobicity) and Hill formula. Inline modifications count
+    towards the masses and formula (unknown mass deltas via averagine, so the formula is
+    then approximate); pI, GRAVY and hydrophobicity use the unmodified residues.
+    """
+    tokens, _, _ = tokenize_peptide(sequence, get_residue_masses(residue_masses))
+    if not tokens:
+        raise ValueError(f"peptide {sequence!r} has no residues")
+    residues = "".join(residue for residue, _ in tokens)
+    monoisotopic = peptide_mass(sequence, residue_masses)
+    known = [HYDROPATHY[r] for r in residues if r in HYDROPATHY]
+    return {
+        "sequence": sequence,
+        "residues": residues,
+        "length": len(residues),
+        "monoisotopic_mass": monoisotopic,
+        "average_mass": peptide_mass(sequence, residue_masses, "average"),
+        "mz": {str(z): mz_from_mass(monoisotopic, z) for z in range(1, 7)},
+        "isoelectric_point": isoelectric_point(residues),
+        "gravy": sum(known) / len(known) if known else None,
+        "hydrophobicity": hydrophobicity(sequence),
+        "formula": format_formula(peptide_composition(sequence, residue_masses)),
+    }
@@ -0,0 +1,146 @@
+"""
+Protein-level views of identified peptides: where they fall on a protein sequence, how
+much of it they cover, and which peptides an enzyme should produce from it.
+"""
+
+from typing import Dict, Iterable, List, Optional
+
+from .calculations import peptide_mass, tokenize_peptide
+
+def plain_sequence(peptide: str) -> str:
+    """Residue letters of a (modified) peptide, e.g. "n[42]PEPM[16]TIDE" -> "PEPMTIDE"."""
+    residues, _, _ = tokenize_peptide(peptide)
+    return "".join(residue for residue, _ in residues)
+
+def _occurrences(protein: str, peptide: str) -> List[int]:
+    # 0-based starts, overlapping matches included
+    starts = []
+    start = protein.find(peptide)
+    while start != -1:
+        starts.append(start)
+        start = protein.find(peptide, start + 1)
+    return starts
+
+def protein_coverage(protein: str, peptides: List[str]) -> Dict:
+    """
+    Coverage of a protein by peptides (modifications allowed, they are stripped): per-residue
+    counts of covering peptide occurrences, the covered ranges (1-based, inclusive, merged
+    when they touch), overall percent covered, and where each distinct peptide was found.
+    Peptides absent from the protein are listed with no positions.
+    """
+    protein = "".join(protein.split()).upper()
+    if not protein:
+        raise ValueError("protein sequence is empty")
+    counts = [0] * len(protein)
+    found = []
+    for peptide in dict.fromkeys(peptides):
+        residues = plain_sequence(peptide)
+        starts = _occurrences(protein, residues) if residues else []
+        for start in starts:
+            for i in range(start, start + len(residues)):
+                counts[i] += 1
+        found.append({"peptide": peptide, "sequence": residues, "starts": [start + 1 for start in starts]})
+
+    ranges = []
+    for i, count in enumerate(counts):
+        if not count:
+            continue
+        if ranges and ranges[-1][1] == i:
+            ranges[-1][1] = i + 1
+        else:
+            ranges.append([i + 1, i + 1])
+    covered = sum(1 for count in counts if count)
+    return {
+        "length": len(protein),
+        "counts": counts,
+        "ranges": ranges,
+        "coverage": covered / len(protein) * 100,
+        "peptides": found,
+    }
+
+# name -> (cleavage residues, cut "after" (C-terminal side) or "before" them, blocked by a following P)
+ENZYMES = {
+    "trypsin": ("KR", "after", True),
+    "trypsin/p": ("KR", "after", False),
+    "lys-c": ("K", "after", False),
+    "chymotrypsin": ("FWY", "after", True),
+    "glu-c": ("E", "after", False),
+    "asp-n": ("D", "before", False),
+}
+
+def cleavage_sites(sequence: str, enzyme: str = "trypsin") -> List[int]:
+    """0-based positions a cut is made before, always including 0 and len(sequence)."""
+    if enzyme not in ENZYMES:
+        raise ValueError(f"Unknown enzyme {enzyme!r}, expected one of {tuple(ENZYMES)}")
+    residues, side, proline_rule = ENZYMES[enzyme]
+    sites = [0]
+    for i in range(1, len(sequence)):
+        if side == "after":
+            cut = sequence[i - 1] in residues and not (proline_rule and sequence[i] == "P")
+        else:
+            cut = sequence[i] in residues
+        if cut:
+            sites.append(i)
+    sites.append(len(sequence))
+    return sites
+
+def digest(sequence: str, enzyme: str = "trypsin", missed_cleavages: int = 2, semi_specific: bool = False,
+           min_length: int = 7, max_length: int = 50, min_mass: Optional[float] = None,
+           max_mass: Optional[float] = None) -> List[Dict]:
+    """
+    In-silico digest of a protein: peptides with up to missed_cleavages internal sites,
+    within the length and neutral monoisotopic mass limits. semi_specific also yields
+    peptides with only one enzymatic terminus. Each peptide has "sequence", 1-based
+    "start"/"end", "missed_cleavages" and "mass", ordered by start then end.
+    """
+    if missed_cleavages < 0:
+        raise ValueError(f"missed_cleavages must be >= 0, got {missed_cleavages}")
+    sequence = "".join(sequence.split()).upper()
+    sites = cleavage_sites(sequence, enzyme)
+    spans = {}
+    for a in range(len(sites) - 1):
+        for b in range(a + 1, min(a + missed_cleavages + 2, len(sites))):
+            start, end = sites[a], sites[b]
+            spans[(start, end)] = b - a - 1
+            if semi_specific:
+                # Keep one enzymatic end, trim the other
+                for cut in range(start + 1, end):
+                    spans.setdefault((start, cut), b - a - 1 - sum(1 for s in sites[a + 1:b] if s >= cut))
+                    spans.setdefault((cut, end), b - a - 1 - sum(1 for s in sites[a + 1:b] if s <= cut))
+
+    peptides = []
+    for (start, end), missed in sorted(spans.items()):
+        if not min_length <= end - start <= max_length:
+            continue
+        peptide = sequence[start:end]
+        mass = peptide_mass(peptide)
+        if (min_mass is not None and mass < min_mass) or (max_mass is not None and mass > max_mass):
+            continue
+        peptides.append({"sequence": peptide, "start": start + 1, "end": end, "missed_cleavages": missed, "mass": mass})
+    return peptides
+
+def map_peptide(peptide: str, proteins: Iterable[Dict], equate_il: bool = False) -> List[Dict]:
+    """
+    Every occurrence of a (modified) peptide in proteins (parse_fasta entries): accession,
+    1-based start/end and the flanking residues ("-" at a protein terminus). With equate_il,
+    I and L are treated as the same residue, as mass spectrometry cannot tell them apart.
+    """
+    residues = plain_sequence(peptide)
+    if not residues:
+        raise ValueError(f"peptide {peptide!r} has no residues")
+    if equate_il:
+        residues = residues.replace("I", "L")
+    hits = []
+    for protein in proteins:
+        sequence = protein["sequence"]
+        searched = sequence.replace("I", "L") if equate_il else sequence
+        for start in _occurrences(searched, residues):
+            end = start + len(residues)
+            hits.append({
+                "accession": protein["accession"],
+                "start": start + 1,
+                "end": end,
+                "previous_residue": sequence[start - 1] if start > 0 else "-",
+                "next_residue": sequence[end] if end < len(sequence) else "-",
+            })
+    return hits
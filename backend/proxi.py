@@ -0,0 +1,114 @@
+"""
+ProteomeXchange PROXI spectrum JSON: lets the viewer pull a spectrum by USI from PRIDE,
+MassIVE or PeptideAtlas and annotate it like one read from a local file.
+"""
+
+import json
+import re
+import urllib.error
+import urllib.parse
+import urllib.request
+from typing import Dict, List, Union
+
+from .mgf import empty_summary, precursor_entry
+
+PROXI_PROVIDERS = {
+    "pride": "https://www.ebi.ac.uk/pride/proxi/archive/v0.1/spectra",
+    "massive": "https://massive.ucsd.edu/ProteoSAFe/proxi/v0.1/spectra",
+    "peptideatlas": "https://peptideatlas.org/api/proxi/v0.1/spectra",
+}
+PROXI_TIMEOUT = 30
+
+USI_SCAN = re.compile(r':scan:(\d+)(?::|$)')
+
+# PSI-MS attribute accessions -> summary fields (values arrive as strings)
+PROXI_ATTRIBUTES = {
+    'MS:1000511': ('ms_level', int),
+    'MS:1000016': ('retention_time', float),
+    'MS:1000894': ('retention_time', float),
+    'MS:1000285': ('total_ion_current', float),
+    'MS:1000504': ('base_peak_mz', float),
+    'MS:1000505': ('base_peak_intensity', float),
+    'MS:1000512': ('filter_string', str),
+}
+SELECTED_ION_MZ = ('MS:1000744', 'MS:1000827')
+CHARGE_STATE = 'MS:1000041'
+SPECTRUM_TITLE = ('MS:1000796', 'MS:1003061')
+
+def _proxi_spectrum(entry: Dict, index: int) -> Dict:
+    if not isinstance(entry, dict):
+        raise ValueError(f"PROXI spectrum {index}: expected an object, got {type(entry).__name__}")
+    mzs = entry.get("mzs") or []
+    intensities = entry.get("intensities") or []
+    if len(mzs) != len(intensities):
+        raise ValueError(f"PROXI spectrum {index}: {len(mzs)} mzs but {len(intensities)} intensities")
+
+    summary = empty_summary(index)
+    summary["id"] = entry.get("usi") or ""
+    scan = USI_SCAN.search(summary["id"])
+    if scan:
+        summary["scan_nr"] = int(scan.group(1))
+    mz = charge = None
+    attributes = {}
+    for attribute in entry.get("attributes") or []:
+        accession, value = attribute.get("accession"), attribute.get("value")
+        attributes[attribute.get("name") or accession] = value
+        try:
+            if accession in PROXI_ATTRIBUTES:
+                field, convert = PROXI_ATTRIBUTES[accession]
+                summary[field] = convert(value)
+            elif accession in SELECTED_ION_MZ and mz is None:
+                mz = float(value)
+            elif accession == CHARGE_STATE:
+                charge = int(float(value))
+            elif accession in SPECTRUM_TITLE and not summary["id"]:
+                summary["id"] = str(value)
+        except (TypeError, ValueError):
+            raise ValueError(f"PROXI spectrum {index}: attribute {accession} has invalid value {value!r}") from None
+    if mz is not None or charge is not None:
+        summary["precursors"] = [precursor_entry(mz, charge, None)]
+
+    try:
+        peaks = [{"mz": float(m), "intensity": float(i)} for m, i in zip(mzs, intensities)]
+    except (TypeError, ValueError):
+        raise ValueError(f"PROXI spectrum {index}: non-numeric m/z or intensity") from None
+    summary["peak_count"] = len(peaks)
+    summary["params"] = attributes
+    summary["peaks"] = peaks
+    return summary
+
+def parse_proxi_json(data: Union[str, bytes, Dict, List]) -> List[Dict]:
+    """
+    Spectra from a PROXI /spectra response (a list of objects with "mzs", "intensities" and
+    "attributes"; a single object is accepted too), as spectrum summaries with "peaks".
+    The USI becomes the id, selected ion m/z and charge state the precursor, and all
+    attributes are kept by name under "params". Raises ValueError on malformed JSON.
+    """
+    if isinstance(data, (str, bytes)):
+        try:
+            data = json.loads(data)
+        except json.JSONDecodeError as e:
+            raise ValueError(f"invalid PROXI JSON: {e}") from e
+    entries = data if isinstance(data, list) else [data]
+    # Entries a provider could not read come back with status but no peaks
+    return [_proxi_spectrum(entry, i) for i, entry in enumerate(entries)
+            if not (isinstance(entry, dict) and entry.get("status") == "ERROR")]
+
+def fetch_usi(usi: str, provider: str = "pride") -> Dict:
+    """Fetches one spectrum by USI from a PROXI provider."""
+    if provider not in PROXI_PROVIDERS:
+        raise ValueError(f"Unknown PROXI provider {provider!r}, expected one of {tuple(PROXI_PROVIDERS)}")
+    if not usi.startswith("mzspec:"):
+        raise ValueError(f"not a USI (expected 'mzspec:...'): {usi!r}")
+    url = f"{PROXI_PROVIDERS[provider]}?{urllib.parse.urlencode({'resultType': 'full', 'usi': usi})}"
+    try:
+        with urllib.request.urlopen(url, timeout=PROXI_TIMEOUT) as response:
+            body = response.read()
+    except urllib.error.HTTPError as e:
+        raise ValueError(f"{provider} returned HTTP {e.code} for {usi}") from e
+    except urllib.error.URLError as e:
+        raise ValueError(f"could not reach {provider}: {e.reason}") from e
+    spectra = parse_proxi_json(body)
+    if not spectra:
+        raise ValueError(f"{provider} has no spectrum for {usi}")
+    return spectra[0]
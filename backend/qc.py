@@ -0,0 +1,132 @@
+"""
+Quality control: fragment mass error distributions over one or many spectra, for
+calibration checks and choosing a matching tolerance, and per-spectrum quality metrics.
+"""
+
+import math
+import statistics
+from typing import Dict, List, Optional, Union
+
+import numpy as np
+
+from .settings import TOLERANCE_UNITS
+
+MAD_TO_SD = 1.4826
+# Robust standard deviations either side of the median the suggested tolerance spans
+TOLERANCE_SD = 3.0
+MAX_ERROR_BINS = 200
+# Peaks whose share of the TIC spectrum_quality reports
+TOP_PEAKS = 20
+
+def _auto_bin_width(errors: List[float]) -> float:
+    # Freedman-Diaconis, 2 IQR / n^(1/3), falling back to a 20th of the range (or of the error)
+    quartiles = statistics.quantiles(errors, n=4) if len(errors) >= 4 else [min(errors), 0.0, max(errors)]
+    width = 2 * (quartiles[2] - quartiles[0]) / len(errors) ** (1 / 3)
+    return width or (max(errors) - min(errors)) / 20 or abs(errors[0]) / 20 or 1.0
+
+def mass_error_statistics(matches: Union[List[dict], List[List[dict]]], unit: str = "ppm",
+                          bin_width: Optional[float] = None, max_abs_error: Optional[float] = None) -> Dict:
+    """
+    Distribution of the signed errors (observed - theoretical) of match_ions results,
+    given for one spectrum or as one list per spectrum. Returns "count", "mean",
+    "median", "mad" and "robust_sd" (MAD * 1.4826), "min"/"max", the histogram as
+    "bins" [{"error" (bin centre), "count"}] from min to max including empty bins
+    (bin_width by default from the Freedman-Diaconis rule, widened to keep at most
+    MAX_ERROR_BINS), "recalibration_offset" (the median: subtract it from observed
+    m/z, in ppm or Da) and "suggested_tolerance", a [low, high] MatchSettings window of
+    median +- TOLERANCE_SD robust SDs. Several spectra add "spectra", the per-spectrum
+    "count" and "median". Errors beyond max_abs_error are left out as outliers.
+    """
+    if unit not in TOLERANCE_UNITS:
+        raise ValueError(f"Unknown error unit {unit!r}, expected one of {TOLERANCE_UNITS}")
+    if bin_width is not None and bin_width <= 0:
+        raise ValueError(f"bin_width must be positive, got {bin_width}")
+    grouped = bool(matches) and all(isinstance(m, list) for m in matches)
+    spectra = matches if grouped else [matches]
+    key = "error_ppm" if unit == "ppm" else "error_da"
+
+    per_spectrum = []
+    errors: List[float] = []
+    for spectrum_matches in spectra:
+        values = [m[key] for m in spectrum_matches
+                  if m.get(key) is not None and (max_abs_error is None or abs(m[key]) <= max_abs_error)]
+        per_spectrum.append({"count": len(values), "median": statistics.median(values) if values else None})
+        errors.extend(values)
+
+    result = {"unit": unit, "count": len(errors)}
+    if not errors:
+        result.update({"mean": None, "median": None, "mad": None, "robust_sd": None, "min": None, "max": None,
+                       "bin_width": bin_width, "bins": [], "recalibration_offset": None, "suggested_tolerance": None})
+    else:
+        median = statistics.median(errors)
+        mad = statistics.median(abs(e - median) for e in errors)
+        low, high = min(errors), max(errors)
+        width = bin_width or _auto_bin_width(errors)
+        width = max(width, (high - low) / MAX_ERROR_BINS)
+        first = math.floor(low / width)
+        counts = [0] * (math.floor(high / width) - first + 1)
+        for error in errors:
+            counts[math.floor(error / width) - first] += 1
+        result.update({
+            "mean": statistics.fmean(errors),
+            "median": median,
+            "mad": mad,
+            "robust_sd": MAD_TO_SD * mad,
+            "min": low,
+            "max": high,
+            "bin_width": width,
+            "bins": [{"error": (first + k + 0.5) * width, "count": n} for k, n in enumerate(counts)],
+            "recalibration_offset": median,
+            # A single match (or identical errors) gives no spread; the window is then just its error
+            "suggested_tolerance": [median - TOLERANCE_SD * MAD_TO_SD * mad, median + TOLERANCE_SD * MAD_TO_SD * mad],
+        })
+    if grouped:
+        result["spectra"] = per_spectrum
+    return result
+
+def spectrum_quality(mz, intensity, precursor_mz: Optional[float] = None, tolerance: float = 20.0,
+                     tolerance_unit: str = "ppm", top_n: int = TOP_PEAKS) -> Dict:
+    """
+    Quality indicators of one peak list: "peak_count", "tic" (summed intensity),
+    "base_peak_mz"/"base_peak_intensity", "dynamic_range" (log10 of the base peak over the
+    weakest non-zero peak), "top_fraction" (share of the TIC in the top_n most intense
+    peaks) and, given the precursor m/z, "precursor_intensity" (the most intense peak
+    within tolerance of it, 0 when there is none; None without a precursor m/z) and
+    "precursor_present". Empty spectra give zeros and None.
+    """
+    if tolerance_unit not in TOLERANCE_UNITS:
+        raise ValueError(f"Unknown tolerance unit {tolerance_unit!r}, expected one of {TOLERANCE_UNITS}")
+    if top_n < 1:
+        raise ValueError(f"top_n must be >= 1, got {top_n}")
+    mz = np.asarray(mz, dtype=np.float64)
+    intensity = np.asarray(intensity, dtype=np.float64)
+    if len(mz) != len(intensity):
+        raise ValueError(f"m/z and intensity arrays differ in length ({len(mz)} vs {len(intensity)})")
+
+    tic = float(intensity.sum())
+    positive = intensity[intensity > 0]
+    result = {
+        "peak_count": len(mz),
+        "tic": tic,
+        "base_peak_mz": None,
+        "base_peak_intensity": None,
+        "dynamic_range": None,
+        "top_fraction": None,
+        "precursor_mz": precursor_mz,
+        "precursor_intensity": None,
+        "precursor_present": None,
+    }
+    if len(mz):
+        base = int(np.argmax(intensity))
+        result["base_peak_mz"] = float(mz[base])
+        result["base_peak_intensity"] = float(intensity[base])
+        if len(positive):
+            result["dynamic_range"] = math.log10(positive.max() / positive.min())
+        if tic > 0:
+            result["top_fraction"] = float(np.sort(intensity)[-top_n:].sum()) / tic
+    if precursor_mz is not None:
+        window = tolerance * precursor_mz / 1e6 if tolerance_unit == "ppm" else tolerance
+        inside = intensity[np.abs(mz - precursor_mz) <= window]
+        result["precursor_intensity"] = float(inside.max()) if len(inside) else 0.0
+        result["precursor_present"] = bool(len(inside) and inside.max() > 0)
+    return result
@@ -0,0 +1,78 @@
+"""
+Picks the reader for a spectrum file from its content, so callers handle mzML, mzXML, MGF and MS1/MS2 alike.
+"""
+
+import gzip
+import re
+import zlib
+from pathlib import Path
+from typing import Dict, List, Union
+
+from .mgf import MgfReader, parse_mgf
+from .ms2 import Ms2Reader, parse_ms2
+from .mzml import LazyMzmlReader, is_gzip, parse_spectrum_bytes
+from .mzxml import MzxmlReader, parse_mzxml_scan_bytes
+from .proxi import parse_proxi_json
+
+SNIFF_SIZE = 64 * 1024
+
+def sniff_head(data: bytes) -> bytes:
+    """First bytes of the (decompressed) content."""
+    if is_gzip(data):
+        try:
+            return zlib.decompressobj(16 + zlib.MAX_WBITS).decompress(data, SNIFF_SIZE)
+        except zlib.error as e:
+            raise ValueError(f"corrupt gzip data: {e}") from e
+    return data[:SNIFF_SIZE]
+
+def sniff_format(data: bytes) -> str:
+    """
+    "mzml", "mzxml", "mgf", "ms2" or "proxi", judged from the root element (or BEGIN IONS,
+    H and S lines for MS1/MS2 text, or a JSON array/object for PROXI) near the start.
+    """
+    head = sniff_head(data)
+    if re.match(rb'\s*[\[{]', head):
+        return "proxi"
+    if re.search(rb'^\s*BEGIN IONS\s*$', head, re.M | re.I):
+        return "mgf"
+    if re.match(rb'\s*(H\t[^\n]*\n\s*)*S[\t ]', head):
+        return "ms2"
+    if b'<mzXML' in head or b'<msRun' in head:
+        return "mzxml"
+    if b'<mzML' in head or b'<indexedmzML' in head or b'<spectrum' in head:
+        return "mzml"
+    raise ValueError("unrecognised spectrum file: expected mzML, mzXML, MGF or MS1/MS2")
+
+def open_reader(file_path: Union[str, Path]) -> LazyMzmlReader:
+    with open(file_path, 'rb') as f:
+        head = f.read(SNIFF_SIZE)
+    file_format = sniff_format(head)
+    if file_format == "proxi":
+        raise ValueError(f"{Path(file_path).name} is PROXI JSON; upload it or fetch the spectrum by USI instead")
+    if file_format == "mzxml":
+        return MzxmlReader(file_path)
+    if file_format == "mgf":
+        return MgfReader(file_path)
+    if file_format == "ms2":
+        return Ms2Reader(file_path)
+    return LazyMzmlReader(file_path)
+
+def parse_spectrum_upload(data: bytes) -> List[Dict]:
+    """Peaks of the first spectrum in uploaded mzML, mzXML, MGF, MS1/MS2 or PROXI JSON bytes."""
+    file_format = sniff_format(data)
+    if file_format == "proxi":
+        spectra = parse_proxi_json(gzip.decompress(data) if is_gzip(data) else data)
+        if not spectra:
+            raise ValueError("PROXI JSON contains no readable spectrum")
+        return spectra[0]["peaks"]
+    if file_format == "mzxml":
+        return parse_mzxml_scan_bytes(data)
+    if file_format in ("mgf", "ms2"):
+        if is_gzip(data):
+            data = gzip.decompress(data)
+        text = data.decode('utf-8', 'replace')
+        spectra = parse_mgf(text) if file_format == "mgf" else parse_ms2(text)
+        if not spectra:
+            raise ValueError("MGF contains no BEGIN IONS block" if file_format == "mgf" else "MS2 contains no S line")
+        return spectra[0]["peaks"]
+    return parse_spectrum_bytes(data)
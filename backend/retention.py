@@ -0,0 +1,99 @@
+"""
+Peptide retention time prediction from an SSRCalc-style hydrophobicity index, calibrated
+per run against observed retention times so PSMs eluting far from prediction stand out.
+"""
+
+import statistics
+from typing import Dict, List, Optional
+
+from .calculations import tokenize_peptide
+
+# Retention coefficients for reversed-phase C18 with TFA, after Krokhin et al. (2004):
+# RC for any position, RC_N_TERM for residues at the N-terminus
+RC = {
+    "W": 11.0, "F": 10.5, "L": 9.6, "I": 8.4, "M": 5.8, "V": 5.0, "Y": 4.0, "C": 0.7, "P": 0.2, "A": 0.8,
+    "E": 0.0, "T": 0.4, "D": -0.5, "Q": -0.9, "S": -0.8, "G": -0.9, "R": -1.3, "N": -1.2, "H": -1.3, "K": -1.9,
+}
+RC_N_TERM = {
+    "W": -4.0, "F": -7.0, "L": -9.0, "I": -8.0, "M": -5.5, "V": -5.5, "Y": -3.0, "C": 4.0, "P": 9.0, "A": -1.5,
+    "E": 7.0, "T": 5.0, "D": 9.0, "Q": 1.0, "S": 5.0, "G": 5.0, "R": 8.0, "N": 5.0, "H": 4.0, "K": 4.6,
+}
+# Weights of the N-terminal coefficient for the first three residues
+N_TERM_WEIGHTS = (0.42, 0.22, 0.05)
+# Above this index the response flattens
+HIGH_HYDROPHOBICITY = 38.0
+
+# A PSM is an outlier when its residual exceeds this many robust standard deviations
+OUTLIER_SD = 3.0
+
+def hydrophobicity(sequence: str) -> float:
+    """
+    SSRCalc-style hydrophobicity index of a peptide: summed retention coefficients plus
+    weighted N-terminal terms, corrected for short (< 10) and long (> 20) peptides.
+    Modifications and unknown residues add nothing.
+    """
+    residues = "".join(r for r, _ in tokenize_peptide(sequence)[0])
+    if not residues:
+        raise ValueError(f"peptide {sequence!r} has no residues")
+    total = sum(RC.get(r, 0.0) for r in residues)
+    total += sum(weight * RC_N_TERM.get(r, 0.0) for weight, r in zip(N_TERM_WEIGHTS, residues))
+    n = len(residues)
+    if n < 10:
+        total *= 1 - 0.027 * (10 - n)
+    elif n > 20:
+        total *= 1 - 0.014 * (n - 20)
+    if total > HIGH_HYDROPHOBICITY:
+        total -= 0.3 * (total - HIGH_HYDROPHOBICITY)
+    return total
+
+def predict_rt(sequence: str, slope: float = 1.0, intercept: float = 0.0) -> float:
+    """Retention time slope * hydrophobicity + intercept; the defaults return the index itself."""
+    return slope * hydrophobicity(sequence) + intercept
+
+def calibrate_rt(psms: List[Dict]) -> Dict:
+    """
+    Least-squares slope/intercept mapping hydrophobicity to the observed "retention_time"
+    of PSMs ({"sequence", "retention_time"}), with r2. Needs two distinct indices.
+    """
+    points = [(hydrophobicity(p["sequence"]), p["retention_time"]) for p in psms if p.get("retention_time") is not None]
+    if len({h for h, _ in points}) < 2:
+        raise ValueError(f"RT calibration needs PSMs with at least 2 distinct hydrophobicities, got {len(points)} PSMs")
+    mean_h = statistics.fmean(h for h, _ in points)
+    mean_t = statistics.fmean(t for _, t in points)
+    sxx = sum((h - mean_h) ** 2 for h, _ in points)
+    slope = sum((h - mean_h) * (t - mean_t) for h, t in points) / sxx
+    intercept = mean_t - slope * mean_h
+    ss_total = sum((t - mean_t) ** 2 for _, t in points)
+    ss_residual = sum((t - slope * h - intercept) ** 2 for h, t in points)
+    return {"slope": slope, "intercept": intercept, "r2": 1 - ss_residual / ss_total if ss_total else 1.0,
+            "psms": len(points)}
+
+def rt_deviations(psms: List[Dict], slope: Optional[float] = None, intercept: Optional[float] = None,
+                  max_deviation: Optional[float] = None) -> Dict:
+    """
+    Predicted RT and deviation (observed - predicted) of each PSM, in input order. Without
+    slope/intercept the PSMs calibrate themselves (calibrate_rt). A PSM is flagged as an
+    outlier beyond max_deviation, or by default beyond OUTLIER_SD robust (MAD-based)
+    standard deviations from the median deviation, so a systematic offset left by the given
+    slope/intercept does not flag every PSM on one side.
+    """
+    calibration = (calibrate_rt(psms) if slope is None or intercept is None
+                   else {"slope": slope, "intercept": intercept, "r2": None, "psms": len(psms)})
+    results = []
+    for psm in psms:
+        index = hydrophobicity(psm["sequence"])
+        predicted = calibration["slope"] * index + calibration["intercept"]
+        observed = psm.get("retention_time")
+        results.append({"sequence": psm["sequence"], "hydrophobicity": index, "predicted_rt": predicted,
+                        "retention_time": observed,
+                        "deviation": observed - predicted if observed is not None else None})
+    deviations = [r["deviation"] for r in results if r["deviation"] is not None]
+    center = 0.0
+    if max_deviation is None and len(deviations) >= 3:
+        center = statistics.median(deviations)
+        mad = statistics.median(abs(d - center) for d in deviations)
+        max_deviation = OUTLIER_SD * 1.4826 * mad if mad else None
+    for r in results:
+        r["outlier"] = (r["deviation"] is not None and max_deviation is not None
+                        and abs(r["deviation"] - center) > max_deviation)
+    return {"calibration": calibration, "max_deviation": max_deviation, "psms": results}
@@ -0,0 +1,95 @@
+"""
+Peptide-spectrum match scores, computed from match_ions output or peak lists so candidates
+can be compared with the same math as the search engines that reported them.
+"""
+
+import math
+from typing import Dict, List
+
+def _is_backbone_ion(match: dict, series: str) -> bool:
+    # "b5" or "y3", not neutral-loss or other series labels
+    label = match["ion_type"]
+    return label[:1] == series and label[1:].isdigit()
+
+def hyperscore(matches: List[dict]) -> Dict:
+    """
+    X!Tandem hyperscore of a match_ions result: the summed relative intensities (0-100) of
+    the matched b and y ions times Nb! * Ny!, reported as log10 like X!Tandem does.
+    Neutral-loss and other series are ignored; each (ion, charge) counts once.
+    """
+    counts = {}
+    intensity = 0.0
+    seen = set()
+    for series in ("b", "y"):
+        counts[series] = 0
+        for match in matches:
+            key = (match["ion_type"], match["ion_charge"])
+            if key in seen or not _is_backbone_ion(match, series):
+                continue
+            seen.add(key)
+            counts[series] += 1
+            intensity += match["relative_intensity"]
+    score = None
+    if intensity > 0:
+        score = math.log10(intensity) + math.log10(math.factorial(counts["b"])) + math.log10(math.factorial(counts["y"]))
+    return {"hyperscore": score, "b_ions": counts["b"], "y_ions": counts["y"], "matched_intensity": intensity}
+
+# Comet defaults for high-resolution fragment ions are 0.02 / 0.0; these are its low-res ones
+XCORR_BIN_WIDTH = 1.0005079
+XCORR_BIN_OFFSET = 0.4
+XCORR_WINDOWS = 10
+XCORR_OFFSET = 75
+
+def _xcorr_bin(mz: float, bin_width: float, bin_offset: float) -> int:
+    return int(mz / bin_width + (1.0 - bin_offset))
+
+def xcorr_preprocess(peaks: List[dict], bin_width: float = XCORR_BIN_WIDTH, bin_offset: float = XCORR_BIN_OFFSET,
+                     flanking: bool = False) -> List[float]:
+    """
+    Comet's fast-XCorr spectrum: square-rooted intensities binned, each of 10 m/z regions
+    scaled to a maximum of 50 (peaks under 5% of the base peak dropped), then the mean of
+    the surrounding +-75 bins subtracted from every bin. With flanking, half of each
+    neighbouring bin is added, as Comet's theoretical_fragment_ions = 0 does.
+    """
+    if bin_width <= 0:
+        raise ValueError(f"bin_width must be positive, got {bin_width}")
+    if not peaks:
+        return []
+    size = _xcorr_bin(max(peak["mz"] for peak in peaks), bin_width, bin_offset) + XCORR_OFFSET + 1
+    binned = [0.0] * size
+    for peak in peaks:
+        i = _xcorr_bin(peak["mz"], bin_width, bin_offset)
+        if 0 <= i < size:
+            binned[i] = max(binned[i], math.sqrt(max(peak["intensity"], 0.0)))
+
+    threshold = max(binned) * 0.05
+    region = size // XCORR_WINDOWS + 1
+    for start in range(0, size, region):
+        top = max(binned[start:start + region])
+        for i in range(start, min(start + region, size)):
+            binned[i] = binned[i] * 50.0 / top if top > 0 and binned[i] > threshold else 0.0
+
+    # Running window sum keeps the background subtraction O(bins)
+    processed = [0.0] * size
+    window = sum(binned[:XCORR_OFFSET + 1])
+    for i in range(size):
+        processed[i] = binned[i] - (window - binned[i]) / (2 * XCORR_OFFSET)
+        if i + XCORR_OFFSET + 1 < size:
+            window += binned[i + XCORR_OFFSET + 1]
+        if i - XCORR_OFFSET >= 0:
+            window -= binned[i - XCORR_OFFSET]
+    if flanking:
+        processed = [value + 0.5 * ((processed[i - 1] if i > 0 else 0.0) + (processed[i + 1] if i + 1 < size else 0.0))
+                     for i, value in enumerate(processed)]
+    return processed
+
+def xcorr(peaks: List[dict], theoretical_ions: List[dict], bin_width: float = XCORR_BIN_WIDTH,
+          bin_offset: float = XCORR_BIN_OFFSET, flanking: bool = False) -> float:
+    """
+    SEQUEST/Comet XCorr of a peak list against theoretical ions (calculate_ions output,
+    typically b/y without losses at charges below the precursor's). Each occupied fragment
+    bin adds its preprocessed intensity once; the sum is scaled by 0.005 as in Comet.
+    """
+    processed = xcorr_preprocess(peaks, bin_width, bin_offset, flanking)
+    bins = {_xcorr_bin(ion["mz"], bin_width, bin_offset) for ion in theoretical_ions}
+    return sum(processed[i] for i in bins if 0 <= i < len(processed)) * 0.005
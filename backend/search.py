@@ -0,0 +1,108 @@
+"""
+Scoring one spectrum against a list of candidate peptides: a quick "could this spectrum be
+peptide X?" check with the same fragment matching and scores as the annotation view.
+"""
+
+from typing import Dict, List, Optional
+
+from .calculations import calculate_ions, get_residue_masses, match_ions, precursor_mz, tokenize_peptide
+from .decoys import format_peptide
+from .masses import mass_from_mz
+from .mzpaf import format_mzpaf
+from .scoring import hyperscore, xcorr
+from .settings import SearchSettings
+
+def _precursor_error(observed: float, theoretical: float, unit: str) -> float:
+    # Signed, observed minus theoretical
+    return (observed - theoretical) / theoretical * 1e6 if unit == "ppm" else observed - theoretical
+
+def score_candidate(peaks: List[dict], sequence: str, settings: SearchSettings) -> Dict:
+    """Matches and scores of one candidate; see search_spectrum for the fields."""
+    fragmentation = settings.fragmentation
+    ions = calculate_ions(sequence, fragmentation)
+    matches = match_ions(peaks, ions, mass_type=fragmentation.mass_type, settings=settings.matching)
+    theoretical_mz = precursor_mz(sequence, fragmentation.precursor_charge, fragmentation.residue_masses or None,
+                                  fragmentation.mass_type, fragmentation.electron_aware)
+    backbone = [ion for ion in ions if ion["series"] in ("b", "y") and not ion["loss"]]
+    scores = hyperscore(matches)
+    return {
+        "sequence": sequence,
+        "hyperscore": scores["hyperscore"],
+        "xcorr": xcorr(peaks, backbone),
+        "matched_ions": len(matches),
+        "theoretical_ions": len(ions),
+        "precursor_mz": theoretical_mz,
+        "precursor_error": (_precursor_error(settings.precursor_mz, theoretical_mz, settings.precursor_tolerance_unit)
+                            if settings.precursor_mz is not None else None),
+        "matches": matches,
+    }
+
+def _key(result: Dict, score: str) -> float:
+    return result[score] if result[score] is not None else float("-inf")
+
+def localize_offset(peaks: List[dict], sequence: str, offset: float, settings: SearchSettings) -> Dict:
+    """
+    Places a precursor mass offset on each residue of a candidate in turn and keeps the
+    placement with the best settings.score. The score_candidate fields of that placement
+    come back with "mass_offset", 1-based "offset_position", "offset_residue",
+    "offset_sites" (all positions scoring as well, i.e. the ambiguity) and
+    "localized_sequence"; "sequence" stays the unmodified candidate.
+    """
+    fragmentation = settings.fragmentation
+    tokens, n_term, c_term = tokenize_peptide(sequence, get_residue_masses(fragmentation.residue_masses, fragmentation.mass_type))
+    best, sites = None, []
+    for k in range(len(tokens)):
+        placed = list(tokens)
+        placed[k] = (tokens[k][0], tokens[k][1] + offset)
+        result = score_candidate(peaks, format_peptide(placed, n_term, c_term), settings)
+        if best is None or _key(result, settings.score) > _key(best, settings.score):
+            best, sites = result, [k + 1]
+        elif _key(result, settings.score) == _key(best, settings.score):
+            sites.append(k + 1)
+    best["localized_sequence"] = best["sequence"]
+    best.update(sequence=sequence, mass_offset=offset, offset_position=sites[0],
+                offset_residue=tokens[sites[0] - 1][0], offset_sites=sites)
+    return best
+
+def search_spectrum(peaks: List[dict], candidates: List[str], settings: Optional[SearchSettings] = None) -> List[Dict]:
+    """
+    Ranks candidate peptides (modified sequences allowed) against a spectrum. With a
+    precursor_mz and precursor_tolerance, candidates whose precursor m/z at the fragmentation
+    precursor_charge lies outside the tolerance are skipped. The best settings.max_results
+    come back best first with "rank", "score" (the settings.score value), "hyperscore",
+    "xcorr", "matched_ions", "theoretical_ions", theoretical "precursor_mz", signed
+    "precursor_error" and the "matches" for annotation. Ties keep the candidate order.
+    In open_search mode a candidate whose precursor differs by more than min_offset gets the
+    difference localized (see localize_offset) and is scored with it in place.
+    """
+    settings = (settings or SearchSettings()).validate_options()
+    results = []
+    fragmentation = settings.fragmentation
+    for sequence in dict.fromkeys(candidates):
+        if settings.precursor_mz is not None:
+            theoretical_mz = precursor_mz(sequence, fragmentation.precursor_charge, fragmentation.residue_masses or None,
+                                          fragmentation.mass_type, fragmentation.electron_aware)
+            error = _precursor_error(settings.precursor_mz, theoretical_mz, settings.precursor_tolerance_unit)
+            if settings.precursor_tolerance is not None and abs(error) > settings.precursor_tolerance:
+                continue
+        result = None
+        if settings.open_search and settings.precursor_mz is not None:
+            charge = fragmentation.precursor_charge
+            offset = (mass_from_mz(settings.precursor_mz, charge, fragmentation.electron_aware)
+                      - mass_from_mz(theoretical_mz, charge, fragmentation.electron_aware))
+            if abs(offset) > settings.min_offset:
+                result = localize_offset(peaks, sequence, offset, settings)
+                # Relative to the unmodified candidate, i.e. the offset itself
+                result.update(precursor_mz=theoretical_mz, precursor_error=error)
+        if result is None:
+            result = score_candidate(peaks, sequence, settings)
+        result["score"] = result[settings.score]
+        results.append(result)
+    results.sort(key=lambda r: -_key(r, "score"))
+    results = results[:settings.max_results]
+    for rank, result in enumerate(results, 1):
+        result["rank"] = rank
+        # Labelled only for the candidates that come back
+        for match in result["matches"]:
+            match["mzpaf"] = format_mzpaf(match)
+    return results
@@ -0,0 +1,206 @@
+"""
+Option objects shared by the calculation functions and the API.
+"""
+
+from typing import Dict, List, Optional, Tuple, Union
+
+from pydantic import BaseModel, Field
+
+from .glyco import parse_glycan_composition
+from .masses import ISOTOPE_LABELS, check_mass_type
+
+ION_SERIES = ("a", "b", "c", "x", "y", "z")
+N_TERMINAL_SERIES = ("a", "b", "c")
+NEUTRAL_LOSSES = ("H2O", "NH3")
+
+# How match_ions picks among the peaks inside an ion's tolerance window
+MATCH_STRATEGIES = ("closest", "most_intense", "intensity_weighted")
+# "all": a peak may explain several ions; "one_to_one": each peak is assigned to one ion
+ASSIGNMENT_MODES = ("all", "one_to_one")
+TOLERANCE_UNITS = ("da", "ppm")
+
+class FragmentationSettings(BaseModel):
+    """
+    Options for calculate_ions, deserialized from a JSON object such as
+    {"precursor_charge": 3, "series": ["b", "y"], "losses": ["H2O"], "series_max_charge": {"b": "auto"}}.
+    New options get a default here so existing callers keep working.
+    """
+    precursor_charge: int = 1
+    series: List[str] = Field(default_factory=lambda: ["b", "y"])
+    # Neutral losses; with restrict_losses only from fragments containing S/T/E/D (H2O) or R/K/N/Q (NH3)
+    losses: List[str] = Field(default_factory=list)
+    restrict_losses: bool = True
+    # -H3PO4 fragments (and precursor ions, type "M-H3PO4") for peptides with phospho S/T
+    phospho_losses: bool = True
+    # Glyco mode: oxonium ions and the peptide + glycan Y ions of a composition such as
+    # "HexNAc(4)Hex(5)NeuAc(2)"; the sequence is the peptide without its glycan
+    glycan: Optional[str] = None
+    # Fragment charge range, max_charge defaults to precursor_charge
+    min_charge: int = 1
+    max_charge: Optional[int] = None
+    # Per-series cap: a number or "auto" (derived from basic residues)
+    series_max_charge: Dict[str, Union[int, str]] = Field(default_factory=dict)
+    # Round m/z values in the output
+    decimals: Optional[int] = None
+    # Terminal mods added on top of any in the sequence string
+    n_term_mod: float = 0.0
+    c_term_mod: float = 0.0
+    label: Optional[str] = None
+    mass_type: str = "monoisotopic"
+    electron_aware: bool = True
+    residue_masses: Dict[str, float] = Field(default_factory=dict)
+
+    def validate_options(self) -> "FragmentationSettings":
+        """Raises ValueError describing the first invalid option."""
+        if self.precursor_charge < 1:
+            raise ValueError(f"precursor_charge must be positive, got {self.precursor_charge}")
+        for series in self.series:
+            if series not in ION_SERIES:
+                raise ValueError(f"Unknown ion series {series!r}, expected one of {ION_SERIES}")
+        for loss in self.losses:
+            if loss not in NEUTRAL_LOSSES:
+                raise ValueError(f"Unknown neutral loss {loss!r}, expected one of {NEUTRAL_LOSSES}")
+        if self.min_charge < 1 or (self.max_charge is not None and self.max_charge < self.min_charge):
+            raise ValueError(f"Invalid fragment charge range {self.min_charge}..{self.max_charge}")
+        if self.decimals is not None and self.decimals < 0:
+            raise ValueError(f"decimals must be >= 0, got {self.decimals}")
+        if self.label is not None and self.label not in ISOTOPE_LABELS:
+            raise ValueError(f"Unknown isotope label {self.label!r}, expected one of {tuple(ISOTOPE_LABELS)}")
+        check_mass_type(self.mass_type)
+        if self.glycan is not None:
+            parse_glycan_composition(self.glycan)
+        return self
+
+    @property
+    def fragment_max_charge(self) -> int:
+        return self.max_charge if self.max_charge is not None else self.precursor_charge
+
+class ToleranceRule(BaseModel):
+    """
+    A tolerance for the ions it covers: those of the listed series (all when empty) whose
+    theoretical m/z lies in [min_mz, max_mz], e.g. {"series": ["y"], "tolerance": 10, "unit": "ppm"}
+    or {"max_mz": 300, "tolerance": 0.6} for the low-mass region on ion-trap data.
+    """
+    tolerance: Union[float, Tuple[float, float]]
+    unit: str = "da"
+    series: List[str] = Field(default_factory=list)
+    min_mz: Optional[float] = None
+    max_mz: Optional[float] = None
+
+    def covers(self, ion: dict) -> bool:
+        series = ion.get("series") or ion["type"][:1]
+        if self.series and series not in self.series:
+            return False
+        if self.min_mz is not None and ion["mz"] < self.min_mz:
+            return False
+        return self.max_mz is None or ion["mz"] <= self.max_mz
+
+class MatchSettings(BaseModel):
+    """
+    Options for match_ions, e.g. {"tolerance": [-10, 20], "tolerance_unit": "ppm",
+    "tolerance_rules": [{"series": ["b"], "tolerance": 0.02}]}. The first rule covering an
+    ion sets its tolerance; other ions use tolerance/tolerance_unit.
+    """
+    # A number, or [low, high] bounds on observed - theoretical
+    tolerance: Union[float, Tuple[float, float]] = 0.5
+    tolerance_unit: str = "da"
+    strategy: str = "closest"
+    assignment: str = "all"
+    tolerance_rules: List[ToleranceRule] = Field(default_factory=list)
+    # Look for this many isotope peaks (+1, +2, ...) after each monoisotopic match
+    isotope_peaks: int = 0
+    # Ignore peaks below this signal-to-noise, with noise estimated over noise_window Da
+    min_snr: Optional[float] = None
+    noise_window: float = 100.0
+
+    def validate_options(self) -> "MatchSettings":
+        """Raises ValueError describing the first invalid option."""
+        if self.isotope_peaks < 0:
+            raise ValueError(f"isotope_peaks must be >= 0, got {self.isotope_peaks}")
+        if self.min_snr is not None and self.min_snr < 0:
+            raise ValueError(f"min_snr must be >= 0, got {self.min_snr}")
+        if self.noise_window <= 0:
+            raise ValueError(f"noise_window must be positive, got {self.noise_window}")
+        if self.strategy not in MATCH_STRATEGIES:
+            raise ValueError(f"Unknown match strategy {self.strategy!r}, expected one of {MATCH_STRATEGIES}")
+        if self.assignment not in ASSIGNMENT_MODES:
+            raise ValueError(f"Unknown assignment mode {self.assignment!r}, expected one of {ASSIGNMENT_MODES}")
+        for unit in [self.tolerance_unit] + [rule.unit for rule in self.tolerance_rules]:
+            if unit not in TOLERANCE_UNITS:
+                raise ValueError(f"Unknown tolerance unit {unit!r}, expected one of {TOLERANCE_UNITS}")
+        for rule in self.tolerance_rules:
+            for series in rule.series:
+                if series not in ION_SERIES:
+                    raise ValueError(f"Unknown ion series {series!r} in tolerance rule, expected one of {ION_SERIES}")
+        return self
+
+    def tolerance_for(self, ion: dict) -> Tuple[Union[float, Tuple[float, float]], str]:
+        """(tolerance, unit) that applies to a theoretical ion."""
+        for rule in self.tolerance_rules:
+            if rule.covers(ion):
+                return rule.tolerance, rule.unit
+        return self.tolerance, self.tolerance_unit
+
+SEARCH_SCORES = ("hyperscore", "xcorr", "matched_ions")
+
+class SearchSettings(BaseModel):
+    """
+    Options for search.search_spectrum: how candidates are fragmented and matched, the
+    precursor filter (precursor_tolerance None scores every candidate), the ranking score
+    and open (mass-offset) search, e.g. {"open_search": true, "precursor_tolerance": 200,
+    "precursor_tolerance_unit": "da"}.
+    """
+    fragmentation: FragmentationSettings = Field(default_factory=FragmentationSettings)
+    matching: MatchSettings = Field(default_factory=MatchSettings)
+    precursor_mz: Optional[float] = None
+    precursor_tolerance: Optional[float] = 20.0
+    precursor_tolerance_unit: str = "ppm"
+    score: str = "hyperscore"
+    max_results: int = 10
+    # Open search: the precursor mass difference (within a wide precursor_tolerance, e.g.
+    # 200 Da) is placed on the residue that explains most fragments; smaller ones are ignored
+    open_search: bool = False
+    min_offset: float = 0.02
+
+    def validate_options(self) -> "SearchSettings":
+        """Raises ValueError describing the first invalid option."""
+        self.fragmentation.validate_options()
+        self.matching.validate_options()
+        if self.score not in SEARCH_SCORES:
+            raise ValueError(f"Unknown search score {self.score!r}, expected one of {SEARCH_SCORES}")
+        if self.precursor_tolerance_unit not in TOLERANCE_UNITS:
+            raise ValueError(f"Unknown tolerance unit {self.precursor_tolerance_unit!r}, expected one of {TOLERANCE_UNITS}")
+        if self.max_results < 1:
+            raise ValueError(f"max_results must be >= 1, got {self.max_results}")
+        return self
+
+class SvgOptions(BaseModel):
+    """
+    Options for svg.render_annotated_svg, e.g. {"width": 1200, "min_mz": 200, "title": "Scan 1234"}.
+    Sizes are in pixels (SVG user units); the m/z range defaults to the peaks' +/- 50.
+    """
+    width: int = 900
+    height: int = 500
+    title: Optional[str] = None
+    min_mz: Optional[float] = None
+    max_mz: Optional[float] = None
+    # Mass error strip under the spectrum, in ppm or Da
+    show_errors: bool = True
+    error_unit: str = "ppm"
+    font_size: int = 11
+    font_family: str = "Inter, Arial, sans-serif"
+    # Labels on at most this many matched peaks, most intense first
+    max_labels: Optional[int] = None
+    legend: bool = True
+
+    def validate_options(self) -> "SvgOptions":
+        """Raises ValueError describing the first invalid option."""
+        if self.width < 200 or self.height < 150:
+            raise ValueError(f"SVG must be at least 200 x 150, got {self.width} x {self.height}")
+        if self.error_unit not in TOLERANCE_UNITS:
+            raise ValueError(f"Unknown error unit {self.error_unit!r}, expected one of {TOLERANCE_UNITS}")
+        if self.min_mz is not None and self.max_mz is not None and self.max_mz <= self.min_mz:
+            raise ValueError(f"max_mz must exceed min_mz, got {self.min_mz}..{self.max_mz}")
+        if self.font_size < 4:
+            raise ValueError(f"font_size must be >= 4, got {self.font_size}")
+        return self
@@ -0,0 +1,197 @@
+"""
+Spectrum-to-spectrum comparison: peak pairing within a tolerance and the similarity scores
+built on it, for comparing an acquired spectrum with a predicted or library one, and
+merging replicate spectra into a consensus.
+"""
+
+import bisect
+import math
+from typing import Dict, List, Optional, Tuple
+
+import numpy as np
+
+from .calculations import validate_peaks
+from .processing import normalize, tolerance_to_da
+
+def _pair_peaks(peaks_a: List[dict], peaks_b: List[dict], tolerance: float, tolerance_unit: str = "da",
+                shifts: Tuple[float, ...] = (0.0,)) -> List[Tuple[int, int, float]]:
+    """
+    One-to-one (index a, index b, shift) pairs of peaks within tolerance of each other once
+    a shift is added to the m/z of b, taken highest intensity product first (then closest).
+    """
+    order_b = sorted(range(len(peaks_b)), key=lambda j: peaks_b[j]["mz"])
+    mzs_a = np.asarray([peak["mz"] for peak in peaks_a], dtype=np.float64)
+    windows = tolerance_to_da(mzs_a, tolerance, tolerance_unit)
+    candidates = []
+    for shift in dict.fromkeys(shifts):
+        shifted = np.asarray([peaks_b[j]["mz"] for j in order_b], dtype=np.float64) + shift
+        # The b peaks in every a peak's window, located for all a peaks in one search
+        starts = np.searchsorted(shifted, mzs_a - windows, side="left").tolist()
+        ends = np.searchsorted(shifted, mzs_a + windows, side="right").tolist()
+        mzs_b = shifted.tolist()
+        for i, peak in enumerate(peaks_a):
+            for k in range(starts[i], ends[i]):
+                j = order_b[k]
+                candidates.append((peak["intensity"] * peaks_b[j]["intensity"], abs(mzs_b[k] - peak["mz"]), i, j, shift))
+    candidates.sort(key=lambda c: (-c[0], c[1]))
+    used_a, used_b, pairs = set(), set(), []
+    for _, _, i, j, shift in candidates:
+        if i not in used_a and j not in used_b:
+            used_a.add(i)
+            used_b.add(j)
+            pairs.append((i, j, shift))
+    return sorted(pairs)
+
+def _cosine_scores(peaks_a: List[dict], peaks_b: List[dict], pairs: List[Tuple[int, int, float]]) -> Dict:
+    dot = sum(peaks_a[i]["intensity"] * peaks_b[j]["intensity"] for i, j, _ in pairs)
+    norm = math.sqrt(sum(p["intensity"] ** 2 for p in peaks_a)) * math.sqrt(sum(p["intensity"] ** 2 for p in peaks_b))
+    cosine = min(dot / norm, 1.0) if norm else 0.0
+    return {
+        "dot_product": dot,
+        "cosine": cosine,
+        # 1 for identical spectra, 0 for orthogonal ones
+        "spectral_angle": 1 - 2 * math.acos(cosine) / math.pi,
+        "matched_peaks": len(pairs),
+    }
+
+def spectrum_similarity(peaks_a: List[dict], peaks_b: List[dict], tolerance: float = 0.02,
+                        tolerance_unit: str = "da") -> Dict:
+    """
+    Dot product, cosine and normalized spectral contrast angle of two peak lists, pairing
+    each peak with at most one peak of the other spectrum. Intensities are used as given,
+    so apply processing.normalize (e.g. sqrt) first for the usual weighting.
+    """
+    validate_peaks(peaks_a)
+    validate_peaks(peaks_b)
+    return _cosine_scores(peaks_a, peaks_b, _pair_peaks(peaks_a, peaks_b, tolerance, tolerance_unit))
+
+def align_spectra(peaks_top: List[dict], peaks_bottom: List[dict], tolerance: float = 0.02,
+                  tolerance_unit: str = "da") -> Dict:
+    """
+    Peak pairing for mirror plots, with the same one-to-one rule as spectrum_similarity.
+    Indices refer to the input lists: "pairs" holds {"top", "bottom", "error_da"} (top minus
+    bottom m/z), "unpaired_top" / "unpaired_bottom" the peaks left over.
+    """
+    validate_peaks(peaks_top)
+    validate_peaks(peaks_bottom)
+    pairs = _pair_peaks(peaks_top, peaks_bottom, tolerance, tolerance_unit)
+    paired_top, paired_bottom = {i for i, _, _ in pairs}, {j for _, j, _ in pairs}
+    return {
+        "pairs": [{"top": i, "bottom": j, "error_da": peaks_top[i]["mz"] - peaks_bottom[j]["mz"]} for i, j, _ in pairs],
+        "unpaired_top": [i for i in range(len(peaks_top)) if i not in paired_top],
+        "unpaired_bottom": [j for j in range(len(peaks_bottom)) if j not in paired_bottom],
+    }
+
+def modified_cosine(peaks_a: List[dict], peaks_b: List[dict], precursor_mass_a: float, precursor_mass_b: float,
+                    tolerance: float = 0.02, tolerance_unit: str = "da") -> Dict:
+    """
+    GNPS-style modified cosine: peaks pair either directly or offset by the precursor mass
+    difference (a - b), so fragments carrying a modification still count. The shift is
+    applied as a mass, i.e. for singly charged fragments. Returns the spectrum_similarity
+    scores plus "shifted_matches", the (index a, index b, m/z a, m/z b) of the offset pairs.
+    """
+    validate_peaks(peaks_a)
+    validate_peaks(peaks_b)
+    shift = precursor_mass_a - precursor_mass_b
+    pairs = _pair_peaks(peaks_a, peaks_b, tolerance, tolerance_unit, (0.0, shift))
+    result = _cosine_scores(peaks_a, peaks_b, pairs)
+    result["mass_shift"] = shift
+    result["shifted_matches"] = [{"index_a": i, "index_b": j, "mz_a": peaks_a[i]["mz"], "mz_b": peaks_b[j]["mz"]}
+                                 for i, j, pair_shift in pairs if pair_shift != 0.0]
+    return result
+
+def _entropy(intensities: List[float]) -> float:
+    total = sum(intensities)
+    return -sum(i / total * math.log(i / total) for i in intensities if i > 0) if total > 0 else 0.0
+
+def _entropy_weighted(intensities: List[float]) -> List[float]:
+    # Low-entropy spectra get their minor peaks boosted: I^w, w = 0.25 + 0.25 * S for S < 3
+    entropy = _entropy(intensities)
+    if entropy < 3:
+        intensities = [i ** (0.25 + 0.25 * entropy) for i in intensities]
+    total = sum(intensities)
+    return [i / total for i in intensities] if total > 0 else intensities
+
+def spectral_entropy(peaks: List[dict]) -> float:
+    """Shannon entropy (natural log) of the intensities normalized to sum 1."""
+    return _entropy([max(peak["intensity"], 0.0) for peak in peaks])
+
+def entropy_similarity(peaks_a: List[dict], peaks_b: List[dict], tolerance: float = 0.02,
+                       tolerance_unit: str = "da", weighted: bool = True) -> float:
+    """
+    Spectral entropy similarity (Li et al., Nat. Methods 2021): 1 - (2 S_AB - S_A - S_B) / ln 4,
+    where AB is the half-and-half mixture of the two spectra with paired peaks merged.
+    weighted applies the standard entropy-based intensity weighting first. 1 = identical.
+    """
+    validate_peaks(peaks_a)
+    validate_peaks(peaks_b)
+    intensities_a = [max(peak["intensity"], 0.0) for peak in peaks_a]
+    intensities_b = [max(peak["intensity"], 0.0) for peak in peaks_b]
+    if not sum(intensities_a) or not sum(intensities_b):
+        return 0.0
+    if weighted:
+        intensities_a, intensities_b = _entropy_weighted(intensities_a), _entropy_weighted(intensities_b)
+    else:
+        intensities_a = [i / sum(intensities_a) for i in intensities_a]
+        intensities_b = [i / sum(intensities_b) for i in intensities_b]
+
+    pairs = _pair_peaks(peaks_a, peaks_b, tolerance, tolerance_unit)
+    paired_a, paired_b = {i for i, _, _ in pairs}, {j for _, j, _ in pairs}
+    merged = [(intensities_a[i] + intensities_b[j]) / 2 for i, j, _ in pairs]
+    merged += [value / 2 for i, value in enumerate(intensities_a) if i not in paired_a]
+    merged += [value / 2 for j, value in enumerate(intensities_b) if j not in paired_b]
+    divergence = 2 * _entropy(merged) - _entropy(intensities_a) - _entropy(intensities_b)
+    return max(0.0, min(1.0, 1 - divergence / math.log(4)))
+
+def consensus_spectrum(spectra: List[List[dict]], tolerance: float = 0.02, tolerance_unit: str = "da",
+                       min_fraction: float = 0.5, normalization: Optional[str] = "base_peak") -> List[dict]:
+    """
+    Merges replicate spectra of one precursor. Each spectrum is normalized first (see
+    processing.normalize; None keeps raw intensities) so none dominates. Peaks are then
+    clustered most intense first: a peak joins the closest cluster within tolerance that has
+    no peak of its spectrum yet, else starts a new one. Clusters seen in fewer than
+    min_fraction of the spectra are dropped; the rest give a peak at the intensity-weighted
+    mean m/z with the mean intensity over the spectra containing it, plus "frequency".
+    """
+    if not spectra:
+        raise ValueError("consensus needs at least one spectrum")
+    if not 0 <= min_fraction <= 1:
+        raise ValueError(f"min_fraction must be between 0 and 1, got {min_fraction}")
+    pooled = []
+    for n, peaks in enumerate(spectra):
+        validate_peaks(peaks)
+        if normalization is not None:
+            peaks = normalize(peaks, normalization)
+        pooled.extend((peak["mz"], peak["intensity"], n) for peak in peaks)
+    pooled.sort(key=lambda p: -p[1])
+
+    # Clusters keyed by the m/z of their first (most intense) peak, kept sorted for bisect
+    centres: List[float] = []
+    clusters: List[List[Tuple[float, float, int]]] = []
+    for mz, intensity, n in pooled:
+        window = tolerance_to_da(mz, tolerance, tolerance_unit)
+        best = None
+        k = bisect.bisect_left(centres, mz - window)
+        while k < len(centres) and centres[k] <= mz + window:
+            if all(member[2] != n for member in clusters[k]) and (best is None or abs(centres[k] - mz) < abs(centres[best] - mz)):
+                best = k
+            k += 1
+        if best is None:
+            k = bisect.bisect_left(centres, mz)
+            centres.insert(k, mz)
+            clusters.insert(k, [(mz, intensity, n)])
+        else:
+            clusters[best].append((mz, intensity, n))
+
+    consensus = []
+    for cluster in clusters:
+        frequency = len(cluster) / len(spectra)
+        if frequency < min_fraction:
+            continue
+        total = sum(intensity for _, intensity, _ in cluster)
+        consensus.append({
+            "mz": sum(mz * intensity for mz, intensity, _ in cluster) / total if total else cluster[0][0],
+            "intensity": total / len(cluster),
+            "frequency": frequency,
+        })
+    return consensus
@@ -0,0 +1,129 @@
+"""
+In-memory spectrum object reused across re-annotations.
+"""
+
+from typing import List, Optional, Tuple, Union
+
+import numpy as np
+
+from .calculations import annotate_spectrum, match_ions
+from .processing import apply_processing, top_n_indices
+from .settings import MatchSettings
+
+class Spectrum:
+    """
+    A peak list held as m/z-sorted numpy arrays. Keeping one of these per scan
+    lets the viewer re-match with a new tolerance or filter without re-reading
+    and re-parsing the mzML.
+    """
+
+    def __init__(self, mz, intensity, scan_nr: Optional[int] = None, metadata: Optional[dict] = None, ion_mobility=None):
+        mz = np.asarray(mz, dtype=np.float64)
+        intensity = np.asarray(intensity, dtype=np.float64)
+        if len(mz) != len(intensity):
+            raise ValueError(f"m/z and intensity arrays differ in length ({len(mz)} vs {len(intensity)})")
+        # Files nearly always store peaks sorted; only reorder (copying every array) when not
+        order = None if len(mz) < 2 or bool(np.all(mz[1:] >= mz[:-1])) else np.argsort(mz, kind="stable")
+        self.mz = mz if order is None else mz[order]
+        self.intensity = intensity if order is None else intensity[order]
+        # Per-peak ion mobility (1/K0 or drift time) for timsTOF/FAIMS data, else None
+        self.ion_mobility = None
+        if ion_mobility is not None:
+            ion_mobility = np.asarray(ion_mobility, dtype=np.float64)
+            if len(ion_mobility) != len(mz):
+                raise ValueError(f"ion mobility array has {len(ion_mobility)} values for {len(mz)} peaks")
+            self.ion_mobility = ion_mobility if order is None else ion_mobility[order]
+        self.scan_nr = scan_nr
+        # Scan-level information from the mzML (precursors, ...), see mzml.spectrum_summary
+        self.metadata = metadata or {}
+        self._peaks: Optional[List[dict]] = None
+
+    @classmethod
+    def from_peaks(cls, peaks: List[dict], scan_nr: Optional[int] = None, metadata: Optional[dict] = None) -> "Spectrum":
+        mobility = [p["ion_mobility"] for p in peaks] if peaks and "ion_mobility" in peaks[0] else None
+        return cls([p["mz"] for p in peaks], [p["intensity"] for p in peaks], scan_nr, metadata, mobility)
+
+    def __len__(self) -> int:
+        return len(self.mz)
+
+    def peaks(self) -> List[dict]:
+        """Peaks as {"mz", "intensity"} dicts (plus "ion_mobility" when known), built once and reused."""
+        if self._peaks is None:
+            self._peaks = [{"mz": float(m), "intensity": float(i)} for m, i in zip(self.mz, self.intensity)]
+            if self.ion_mobility is not None:
+                for peak, mobility in zip(self._peaks, self.ion_mobility.tolist()):
+                    peak["ion_mobility"] = mobility
+        return self._peaks
+
+    def arrays(self) -> dict:
+        """Columnar {"mz": [...], "intensity": [...]} output, cheap for large profile spectra."""
+        arrays = {"mz": self.mz.tolist(), "intensity": self.intensity.tolist()}
+        if self.ion_mobility is not None:
+            arrays["ion_mobility"] = self.ion_mobility.tolist()
+        return arrays
+
+    def match(self, theoretical_ions: List[dict], tolerance: Union[float, Tuple[float, float]] = 0.5,
+              mass_type: str = "monoisotopic", strategy: str = "closest", assignment: str = "all",
+              tolerance_unit: str = "da", settings: Optional[MatchSettings] = None, mzpaf: bool = False) -> List[dict]:
+        return match_ions(self.peaks(), theoretical_ions, tolerance, mass_type=mass_type, strategy=strategy,
+                          assignment=assignment, tolerance_unit=tolerance_unit, settings=settings, mzpaf=mzpaf)
+
+    def annotate(self, theoretical_ions: List[dict], tolerance: Union[float, Tuple[float, float]] = 0.5,
+                 mass_type: str = "monoisotopic", strategy: str = "closest", assignment: str = "all",
+                 tolerance_unit: str = "da", settings: Optional[MatchSettings] = None) -> dict:
+        return annotate_spectrum(self.peaks(), theoretical_ions, tolerance, mass_type, strategy, assignment,
+                                 tolerance_unit, settings)
+
+    def filter_top_n(self, n: int, window_da: Optional[float] = None) -> "Spectrum":
+        """New spectrum with only the n most intense peaks, per window_da Da bin when given."""
+        if n < 0:
+            raise ValueError(f"n must be non-negative, got {n}")
+        if window_da is not None:
+            keep = top_n_indices(self.mz.tolist(), self.intensity.tolist(), n, window_da)
+            return self._subset(np.asarray(keep, dtype=np.int64))
+        if n >= len(self):
+            return self._subset(np.arange(len(self)))
+        return self._subset(np.argsort(-self.intensity, kind="stable")[:n])
+
+    def filter_mobility(self, low: Optional[float] = None, high: Optional[float] = None) -> "Spectrum":
+        """New spectrum with only the peaks whose ion mobility lies within [low, high]."""
+        if self.ion_mobility is None:
+            raise ValueError("spectrum has no ion mobility array")
+        keep = np.ones(len(self), dtype=bool)
+        if low is not None:
+            keep &= self.ion_mobility >= low
+        if high is not None:
+            keep &= self.ion_mobility <= high
+        return self._subset(np.nonzero(keep)[0])
+
+    @property
+    def is_profile(self) -> bool:
+        """Whether the file marks this spectrum as profile data (MS:1000128)."""
+        return self.metadata.get("spectrum_type") == "profile"
+
+    def normalize(self, *methods: str) -> "Spectrum":
+        """New spectrum with the normalizations applied in order, e.g. normalize("sqrt", "base_peak")."""
+        return self.process([{"step": "normalize", "method": method} for method in methods])
+
+    def process(self, steps: List[dict]) -> "Spectrum":
+        """
+        New spectrum from a chain of processing steps (see processing.apply_processing).
+        remove_precursor steps without a precursor_mz use the first recorded precursor.
+        """
+        precursor = next((p for p in self.metadata.get("precursors") or [] if p.get("mz") and p.get("charge")), None)
+        if precursor:
+            steps = [{"precursor_mz": precursor["mz"], "charge": precursor["charge"], **step}
+                     if step.get("step") == "remove_precursor" and "precursor_mz" not in step else step
+                     for step in steps]
+        peaks = sorted(apply_processing(self.peaks(), steps), key=lambda p: p["mz"])
+        metadata = self.metadata
+        if any(step.get("step") == "centroid" for step in steps):
+            metadata = {**metadata, "spectrum_type": "centroid"}
+        processed = Spectrum.from_peaks(peaks, self.scan_nr, metadata)
+        # Keep per-peak fields added by the steps (charge, ...)
+        processed._peaks = peaks
+        return processed
+
+    def _subset(self, keep) -> "Spectrum":
+        mobility = self.ion_mobility[keep] if self.ion_mobility is not None else None
+        return Spectrum(self.mz[keep], self.intensity[keep], self.scan_nr, self.metadata, mobility)
@@ -0,0 +1,192 @@
+"""
+Annotated spectra as standalone SVG: peak sticks coloured by ion series, labels placed
+without overlaps and the mass error strip, in the colours and layout of the viewer plot.
+"""
+
+import math
+from typing import Dict, List, Optional, Tuple
+from xml.sax.saxutils import escape
+
+from .calculations import validate_peaks
+from .settings import SvgOptions
+
+# The viewer's colours (frontend/app.js renderPlot)
+SERIES_COLORS = {"b": "#3b82f6", "y": "#ef4444", "phospho": "#f59e0b", "glycan": "#10b981", "other": "#8b5cf6"}
+SERIES_NAMES = {"b": "b-ions", "y": "y-ions", "phospho": "-H3PO4", "glycan": "Glycan", "other": "Other"}
+UNMATCHED_COLOR = "#606060"
+AXIS_COLOR = "#1f2937"
+# Character width as a share of the font size, for label boxes
+CHAR_WIDTH = 0.6
+# Upward steps tried per label before it is dropped
+LABEL_STEPS = 6
+MARGIN = {"left": 70, "right": 20, "top": 40, "bottom": 45}
+
+def _category(ion_type: str) -> str:
+    if ion_type.endswith("-H3PO4"):
+        return "phospho"
+    if ion_type.startswith("oxo-") or ion_type.startswith("Y"):
+        return "glycan"
+    return ion_type[0] if ion_type[:1] in ("b", "y") else "other"
+
+def _ticks(low: float, high: float, count: int = 6) -> List[float]:
+    # "Nice" steps of 1, 2 or 5 times a power of ten
+    span = high - low
+    if span <= 0:
+        return [low]
+    raw = span / count
+    magnitude = 10 ** math.floor(math.log10(raw))
+    step = next(m * magnitude for m in (1, 2, 5, 10) if m * magnitude >= raw)
+    first = math.ceil(low / step) * step
+    return [first + k * step for k in range(int((high - first) / step + 1e-9) + 1)]
+
+def _format_tick(value: float) -> str:
+    if abs(value) >= 1e4:
+        return f"{value:.1e}"
+    return f"{value:g}"
+
+def _overlaps(box: Tuple[float, float, float, float], boxes: List[Tuple[float, float, float, float]]) -> bool:
+    x0, y0, x1, y1 = box
+    return any(x0 < b[2] and b[0] < x1 and y0 < b[3] and b[1] < y1 for b in boxes)
+
+def render_annotated_svg(peaks: List[dict], matches: List[dict], options: Optional[SvgOptions] = None) -> str:
+    """
+    SVG text of a spectrum with its matches (match_ions output). Matched peaks are drawn in
+    their series colour and labelled most intense first; a label that would overlap one
+    already placed moves up (with a leader line) for up to LABEL_STEPS lines, else it is
+    left out. Peaks with several matches get one label joining them. Below, the error strip
+    plots each match's error_ppm (or error_da) against its m/z.
+    """
+    options = (options or SvgOptions()).validate_options()
+    validate_peaks(peaks)
+    width, height = options.width, options.height
+    font = options.font_size
+    show_errors = options.show_errors and bool(matches)
+
+    mzs = [p["mz"] for p in peaks] or [0.0, 1.0]
+    low = options.min_mz if options.min_mz is not None else max(0.0, min(mzs) - 50)
+    high = options.max_mz if options.max_mz is not None else max(mzs) + 50
+    if high <= low:
+        high = low + 1.0
+    visible = [p for p in peaks if low <= p["mz"] <= high]
+    max_intensity = max((p["intensity"] for p in visible), default=0.0) or 1.0
+
+    left, right = MARGIN["left"], width - MARGIN["right"]
+    top = MARGIN["top"]
+    bottom = height - MARGIN["bottom"]
+    # Same split as the viewer: spectrum on the upper 75%, errors on the lower 18%
+    plot_height = bottom - top
+    spectrum_bottom = top + plot_height * (0.75 if show_errors else 1.0)
+    error_top = bottom - plot_height * 0.18
+
+    def x_of(mz: float) -> float:
+        return left + (mz - low) / (high - low) * (right - left)
+
+    def y_of(intensity: float) -> float:
+        # 20% headroom for labels, as in the viewer
+        return spectrum_bottom - intensity / (max_intensity * 1.2) * (spectrum_bottom - top)
+
+    family = escape(options.font_family, {'"': "&quot;"})
+    out = [f'<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" '
+           f'viewBox="0 0 {width} {height}" font-family="{family}" font-size="{font}">',
+           f'<rect width="{width}" height="{height}" fill="white"/>']
+    if options.title:
+        out.append(f'<text x="{width / 2:.1f}" y="{top / 2 + font / 2:.1f}" text-anchor="middle" '
+                   f'font-size="{font * 1.4:.0f}" fill="{AXIS_COLOR}">{escape(options.title)}</text>')
+
+    # Axes and ticks
+    out.append(f'<line x1="{left}" y1="{spectrum_bottom:.1f}" x2="{right}" y2="{spectrum_bottom:.1f}" stroke="{AXIS_COLOR}"/>')
+    out.append(f'<line x1="{left}" y1="{top}" x2="{left}" y2="{spectrum_bottom:.1f}" stroke="{AXIS_COLOR}"/>')
+    axis_y = bottom if show_errors else spectrum_bottom
+    for tick in _ticks(low, high):
+        x = x_of(tick)
+        out.append(f'<line x1="{x:.1f}" y1="{axis_y:.1f}" x2="{x:.1f}" y2="{axis_y + 4:.1f}" stroke="{AXIS_COLOR}"/>')
+        out.append(f'<text x="{x:.1f}" y="{axis_y + 6 + font:.1f}" text-anchor="middle" fill="{AXIS_COLOR}">{_format_tick(tick)}</text>')
+    for tick in _ticks(0.0, max_intensity * 1.2, 5):
+        y = y_of(tick)
+        out.append(f'<line x1="{left - 4}" y1="{y:.1f}" x2="{left}" y2="{y:.1f}" stroke="{AXIS_COLOR}"/>')
+        out.append(f'<text x="{left - 6}" y="{y + font / 3:.1f}" text-anchor="end" fill="{AXIS_COLOR}">{_format_tick(tick)}</text>')
+    out.append(f'<text x="{(left + right) / 2:.1f}" y="{height - 8}" text-anchor="middle" fill="{AXIS_COLOR}">m/z</text>')
+    out.append(f'<text transform="translate(14 {(top + spectrum_bottom) / 2:.1f}) rotate(-90)" text-anchor="middle" '
+               f'fill="{AXIS_COLOR}">Intensity</text>')
+
+    # Matches grouped by peak; a peak takes the colour of its first match
+    by_peak: Dict[float, List[dict]] = {}
+    for match in matches:
+        if low <= match["peak_mz"] <= high:
+            by_peak.setdefault(match["peak_mz"], []).append(match)
+
+    for peak in visible:
+        if peak["mz"] not in by_peak:
+            x = x_of(peak["mz"])
+            out.append(f'<line x1="{x:.2f}" y1="{spectrum_bottom:.1f}" x2="{x:.2f}" y2="{y_of(peak["intensity"]):.2f}" '
+                       f'stroke="{UNMATCHED_COLOR}" stroke-width="1"/>')
+    for mz, group in by_peak.items():
+        x = x_of(mz)
+        color = SERIES_COLORS[_category(group[0]["ion_type"])]
+        out.append(f'<line x1="{x:.2f}" y1="{spectrum_bottom:.1f}" x2="{x:.2f}" y2="{y_of(group[0]["peak_intensity"]):.2f}" '
+                   f'stroke="{color}" stroke-width="1.5"/>')
+
+    # Labels, most intense peak first; boxes are estimated from the character count
+    placed: List[Tuple[float, float, float, float]] = []
+    order = sorted(by_peak.items(), key=lambda item: -item[1][0]["peak_intensity"])
+    if options.max_labels is not None:
+        order = order[:options.max_labels]
+    for mz, group in order:
+        parts = []
+        for match in group:
+            charge = abs(match.get("ion_charge", 1))
+            parts.append((match["ion_type"] + ("*" if match.get("alternatives") else ""), f"{charge}+" if charge > 1 else ""))
+        text_width = sum(len(name) + len(sup) * 0.7 + 1 for name, sup in parts) * font * CHAR_WIDTH
+        x = x_of(mz)
+        peak_top = y_of(group[0]["peak_intensity"])
+        for step in range(LABEL_STEPS + 1):
+            baseline = peak_top - 4 - step * (font + 2)
+            box = (x - text_width / 2, baseline - font, x + text_width / 2, baseline + 2)
+            if box[1] >= top - font and not _overlaps(box, placed):
+                break
+        else:
+            continue
+        placed.append(box)
+        color = SERIES_COLORS[_category(group[0]["ion_type"])]
+        if step:
+            out.append(f'<line x1="{x:.2f}" y1="{peak_top - 2:.1f}" x2="{x:.2f}" y2="{baseline + 2:.1f}" '
+                       f'stroke="{color}" stroke-width="0.5" stroke-dasharray="2,2"/>')
+        spans = []
+        for k, (name, sup) in enumerate(parts):
+            spans.append(("/" if k else "") + escape(name) +
+                         (f'<tspan baseline-shift="super" font-size="{font * 0.7:.1f}">{sup}</tspan>' if sup else ""))
+        out.append(f'<text x="{x:.2f}" y="{baseline:.1f}" text-anchor="middle" fill="{color}">{"".join(spans)}</text>')
+
+    # Error strip
+    if show_errors:
+        key = "error_ppm" if options.error_unit == "ppm" else "error_da"
+        errors = [m.get(key, 0.0) for group in by_peak.values() for m in group]
+        limit = max((abs(e) for e in errors), default=0.0) * 1.2 or 1.0
+        middle = (error_top + bottom) / 2
+
+        def e_of(value: float) -> float:
+            return middle - value / limit * (bottom - error_top) / 2
+
+        out.append(f'<line x1="{left}" y1="{middle:.1f}" x2="{right}" y2="{middle:.1f}" stroke="#9ca3af" stroke-dasharray="3,3"/>')
+        out.append(f'<line x1="{left}" y1="{error_top:.1f}" x2="{left}" y2="{bottom:.1f}" stroke="{AXIS_COLOR}"/>')
+        out.append(f'<line x1="{left}" y1="{bottom:.1f}" x2="{right}" y2="{bottom:.1f}" stroke="{AXIS_COLOR}"/>')
+        for value in (-limit / 1.2, limit / 1.2):
+            out.append(f'<text x="{left - 6}" y="{e_of(value) + font / 3:.1f}" text-anchor="end" fill="{AXIS_COLOR}">{value:.2g}</text>')
+        out.append(f'<text transform="translate(14 {middle:.1f}) rotate(-90)" text-anchor="middle" '
+                   f'fill="{AXIS_COLOR}">{"ppm" if options.error_unit == "ppm" else "Da"}</text>')
+        for mz, group in by_peak.items():
+            for match in group:
+                color = SERIES_COLORS[_category(match["ion_type"])]
+                out.append(f'<circle cx="{x_of(mz):.2f}" cy="{e_of(match.get(key, 0.0)):.2f}" r="2.5" fill="{color}"/>')
+
+    if options.legend and by_peak:
+        present = [c for c in SERIES_COLORS if any(_category(m["ion_type"]) == c for g in by_peak.values() for m in g)]
+        x = right - 10
+        for category in reversed(present):
+            name = SERIES_NAMES[category]
+            x -= len(name) * font * CHAR_WIDTH + 22
+            out.append(f'<rect x="{x:.1f}" y="{top - font - 2:.1f}" width="10" height="10" fill="{SERIES_COLORS[category]}"/>')
+            out.append(f'<text x="{x + 14:.1f}" y="{top - 3:.1f}" fill="{AXIS_COLOR}">{escape(name)}</text>')
+
+    out.append('</svg>')
+    return "\n".join(out) + "\n"
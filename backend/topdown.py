@@ -0,0 +1,125 @@
+"""
+Top-down (intact protein) annotation: the spectrum is decharged into neutral masses first,
+so fragments are matched once per cleavage instead of once per charge state, which keeps
+100-500 residue proteoforms tractable.
+"""
+
+import bisect
+from typing import Dict, List, Optional
+
+from .calculations import calculate_ions, get_residue_masses, peptide_mass, tokenize_peptide
+from .masses import C13_DELTA, PROTON_MASS
+from .mzpaf import mzpaf_ion
+from .processing import deisotope, tolerance_to_da
+from .settings import N_TERMINAL_SERIES, FragmentationSettings
+
+TOP_DOWN_SERIES = ["c", "z"]
+
+# Neighbouring charges re-checked for each envelope
+CHARGE_REFINE_RANGE = 2
+
+def _refine_charge(mzs: List[float], mono_mz: float, charge: int, isotope_count: int, tolerance: float,
+                   tolerance_unit: str) -> int:
+    # At high charge, chained isotope steps within tolerance fit z +- 1 too; the charge whose
+    # isotopes land closest to mono_mz + k * C13_DELTA / z wins
+    window = tolerance_to_da(mono_mz, tolerance, tolerance_unit)
+    best = None
+    for z in range(max(1, charge - CHARGE_REFINE_RANGE), charge + CHARGE_REFINE_RANGE + 1):
+        found, error = 0, 0.0
+        for k in range(1, isotope_count):
+            target = mono_mz + k * C13_DELTA / z
+            i = bisect.bisect_left(mzs, target - window)
+            if i < len(mzs) and mzs[i] <= target + window:
+                nearest = min(mzs[i:bisect.bisect_right(mzs, target + window)], key=lambda mz: abs(mz - target))
+                found += 1
+                error += abs(nearest - target)
+        if best is None or (found, -error) > best[:2]:
+            best = (found, -error, z)
+    return best[2]
+
+def decharge(peaks: List[dict], tolerance: float = 10.0, tolerance_unit: str = "ppm", max_charge: int = 30,
+             min_isotopes: int = 2, include_unassigned: bool = True) -> List[dict]:
+    """
+    Neutral masses of the isotope envelopes in a spectrum (see processing.deisotope), each
+    {"mass", "intensity", "charge", "isotope_count", "mz"} with the lowest observed isotope's
+    m/z. Each envelope's charge is re-checked against its neighbours by absolute isotope
+    positions. Without include_unassigned, peaks in no envelope are dropped; otherwise they
+    are taken as singly charged.
+    """
+    mzs = sorted(p["mz"] for p in peaks)
+    result = []
+    for peak in deisotope(peaks, tolerance, tolerance_unit, max_charge, min_isotopes, include_unassigned):
+        if peak["charge"] is None:
+            result.append({"mass": peak["mz"] - PROTON_MASS, "intensity": peak["intensity"], "charge": 1,
+                           "isotope_count": 1, "mz": peak["mz"]})
+            continue
+        charge = _refine_charge(mzs, peak["original_mz"], peak["charge"], peak["isotope_count"], tolerance,
+                                tolerance_unit)
+        result.append({"mass": (peak["original_mz"] - PROTON_MASS) * charge, "intensity": peak["intensity"],
+                       "charge": charge, "isotope_count": peak["isotope_count"], "mz": peak["original_mz"]})
+    return sorted(result, key=lambda p: p["mass"])
+
+def annotate_top_down(peaks: List[dict], sequence: str, settings: Optional[FragmentationSettings] = None,
+                      tolerance: float = 10.0, tolerance_unit: str = "ppm", max_charge: int = 30,
+                      max_isotope_error: int = 2) -> Dict:
+    """
+    Matches the singly charged fragments of settings.series (c and z-dot by default) as
+    neutral masses against the decharged spectrum. A lowest observed isotope up to
+    max_isotope_error C13_DELTA above the fragment counts (the monoisotopic peak of a large
+    fragment is often too weak to see). Each match carries "ion_type", "theoretical_mass",
+    "observed_mass", "error_ppm", "isotope_error", the envelope "charge", "peak_mz" and
+    "peak_intensity"; the closest envelope wins.
+    """
+    if settings is None:
+        settings = FragmentationSettings(series=TOP_DOWN_SERIES)
+    # One singly charged ion per fragment; charge states come from the deconvolution
+    settings = settings.model_copy(update={"min_charge": 1, "max_charge": 1, "series_max_charge": {}})
+    ions = [ion for ion in calculate_ions(sequence, settings) if ion["series"] in settings.series]
+    masses = decharge(peaks, tolerance, tolerance_unit, max_charge)
+    observed = [m["mass"] for m in masses]
+
+    matches = []
+    for ion in ions:
+        theoretical = ion["mz"] - PROTON_MASS
+        best = None
+        for k in range(max_isotope_error + 1):
+            target = theoretical + k * C13_DELTA
+            window = tolerance_to_da(target, tolerance, tolerance_unit)
+            i = bisect.bisect_left(observed, target - window)
+            while i < len(observed) and observed[i] <= target + window:
+                error = observed[i] - target
+                if best is None or abs(error) < abs(best[2]):
+                    best = (i, k, error)
+                i += 1
+            if best is not None:
+                break
+        if best is None:
+            continue
+        i, k, error = best
+        error_ppm = error / (theoretical + k * C13_DELTA) * 1e6
+        matches.append({
+            "ion_type": ion["type"],
+            "series": ion["series"],
+            "index": ion["index"],
+            "theoretical_mass": theoretical,
+            "observed_mass": observed[i],
+            "error_ppm": error_ppm,
+            "isotope_error": k,
+            "charge": masses[i]["charge"],
+            "peak_mz": masses[i]["mz"],
+            "peak_intensity": masses[i]["intensity"],
+            "mzpaf": mzpaf_ion(ion["type"], masses[i]["charge"], k, error=error_ppm),
+        })
+
+    # A cleavage is explained by either its N-terminal or its C-terminal fragment
+    residues = len(tokenize_peptide(sequence, get_residue_masses(settings.residue_masses, settings.mass_type))[0])
+    cleavages = {m["index"] if m["series"] in N_TERMINAL_SERIES else residues - m["index"] for m in matches}
+    cleavages = {c for c in cleavages if 0 < c < residues}
+    return {
+        "precursor_mass": peptide_mass(sequence, settings.residue_masses or None, settings.mass_type),
+        "masses": masses,
+        "matches": matches,
+        "theoretical_ions": len(ions),
+        "matched_cleavages": len(cleavages),
+        "cleavage_coverage": 100.0 * len(cleavages) / (residues - 1) if residues > 1 else 0.0,
+    }
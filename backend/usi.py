@@ -0,0 +1,65 @@
+"""
+Universal Spectrum Identifiers (mzspec:PXD000561:run:scan:17555:VLHPLEGAVVIIFK/2): split
+into their parts and built for a spectrum on screen, for sharing and PROXI lookups.
+"""
+
+import re
+from typing import Dict, Optional, Union
+
+from .mzspeclib import parse_proforma
+
+USI_INDEX_TYPES = ("scan", "index", "nativeId", "trace")
+# Collection for data not (yet) in a public repository
+USI_PLACEHOLDER_COLLECTION = "USI000000"
+
+# The run name may hold colons, the interpretation too (ProForma [UNIMOD:35]), the index not
+USI = re.compile(r'^mzspec:(?P<collection>[^:]+):(?P<run>.+?):(?P<index_type>' + "|".join(USI_INDEX_TYPES) +
+                 r'):(?P<index>[^:]+)(?::(?P<interpretation>.+))?$')
+
+def parse_usi(usi: str) -> Dict:
+    """
+    The parts of a USI: "collection", "run", "index_type" (USI_INDEX_TYPES), "index" as
+    written, "scan_nr" for scan and index USIs, and for one with an interpretation the
+    ProForma "peptidoform", its "sequence" in [mass] delta form and "charge".
+    """
+    usi = usi.strip()
+    parts = USI.match(usi)
+    if not parts:
+        raise ValueError(f"not a USI (expected 'mzspec:collection:run:scan|index|nativeId:value[:peptide/charge]'): {usi!r}")
+    index_type, index = parts.group("index_type"), parts.group("index")
+    if index_type in ("scan", "index") and not index.isdigit():
+        raise ValueError(f"USI {index_type} must be a number, got {index!r}")
+    interpretation = parts.group("interpretation")
+    sequence = charge = None
+    if interpretation:
+        try:
+            sequence, charge = parse_proforma(interpretation)
+        except ValueError as e:
+            raise ValueError(f"USI interpretation {interpretation!r}: {e}") from None
+    return {
+        "usi": usi,
+        "collection": parts.group("collection"),
+        "run": parts.group("run"),
+        "index_type": index_type,
+        "index": index,
+        "scan_nr": int(index) if index_type in ("scan", "index") else None,
+        "peptidoform": interpretation.partition('/')[0] if interpretation else None,
+        "sequence": sequence,
+        "charge": charge,
+    }
+
+def build_usi(run: str, index: Union[int, str], sequence: Optional[str] = None, charge: Optional[int] = None,
+              collection: Optional[str] = None, index_type: str = "scan") -> str:
+    """
+    A USI for one spectrum. ProtView sequences ([mass] deltas) are valid ProForma and are
+    written as they are; a charge without a sequence is dropped, as a USI interpretation
+    needs both. collection defaults to USI_PLACEHOLDER_COLLECTION.
+    """
+    if index_type not in USI_INDEX_TYPES:
+        raise ValueError(f"Unknown USI index type {index_type!r}, expected one of {USI_INDEX_TYPES}")
+    if not run or ':' in str(collection or ''):
+        raise ValueError("USI needs a run name and a collection without ':'")
+    usi = f"mzspec:{collection or USI_PLACEHOLDER_COLLECTION}:{run}:{index_type}:{index}"
+    if sequence:
+        usi += f":{sequence}" + (f"/{charge}" if charge else "")
+    return usi
@@ -0,0 +1,45 @@
+"""
+Extracted ion chromatograms: the intensity of one m/z window over retention time.
+"""
+
+import bisect
+from typing import Dict, Iterable, Optional
+
+from .processing import tolerance_to_da
+
+XIC_AGGREGATES = ("sum", "max")
+
+def extract_ion_chromatogram(spectra: Iterable[Dict], target_mz: float, tolerance: float = 10.0,
+                             tolerance_unit: str = "ppm", rt_min: Optional[float] = None,
+                             rt_max: Optional[float] = None, ms_level: Optional[int] = 1,
+                             aggregate: str = "sum") -> Dict:
+    """
+    Time/intensity trace of target_mz +- tolerance over spectra given as dicts with
+    "retention_time" (seconds), "ms_level" and "peaks" (parse_run output, or a reader's
+    iter_spectra for a single streaming pass). Spectra of another ms_level (None: any),
+    without a retention time or outside [rt_min, rt_max] are skipped; a spectrum without
+    a peak in the window contributes 0 so the trace stays continuous. aggregate "sum" adds
+    the peaks in the window, "max" takes the most intense.
+    """
+    if aggregate not in XIC_AGGREGATES:
+        raise ValueError(f"Unknown XIC aggregate {aggregate!r}, expected one of {XIC_AGGREGATES}")
+    window = tolerance_to_da(target_mz, tolerance, tolerance_unit)
+    low, high = target_mz - window, target_mz + window
+    times, intensities, scan_nrs = [], [], []
+    for spectrum in spectra:
+        rt = spectrum.get("retention_time")
+        if rt is None or (ms_level is not None and spectrum.get("ms_level") != ms_level):
+            continue
+        if (rt_min is not None and rt < rt_min) or (rt_max is not None and rt > rt_max):
+            continue
+        peaks = spectrum.get("peaks") or []
+        mzs = [p["mz"] for p in peaks]
+        if any(a > b for a, b in zip(mzs, mzs[1:])):
+            peaks = sorted(peaks, key=lambda p: p["mz"])
+            mzs = [p["mz"] for p in peaks]
+        inside = [p["intensity"] for p in peaks[bisect.bisect_left(mzs, low):bisect.bisect_right(mzs, high)]]
+        times.append(rt)
+        intensities.append((sum(inside) if aggregate == "sum" else max(inside)) if inside else 0.0)
+        scan_nrs.append(spectrum.get("scan_nr"))
+    return {"target_mz": target_mz, "window": [low, high], "time": times,
+            "intensity": intensities, "scan_nrs": scan_nrs}
@@ -1,3 +1,8 @@
+// API payload types (see protview.d.ts)
+/** @typedef {import('./protview').PeptideRow} PeptideRow */
+/** @typedef {import('./protview').SpectrumResponse} SpectrumResponse */
+/** @typedef {import('./protview').JobStatus} JobStatus */
+
 // DOM Elements
 const readBtn = document.getElementById('read-btn');
 const mzmlPathInput = document.getElementById('mzml-path');
@@ -5,6 +10,23 @@ const pinPathInput = document.getElementById('pin-path');
 const peptideGrid = document.getElementById('peptide-grid');
 const plotContainer = document.getElementById('plot-container');
 const statusMsg = document.getElementById('status-msg');
+const toleranceInput = document.getElementById('tolerance-input');
+const strategySelect = document.getElementById('strategy-select');
+const toleranceUnitSelect = document.getElementById('tolerance-unit');
+const chimericInput = document.getElementById('chimeric-input');
+const usiInput = document.getElementById('usi-input');
+const usiBtn = document.getElementById('usi-btn');
+const copyUsiBtn = document.getElementById('copy-usi-btn');
+const exportTsvBtn = document.getElementById('export-tsv-btn');
+const exportSvgBtn = document.getElementById('export-svg-btn');
+const exportBinnedBtn = document.getElementById('export-binned-btn');
+const qcErrorsBtn = document.getElementById('qc-errors-btn');
+const saveBundleBtn = document.getElementById('save-bundle-btn');
+const bundleFileInput = document.getElementById('bundle-file');
+const jobProgress = document.getElementById('job-progress');
+const jobProgressBar = document.getElementById('job-progress-bar');
+const jobProgressLabel = document.getElementById('job-progress-label');
+const jobCancelBtn = document.getElementById('job-cancel-btn');
 
 let gridApi = null; // ag-Grid API
 
@@ -15,8 +37,125 @@ if (readBtn) {
     console.error("Read button not found");
 }
 
+if (toleranceInput) {
+    toleranceInput.addEventListener('change', handleToleranceChange);
+}
+if (strategySelect) {
+    strategySelect.addEventListener('change', handleToleranceChange);
+}
+if (toleranceUnitSelect) {
+    toleranceUnitSelect.addEventListener('change', handleToleranceChange);
+}
+if (chimericInput) {
+    chimericInput.addEventListener('change', handleToleranceChange);
+}
+if (usiBtn) {
+    usiBtn.addEventListener('click', handleOpenUsi);
+}
+if (copyUsiBtn) {
+    copyUsiBtn.addEventListener('click', handleCopyUsi);
+}
+if (exportTsvBtn) {
+    exportTsvBtn.addEventListener('click', handleExportTsv);
+}
+if (exportSvgBtn) {
+    exportSvgBtn.addEventListener('click', handleExportSvg);
+}
+if (exportBinnedBtn) {
+    exportBinnedBtn.addEventListener('click', handleExportBinned);
+}
+if (qcErrorsBtn) {
+    qcErrorsBtn.addEventListener('click', handleMassErrorQc);
+}
+if (saveBundleBtn) {
+    saveBundleBtn.addEventListener('click', handleSaveBundle);
+}
+if (bundleFileInput) {
+    bundleFileInput.addEventListener('change', handleOpenBundle);
+}
+if (jobCancelBtn) {
+    jobCancelBtn.addEventListener('click', handleCancelJob);
+}
+
 // State
 let currentData = null;
+/** @type {PeptideRow | null} */
+let currentPeptide = null;
+// Set while the plot shows a spectrum fetched by USI rather than one of the local file
+let currentUsi = null;
+/** @type {PeptideRow[]} */
+let loadedPeptides = [];
+
+// PSMs sent for the mass error QC; the first ones of the identification file
+const QC_PSM_LIMIT = 200;
+
+// The background job whose progress is shown, and how often it is polled (ms)
+let currentJobId = null;
+const JOB_POLL_MS = 500;
+
+function getTolerance() {
+    const value = parseFloat(toleranceInput ? toleranceInput.value : '');
+    return Number.isFinite(value) && value >= 0 ? value : 0.5;
+}
+
+function getToleranceUnit() {
+    return toleranceUnitSelect ? toleranceUnitSelect.value : 'da';
+}
+
+/** @returns {import('./protview').MatchStrategy} */
+function getStrategy() {
+    return strategySelect ? /** @type {any} */ (strategySelect.value) : 'closest';
+}
+
+/**
+ * Co-isolated candidates typed as "ELVISK/2, SAMPLER"; a missing charge means the PSM's.
+ * @returns {import('./protview').ChimericCandidate[]}
+ */
+function getChimericPeptides() {
+    if (!chimericInput) return [];
+    return chimericInput.value.split(/[,\s]+/).filter(t => t).map(token => {
+        const [sequence, charge] = token.split('/');
+        return { sequence, charge: charge ? parseInt(charge, 10) : null };
+    });
+}
+
+/**
+ * Fixed-bin intensity vector of a scan, e.g. for correlation analyses or ML models.
+ * @param {number} scanNr
+ * @param {import('./protview').BinnedSpectrumOptions} [options]
+ * @returns {Promise<Float32Array>}
+ */
+async function fetchBinnedSpectrum(scanNr, options = {}) {
+    const params = new URLSearchParams();
+    if (options.binWidth != null) params.set('bin_width', String(options.binWidth));
+    if (options.minMz != null) params.set('min_mz', String(options.minMz));
+    if (options.maxMz != null) params.set('max_mz', String(options.maxMz));
+    if (options.aggregate) params.set('aggregate', options.aggregate);
+    const response = await fetch(`/api/spectrum/${scanNr}/binned?${params}`);
+    if (!response.ok) {
+        const err = await response.json();
+        throw new Error(err.detail || 'Failed to bin spectrum');
+    }
+    return new Float32Array(await response.arrayBuffer());
+}
+
+/**
+ * " · isolation purity 83%" for the status line, or "" when it cannot be computed
+ * (no preceding MS1, MGF input, ...); low purity hints at a chimeric spectrum.
+ * @param {number} scanNr
+ * @returns {Promise<string>}
+ */
+async function purityLabel(scanNr) {
+    try {
+        const response = await fetch(`/api/spectrum/${scanNr}/isolation_purity`);
+        if (!response.ok) return '';
+        /** @type {import('./protview').IsolationPurityResponse} */
+        const purity = await response.json();
+        return purity.purity != null ? ` · isolation purity ${(purity.purity * 100).toFixed(0)}%` : '';
+    } catch (error) {
+        return '';
+    }
+}
 
 async function handleReadLocal() {
     console.log("Read Local Clicked");
@@ -24,7 +163,7 @@ async function handleReadLocal() {
     const pinPath = pinPathInput.value.trim();
 
     if (!mzmlPath || !pinPath) {
-        showStatus("Please enter both mzML and .pin file paths.", "error");
+        showStatus("Please enter both the mzML and the identification file paths.", "error");
         return;
     }
 
@@ -48,8 +187,10 @@ async function handleReadLocal() {
         }
 
         const data = await response.json();
+        loadedPeptides = data.peptides;
         renderAgGrid(data.peptides);
         showStatus(data.message, "success");
+        loadSpectrumQuality();
 
     } catch (error) {
         console.error(error);
@@ -59,6 +200,84 @@ async function handleReadLocal() {
     }
 }
 
+/**
+ * Starts a background job, shows its progress until it leaves "running" and returns its
+ * result; the Cancel button stops it on the server.
+ * @param {string} url
+ * @param {object | null} body
+ * @param {string} label
+ */
+async function runJob(url, body, label) {
+    if (currentJobId) {
+        throw new Error("Another job is running; wait for it or cancel it");
+    }
+    const response = await fetch(url, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: body ? JSON.stringify(body) : undefined
+    });
+    if (!response.ok) {
+        const err = await response.json();
+        throw new Error(err.detail || `${label} failed`);
+    }
+
+    /** @type {JobStatus} */
+    let job = await response.json();
+    currentJobId = job.id;
+    jobProgress.classList.remove('hidden');
+    try {
+        while (job.status === 'running') {
+            jobProgressBar.value = job.percent;
+            jobProgressLabel.textContent = `${label} ${job.percent.toFixed(0)}%` +
+                (job.current_scan != null ? ` · scan ${job.current_scan}` : '');
+            await new Promise(resolve => setTimeout(resolve, JOB_POLL_MS));
+            const poll = await fetch(`/api/jobs/${job.id}`);
+            if (!poll.ok) {
+                const err = await poll.json();
+                throw new Error(err.detail || `${label} failed`);
+            }
+            job = await poll.json();
+        }
+    } finally {
+        currentJobId = null;
+        jobProgress.classList.add('hidden');
+    }
+
+    if (job.status === 'cancelled') throw new Error(`${label} cancelled`);
+    if (job.status === 'failed') throw new Error(job.error || `${label} failed`);
+    return job.result;
+}
+
+async function handleCancelJob() {
+    if (!currentJobId) return;
+    try {
+        await fetch(`/api/jobs/${currentJobId}`, { method: 'DELETE' });
+    } catch (error) {
+        console.error(error);
+    }
+}
+
+// Quality columns for the grid, filled in after the whole run has been read once
+async function loadSpectrumQuality() {
+    try {
+        /** @type {import('./protview').SpectrumQualityResponse} */
+        const data = await runJob('/api/jobs/spectrum_quality?ms_level=2', null, "Spectrum quality");
+        const byScan = new Map(data.spectra.map(q => [q.scan_nr, q]));
+        loadedPeptides.forEach(p => { p.quality = byScan.get(p.scan_nr); });
+        if (gridApi) gridApi.setGridOption('rowData', loadedPeptides);
+
+    } catch (error) {
+        console.error(error);
+        showStatus("Error computing spectrum quality: " + error.message, "error");
+    }
+}
+
+/** @param {{ value: number | null | undefined }} params */
+function formatIntensity(params) {
+    return params.value == null ? '' : params.value.toExponential(1);
+}
+
+/** @param {PeptideRow[]} peptides */
 function renderAgGrid(peptides) {
     // Clear previous grid
     peptideGrid.innerHTML = '';
@@ -74,7 +293,16 @@ function renderAgGrid(peptides) {
             { field: 'sequence', headerName: 'Peptide', flex: 2, sortable: true },
             { field: 'charge', headerName: 'Chg', width: 70, sortable: true },
             { field: 'scan_nr', headerName: 'Scan', width: 80, sortable: true },
-            { field: 'spec_id', headerName: 'Spec ID', flex: 1, hide: true }
+            { field: 'spec_id', headerName: 'Spec ID', flex: 1, hide: true },
+            { field: 'quality.peak_count', headerName: 'Peaks', width: 80, filter: 'agNumberColumnFilter' },
+            { field: 'quality.tic', headerName: 'TIC', width: 90, filter: 'agNumberColumnFilter', valueFormatter: formatIntensity },
+            { field: 'quality.base_peak_intensity', headerName: 'Base Peak', width: 90, filter: 'agNumberColumnFilter', valueFormatter: formatIntensity, hide: true },
+            { field: 'quality.dynamic_range', headerName: 'DR', width: 70, filter: 'agNumberColumnFilter', headerTooltip: 'log10 of base peak / weakest peak',
+              valueFormatter: params => params.value == null ? '' : params.value.toFixed(1) },
+            { field: 'quality.top_fraction', headerName: 'Top 20', width: 80, filter: 'agNumberColumnFilter', headerTooltip: 'Share of the TIC in the 20 most intense peaks',
+              valueFormatter: params => params.value == null ? '' : (params.value * 100).toFixed(0) + '%' },
+            { field: 'quality.precursor_present', headerName: 'Prec', width: 70, headerTooltip: 'Unfragmented precursor peak in the MS2',
+              valueFormatter: params => params.value == null ? '' : (params.value ? 'yes' : 'no') }
         ],
         defaultColDef: {
             resizable: true,
@@ -98,12 +326,13 @@ function renderAgGrid(peptides) {
     }
 }
 
+/** @param {PeptideRow} peptide */
 async function loadSpectrum(peptide) {
     showStatus(`Loading Scan ${peptide.scan_nr}...`, "normal");
 
     try {
         // Fix encoding for sequences with brackets
-        const url = `/api/spectrum/${peptide.scan_nr}?sequence=${encodeURIComponent(peptide.sequence)}&charge=${peptide.charge}`;
+        const url = `/api/spectrum/${peptide.scan_nr}?sequence=${encodeURIComponent(peptide.sequence)}&charge=${peptide.charge}&tolerance=${getTolerance()}&tolerance_unit=${getToleranceUnit()}&strategy=${getStrategy()}&peak_format=arrays&scores=true`;
         const response = await fetch(url);
 
         if (!response.ok) {
@@ -111,9 +340,27 @@ async function loadSpectrum(peptide) {
             throw new Error(err.detail || "Failed to load spectrum");
         }
 
+        /** @type {SpectrumResponse} */
         const data = await response.json();
+        currentPeptide = peptide;
+        currentData = data;
+        currentUsi = null;
         renderPlot(data, peptide.sequence, peptide.charge);
-        showStatus(`Loaded Scan ${peptide.scan_nr}`, "success");
+        if (getChimericPeptides().length > 0) {
+            await annotateChimeric(peptide);
+            return;
+        }
+
+        // The PSM charge should agree with what the instrument recorded
+        const recorded = (data.precursors || []).map(p => p.charge).filter(z => z);
+        if (recorded.length > 0 && !recorded.includes(peptide.charge)) {
+            showStatus(`Loaded Scan ${peptide.scan_nr}: mzML precursor charge ${recorded[0]}+ differs from PSM charge ${peptide.charge}+`, "error");
+        } else if (data.warnings && data.warnings.length > 0) {
+            // Explains spectra that look empty or cut short
+            showStatus(`Loaded Scan ${peptide.scan_nr} with parse warnings: ${data.warnings.map(w => w.message).join('; ')}`, "error");
+        } else {
+            showStatus(`Loaded Scan ${peptide.scan_nr}${await purityLabel(peptide.scan_nr)}`, "success");
+        }
 
     } catch (error) {
         console.error(error);
@@ -121,6 +368,387 @@ async function loadSpectrum(peptide) {
     }
 }
 
+// Re-match the spectrum already on screen; the server reuses its cached copy
+// and the peaks are not sent again.
+async function handleToleranceChange() {
+    if (!currentPeptide || !currentData) return;
+    const peptide = currentPeptide;
+    if (getChimericPeptides().length > 0) {
+        await annotateChimeric(peptide);
+        return;
+    }
+
+    try {
+        const response = await fetch(`/api/spectrum/${peptide.scan_nr}/annotate`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({
+                sequence: peptide.sequence,
+                settings: { precursor_charge: peptide.charge },
+                tolerance: getTolerance(),
+                tolerance_unit: getToleranceUnit(),
+                strategy: getStrategy(),
+                include_peaks: false,
+                scores: true
+            })
+        });
+
+        if (!response.ok) {
+            const err = await response.json();
+            throw new Error(err.detail || "Failed to re-annotate spectrum");
+        }
+
+        /** @type {SpectrumResponse} */
+        const data = await response.json();
+        currentData = { ...currentData, matches: data.matches };
+        renderPlot(currentData, peptide.sequence, peptide.charge);
+        showStatus(`Re-annotated Scan ${peptide.scan_nr} at ${getTolerance()} ${getToleranceUnit() === 'ppm' ? 'ppm' : 'Da'}`, "success");
+
+    } catch (error) {
+        console.error(error);
+        showStatus("Error re-annotating spectrum: " + error.message, "error");
+    }
+}
+
+// The PSM plus the co-isolated candidates against the same spectrum, one colour each
+/** @param {PeptideRow} peptide */
+async function annotateChimeric(peptide) {
+    try {
+        const response = await fetch(`/api/spectrum/${peptide.scan_nr}/chimeric`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({
+                peptides: [{ sequence: peptide.sequence, charge: peptide.charge }, ...getChimericPeptides()],
+                settings: { precursor_charge: peptide.charge },
+                tolerance: getTolerance(),
+                tolerance_unit: getToleranceUnit(),
+                strategy: getStrategy()
+            })
+        });
+
+        if (!response.ok) {
+            const err = await response.json();
+            throw new Error(err.detail || "Failed to annotate chimeric spectrum");
+        }
+
+        /** @type {import('./protview').ChimericResponse} */
+        const data = await response.json();
+        renderChimericPlot(data);
+        const { shared, unexplained } = data.breakdown;
+        showStatus(`Chimeric Scan ${peptide.scan_nr}: ${shared.toFixed(1)}% shared, ${unexplained.toFixed(1)}% unexplained`, "success");
+
+    } catch (error) {
+        console.error(error);
+        showStatus("Error annotating chimeric spectrum: " + error.message, "error");
+    }
+}
+
+// Fetch a spectrum from PROXI; a USI with a peptide/charge interpretation is annotated with it
+async function handleOpenUsi() {
+    const usi = usiInput ? usiInput.value.trim() : '';
+    if (!usi) {
+        showStatus("Please enter a USI (mzspec:...).", "error");
+        return;
+    }
+    showStatus(`Fetching ${usi}...`, "normal");
+    usiBtn.disabled = true;
+
+    try {
+        const params = new URLSearchParams({ usi });
+        // /api/usi matches in Da only
+        if (getToleranceUnit() === 'da') params.set('tolerance', String(getTolerance()));
+        const response = await fetch(`/api/usi?${params}`);
+
+        if (!response.ok) {
+            const err = await response.json();
+            throw new Error(err.detail || "Failed to fetch spectrum");
+        }
+
+        const data = await response.json();
+        currentPeptide = null;
+        currentData = null;
+        currentUsi = data.usi;
+        renderPlot(data, data.sequence || '', data.charge || '');
+        showStatus(`Loaded ${data.usi}`, "success");
+
+    } catch (error) {
+        console.error(error);
+        showStatus("Error fetching USI: " + error.message, "error");
+    } finally {
+        usiBtn.disabled = false;
+    }
+}
+
+// USI of the spectrum on screen, with the peptide as its interpretation, to the clipboard
+async function handleCopyUsi() {
+    try {
+        let usi = currentUsi;
+        if (currentPeptide) {
+            const params = new URLSearchParams({ sequence: currentPeptide.sequence, charge: String(currentPeptide.charge) });
+            const response = await fetch(`/api/spectrum/${currentPeptide.scan_nr}/usi?${params}`);
+            if (!response.ok) {
+                const err = await response.json();
+                throw new Error(err.detail || "Failed to build USI");
+            }
+            /** @type {import('./protview').UsiComponents} */
+            const components = await response.json();
+            usi = components.usi;
+        }
+        if (!usi) {
+            showStatus("No spectrum loaded to copy a USI for.", "error");
+            return;
+        }
+        if (usiInput) usiInput.value = usi;
+        await navigator.clipboard.writeText(usi);
+        showStatus(`Copied ${usi}`, "success");
+
+    } catch (error) {
+        console.error(error);
+        showStatus("Error copying USI: " + error.message, "error");
+    }
+}
+
+/**
+ * Save a server response body as a file
+ * @param {Blob} blob
+ * @param {string} filename
+ */
+function downloadBlob(blob, filename) {
+    const url = URL.createObjectURL(blob);
+    const link = document.createElement('a');
+    link.href = url;
+    link.download = filename;
+    link.click();
+    URL.revokeObjectURL(url);
+}
+
+// The matches on screen as a tab-separated file, for spreadsheets and supplementary tables
+async function handleExportTsv() {
+    if (!currentPeptide) {
+        showStatus("Select a peptide before exporting its matches.", "error");
+        return;
+    }
+    const peptide = currentPeptide;
+    try {
+        const response = await fetch(`/api/spectrum/${peptide.scan_nr}/export_table`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({
+                sequence: peptide.sequence,
+                settings: { precursor_charge: peptide.charge },
+                tolerance: getTolerance(),
+                tolerance_unit: getToleranceUnit(),
+                strategy: getStrategy(),
+                table: 'matches'
+            })
+        });
+
+        if (!response.ok) {
+            const err = await response.json();
+            throw new Error(err.detail || "Failed to export matches");
+        }
+
+        downloadBlob(await response.blob(), `scan_${peptide.scan_nr}_matches.tsv`);
+        showStatus(`Exported matches of Scan ${peptide.scan_nr}`, "success");
+
+    } catch (error) {
+        console.error(error);
+        showStatus("Error exporting matches: " + error.message, "error");
+    }
+}
+
+// Vector figure of the spectrum on screen, laid out by the server from the same matches
+async function handleExportSvg() {
+    if (!currentPeptide) {
+        showStatus("Select a peptide before exporting a figure.", "error");
+        return;
+    }
+    const peptide = currentPeptide;
+    try {
+        const response = await fetch(`/api/spectrum/${peptide.scan_nr}/svg`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({
+                sequence: peptide.sequence,
+                settings: { precursor_charge: peptide.charge },
+                tolerance: getTolerance(),
+                tolerance_unit: getToleranceUnit(),
+                strategy: getStrategy(),
+                options: { title: `${peptide.sequence} (${peptide.charge}+) · Scan ${peptide.scan_nr}` }
+            })
+        });
+
+        if (!response.ok) {
+            const err = await response.json();
+            throw new Error(err.detail || "Failed to export figure");
+        }
+
+        downloadBlob(await response.blob(), `scan_${peptide.scan_nr}.svg`);
+        showStatus(`Exported the figure of Scan ${peptide.scan_nr}`, "success");
+
+    } catch (error) {
+        console.error(error);
+        showStatus("Error exporting figure: " + error.message, "error");
+    }
+}
+
+// The scan as a raw little-endian Float32 vector (1 Da bins over m/z 0-2000), e.g. for numpy.fromfile or an ML pipeline
+async function handleExportBinned() {
+    if (!currentPeptide) {
+        showStatus("Select a peptide before exporting its spectrum.", "error");
+        return;
+    }
+    const scanNr = currentPeptide.scan_nr;
+    try {
+        const vector = await fetchBinnedSpectrum(scanNr);
+        downloadBlob(new Blob([vector.buffer], { type: 'application/octet-stream' }), `scan_${scanNr}_binned.f32`);
+        showStatus(`Exported ${vector.length} bins of Scan ${scanNr}`, "success");
+
+    } catch (error) {
+        console.error(error);
+        showStatus("Error exporting binned spectrum: " + error.message, "error");
+    }
+}
+
+// Fragment error histogram over the loaded PSMs at the current settings, with the
+// tolerance window it suggests
+async function handleMassErrorQc() {
+    if (loadedPeptides.length === 0) {
+        showStatus("Read an identification file before running the mass error QC.", "error");
+        return;
+    }
+    const psms = loadedPeptides.slice(0, QC_PSM_LIMIT).map(p => ({ scan_nr: p.scan_nr, sequence: p.sequence, charge: p.charge }));
+    const unit = getToleranceUnit();
+    showStatus(`Matching ${psms.length} PSMs for the mass error QC...`, "normal");
+    qcErrorsBtn.disabled = true;
+
+    try {
+        /** @type {import('./protview').MassErrorStatistics} */
+        const stats = await runJob('/api/jobs/mass_errors',
+            { psms, tolerance: getTolerance(), tolerance_unit: unit, strategy: getStrategy(), unit }, "Mass error QC");
+        renderMassErrorPlot(stats);
+        if (stats.count === 0) {
+            showStatus("No fragment matches at the current tolerance.", "error");
+            return;
+        }
+        const label = unit === 'ppm' ? 'ppm' : 'Da';
+        const [low, high] = stats.suggested_tolerance;
+        showStatus(`${stats.count} matches: median ${stats.median.toFixed(3)} ${label}, robust SD ${stats.robust_sd.toFixed(3)} · suggested window ${low.toFixed(3)} to ${high.toFixed(3)} ${label}`, "success");
+
+    } catch (error) {
+        console.error(error);
+        showStatus("Error in mass error QC: " + error.message, "error");
+    } finally {
+        qcErrorsBtn.disabled = false;
+    }
+}
+
+/** @param {import('./protview').MassErrorStatistics} stats */
+function renderMassErrorPlot(stats) {
+    if (!plotContainer) return;
+    plotContainer.innerHTML = '';
+    const label = stats.unit === 'ppm' ? 'ppm' : 'Da';
+    const shapes = (stats.suggested_tolerance || []).concat(stats.median != null ? [stats.median] : []).map((x, i, all) => ({
+        type: 'line',
+        x0: x,
+        x1: x,
+        yref: 'paper',
+        y0: 0,
+        y1: 1,
+        line: i === all.length - 1 && stats.median != null
+            ? { color: '#ef4444', width: 2 }
+            : { color: '#8b5cf6', width: 1, dash: 'dash' }
+    }));
+    Plotly.newPlot('plot-container', [{
+        x: stats.bins.map(b => b.error),
+        y: stats.bins.map(b => b.count),
+        type: 'bar',
+        width: stats.bin_width,
+        name: 'Matches',
+        marker: { color: '#3b82f6', line: { width: 0 } },
+        hovertemplate: `%{x:.3f} ${label}: %{y}<extra></extra>`
+    }], {
+        title: { text: `Fragment mass error · ${stats.count} matches`, font: { size: 16 } },
+        xaxis: { title: `Error (${label})` },
+        yaxis: { title: 'Matches' },
+        shapes,
+        margin: { t: 60, r: 20, l: 60, b: 50 },
+        autosize: true
+    }, { responsive: true, displayModeBar: true });
+}
+
+// The view on screen (peaks, peptide, settings, matches) as one JSON file to share
+async function handleSaveBundle() {
+    if (!currentPeptide) {
+        showStatus("Select a peptide before saving the view.", "error");
+        return;
+    }
+    const peptide = currentPeptide;
+    try {
+        const response = await fetch(`/api/spectrum/${peptide.scan_nr}/bundle`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({
+                sequence: peptide.sequence,
+                settings: { precursor_charge: peptide.charge },
+                tolerance: getTolerance(),
+                tolerance_unit: getToleranceUnit(),
+                strategy: getStrategy()
+            })
+        });
+
+        if (!response.ok) {
+            const err = await response.json();
+            throw new Error(err.detail || "Failed to save view");
+        }
+
+        downloadBlob(await response.blob(), `scan_${peptide.scan_nr}.protview.json`);
+        showStatus(`Saved the view of Scan ${peptide.scan_nr}`, "success");
+
+    } catch (error) {
+        console.error(error);
+        showStatus("Error saving view: " + error.message, "error");
+    }
+}
+
+// Restore a saved view as it was, including the tolerance and peak selection it used
+async function handleOpenBundle() {
+    const file = bundleFileInput.files && bundleFileInput.files[0];
+    if (!file) return;
+    try {
+        const response = await fetch('/api/bundle/load', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: await file.text()
+        });
+
+        if (!response.ok) {
+            const err = await response.json();
+            throw new Error(err.detail || "Failed to open view");
+        }
+
+        /** @type {import('./protview').AnalysisBundle} */
+        const bundle = await response.json();
+        const matchSettings = /** @type {any} */ (bundle.match_settings);
+        if (toleranceInput && typeof matchSettings.tolerance === 'number') toleranceInput.value = String(matchSettings.tolerance);
+        if (toleranceUnitSelect && matchSettings.tolerance_unit) toleranceUnitSelect.value = matchSettings.tolerance_unit;
+        if (strategySelect && matchSettings.strategy) strategySelect.value = matchSettings.strategy;
+
+        currentPeptide = null;
+        currentData = null;
+        currentUsi = bundle.spectrum.usi;
+        const { sequence, charge } = bundle.peptide;
+        renderPlot({ ...bundle.spectrum, matches: bundle.matches }, sequence, charge);
+        showStatus(`Opened ${file.name}${bundle.spectrum.source ? ` (${bundle.spectrum.source}, scan ${bundle.spectrum.scan_nr})` : ''}`, "success");
+
+    } catch (error) {
+        console.error(error);
+        showStatus("Error opening view: " + error.message, "error");
+    } finally {
+        bundleFileInput.value = '';
+    }
+}
+
 function showStatus(msg, type) {
     if (statusMsg) {
         statusMsg.textContent = msg;
@@ -130,20 +758,27 @@ function showStatus(msg, type) {
     }
 }
 
+/**
+ * @param {SpectrumResponse} data
+ * @param {string} sequence
+ * @param {number} charge
+ */
 function renderPlot(data, sequence, charge) {
     if (!plotContainer) return;
     plotContainer.innerHTML = '';
 
-    const peaks = data.peaks;
     const matches = data.matches;
 
     // Helper for min/max
     const getMin = (arr) => { let m = Infinity; for (let v of arr) if (v < m) m = v; return m; };
     const getMax = (arr) => { let m = -Infinity; for (let v of arr) if (v > m) m = v; return m; };
 
-    const xPeaks = peaks.map(p => p.mz);
-    const yPeaks = peaks.map(p => p.intensity);
-    const hoverTexts = peaks.map(p => `m/z: ${p.mz.toFixed(4)}<br>Int: ${p.intensity.toFixed(1)}`);
+    // Columnar arrays (peak_format=arrays) go to Plotly as typed arrays without per-peak objects
+    const xPeaks = data.mz ? Float64Array.from(data.mz) : data.peaks.map(p => p.mz);
+    const yPeaks = data.intensity ? Float64Array.from(data.intensity) : data.peaks.map(p => p.intensity);
+    // timsTOF/FAIMS spectra carry a per-peak ion mobility, shown on hover
+    const mobility = data.ion_mobility || (data.peaks.length && data.peaks[0].ion_mobility != null
+        ? data.peaks.map(p => p.ion_mobility) : null);
 
     const minMz = getMin(xPeaks);
     const maxMz = getMax(xPeaks);
@@ -162,15 +797,22 @@ function renderPlot(data, sequence, charge) {
             line: { width: 0 }
         },
         width: barWidth,
-        hoverinfo: 'text',
-        text: hoverTexts,
-        textposition: 'none'
+        hovertemplate: mobility
+            ? 'm/z: %{x:.4f}<br>Int: %{y:.1f}<br>Mobility: %{customdata:.4f}<extra></extra>'
+            : 'm/z: %{x:.4f}<br>Int: %{y:.1f}<extra></extra>'
     };
+    if (mobility) tracePeaks.customdata = mobility;
 
     const traces = [tracePeaks];
 
-    const bMatches = matches.filter(m => m.ion_type.startsWith('b'));
-    const yMatches = matches.filter(m => m.ion_type.startsWith('y'));
+    // Phosphate losses get their own colour so the -98 pattern of pS/pT stands out
+    const isPhosphoLoss = (m) => m.ion_type.endsWith('-H3PO4');
+    const bMatches = matches.filter(m => m.ion_type.startsWith('b') && !isPhosphoLoss(m));
+    const yMatches = matches.filter(m => m.ion_type.startsWith('y') && !isPhosphoLoss(m));
+    const phosphoMatches = matches.filter(isPhosphoLoss);
+    // Glyco mode: oxonium ions ("oxo-HexNAc") and peptide + glycan Y ions ("Y0", "Y+HexNAc(1)")
+    const isGlycoIon = (m) => m.ion_type.startsWith('oxo-') || m.ion_type.startsWith('Y');
+    const glycoMatches = matches.filter(isGlycoIon);
 
     if (bMatches.length > 0) {
         traces.push({
@@ -196,15 +838,81 @@ function renderPlot(data, sequence, charge) {
         });
     }
 
+    if (phosphoMatches.length > 0) {
+        traces.push({
+            x: phosphoMatches.map(m => m.peak_mz),
+            y: phosphoMatches.map(m => m.peak_intensity),
+            type: 'bar',
+            name: '-H3PO4',
+            marker: { color: '#f59e0b', line: { width: 0 } },
+            width: barWidth,
+            hoverinfo: 'x+y+name'
+        });
+    }
+
+    if (glycoMatches.length > 0) {
+        traces.push({
+            x: glycoMatches.map(m => m.peak_mz),
+            y: glycoMatches.map(m => m.peak_intensity),
+            type: 'bar',
+            name: 'Glycan',
+            marker: { color: '#10b981', line: { width: 0 } },
+            width: barWidth,
+            hoverinfo: 'x+y+name'
+        });
+    }
+
+    // Mass error (ppm) of each match in a strip under the spectrum
+    if (matches.length > 0) {
+        traces.push({
+            x: matches.map(m => m.peak_mz),
+            y: matches.map(m => m.error_ppm),
+            text: matches.map(m => m.ion_type),
+            type: 'scatter',
+            mode: 'markers',
+            name: 'Error (ppm)',
+            yaxis: 'y2',
+            marker: { size: 5, color: matches.map(m => isPhosphoLoss(m) ? '#f59e0b' : isGlycoIon(m) ? '#10b981' : m.ion_type.startsWith('y') ? '#ef4444' : '#3b82f6') },
+            hovertemplate: '%{text}: %{y:.2f} ppm<extra></extra>',
+            showlegend: false
+        });
+    }
+
     // Create annotations for b and y ions
     const annotations = [];
 
     matches.forEach(m => {
-        if (m.ion_type.startsWith('b')) {
+        // One-to-one assignment: ambiguous peaks are starred, the other candidates shown on hover
+        const ambiguous = m.alternatives && m.alternatives.length > 0;
+        const text = ambiguous ? `${m.ion_type}*` : m.ion_type;
+        const hover = [m.mzpaf, ambiguous ? `Also fits: ${m.alternatives.join(', ')}` : null].filter(Boolean);
+        const hovertext = hover.length ? hover.join('<br>') : undefined;
+        if (isPhosphoLoss(m)) {
+            annotations.push({
+                x: m.peak_mz,
+                y: m.peak_intensity,
+                text,
+                hovertext,
+                showarrow: false,
+                yshift: 10,
+                font: { color: '#f59e0b', size: 12 }
+            });
+        } else if (isGlycoIon(m)) {
+            annotations.push({
+                x: m.peak_mz,
+                y: m.peak_intensity,
+                text,
+                hovertext,
+                showarrow: false,
+                yshift: 10,
+                font: { color: '#10b981', size: 12 }
+            });
+        } else if (m.ion_type.startsWith('b')) {
             annotations.push({
                 x: m.peak_mz,
                 y: m.peak_intensity,
-                text: m.ion_type,
+                text,
+                hovertext,
                 showarrow: false,
                 yshift: 10,
                 font: { color: '#3b82f6', size: 12 }
@@ -213,7 +921,8 @@ function renderPlot(data, sequence, charge) {
             annotations.push({
                 x: m.peak_mz,
                 y: m.peak_intensity,
-                text: m.ion_type,
+                text,
+                hovertext,
                 showarrow: false,
                 yshift: 10,
                 font: { color: '#ef4444', size: 12 }
@@ -221,9 +930,24 @@ function renderPlot(data, sequence, charge) {
         }
     });
 
+    // Dashed marker at each recorded precursor m/z
+    const shapes = (data.precursors || []).filter(p => p.mz).map(p => ({
+        type: 'line',
+        x0: p.mz,
+        x1: p.mz,
+        yref: 'paper',
+        y0: 0,
+        y1: 1,
+        line: { color: '#8b5cf6', width: 1, dash: 'dash' }
+    }));
+
+    const rtLabel = data.retention_time != null ? ` · RT ${(data.retention_time / 60).toFixed(2)} min` : '';
+    const scoreLabel = (data.hyperscore != null ? ` · hyperscore ${data.hyperscore.toFixed(1)}` : '') +
+        (data.xcorr != null ? ` · XCorr ${data.xcorr.toFixed(2)}` : '');
+
     const layout = {
         title: {
-            text: `Spectrum for [${sequence}]${charge}+`,
+            text: `Spectrum for [${sequence}]${charge}+${rtLabel}${scoreLabel}`,
             font: { size: 16 }
         },
         xaxis: {
@@ -234,9 +958,17 @@ function renderPlot(data, sequence, charge) {
         yaxis: {
             title: 'Intensity',
             range: [0, maxY * 1.2],
+            domain: [0.25, 1],
+            fixedrange: true
+        },
+        yaxis2: {
+            title: 'ppm',
+            domain: [0, 0.18],
+            zeroline: true,
             fixedrange: true
         },
         annotations: annotations,
+        shapes: shapes,
         showlegend: true,
         legend: {
             font: { color: '#1f2937' },
@@ -256,3 +988,79 @@ function renderPlot(data, sequence, charge) {
 
     Plotly.newPlot('plot-container', traces, layout, config);
 }
+
+const CHIMERIC_COLORS = ['#3b82f6', '#ef4444', '#10b981', '#f59e0b', '#8b5cf6', '#ec4899'];
+
+/** @param {import('./protview').ChimericResponse} data */
+function renderChimericPlot(data) {
+    if (!plotContainer) return;
+    plotContainer.innerHTML = '';
+
+    const color = (i) => CHIMERIC_COLORS[i % CHIMERIC_COLORS.length];
+    const barWidth = 0.05;
+    const xPeaks = data.peaks.map(p => p.mz);
+    const yPeaks = data.peaks.map(p => p.intensity);
+    const maxY = yPeaks.reduce((m, v) => Math.max(m, v), 0);
+
+    const traces = [{
+        x: xPeaks,
+        y: yPeaks,
+        type: 'bar',
+        name: 'Peaks',
+        marker: { color: '#606060', line: { width: 0 } },
+        width: barWidth,
+        hovertemplate: 'm/z: %{x:.4f}<br>Int: %{y:.1f}<extra></extra>'
+    }];
+
+    // Legend entries carry each peptide's explained intensity (unique / incl. shared peaks)
+    data.peptides.forEach((p, i) => {
+        const matches = data.matches.filter(m => m.peptide === i);
+        traces.push({
+            x: matches.map(m => m.peak_mz),
+            y: matches.map(m => m.peak_intensity),
+            text: matches.map(m => m.ion_type),
+            type: 'bar',
+            name: `${p.sequence} ${p.charge}+ (${p.unique_intensity.toFixed(1)}% / ${p.explained_intensity.toFixed(1)}%)`,
+            marker: { color: color(i), line: { width: 0 } },
+            width: barWidth,
+            hovertemplate: '%{text}: %{x:.4f}<extra></extra>'
+        });
+    });
+
+    // A peak claimed by several peptides gets one label per peptide, stacked
+    const stack = new Map();
+    const annotations = data.matches.map(m => {
+        const level = stack.get(m.peak_mz) || 0;
+        stack.set(m.peak_mz, level + 1);
+        return {
+            x: m.peak_mz,
+            y: m.peak_intensity,
+            text: m.ion_type,
+            showarrow: false,
+            yshift: 10 + 14 * level,
+            font: { color: color(m.peptide), size: 12 }
+        };
+    });
+
+    const { shared, unexplained } = data.breakdown;
+    const layout = {
+        title: {
+            text: `Chimeric scan ${data.scan_nr} · shared ${shared.toFixed(1)}% · unexplained ${unexplained.toFixed(1)}%`,
+            font: { size: 16 }
+        },
+        xaxis: { title: 'm/z', fixedrange: false },
+        yaxis: { title: 'Intensity', range: [0, maxY * 1.3], fixedrange: true },
+        annotations: annotations,
+        showlegend: true,
+        legend: { font: { color: '#1f2937' }, orientation: 'h', y: 1.15 },
+        margin: { t: 60, r: 20, l: 60, b: 50 },
+        autosize: true
+    };
+
+    Plotly.newPlot('plot-container', traces, layout, {
+        responsive: true,
+        displayModeBar: true,
+        modeBarButtonsToRemove: ['lasso2d', 'select2d'],
+        doubleClickDelay: 1000
+    });
+}
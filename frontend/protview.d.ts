@@ -0,0 +1,1074 @@
+// Types of the ProtView API payloads, mirroring backend/models.py and backend/settings.py.
+// app.js references them through JSDoc so editors can type-check field access.
+
+export interface Peak {
+    mz: number;
+    intensity: number;
+    /** 1/K0 or drift time, when the file has an ion mobility array */
+    ion_mobility?: number | null;
+    /** Set by deisotoping; mz is then the [M+H]+ of the envelope */
+    charge?: number | null;
+    isotope_count?: number | null;
+    /** Observed monoisotopic m/z before deconvolution */
+    original_mz?: number | null;
+    /** Set by centroiding: integrated area of the profile peak */
+    area?: number | null;
+    /** Set by the "snr" step: local noise level and intensity / noise */
+    noise?: number | null;
+    snr?: number | null;
+}
+
+export type IonSeries = "a" | "b" | "c" | "x" | "y" | "z";
+export type NeutralLoss = "H2O" | "NH3";
+
+export interface Ion {
+    /** Label such as "b5", "y3-H2O", "b4-H3PO4", "M-H3PO4" (precursor phospho loss), "oxo-HexNAc" or "Y+HexNAc(1)" */
+    type: string;
+    /** "M" for precursor ions, "oxonium" and "Y" in glyco mode */
+    series: IonSeries | "M" | "oxonium" | "Y";
+    index: number;
+    /** H3PO4 comes from phospho_losses, not the losses list */
+    loss: NeutralLoss | "H3PO4" | "";
+    charge: number;
+    mz: number;
+    /** Isotope label, only set for labeled series */
+    label?: string | null;
+    /** Crosslinks: which peptide of the pair */
+    peptide?: "alpha" | "beta" | null;
+    /** Crosslinks: "partner", a stub name, or null for fragments without the link site */
+    link?: string | null;
+}
+
+export interface IsotopeMatch {
+    /** 1 for M+1, ... */
+    isotope: number;
+    theoretical_mz: number;
+    peak_mz: number;
+    peak_intensity: number;
+    error_da: number;
+}
+
+/** How an ion picks among the peaks within tolerance */
+export type MatchStrategy = "closest" | "most_intense" | "intensity_weighted";
+export type AssignmentMode = "all" | "one_to_one";
+
+export interface MatchResult {
+    peak_mz: number;
+    peak_intensity: number;
+    ion_type: string;
+    ion_charge: number;
+    theoretical_mz: number;
+    /** Unsigned, Da */
+    error: number;
+    /** Signed (observed - theoretical), Da and ppm */
+    error_da: number;
+    error_ppm: number;
+    /** Percent of the base peak intensity */
+    relative_intensity: number;
+    /** 1 = base peak */
+    intensity_rank: number;
+    /** One-to-one assignment: ion types that also fit this peak but lost it */
+    alternatives?: string[];
+    /** Isotope peaks found after the monoisotopic one (MatchSettings.isotope_peaks) */
+    isotopes?: IsotopeMatch[];
+    /** HUPO-PSI mzPAF, e.g. "b5-H2O^2/-3.4ppm" */
+    mzpaf?: string;
+}
+
+export interface IsotopePeak {
+    isotope: number;
+    mz: number;
+    abundance: number;
+}
+
+export interface FragmentationSettings {
+    precursor_charge?: number;
+    series?: IonSeries[];
+    losses?: NeutralLoss[];
+    restrict_losses?: boolean;
+    /** -H3PO4 fragment and precursor ions for phospho S/T (default true) */
+    phospho_losses?: boolean;
+    /** Glyco mode: composition such as "HexNAc(4)Hex(5)NeuAc(2)"; the sequence is the bare peptide */
+    glycan?: string | null;
+    min_charge?: number;
+    max_charge?: number | null;
+    series_max_charge?: Partial<Record<IonSeries, number | "auto">>;
+    decimals?: number | null;
+    n_term_mod?: number;
+    c_term_mod?: number;
+    label?: string | null;
+    mass_type?: "monoisotopic" | "average";
+    electron_aware?: boolean;
+    residue_masses?: Record<string, number>;
+}
+
+/** Precursor as recorded in the mzML; absent terms are null */
+export type ToleranceUnit = "da" | "ppm";
+/** A symmetric tolerance, or [low, high] bounds on observed - theoretical */
+export type Tolerance = number | [number, number];
+
+export interface ToleranceRule {
+    tolerance: Tolerance;
+    unit?: ToleranceUnit;
+    /** Series the rule applies to; all when empty */
+    series?: IonSeries[];
+    min_mz?: number | null;
+    max_mz?: number | null;
+}
+
+/** Matching options; the first rule covering an ion sets its tolerance */
+export interface MatchSettings {
+    tolerance?: Tolerance;
+    tolerance_unit?: ToleranceUnit;
+    strategy?: MatchStrategy;
+    assignment?: AssignmentMode;
+    tolerance_rules?: ToleranceRule[];
+    /** Number of isotope peaks to look for after each match */
+    isotope_peaks?: number;
+    /** Ignore peaks below this signal-to-noise */
+    min_snr?: number | null;
+    /** Width in Da of the windows the noise level is estimated in */
+    noise_window?: number;
+}
+
+export interface Precursor {
+    /** Selected ion m/z */
+    mz: number | null;
+    charge: number | null;
+    intensity: number | null;
+    isolation_target: number | null;
+    isolation_lower_offset: number | null;
+    isolation_upper_offset: number | null;
+    /** Dissociation methods, e.g. ["HCD"] or ["ETD", "CID"] */
+    activation: string[];
+    collision_energy: number | null;
+    spectrum_ref: string | null;
+}
+
+/** Scan-level fields read from the mzML; absent terms are null */
+export interface ScanMetadata {
+    /** Scan start time in seconds */
+    retention_time: number | null;
+    ms_level: number | null;
+    polarity: "positive" | "negative" | null;
+    total_ion_current: number | null;
+    base_peak_mz: number | null;
+    base_peak_intensity: number | null;
+    /** Thermo filter string */
+    filter_string: string | null;
+    /** Centroided or profile arrays (MS:1000127 / MS:1000128) */
+    spectrum_type: "centroid" | "profile" | null;
+}
+
+/** Backbone cleavage between residue `position` and `position + 1` (1-based) */
+export interface CleavageSite {
+    position: number;
+    residue: string;
+    next_residue: string;
+    /** a/b/c series with a matched fragment ending at this site */
+    n_terminal: IonSeries[];
+    /** x/y/z series */
+    c_terminal: IonSeries[];
+    /** Matched labels, e.g. "b4" or "y6-H2O (2+)" */
+    ions: string[];
+    covered: boolean;
+}
+
+export type PeakFormat = "objects" | "arrays";
+
+export type ParseWarningCode =
+    | "unknown_compression"
+    | "unknown_data_type"
+    | "decode_error"
+    | "truncated_array"
+    | "array_length"
+    | "length_mismatch";
+
+/** A binary array issue that was skipped over rather than failing the spectrum */
+export interface ParseWarning {
+    code: ParseWarningCode;
+    /** "m/z", "intensity", another array's CV name, or null for the spectrum as a whole */
+    array: string | null;
+    message: string;
+}
+
+export interface SpectrumResponse extends ScanMetadata {
+    scan_nr: number;
+    /** Empty when peak_format is "arrays" or include_peaks is false */
+    peaks: Peak[];
+    /** Set instead of peaks when peak_format is "arrays" */
+    mz?: number[] | null;
+    intensity?: number[] | null;
+    ion_mobility?: number[] | null;
+    /** Other arrays of the spectrum (e.g. "charge array", "sampled noise intensity array") in file order */
+    data_arrays?: Record<string, number[]>;
+    /** Non-fatal problems met while decoding the spectrum */
+    warnings?: ParseWarning[];
+    matches: MatchResult[];
+    /** Precursors recorded in the mzML */
+    precursors: Precursor[];
+    /** Theoretical precursor m/z of the annotated sequence */
+    precursor_mz: number;
+    /** Fragment evidence per backbone cleavage site, for the sequence ladder */
+    coverage?: CleavageSite[];
+    /** log10 X!Tandem hyperscore of the matches; only with scores=true, null when nothing matched */
+    hyperscore?: number | null;
+    /** Comet-style fast XCorr of the b/y ions without losses; only with scores=true */
+    xcorr?: number | null;
+}
+
+/** A MatchResult without the peak fields, attached to its peak */
+export type PeakAnnotation = Omit<MatchResult, "peak_mz" | "peak_intensity">;
+
+export interface AnnotatedPeak extends Peak {
+    status: "matched" | "isotope" | "unmatched";
+    annotations: PeakAnnotation[];
+}
+
+export interface AnnotationSummary {
+    peak_count: number;
+    matched_peaks: number;
+    unmatched_peaks: number;
+    theoretical_ions: number;
+    matched_ions: number;
+    /** Percent of the total ion intensity */
+    explained_intensity: number;
+    mean_abs_error_ppm: number | null;
+}
+
+export interface AnnotatedSpectrumResponse {
+    scan_nr: number;
+    peaks: AnnotatedPeak[];
+    summary: AnnotationSummary;
+}
+
+export type SearchScore = "hyperscore" | "xcorr" | "matched_ions";
+
+/** Options of /api/spectrum/{scan_nr}/search; precursor_tolerance null scores every candidate */
+export interface SearchSettings {
+    fragmentation?: FragmentationSettings;
+    matching?: MatchSettings;
+    precursor_mz?: number | null;
+    precursor_tolerance?: number | null;
+    precursor_tolerance_unit?: ToleranceUnit;
+    score?: SearchScore;
+    max_results?: number;
+    /** Localize the precursor mass difference instead of requiring a match */
+    open_search?: boolean;
+    /** Da; smaller differences count as unmodified */
+    min_offset?: number;
+}
+
+export interface SearchResult {
+    rank: number;
+    sequence: string;
+    /** Value of the ranking score */
+    score: number | null;
+    hyperscore: number | null;
+    xcorr: number;
+    matched_ions: number;
+    theoretical_ions: number;
+    /** Theoretical, at the search precursor charge */
+    precursor_mz: number;
+    /** Observed minus theoretical, in precursor_tolerance_unit */
+    precursor_error: number | null;
+    matches: MatchResult[];
+    /** Open search: observed minus theoretical neutral mass (Da), when beyond min_offset */
+    mass_offset?: number | null;
+    /** 1-based residue the offset was placed on */
+    offset_position?: number | null;
+    offset_residue?: string | null;
+    /** All positions scoring as well as offset_position */
+    offset_sites?: number[];
+    /** Candidate with the offset in place, e.g. "PEPT[+79.96633]IDEK" */
+    localized_sequence?: string | null;
+}
+
+export interface SearchResponse {
+    scan_nr: number;
+    /** Observed m/z the candidates were filtered on */
+    precursor_mz: number | null;
+    results: SearchResult[];
+}
+
+export interface LocalizationIsoform {
+    /** The modification(s) placed on sites */
+    sequence: string;
+    /** 1-based */
+    sites: number[];
+    /** Ions not shared by all isoforms */
+    site_determining_ions: number;
+    matched_site_determining_ions: number;
+    /** -10 log10 of the chance of matching as many at random */
+    score: number;
+    probability: number;
+    /** Matches of the site-determining ions */
+    matches: MatchResult[];
+}
+
+export interface SiteProbability {
+    /** 1-based */
+    position: number;
+    residue: string;
+    probability: number;
+}
+
+export interface LocalizationResponse {
+    scan_nr: number;
+    sequence: string;
+    modification_mass: number;
+    /** Instances being localized */
+    modifications: number;
+    /** Chance of a random m/z matching a peak */
+    match_probability: number;
+    /** Most probable first */
+    isoforms: LocalizationIsoform[];
+    site_probabilities: SiteProbability[];
+}
+
+export type Crosslinker = "DSS" | "BS3" | "DSG" | "EDC" | "DSSO" | "DSBU";
+
+export interface CrosslinkResponse {
+    scan_nr: number;
+    peaks: Peak[];
+    linker_mass: number;
+    /** Cleavable crosslinkers: stub name to mass */
+    stub_masses: Record<string, number>;
+    /** Crosslinked pair at the precursor charge */
+    precursor_mz: number;
+    theoretical_ions: number;
+    /** Labels like "b3α", "y5β", "y7β+A" */
+    matches: MatchResult[];
+}
+
+export interface ChimericCandidate {
+    sequence: string;
+    /** Defaults to the fragmentation precursor_charge */
+    charge?: number | null;
+}
+
+export interface ChimericMatch extends MatchResult {
+    /** Index into the request's peptides */
+    peptide: number;
+}
+
+export interface ChimericPeptide {
+    sequence: string;
+    charge: number;
+    theoretical_ions: number;
+    matched_ions: number;
+    /** % of the total, shared peaks included */
+    explained_intensity: number;
+    /** % of the total in peaks no other peptide matches */
+    unique_intensity: number;
+}
+
+export interface ChimericResponse {
+    scan_nr: number;
+    peaks: Peak[];
+    peptides: ChimericPeptide[];
+    /** By peak m/z */
+    matches: ChimericMatch[];
+    breakdown: {
+        /** unique_intensity of each peptide */
+        peptides: number[];
+        /** % in peaks matched by several peptides */
+        shared: number;
+        unexplained: number;
+    };
+}
+
+export interface LibraryPeak {
+    mz: number;
+    intensity: number;
+    /** As written in the library, e.g. "y3/0.01" */
+    annotations: string[];
+}
+
+export interface LibraryEntry {
+    index: number;
+    /** e.g. "PEPTIDEK/2" */
+    name: string;
+    /** Modifications as [mass] deltas */
+    sequence: string;
+    charge: number | null;
+    precursor_mz: number | null;
+    proteins: string[];
+    is_decoy: boolean;
+    comment: Record<string, string>;
+    peaks: LibraryPeak[];
+}
+
+export type LibraryScore = "cosine" | "entropy";
+
+export interface LibraryMatch {
+    rank: number;
+    /** Of the library entry (GET /api/library/{index}) */
+    index: number;
+    name: string;
+    sequence: string;
+    charge: number | null;
+    precursor_mz: number | null;
+    precursor_error_ppm: number | null;
+    /** The requested score */
+    score: number;
+    /** On square-root intensities */
+    cosine: number;
+    entropy: number;
+    matched_peaks: number;
+}
+
+export interface LibraryMatchResponse {
+    scan_nr: number;
+    precursor_mz: number | null;
+    /** Best first */
+    results: LibraryMatch[];
+}
+
+export interface UsiComponents {
+    usi: string;
+    /** e.g. PXD000561; USI000000 for unpublished data */
+    collection: string;
+    run: string;
+    index_type: "scan" | "index" | "nativeId" | "trace";
+    index: string;
+    scan_nr: number | null;
+    /** ProForma, as written in the USI */
+    peptidoform: string | null;
+    /** With [mass] deltas */
+    sequence: string | null;
+    charge: number | null;
+}
+
+export interface MassErrorBin {
+    /** Bin centre */
+    error: number;
+    count: number;
+}
+
+/** Fragment mass error distribution of POST /api/qc/mass_errors */
+export interface MassErrorStatistics {
+    unit: ToleranceUnit;
+    count: number;
+    mean: number | null;
+    median: number | null;
+    mad: number | null;
+    /** 1.4826 * MAD */
+    robust_sd: number | null;
+    min: number | null;
+    max: number | null;
+    bin_width: number | null;
+    /** From min to max, empty bins included */
+    bins: MassErrorBin[];
+    /** Subtract from observed m/z */
+    recalibration_offset: number | null;
+    /** [low, high] bounds on observed - theoretical, usable as MatchSettings.tolerance */
+    suggested_tolerance: [number, number] | null;
+    /** Per PSM, in request order */
+    spectra?: { count: number; median: number | null }[];
+}
+
+/** Options of POST /api/spectrum/{scan}/svg; sizes in pixels */
+export interface SvgOptions {
+    width?: number;
+    height?: number;
+    title?: string | null;
+    /** The m/z range defaults to the peaks' +/- 50 */
+    min_mz?: number | null;
+    max_mz?: number | null;
+    /** Mass error strip under the spectrum */
+    show_errors?: boolean;
+    error_unit?: ToleranceUnit;
+    font_size?: number;
+    font_family?: string;
+    /** Labels on at most this many matched peaks, most intense first */
+    max_labels?: number | null;
+    legend?: boolean;
+}
+
+export interface BundleSpectrum {
+    scan_nr: number | null;
+    /** Native id */
+    id: string | null;
+    usi: string | null;
+    /** Spectrum file name */
+    source: string | null;
+    ms_level: number | null;
+    /** Seconds */
+    retention_time: number | null;
+    precursors: Precursor[] | null;
+    /** As matched, after any processing */
+    peaks: Peak[];
+}
+
+/** A shareable annotated view (see bundle.py); restore with POST /api/bundle/load */
+export interface AnalysisBundle {
+    format: "protview-annotation";
+    version: number;
+    /** ISO 8601, UTC */
+    created: string | null;
+    spectrum: BundleSpectrum;
+    peptide: { sequence: string; charge: number };
+    settings: FragmentationSettings;
+    match_settings: Record<string, unknown>;
+    matches: MatchResult[];
+}
+
+export interface DechargedPeak {
+    /** Neutral, of the lowest observed isotope */
+    mass: number;
+    /** Summed over the envelope */
+    intensity: number;
+    /** 1 for peaks in no envelope */
+    charge: number;
+    isotope_count: number;
+    /** Lowest observed isotope */
+    mz: number;
+}
+
+export interface TopDownMatch {
+    /** e.g. "c42", "z118" */
+    ion_type: string;
+    series: IonSeries;
+    index: number;
+    /** Neutral */
+    theoretical_mass: number;
+    observed_mass: number;
+    error_ppm: number;
+    /** Observed envelope starting this many C13 above the fragment */
+    isotope_error: number;
+    charge: number;
+    peak_mz: number;
+    peak_intensity: number;
+    /** HUPO-PSI mzPAF, e.g. "c42+i^7/2.1ppm" */
+    mzpaf?: string;
+}
+
+export interface TopDownResponse {
+    scan_nr: number;
+    sequence: string;
+    /** Neutral */
+    precursor_mass: number;
+    masses: DechargedPeak[];
+    matches: TopDownMatch[];
+    theoretical_ions: number;
+    matched_cleavages: number;
+    /** Percent of inter-residue bonds */
+    cleavage_coverage: number;
+}
+
+export type ReporterPlex = "tmt6" | "tmt10" | "tmt11" | "tmt16" | "tmt18" | "itraq4" | "itraq8";
+
+export interface ReporterChannel {
+    /** e.g. "127N" */
+    channel: string;
+    /** Theoretical reporter m/z */
+    mz: number;
+    /** 0 when no peak was found */
+    intensity: number;
+    observed_mz: number | null;
+    error_ppm: number | null;
+    /** Share of the summed reporter intensity */
+    relative: number;
+}
+
+export interface ReporterIonsResponse {
+    scan_nr: number;
+    plex: ReporterPlex;
+    channels: ReporterChannel[];
+    total_intensity: number;
+}
+
+export interface IsotopePeak {
+    /** Offset from the selected precursor peak, may be negative */
+    isotope: number;
+    mz: number;
+    intensity: number;
+}
+
+export interface IsolationPurityResponse {
+    scan_nr: number;
+    ms1_scan: number;
+    precursor_mz: number;
+    /** Recorded, or the best fitting when unknown */
+    charge: number;
+    /** [low, high] m/z */
+    window: [number, number];
+    /** Precursor share of the window intensity; null for an empty window */
+    purity: number | null;
+    precursor_intensity: number;
+    window_intensity: number;
+    isotopes: IsotopePeak[];
+    interfering_peaks: number;
+}
+
+export interface ChargeCandidate {
+    charge: number;
+    /** Isotope peaks found at this spacing */
+    isotopes: number;
+    /** Cosine to the averagine envelope */
+    shape_similarity: number;
+    score: number;
+}
+
+export interface ChargeDeterminationResponse {
+    scan_nr: number;
+    /** The MS1 used, or the scan itself without one */
+    source_scan: number;
+    recorded_charge: number | null;
+    /** null when no isotope envelope was found */
+    charge: number | null;
+    /** Snapped to the observed peak */
+    precursor_mz: number;
+    /** Best first */
+    candidates: ChargeCandidate[];
+}
+
+export interface IonsResponse {
+    ions: Ion[];
+}
+
+export interface BatchIonsResult {
+    sequence: string;
+    charge: number;
+    ions: Ion[];
+    /** Set instead of ions when this sequence could not be processed */
+    error: string | null;
+}
+
+export interface BatchIonsResponse {
+    results: BatchIonsResult[];
+}
+
+/** Query options of /api/spectrum/{scan_nr}/binned (fetchBinnedSpectrum); the response body is a Float32Array */
+export interface BinnedSpectrumOptions {
+    /** Da, default 1 */
+    binWidth?: number;
+    minMz?: number;
+    maxMz?: number;
+    aggregate?: "sum" | "max";
+}
+
+export interface ConsensusPeak extends Peak {
+    /** Fraction of the merged spectra containing the peak */
+    frequency: number;
+}
+
+export interface ConsensusResponse {
+    /** Number of spectra merged */
+    spectra: number;
+    peaks: ConsensusPeak[];
+}
+
+/** Indices into the top and bottom peak lists of a mirror plot */
+export interface PeakPair {
+    top: number;
+    bottom: number;
+    /** Top minus bottom m/z */
+    error_da: number;
+}
+
+export interface AlignmentResponse {
+    pairs: PeakPair[];
+    unpaired_top: number[];
+    unpaired_bottom: number[];
+}
+
+/** Peak pair of a modified cosine matched across the precursor mass difference */
+export interface ShiftedMatch {
+    index_a: number;
+    index_b: number;
+    mz_a: number;
+    mz_b: number;
+}
+
+export interface SimilarityResponse {
+    dot_product: number;
+    cosine: number;
+    /** Normalized spectral contrast angle: 1 for identical spectra, 0 for orthogonal ones */
+    spectral_angle: number;
+    matched_peaks: number;
+    /** Weighted spectral entropy similarity (Li et al. 2021), 1 for identical spectra */
+    entropy_similarity?: number | null;
+    /** Set when both precursor masses were sent */
+    modified_cosine?: number | null;
+    mass_shift?: number | null;
+    shifted_matches?: ShiftedMatch[];
+}
+
+/** A FASTA entry; UniProt headers fill entry_name, database, gene and organism */
+export interface ProteinEntry {
+    accession: string;
+    entry_name: string | null;
+    database: "sp" | "tr" | null;
+    gene: string | null;
+    organism: string | null;
+    description: string;
+    header: string;
+    sequence: string;
+}
+
+export type Enzyme = "trypsin" | "trypsin/p" | "lys-c" | "chymotrypsin" | "glu-c" | "asp-n";
+
+export interface DigestPeptide {
+    sequence: string;
+    /** 1-based, inclusive */
+    start: number;
+    end: number;
+    missed_cleavages: number;
+    /** Neutral monoisotopic mass */
+    mass: number;
+}
+
+export interface DigestResponse {
+    enzyme: Enzyme;
+    peptides: DigestPeptide[];
+}
+
+export interface ProteinHit {
+    accession: string;
+    /** 1-based, inclusive */
+    start: number;
+    end: number;
+    /** "-" at the protein N-terminus */
+    previous_residue: string;
+    /** "-" at the protein C-terminus */
+    next_residue: string;
+}
+
+export interface PeptideMappingResponse {
+    peptide: string;
+    proteins: ProteinHit[];
+}
+
+export interface PeptidePlacement {
+    /** As sent, with modifications */
+    peptide: string;
+    /** Residues only */
+    sequence: string;
+    /** 1-based start positions in the protein; empty when the peptide is absent */
+    starts: number[];
+}
+
+export interface ProteinCoverageResponse {
+    length: number;
+    /** Covering peptide occurrences per residue */
+    counts: number[];
+    /** [start, end] covered stretches, 1-based and inclusive */
+    ranges: [number, number][];
+    /** Percent of residues covered */
+    coverage: number;
+    peptides: PeptidePlacement[];
+}
+
+export interface PeptideProperties {
+    sequence: string;
+    /** Residues without modifications */
+    residues: string;
+    length: number;
+    /** Neutral masses, modifications included */
+    monoisotopic_mass: number;
+    average_mass: number;
+    /** [M+zH]z+ m/z keyed by charge "1" to "6" */
+    mz: Record<string, number>;
+    isoelectric_point: number;
+    /** Mean Kyte-Doolittle hydropathy */
+    gravy: number | null;
+    /** SSRCalc-style retention index */
+    hydrophobicity: number;
+    /** Hill notation; approximate when a modification mass has no known composition */
+    formula: string;
+}
+
+export type DecoyMethod = "reverse" | "pseudo_reverse" | "shuffle";
+
+export interface DecoyPair {
+    target: string;
+    /** Same modifications and mass as the target */
+    decoy: string;
+}
+
+export interface DecoyResponse {
+    method: DecoyMethod;
+    decoys: DecoyPair[];
+}
+
+/** A PSM for the delta-mass histogram: a neutral precursor_mass, or precursor_mz with charge */
+export interface DeltaMassPsm {
+    /** Or sequence, as in PeptideRow */
+    peptide?: string;
+    sequence?: string;
+    precursor_mass?: number;
+    precursor_mz?: number;
+    charge?: number;
+}
+
+export interface DeltaMassBin {
+    /** Da, bin centre */
+    delta: number;
+    count: number;
+}
+
+export interface DeltaMassPeak {
+    /** Da, mean over the PSMs in the bin */
+    delta: number;
+    count: number;
+    /** Likely Unimod names, closest first */
+    modifications: string[];
+}
+
+export interface DeltaMassResponse {
+    bin_width: number;
+    /** Non-empty bins only */
+    bins: DeltaMassBin[];
+    /** Most populated first */
+    peaks: DeltaMassPeak[];
+    total: number;
+    /** PSMs outside min_delta..max_delta */
+    outside_range: number;
+}
+
+/** A PSM for target-decoy statistics; other fields are passed through */
+export interface FdrPsm {
+    score: number;
+    is_decoy?: boolean;
+    [key: string]: unknown;
+}
+
+export interface ScoredPsm extends FdrPsm {
+    is_decoy: boolean;
+    q_value: number;
+    /** Posterior error probability, when requested */
+    pep: number | null;
+}
+
+export interface FdrResponse {
+    /** Best score first */
+    psms: ScoredPsm[];
+    targets: number;
+    decoys: number;
+    fdr_threshold: number;
+    /** Targets with q_value <= fdr_threshold */
+    accepted: number;
+}
+
+export interface RtCalibration {
+    slope: number;
+    intercept: number;
+    /** Only when fitted to the PSMs */
+    r2: number | null;
+    psms: number;
+}
+
+export interface RtPrediction {
+    sequence: string;
+    hydrophobicity: number;
+    predicted_rt: number;
+    retention_time: number | null;
+    /** Observed minus predicted */
+    deviation: number | null;
+    outlier: boolean;
+}
+
+export interface RtPredictionResponse {
+    calibration: RtCalibration;
+    /** Outlier threshold used */
+    max_deviation: number | null;
+    psms: RtPrediction[];
+}
+
+export interface SpectrumSummary extends ScanMetadata {
+    index: number;
+    /** nativeID, e.g. "controllerType=0 controllerNumber=1 scan=42" */
+    id: string;
+    scan_nr: number | null;
+    peak_count: number;
+    precursors: Precursor[];
+}
+
+export interface SpectrumListResponse {
+    spectra: SpectrumSummary[];
+}
+
+/** Per-spectrum quality indicators of GET /api/spectra/quality */
+export interface SpectrumQuality {
+    index: number;
+    id: string;
+    scan_nr: number | null;
+    ms_level: number | null;
+    /** Seconds */
+    retention_time: number | null;
+    peak_count: number;
+    /** Summed from the peaks */
+    tic: number;
+    base_peak_mz: number | null;
+    base_peak_intensity: number | null;
+    /** log10 of the base peak over the weakest non-zero peak */
+    dynamic_range: number | null;
+    /** Share of the TIC in the top_n most intense peaks */
+    top_fraction: number | null;
+    precursor_mz: number | null;
+    /** Most intense peak within tolerance of the precursor m/z, 0 when none */
+    precursor_intensity: number | null;
+    /** null for spectra without a precursor */
+    precursor_present: boolean | null;
+}
+
+export interface SpectrumQualityResponse {
+    top_n: number;
+    spectra: SpectrumQuality[];
+}
+
+/**
+ * Schema of the binary buffers of POST /api/spectrum/{scan}/binary and GET /api/spectra/binary.
+ * Buffer: "PVB1", uint32 schema length, this schema as UTF-8 JSON, zero padding to a multiple
+ * of 8; section offsets count from there. All values little-endian.
+ */
+export interface BinarySchema {
+    format: "protview-binary";
+    version: number;
+    byte_order: "little";
+    /** Scan number, precursors, scan metadata, or the source file for run output */
+    metadata: Record<string, unknown>;
+    sections: {
+        peaks?: BinaryPeakSection;
+        matches?: BinaryMatchSection;
+        spectra?: BinaryRecordSection;
+    };
+}
+
+export interface BinarySection {
+    /** Bytes from the end of the padded header; always a multiple of 8 */
+    offset: number;
+    /** Bytes */
+    length: number;
+    count: number;
+}
+
+/** Interleaved float64 rows, e.g. new Float64Array(buffer, start + offset, count * columns.length) */
+export interface BinaryPeakSection extends BinarySection {
+    dtype: "float64";
+    columns: ("mz" | "intensity" | "ion_mobility")[];
+}
+
+export interface BinaryRecordField {
+    name: string;
+    /** DataView getter, e.g. "float64" for getFloat64 */
+    type: "uint8" | "int8" | "uint16" | "uint32" | "int32" | "float64";
+    /** Byte offset inside the record */
+    offset: number;
+}
+
+/** Fixed-size records; spectra records have first_peak, peak_count, scan_nr, ms_level, retention_time, precursor_mz */
+export interface BinaryRecordSection extends BinarySection {
+    record_size: number;
+    fields: BinaryRecordField[];
+}
+
+/** Match records: peak_index, ion_type (into ion_types), ion_charge, flags and the m/z and error values */
+export interface BinaryMatchSection extends BinaryRecordSection {
+    ion_types: string[];
+    /** Bits of the flags field */
+    flags: { alternatives: number; isotopes: number };
+    /** peak_index of a match whose peak is not in the peaks section */
+    no_peak: number;
+}
+
+export type JobState = "running" | "done" | "cancelled" | "failed";
+
+/**
+ * A background job of POST /api/jobs/...; poll GET /api/jobs/{id} until status leaves
+ * "running", DELETE it to cancel.
+ */
+export interface JobStatus<T = unknown> {
+    id: string;
+    kind: "spectrum_quality" | "mass_errors" | "library_match";
+    status: JobState;
+    /** 0-100 */
+    percent: number;
+    current_scan: number | null;
+    error: string | null;
+    /** The matching synchronous endpoint's response, once done */
+    result: T | null;
+}
+
+export interface Chromatogram {
+    index: number;
+    id: string;
+    type: "tic" | "bpc" | "sic" | "srm" | null;
+    /** SRM transition Q1/Q3 */
+    precursor_mz: number | null;
+    product_mz: number | null;
+    /** Seconds */
+    time: number[];
+    intensity: number[];
+}
+
+export interface ChromatogramListResponse {
+    chromatograms: Chromatogram[];
+}
+
+export interface Feature {
+    /** Intensity-weighted over its scans */
+    mono_mz: number;
+    charge: number;
+    /** Seconds */
+    rt_start: number;
+    rt_end: number;
+    apex_rt: number;
+    apex_scan: number | null;
+    /** Summed isotope intensity at the apex */
+    apex_intensity: number;
+    scan_count: number;
+    /** Largest envelope */
+    isotope_count: number;
+    /** MS2 scans whose precursor hits the feature, for jumping from the LC-MS map */
+    ms2_scans: (number | null)[];
+}
+
+export interface FeatureListResponse {
+    /** Most intense first */
+    features: Feature[];
+}
+
+export interface XicResponse {
+    target_mz: number;
+    /** [low, high] m/z */
+    window: [number, number];
+    /** Seconds */
+    time: number[];
+    /** 0 for spectra without a peak in the window */
+    intensity: number[];
+    scan_nrs: (number | null)[];
+}
+
+export interface PeptideRow {
+    scan_nr: number;
+    spec_id: string;
+    sequence: string;
+    charge: number;
+    /** mzIdentML and other search engine output */
+    rank?: number;
+    precursor_mz?: number | null;
+    calculated_mz?: number | null;
+    retention_time?: number | null;
+    pass_threshold?: boolean;
+    is_decoy?: boolean;
+    proteins?: string[];
+    /** Filled in from GET /api/spectra/quality once the run is read */
+    quality?: SpectrumQuality;
+    /** Percolator and DIA-NN: primary score, q-value and posterior error probability */
+    score?: number | null;
+    q_value?: number | null;
+    pep?: number | null;
+    /** Engine scores by name, e.g. "MS-GF:SpecEValue" */
+    scores?: Record<string, number | string>;
+    /** Source spectrum file */
+    spectra_data?: string;
+    /** The search engine's own fragment matches (MaxQuant), labels as written */
+    fragments?: { ion_type: string; mz: number | null; intensity: number | null }[];
+}
+
+export interface ApiError {
+    detail: string;
+}
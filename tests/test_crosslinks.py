@@ -0,0 +1,41 @@
+import pytest
+
+from backend.calculations import calculate_ions, peptide_mass
+from backend.crosslinks import annotate_crosslink, crosslink_ions
+from backend.masses import mz_from_mass
+from backend.settings import FragmentationSettings
+
+def test_crosslink_with_custom_residue():
+    settings = FragmentationSettings(residue_masses={"J": 100.0})
+    ions = crosslink_ions("PEJKR", "AKJR", 4, 2, 138.06808, settings=settings)
+
+    # Every residue, standard or custom, is part of the ladder
+    alpha = {ion["index"] for ion in ions if ion["peptide"] == "alpha" and ion["series"] == "b"}
+    beta = {ion["index"] for ion in ions if ion["peptide"] == "beta" and ion["series"] == "y"}
+    assert alpha == {1, 2, 3, 4, 5}
+    assert beta == {1, 2, 3, 4}
+
+    # Fragments short of the link site are the plain peptide's, custom residue included
+    plain = {ion["type"]: ion["mz"] for ion in calculate_ions("PEJKR", settings)}
+    unlinked = [ion for ion in ions if ion["peptide"] == "alpha" and ion["link"] is None]
+    assert {ion["series"] + str(ion["index"]) for ion in unlinked} >= {"b1", "b2", "b3", "y1"}
+    for ion in unlinked:
+        assert ion["mz"] == pytest.approx(plain[f"{ion['series']}{ion['index']}" + (f"-{ion['loss']}" if ion["loss"] else "")])
+
+def test_crosslink_site_out_of_range():
+    settings = FragmentationSettings(residue_masses={"J": 100.0})
+    with pytest.raises(ValueError, match="out of range"):
+        crosslink_ions("PEJKR", "AKJR", 6, 2, 138.06808, settings=settings)
+
+def test_crosslink_average_masses():
+    settings = FragmentationSettings(mass_type="average", precursor_charge=3)
+    result = annotate_crosslink([{"mz": 500.0, "intensity": 1.0}], "PEPKR", "AKDR", 4, 2, settings=settings)
+    expected = (peptide_mass("PEPKR", mass_type="average") + peptide_mass("AKDR", mass_type="average")
+                + result["linker_mass"])
+    assert result["precursor_mz"] == pytest.approx(mz_from_mass(expected, 3))
+
+    # The partner's average mass rides on the linked fragments
+    ions = {ion["type"]: ion["mz"] for ion in crosslink_ions("PEPKR", "AKDR", 4, 2, 138.06808, settings=settings)
+            if ion["charge"] == 1}
+    plain = {ion["type"]: ion["mz"] for ion in calculate_ions("PEPKR", settings) if ion["charge"] == 1}
+    assert ions["y2α"] - plain["y2"] == pytest.approx(138.06808 + peptide_mass("AKDR", mass_type="average"))
@@ -0,0 +1,55 @@
+import pytest
+
+from backend.fdr import posterior_error_probabilities, q_values, target_decoy_statistics
+
+# Best first: T10, T9, then the tie at 8 (ranked decoy first), T7, D5, T4
+PSMS = [
+    {"score": 10, "is_decoy": False},
+    {"score": 9, "is_decoy": False},
+    {"score": 8, "is_decoy": False},
+    {"score": 8, "is_decoy": True},
+    {"score": 7, "is_decoy": False},
+    {"score": 5, "is_decoy": True},
+    {"score": 4, "is_decoy": False},
+]
+
+def test_q_values_with_decoy_correction():
+    # FDR (decoys + 1) / targets down the ranking: 1, 1/2, 2/2, 2/3, 2/4, 3/4, 3/5
+    assert q_values(PSMS) == pytest.approx([0.5, 0.5, 0.5, 0.5, 0.5, 0.6, 0.6])
+
+def test_q_values_without_decoy_correction():
+    # FDR decoys / targets: 0, 0, 1/2, 1/3, 1/4, 2/4, 2/5
+    assert q_values(PSMS, decoy_correction=False) == pytest.approx([0.0, 0.0, 0.25, 0.25, 0.25, 0.4, 0.4])
+
+def test_tied_target_ranks_after_decoy():
+    # Taken before the decoy it shares a score with, T8 would get q = 0
+    assert q_values(PSMS, decoy_correction=False)[2] == pytest.approx(0.25)
+
+def test_q_values_monotone_and_direction():
+    qs = q_values(PSMS)
+    ranked = sorted(range(len(PSMS)), key=lambda i: -PSMS[i]["score"])
+    assert all(qs[a] <= qs[b] for a, b in zip(ranked, ranked[1:]))
+    flipped = [{**psm, "score": -psm["score"]} for psm in PSMS]
+    assert q_values(flipped, higher_is_better=False) == pytest.approx(qs)
+
+def test_target_decoy_statistics():
+    result = target_decoy_statistics(PSMS, decoy_correction=False, fdr_threshold=0.3)
+    assert [p["score"] for p in result["psms"]] == [10, 9, 8, 8, 7, 5, 4]
+    assert result["psms"][2]["is_decoy"] is True
+    assert (result["targets"], result["decoys"], result["accepted"]) == (5, 2, 4)
+
+def test_pep_monotone():
+    psms = [{"score": s, "is_decoy": True} for s in (0.0, 0.5, 1.0, 1.5, 2.0)]
+    psms += [{"score": s, "is_decoy": False} for s in (0.2, 1.1, 1.8, 8.0, 8.5, 9.0, 9.5, 10.0)]
+    peps = posterior_error_probabilities(psms)
+    by_score = [peps[i] for i in sorted(range(len(psms)), key=lambda i: -psms[i]["score"])]
+    assert all(0.0 <= p <= 1.0 for p in peps)
+    assert all(a <= b for a, b in zip(by_score, by_score[1:]))
+    assert peps[-1] < 0.05
+    assert peps[5] > 0.5
+
+def test_invalid_input():
+    with pytest.raises(ValueError, match="numeric score"):
+        q_values([{"score": None}])
+    with pytest.raises(ValueError, match="at least 2 targets and 2 decoys"):
+        posterior_error_probabilities(PSMS[:3])
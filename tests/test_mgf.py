@@ -0,0 +1,36 @@
+from backend.mgf import MgfReader, parse_mgf
+
+MGF = """BEGIN IONS
+TITLE=first
+PEPMASS=500.0
+100.0 10.0
+END IONS
+BEGIN IONS
+TITLE=second
+PEPMASS=600.0
+SCANS=3
+200.0 20.0
+END IONS
+BEGIN IONS
+TITLE=third
+PEPMASS=700.0
+300.0 30.0
+END IONS
+"""
+
+
+def test_unnumbered_spectra_take_their_position():
+    # Position 3 belongs to SCANS=3, so the third spectrum moves past the largest number
+    assert [s["scan_nr"] for s in parse_mgf(MGF)] == [1, 3, 4]
+
+
+def test_index_and_listing_agree(tmp_path):
+    path = tmp_path / "run.mgf"
+    path.write_text(MGF)
+    reader = MgfReader(path)
+    listed = reader.list_spectra()
+    assert sorted(reader.scan_index) == [s["scan_nr"] for s in listed]
+    for spectrum in listed:
+        fetched = reader.get_spectrum(spectrum["scan_nr"], with_metadata=True)
+        assert fetched["id"] == spectrum["id"]
+        assert fetched["scan_nr"] == spectrum["scan_nr"]
@@ -0,0 +1,57 @@
+import math
+
+import pytest
+
+from backend.numpress import decode_linear, decode_pic, decode_slof
+
+# Byte strings as written by the MSNumpress reference encoder (encodeLinear, encodePic,
+# encodeSlof): an 8-byte big-endian fixed point, little-endian 4-byte ints for the first two
+# linear values, then half-byte encoded residuals packed high nibble first
+FIXED_POINT_100 = bytes.fromhex("4059000000000000")
+FIXED_POINT_1000 = bytes.fromhex("408f400000000000")
+
+@pytest.mark.parametrize("data, expected", [
+    # Residuals 0 and 0: half-bytes 8, 8
+    (FIXED_POINT_100 + bytes.fromhex("10270000" "204e0000" "88"), [100.0, 200.0, 300.0, 400.0]),
+    # Residual +5: half-bytes 7, 5
+    (FIXED_POINT_100 + bytes.fromhex("10270000" "204e0000" "75"), [100.0, 200.0, 300.05]),
+    # Residual -1: half-bytes f, f (leading 0xf nibbles implicit)
+    (FIXED_POINT_100 + bytes.fromhex("10270000" "204e0000" "ff"), [100.0, 200.0, 299.99]),
+    # Residuals 0 and +3: an odd number of half-bytes, padded with a zero nibble
+    (FIXED_POINT_100 + bytes.fromhex("10270000" "204e0000" "8730"), [100.0, 200.0, 300.0, 400.03]),
+    (FIXED_POINT_100 + bytes.fromhex("10270000" "204e0000"), [100.0, 200.0]),
+    (FIXED_POINT_100 + bytes.fromhex("10270000"), [100.0]),
+    (FIXED_POINT_100, []),
+])
+def test_decode_linear(data, expected):
+    assert decode_linear(data).tolist() == pytest.approx(expected)
+
+@pytest.mark.parametrize("data, expected", [
+    # 0 -> 8; 5 -> 7 5; 300 -> 5 c 2 1; trailing nibble padded
+    (bytes.fromhex("8755c210"), [0.0, 5.0, 300.0]),
+    # 0x12345678 has neither leading zero nor 0xf nibbles: head 0 and all eight half-bytes
+    (bytes.fromhex("0876543210"), [305419896.0]),
+    # 0xffffffff as leading 0xf nibbles: head f and one half-byte
+    (bytes.fromhex("ff"), [4294967295.0]),
+    (b"", []),
+])
+def test_decode_pic(data, expected):
+    assert decode_pic(data).tolist() == expected
+
+def test_decode_slof():
+    # log(x + 1) * 1000 as little-endian uint16: 0 -> 0, e - 1 -> 1000
+    data = FIXED_POINT_1000 + bytes.fromhex("0000" "e803")
+    assert decode_slof(data).tolist() == pytest.approx([0.0, math.e - 1])
+    assert decode_slof(FIXED_POINT_1000).tolist() == []
+
+@pytest.mark.parametrize("decoder, data, message", [
+    (decode_linear, FIXED_POINT_100 + b"\x10\x27", "too short"),
+    (decode_linear, FIXED_POINT_100 + bytes.fromhex("10270000" "204e"), "too short"),
+    (decode_linear, FIXED_POINT_100 + bytes.fromhex("10270000" "204e0000" "3f"), "past the end"),
+    (decode_pic, bytes.fromhex("3f"), "past the end"),
+    (decode_slof, FIXED_POINT_1000[:5], "corrupt numpress slof"),
+    (decode_slof, FIXED_POINT_1000 + b"\x00", "corrupt numpress slof"),
+])
+def test_corrupt_lengths(decoder, data, message):
+    with pytest.raises(ValueError, match=message):
+        decoder(data)
@@ -0,0 +1,28 @@
+import random
+
+import pytest
+
+from backend.masses import C13_DELTA, PROTON_MASS
+from backend.processing import deisotope
+
+def _envelope(mono_mz, charge, height, count=4):
+    return [{"mz": mono_mz + k * C13_DELTA / charge, "intensity": height * (1.0 - 0.2 * k)} for k in range(count)]
+
+def test_deisotope_interleaved_envelopes():
+    # A 2+ and a 3+ envelope sharing the m/z range; each peak goes to one envelope
+    peaks = _envelope(800.0, 2, 1000.0) + _envelope(800.3, 3, 600.0)
+    result = deisotope(peaks, max_charge=4, keep_unassigned=False)
+    assert [(p["charge"], p["isotope_count"]) for p in result] == [(2, 4), (3, 4)]
+    assert result[0]["mz"] == pytest.approx((800.0 - PROTON_MASS) * 2 + PROTON_MASS)
+    assert result[1]["mz"] == pytest.approx((800.3 - PROTON_MASS) * 3 + PROTON_MASS)
+
+def test_deisotope_dense_spectrum_uses_each_peak_once():
+    rng = random.Random(3)
+    peaks = []
+    for _ in range(300):
+        peaks += _envelope(rng.uniform(400, 1600), rng.randint(1, 6), rng.uniform(1e3, 1e5))
+    peaks += [{"mz": rng.uniform(400, 1600), "intensity": rng.uniform(1, 100)} for _ in range(2000)]
+    result = deisotope(peaks, max_charge=6)
+    # Every peak ends up in exactly one output peak, in an envelope or unassigned
+    assert sum(p["isotope_count"] for p in result) == len(peaks)
+    assert sum(p["intensity"] for p in result) == pytest.approx(sum(p["intensity"] for p in peaks))
@@ -0,0 +1,25 @@
+import random
+
+from backend.masses import C13_DELTA, PROTON_MASS
+from backend.topdown import decharge
+
+def _dense_spectrum(envelopes: int = 1000, noise: int = 8000, seed: int = 7):
+    # Proteoform-sized fragment envelopes at charges up to 25, plus random noise peaks
+    rng = random.Random(seed)
+    masses, peaks = [], []
+    for _ in range(envelopes):
+        mass, charge = rng.uniform(2000, 20000), rng.randint(5, 25)
+        masses.append(mass)
+        height = rng.uniform(1e4, 1e6)
+        for k in range(6):
+            peaks.append({"mz": (mass + k * C13_DELTA) / charge + PROTON_MASS,
+                          "intensity": height * (1.0 - 0.12 * abs(k - 2))})
+    peaks += [{"mz": rng.uniform(300, 2000), "intensity": rng.uniform(10, 1e3)} for _ in range(noise)]
+    return masses, peaks
+
+def test_decharge_recovers_masses():
+    masses, peaks = _dense_spectrum(envelopes=200, noise=1000)
+    found = [peak["mass"] for peak in decharge(peaks, max_charge=30, include_unassigned=False)]
+    recovered = sum(1 for mass in masses if any(abs(f - mass) / mass * 1e6 <= 10 for f in found))
+    # Randomly placed envelopes overlap now and then; the rest come back within 10 ppm
+    assert recovered >= 0.85 * len(masses)